@@ -0,0 +1,33 @@
+use pallas::network::miniprotocols::MAINNET_MAGIC;
+use pallas_hardano::mock::{ImmutableStore, MockNode};
+use tokio::net::{TcpListener, UnixListener};
+
+// usage: mock-node <immutable-dir> <n2n-address> <n2c-socket-path>
+#[tokio::main]
+async fn main() {
+    tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::INFO)
+            .finish(),
+    )
+    .unwrap();
+
+    let args: Vec<_> = std::env::args().collect();
+
+    let [_, dir, address, socket] = args.as_slice() else {
+        eprintln!("usage: mock-node <immutable-dir> <n2n-address> <n2c-socket-path>");
+        std::process::exit(1);
+    };
+
+    let store = ImmutableStore::open(dir).unwrap();
+    let node = MockNode::new(store, MAINNET_MAGIC);
+
+    let tcp = TcpListener::bind(address).await.unwrap();
+
+    let _ = std::fs::remove_file(socket);
+    let unix = UnixListener::bind(socket).unwrap();
+
+    tracing::info!(address, socket, "serving chain");
+
+    tokio::try_join!(node.serve_n2n(tcp), node.serve_n2c(unix)).unwrap();
+}
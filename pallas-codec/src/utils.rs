@@ -575,9 +575,15 @@ impl<C> minicbor::encode::Encode<C> for EmptyMap {
 /// A common pattern seen in the CDDL is to represent optional values as an
 /// array containing zero or more items. This structure reflects that pattern
 /// while providing semantic meaning.
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ZeroOrOneArray<T>(Option<T>);
 
+impl<T> From<Option<T>> for ZeroOrOneArray<T> {
+    fn from(value: Option<T>) -> Self {
+        ZeroOrOneArray(value)
+    }
+}
+
 impl<T> Deref for ZeroOrOneArray<T> {
     type Target = Option<T>;
 
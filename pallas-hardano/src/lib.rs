@@ -1 +1,4 @@
+#[cfg(feature = "mock")]
+pub mod mock;
+
 pub mod storage;
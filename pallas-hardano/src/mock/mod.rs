@@ -0,0 +1,884 @@
+//! A mock node that serves a stored chain through the Ouroboros mini-protocols
+//!
+//! The [`MockNode`] assembles the server side of the `pallas-network`
+//! mini-protocols on top of a [`ChainStore`], which allows running integration
+//! tests against a fixed chain without a real Haskell node. N2N connections
+//! are served chain-sync (headers) and block-fetch, N2C connections are served
+//! chain-sync (blocks) and the local state queries that can be answered from
+//! the chain itself.
+//!
+//! Once a chain-sync client reaches the tip it is told to wait, and the store
+//! is polled until its chain grows past the last block that was sent. Stores
+//! whose chain never grows keep the client waiting until it disconnects.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use pallas_codec::utils::AnyCbor;
+use pallas_network::facades::{self, KeepAliveLoop, PeerServer};
+use pallas_network::miniprotocols::chainsync::{self, ClientRequest, HeaderContent, Tip};
+use pallas_network::miniprotocols::localstate::queries_v16::{
+    self, ChainBlockNumber, HardForkQuery, LedgerQuery, SystemStart,
+};
+use pallas_network::miniprotocols::{blockfetch, localstate};
+use pallas_traverse::MultiEraBlock;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+#[cfg(unix)]
+use pallas_network::facades::NodeServer;
+#[cfg(unix)]
+use pallas_network::miniprotocols::chainsync::BlockContent;
+#[cfg(unix)]
+use pallas_network::miniprotocols::localstate::{AcquireFailure, ClientQueryRequest};
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use crate::storage::immutable::{self, chunk, Block, Point};
+
+mod store;
+
+pub use store::*;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("immutable db error")]
+    Immutable(#[source] immutable::Error),
+
+    #[error("chunk read error")]
+    Chunk(#[source] chunk::Error),
+
+    #[cfg(feature = "rolldb")]
+    #[error("rolldb error")]
+    RollDb(#[source] pallas_rolldb::Error),
+
+    #[error("stored block can't be decoded")]
+    InvalidBlock(#[source] pallas_traverse::Error),
+
+    #[error("connection error")]
+    Connection(#[source] facades::Error),
+
+    #[error("chain-sync error")]
+    ChainSync(#[source] chainsync::ServerError),
+
+    #[error("block-fetch error")]
+    BlockFetch(#[source] blockfetch::ServerError),
+
+    #[error("state query error")]
+    StateQuery(#[source] localstate::Error),
+
+    #[error("unsupported state query: {0}")]
+    UnsupportedQuery(String),
+}
+
+/// Summary of the tip block, as reported by the mini-protocols
+struct ChainTip {
+    tip: Tip,
+    era: u16,
+}
+
+impl ChainTip {
+    fn read(store: &impl ChainStore) -> Result<Self, Error> {
+        match store.tip()? {
+            Some(block) => {
+                let block = MultiEraBlock::decode(&block).map_err(Error::InvalidBlock)?;
+                let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+                Ok(Self {
+                    tip: Tip(point, block.number()),
+                    era: hardfork_era(&block),
+                })
+            }
+            None => Ok(Self {
+                tip: Tip(Point::Origin, 0),
+                era: 0,
+            }),
+        }
+    }
+}
+
+/// Index of the era of the block within the hard-fork combinator
+fn hardfork_era(block: &MultiEraBlock) -> u16 {
+    // the block wrapper tags start at 1 for Byron main blocks
+    u16::from(block.era()) - 1
+}
+
+fn header_content(block: Block) -> Result<HeaderContent, Error> {
+    let block = MultiEraBlock::decode(&block).map_err(Error::InvalidBlock)?;
+
+    let byron_prefix = match block {
+        MultiEraBlock::EpochBoundary(_) => Some((0, block.size() as u64)),
+        MultiEraBlock::Byron(_) => Some((1, block.size() as u64)),
+        _ => None,
+    };
+
+    Ok(HeaderContent {
+        variant: hardfork_era(&block) as u8,
+        byron_prefix,
+        cbor: block.header().cbor().to_vec(),
+    })
+}
+
+fn block_point(block: &[u8]) -> Result<Point, Error> {
+    let block = MultiEraBlock::decode(block).map_err(Error::InvalidBlock)?;
+
+    Ok(Point::Specific(block.slot(), block.hash().to_vec()))
+}
+
+/// How often the store is checked for new blocks while a chain-sync client
+/// waits at the tip
+const TIP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Serves a node-like view of a [`ChainStore`] to N2N and N2C clients
+pub struct MockNode<S> {
+    store: Arc<S>,
+    magic: u64,
+    system_start: SystemStart,
+}
+
+impl<S> Clone for MockNode<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            magic: self.magic,
+            system_start: self.system_start.clone(),
+        }
+    }
+}
+
+impl<S> MockNode<S>
+where
+    S: ChainStore,
+{
+    pub fn new(store: S, magic: u64) -> Self {
+        Self {
+            store: Arc::new(store),
+            magic,
+            // mainnet system start
+            system_start: SystemStart {
+                year: 2017,
+                day_of_year: 266,
+                picoseconds_of_day: 77_400_000_000_000_000,
+            },
+        }
+    }
+
+    /// System start answered to `GetSystemStart` queries, defaults to mainnet
+    pub fn with_system_start(mut self, system_start: SystemStart) -> Self {
+        self.system_start = system_start;
+        self
+    }
+
+    /// Accepts N2N connections until the listener fails
+    ///
+    /// Each connection is served in its own task. Connections that fail the
+    /// handshake are dropped without stopping the listener.
+    pub async fn serve_n2n(&self, listener: TcpListener) -> Result<(), Error> {
+        loop {
+            let server = match PeerServer::accept(&listener, self.magic).await {
+                Ok(x) => x,
+                Err(facades::Error::ConnectFailure(err)) => {
+                    return Err(Error::Connection(facades::Error::ConnectFailure(err)))
+                }
+                Err(err) => {
+                    warn!(?err, "n2n handshake failed");
+                    continue;
+                }
+            };
+
+            info!(address = ?server.accepted_address(), "n2n connection accepted");
+
+            let node = self.clone();
+
+            tokio::spawn(async move {
+                if let Err(err) = node.serve_peer(server).await {
+                    warn!(?err, "n2n connection ended with error");
+                }
+            });
+        }
+    }
+
+    /// Serves a single N2N connection that already went through the handshake
+    ///
+    /// The connection is served until the client is done with both
+    /// chain-sync and block-fetch, or closed as soon as it breaks either of
+    /// them.
+    pub async fn serve_peer(&self, server: PeerServer) -> Result<(), Error> {
+        let PeerServer {
+            plexer,
+            mut chainsync,
+            mut blockfetch,
+            keepalive,
+            // not served, but dropping the channels would break the plexer
+            txsubmission: _txsubmission,
+            peersharing: _peersharing,
+            ..
+        } = server;
+
+        let keepalive = KeepAliveLoop::server(keepalive).spawn();
+
+        let result = tokio::try_join!(
+            serve_chainsync(self.store.as_ref(), &mut chainsync, header_content),
+            serve_blockfetch(self.store.as_ref(), &mut blockfetch),
+        )
+        .map(|_| ());
+
+        keepalive.abort();
+        plexer.abort().await;
+
+        result
+    }
+
+    /// Accepts N2C connections until the listener fails
+    #[cfg(unix)]
+    pub async fn serve_n2c(&self, listener: UnixListener) -> Result<(), Error> {
+        loop {
+            let server = match NodeServer::accept(&listener, self.magic).await {
+                Ok(x) => x,
+                Err(facades::Error::ConnectFailure(err)) => {
+                    return Err(Error::Connection(facades::Error::ConnectFailure(err)))
+                }
+                Err(err) => {
+                    warn!(?err, "n2c handshake failed");
+                    continue;
+                }
+            };
+
+            info!("n2c connection accepted");
+
+            let node = self.clone();
+
+            tokio::spawn(async move {
+                if let Err(err) = node.serve_node(server).await {
+                    warn!(?err, "n2c connection ended with error");
+                }
+            });
+        }
+    }
+
+    /// Serves a single N2C connection that already went through the handshake
+    ///
+    /// The connection is served until the client is done with both
+    /// chain-sync and local state queries, or closed as soon as it breaks
+    /// either of them.
+    #[cfg(unix)]
+    pub async fn serve_node(&self, server: NodeServer) -> Result<(), Error> {
+        let NodeServer {
+            plexer,
+            mut chainsync,
+            mut statequery,
+            submission: _submission,
+            monitor: _monitor,
+            ..
+        } = server;
+
+        let result = tokio::try_join!(
+            serve_chainsync(self.store.as_ref(), &mut chainsync, |x| Ok(BlockContent(x))),
+            self.serve_statequery(&mut statequery),
+        )
+        .map(|_| ());
+
+        plexer.abort().await;
+
+        result
+    }
+
+    #[cfg(unix)]
+    async fn serve_statequery(&self, server: &mut localstate::Server) -> Result<(), Error> {
+        while let Some(request) = server.recv_while_idle().await.map_err(Error::StateQuery)? {
+            self.acquire(server, request.0).await?;
+
+            loop {
+                match server
+                    .recv_while_acquired()
+                    .await
+                    .map_err(Error::StateQuery)?
+                {
+                    ClientQueryRequest::Query(query) => {
+                        let response = self.answer_query(query)?;
+                        server
+                            .send_result(response)
+                            .await
+                            .map_err(Error::StateQuery)?;
+                    }
+                    ClientQueryRequest::ReAcquire(point) => self.acquire(server, point).await?,
+                    ClientQueryRequest::Release => break,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// There's no ledger behind the chain, any point of the chain is as good
+    /// as the tip
+    #[cfg(unix)]
+    async fn acquire(
+        &self,
+        server: &mut localstate::Server,
+        point: Option<Point>,
+    ) -> Result<(), Error> {
+        let on_chain = match &point {
+            Some(point) => self.store.read_from(point)?.is_some(),
+            None => true,
+        };
+
+        if on_chain {
+            server.send_acquired().await.map_err(Error::StateQuery)
+        } else {
+            debug!(?point, "acquire point not on chain");
+
+            server
+                .send_failure(AcquireFailure::PointNotOnChain)
+                .await
+                .map_err(Error::StateQuery)
+        }
+    }
+
+    fn answer_query(&self, query: AnyCbor) -> Result<AnyCbor, Error> {
+        let query: queries_v16::Request = query
+            .into_decode()
+            .map_err(|err| Error::UnsupportedQuery(err.to_string()))?;
+
+        let tip = ChainTip::read(self.store.as_ref())?;
+
+        let response = match query {
+            queries_v16::Request::GetSystemStart => AnyCbor::from_encode(&self.system_start),
+            queries_v16::Request::GetChainPoint => AnyCbor::from_encode(&tip.tip.0),
+            queries_v16::Request::GetChainBlockNo => AnyCbor::from_encode(ChainBlockNumber {
+                slot_timeline: 1,
+                block_number: tip.tip.1 as u32,
+            }),
+            queries_v16::Request::LedgerQuery(LedgerQuery::HardForkQuery(
+                HardForkQuery::GetCurrentEra,
+            )) => AnyCbor::from_encode(tip.era),
+            x => return Err(Error::UnsupportedQuery(format!("{x:?}"))),
+        };
+
+        Ok(response)
+    }
+}
+
+/// Looks for the first of the points that is part of the chain, returning it
+/// along with the blocks that come after it
+fn find_intersect(
+    store: &impl ChainStore,
+    points: Vec<Point>,
+) -> Result<Option<(Point, BlockIter)>, Error> {
+    for point in points {
+        let Some(mut iter) = store.read_from(&point)? else {
+            continue;
+        };
+
+        // the block at the intersection was already seen by the client
+        if let Point::Specific(..) = point {
+            iter.next().transpose()?;
+        }
+
+        return Ok(Some((point, iter)));
+    }
+
+    Ok(None)
+}
+
+/// Update sent to a chain-sync client once the chain changed while it waited
+enum Update {
+    Forward(Block, BlockIter),
+    Backward,
+}
+
+/// Waits until the chain moves past the last point sent to the client
+///
+/// If that point is no longer part of the chain, the client is rolled back to
+/// the origin, the only point that is known to be shared with it.
+async fn wait_for_update(store: &impl ChainStore, last: &Point) -> Result<Update, Error> {
+    loop {
+        tokio::time::sleep(TIP_POLL_INTERVAL).await;
+
+        let tip = match store.tip()? {
+            Some(block) => block_point(&block)?,
+            None => Point::Origin,
+        };
+
+        if tip == *last {
+            continue;
+        }
+
+        let Some(mut iter) = store.read_from(last)? else {
+            return Ok(Update::Backward);
+        };
+
+        if let Point::Specific(..) = last {
+            iter.next().transpose()?;
+        }
+
+        if let Some(block) = iter.next().transpose()? {
+            return Ok(Update::Forward(block, iter));
+        }
+    }
+}
+
+async fn serve_chainsync<O>(
+    store: &impl ChainStore,
+    server: &mut chainsync::Server<O>,
+    content: impl Fn(Block) -> Result<O, Error>,
+) -> Result<(), Error>
+where
+    chainsync::Message<O>: pallas_codec::Fragment,
+{
+    // clients that don't look for an intersection start from the origin
+    let mut cursor = None;
+    let mut rollback = None;
+
+    // the last point the client is known to have
+    let mut last = Point::Origin;
+
+    while let Some(request) = server.recv_while_idle().await.map_err(Error::ChainSync)? {
+        let tip = ChainTip::read(store)?.tip;
+
+        match request {
+            ClientRequest::Intersect(points) => match find_intersect(store, points)? {
+                Some((point, iter)) => {
+                    debug!(?point, "intersection found");
+
+                    cursor = Some(iter);
+                    rollback = Some(point.clone());
+                    last = point.clone();
+
+                    server
+                        .send_intersect_found(point, tip)
+                        .await
+                        .map_err(Error::ChainSync)?;
+                }
+                None => server
+                    .send_intersect_not_found(tip)
+                    .await
+                    .map_err(Error::ChainSync)?,
+            },
+            ClientRequest::RequestNext => {
+                // the first update after an intersection is a rollback to it
+                if let Some(point) = rollback.take() {
+                    server
+                        .send_roll_backward(point, tip)
+                        .await
+                        .map_err(Error::ChainSync)?;
+
+                    continue;
+                }
+
+                let iter = match &mut cursor {
+                    Some(x) => x,
+                    None => cursor.insert(
+                        store
+                            .read_from(&Point::Origin)?
+                            .unwrap_or_else(|| Box::new(std::iter::empty())),
+                    ),
+                };
+
+                if let Some(block) = iter.next().transpose()? {
+                    last = block_point(&block)?;
+
+                    server
+                        .send_roll_forward(content(block)?, tip)
+                        .await
+                        .map_err(Error::ChainSync)?;
+
+                    continue;
+                }
+
+                server.send_await_reply().await.map_err(Error::ChainSync)?;
+
+                let update = wait_for_update(store, &last).await?;
+                let tip = ChainTip::read(store)?.tip;
+
+                match update {
+                    Update::Forward(block, iter) => {
+                        cursor = Some(iter);
+                        last = block_point(&block)?;
+
+                        server
+                            .send_roll_forward(content(block)?, tip)
+                            .await
+                            .map_err(Error::ChainSync)?;
+                    }
+                    Update::Backward => {
+                        debug!(point = ?last, "waited point rolled back");
+
+                        cursor = None;
+                        last = Point::Origin;
+
+                        server
+                            .send_roll_backward(Point::Origin, tip)
+                            .await
+                            .map_err(Error::ChainSync)?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the blocks of an inclusive range, or `None` if the range isn't part
+/// of the chain
+fn read_range(
+    store: &impl ChainStore,
+    (from, to): &blockfetch::Range,
+) -> Result<Option<Vec<Block>>, Error> {
+    let Some(iter) = store.read_from(from)? else {
+        return Ok(None);
+    };
+
+    let Point::Specific(to_slot, _) = to else {
+        return Ok(None);
+    };
+
+    let mut blocks = vec![];
+
+    for block in iter {
+        let block = block?;
+        let point = block_point(&block)?;
+
+        if point.slot_or_default() > *to_slot {
+            break;
+        }
+
+        blocks.push(block);
+
+        if point == *to {
+            return Ok(Some(blocks));
+        }
+    }
+
+    Ok(None)
+}
+
+async fn serve_blockfetch(
+    store: &impl ChainStore,
+    server: &mut blockfetch::Server,
+) -> Result<(), Error> {
+    while let Some(request) = server.recv_while_idle().await.map_err(Error::BlockFetch)? {
+        let blocks = read_range(store, &request.0)?;
+
+        match blocks {
+            Some(blocks) => {
+                debug!(count = blocks.len(), "serving block range");
+
+                server
+                    .send_block_range(blocks)
+                    .await
+                    .map_err(Error::BlockFetch)?;
+            }
+            None => {
+                debug!(range = ?request.0, "block range not found");
+                server.send_no_blocks().await.map_err(Error::BlockFetch)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas_network::facades::PeerClient;
+    use pallas_network::miniprotocols::chainsync::NextResponse;
+    use pallas_network::miniprotocols::MAINNET_MAGIC;
+    use pallas_network::multiplexer::Bearer;
+    use pallas_traverse::MultiEraHeader;
+
+    use super::*;
+
+    fn first_block() -> (Point, Block) {
+        let block = immutable::read_blocks(std::path::Path::new("../test_data"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        (block_point(&block).unwrap(), block)
+    }
+
+    fn tip_point() -> Point {
+        immutable::get_tip(std::path::Path::new("../test_data"))
+            .unwrap()
+            .unwrap()
+    }
+
+    fn mock_node() -> MockNode<ImmutableStore> {
+        let store = ImmutableStore::open("../test_data").unwrap();
+        MockNode::new(store, MAINNET_MAGIC)
+    }
+
+    #[tokio::test]
+    async fn serves_headers_and_blocks_to_peers() {
+        let node = mock_node();
+        let (client_bearer, server_bearer) = Bearer::pair();
+
+        let server = tokio::spawn(async move {
+            let server = PeerServer::accept_bearer(server_bearer, MAINNET_MAGIC)
+                .await
+                .unwrap();
+
+            node.serve_peer(server).await
+        });
+
+        let mut client = PeerClient::connect_bearer(client_bearer, MAINNET_MAGIC)
+            .await
+            .unwrap();
+
+        let (first_point, first_block) = first_block();
+
+        let (point, tip) = client
+            .chainsync()
+            .find_intersect(vec![Point::Origin])
+            .await
+            .unwrap();
+
+        assert_eq!(point, Some(Point::Origin));
+        assert_eq!(tip.0, tip_point());
+
+        match client.chainsync().request_next().await.unwrap() {
+            NextResponse::RollBackward(point, _) => assert_eq!(point, Point::Origin),
+            x => panic!("unexpected response: {x:?}"),
+        }
+
+        match client.chainsync().request_next().await.unwrap() {
+            NextResponse::RollForward(header, _) => {
+                let subtag = header.byron_prefix.map(|(x, _)| x);
+                let header = MultiEraHeader::decode(header.variant, subtag, &header.cbor).unwrap();
+
+                assert_eq!(
+                    Point::Specific(header.slot(), header.hash().to_vec()),
+                    first_point
+                );
+            }
+            x => panic!("unexpected response: {x:?}"),
+        }
+
+        let body = client
+            .blockfetch()
+            .fetch_single(first_point.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(body, first_block);
+
+        let missing = Point::Specific(first_point.slot_or_default(), vec![0; 32]);
+        assert!(client.blockfetch().fetch_single(missing).await.is_err());
+
+        // at the tip the client is told to wait
+        client
+            .chainsync()
+            .find_intersect(vec![tip_point()])
+            .await
+            .unwrap();
+
+        client.chainsync().request_next().await.unwrap();
+
+        assert!(matches!(
+            client.chainsync().request_next().await.unwrap(),
+            NextResponse::Await
+        ));
+
+        client.abort().await;
+
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn keeps_serving_blocks_after_chainsync_is_done() {
+        let node = mock_node();
+        let (client_bearer, server_bearer) = Bearer::pair();
+
+        let server = tokio::spawn(async move {
+            let server = PeerServer::accept_bearer(server_bearer, MAINNET_MAGIC)
+                .await
+                .unwrap();
+
+            node.serve_peer(server).await
+        });
+
+        let mut client = PeerClient::connect_bearer(client_bearer, MAINNET_MAGIC)
+            .await
+            .unwrap();
+
+        client.chainsync().send_done().await.unwrap();
+
+        let (first_point, first_block) = first_block();
+
+        let body = client.blockfetch().fetch_single(first_point).await.unwrap();
+        assert_eq!(body, first_block);
+
+        client.blockfetch().send_done().await.unwrap();
+
+        server.await.unwrap().unwrap();
+    }
+
+    /// Chain that the test appends blocks to while it's being served
+    #[derive(Clone, Default)]
+    struct GrowingStore(Arc<std::sync::Mutex<Vec<Block>>>);
+
+    impl ChainStore for GrowingStore {
+        fn tip(&self) -> Result<Option<Block>, Error> {
+            Ok(self.0.lock().unwrap().last().cloned())
+        }
+
+        fn read_from(&self, point: &Point) -> Result<Option<BlockIter>, Error> {
+            let blocks = self.0.lock().unwrap().clone();
+
+            let start = match point {
+                Point::Origin => 0,
+                point => match blocks
+                    .iter()
+                    .position(|x| block_point(x).unwrap() == *point)
+                {
+                    Some(x) => x,
+                    None => return Ok(None),
+                },
+            };
+
+            Ok(Some(Box::new(blocks.into_iter().skip(start).map(Ok))))
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_new_blocks_to_waiting_clients() {
+        let blocks: Vec<_> = immutable::read_blocks(std::path::Path::new("../test_data"))
+            .unwrap()
+            .take(2)
+            .map(Result::unwrap)
+            .collect();
+
+        let store = GrowingStore::default();
+        store.0.lock().unwrap().push(blocks[0].clone());
+
+        let node = MockNode::new(store.clone(), MAINNET_MAGIC);
+        let (client_bearer, server_bearer) = Bearer::pair();
+
+        tokio::spawn(async move {
+            let server = PeerServer::accept_bearer(server_bearer, MAINNET_MAGIC)
+                .await
+                .unwrap();
+
+            node.serve_peer(server).await
+        });
+
+        let mut client = PeerClient::connect_bearer(client_bearer, MAINNET_MAGIC)
+            .await
+            .unwrap();
+
+        let first = block_point(&blocks[0]).unwrap();
+
+        client
+            .chainsync()
+            .find_intersect(vec![first.clone()])
+            .await
+            .unwrap();
+
+        client.chainsync().request_next().await.unwrap();
+
+        assert!(matches!(
+            client.chainsync().request_next().await.unwrap(),
+            NextResponse::Await
+        ));
+
+        store.0.lock().unwrap().push(blocks[1].clone());
+
+        match client.chainsync().recv_while_must_reply().await.unwrap() {
+            NextResponse::RollForward(header, tip) => {
+                let subtag = header.byron_prefix.map(|(x, _)| x);
+                let header = MultiEraHeader::decode(header.variant, subtag, &header.cbor).unwrap();
+                let point = Point::Specific(header.slot(), header.hash().to_vec());
+
+                assert_eq!(point, block_point(&blocks[1]).unwrap());
+                assert_eq!(tip.0, point);
+            }
+            x => panic!("unexpected response: {x:?}"),
+        }
+
+        // the block the client has is rolled back, which sends it back to origin
+        assert!(matches!(
+            client.chainsync().request_next().await.unwrap(),
+            NextResponse::Await
+        ));
+
+        store.0.lock().unwrap().remove(1);
+
+        match client.chainsync().recv_while_must_reply().await.unwrap() {
+            NextResponse::RollBackward(point, tip) => {
+                assert_eq!(point, Point::Origin);
+                assert_eq!(tip.0, first);
+            }
+            x => panic!("unexpected response: {x:?}"),
+        }
+
+        client.abort().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_blocks_and_queries_to_clients() {
+        use pallas_network::facades::NodeClient;
+
+        let node = mock_node();
+        let (client_bearer, server_bearer) = Bearer::pair();
+
+        tokio::spawn(async move {
+            let server = NodeServer::accept_bearer(server_bearer, MAINNET_MAGIC)
+                .await
+                .unwrap();
+
+            node.serve_node(server).await
+        });
+
+        let mut client = NodeClient::connect_bearer(client_bearer, MAINNET_MAGIC)
+            .await
+            .unwrap();
+
+        let (first_point, first_block) = first_block();
+
+        let statequery = client.statequery();
+        statequery.acquire(None).await.unwrap();
+
+        let point = queries_v16::get_chain_point(statequery).await.unwrap();
+        assert_eq!(point, tip_point());
+
+        let era = queries_v16::get_current_era(statequery).await.unwrap();
+        // the test chain ends in babbage
+        assert_eq!(era, 5);
+
+        let start = queries_v16::get_system_start(statequery).await.unwrap();
+        assert_eq!(start.year, 2017);
+
+        statequery.send_release().await.unwrap();
+
+        let (point, _) = client
+            .chainsync()
+            .find_intersect(vec![first_point.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!(point, Some(first_point.clone()));
+
+        match client.chainsync().request_next().await.unwrap() {
+            NextResponse::RollBackward(point, _) => assert_eq!(point, first_point),
+            x => panic!("unexpected response: {x:?}"),
+        }
+
+        // the block at the intersection isn't sent again
+        match client.chainsync().request_next().await.unwrap() {
+            NextResponse::RollForward(block, _) => {
+                assert_ne!(block.0, first_block);
+
+                let block = MultiEraBlock::decode(&block.0).unwrap();
+                assert!(block.slot() > first_point.slot_or_default());
+            }
+            x => panic!("unexpected response: {x:?}"),
+        }
+
+        client.abort().await;
+    }
+}
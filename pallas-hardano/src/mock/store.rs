@@ -0,0 +1,183 @@
+use std::path::{Path, PathBuf};
+
+use pallas_traverse::MultiEraBlock;
+
+use crate::storage::immutable::{self, Block, Point};
+
+use super::Error;
+
+pub type BlockIter = Box<dyn Iterator<Item = Result<Block, Error>> + Send>;
+
+/// Source of the chain served by the mock node
+pub trait ChainStore: Send + Sync + 'static {
+    /// CBOR of the latest block of the chain, if any
+    fn tip(&self) -> Result<Option<Block>, Error>;
+
+    /// Iterates the chain starting at the given point
+    ///
+    /// The block at a specific point is the first one yielded, while the
+    /// origin yields from the first block of the chain. Returns `None` if the
+    /// point isn't part of the chain.
+    fn read_from(&self, point: &Point) -> Result<Option<BlockIter>, Error>;
+}
+
+fn block_point(block: &[u8]) -> Result<Point, Error> {
+    let block = MultiEraBlock::decode(block).map_err(Error::InvalidBlock)?;
+
+    Ok(Point::Specific(block.slot(), block.hash().to_vec()))
+}
+
+/// Chain read from the ImmutableDB directory of a Haskell node
+///
+/// The immutable chain never changes, so the tip is read once when opening.
+pub struct ImmutableStore {
+    dir: PathBuf,
+    tip: Option<Block>,
+}
+
+impl ImmutableStore {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_owned();
+
+        let tip = match immutable::get_tip(&dir).map_err(Error::Immutable)? {
+            Some(point) => immutable::read_blocks_from_point(&dir, point)
+                .map_err(Error::Immutable)?
+                .next()
+                .transpose()
+                .map_err(Error::Chunk)?,
+            None => None,
+        };
+
+        Ok(Self { dir, tip })
+    }
+}
+
+impl ChainStore for ImmutableStore {
+    fn tip(&self) -> Result<Option<Block>, Error> {
+        Ok(self.tip.clone())
+    }
+
+    fn read_from(&self, point: &Point) -> Result<Option<BlockIter>, Error> {
+        match point {
+            // the db might not start at genesis, we serve whatever comes first
+            Point::Origin => {
+                let iter = immutable::read_blocks(&self.dir).map_err(Error::Immutable)?;
+                Ok(Some(Box::new(iter.map(|x| x.map_err(Error::Chunk)))))
+            }
+            Point::Specific(..) => {
+                let mut iter = match immutable::read_blocks_from_point(&self.dir, point.clone()) {
+                    Ok(x) => x.map(|x| x.map_err(Error::Chunk)).peekable(),
+                    Err(immutable::Error::CannotFindBlock(_)) => return Ok(None),
+                    Err(err) => return Err(Error::Immutable(err)),
+                };
+
+                // the search is fuzzy for points without hash, which doesn't
+                // fit the exact match expected by the mini-protocols
+                match iter.peek() {
+                    Some(Ok(block)) if block_point(block)? == *point => Ok(Some(Box::new(iter))),
+                    Some(Err(_)) => Ok(Some(Box::new(iter))),
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rolldb")]
+pub use rolldb::RollDbStore;
+
+#[cfg(feature = "rolldb")]
+mod rolldb {
+    use pallas_crypto::hash::Hash;
+    use pallas_rolldb::chain;
+
+    use super::{BlockIter, ChainStore, Error, Point};
+    use crate::storage::immutable::Block;
+
+    /// Chain read from the chain store of a `pallas-rolldb` database
+    pub struct RollDbStore(chain::Store);
+
+    impl RollDbStore {
+        pub fn new(store: chain::Store) -> Self {
+            Self(store)
+        }
+    }
+
+    fn read_block(store: &chain::Store, hash: Hash<32>) -> Result<Block, Error> {
+        store
+            .get_block(hash)
+            .map_err(Error::RollDb)?
+            .ok_or(Error::RollDb(pallas_rolldb::Error::NotFound))
+    }
+
+    /// Walks the slot index, the rocksdb iterator borrows the store so we
+    /// re-seek from the last slot on each step
+    struct Cursor {
+        store: chain::Store,
+        first: Option<Hash<32>>,
+        after: Option<u64>,
+    }
+
+    impl Cursor {
+        fn step(&mut self) -> Result<Option<Block>, Error> {
+            if let Some(hash) = self.first.take() {
+                return read_block(&self.store, hash).map(Some);
+            }
+
+            match self.store.crawl_after(self.after).next() {
+                Some(entry) => {
+                    let (slot, hash) = entry.map_err(Error::RollDb)?;
+                    self.after = Some(slot);
+                    read_block(&self.store, hash).map(Some)
+                }
+                None => Ok(None),
+            }
+        }
+    }
+
+    impl Iterator for Cursor {
+        type Item = Result<Block, Error>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.step().transpose()
+        }
+    }
+
+    impl ChainStore for RollDbStore {
+        fn tip(&self) -> Result<Option<Block>, Error> {
+            match self.0.find_tip().map_err(Error::RollDb)? {
+                Some((_, hash)) => read_block(&self.0, hash).map(Some),
+                None => Ok(None),
+            }
+        }
+
+        fn read_from(&self, point: &Point) -> Result<Option<BlockIter>, Error> {
+            let cursor = match point {
+                Point::Origin => Cursor {
+                    store: self.0.clone(),
+                    first: None,
+                    after: None,
+                },
+                Point::Specific(slot, hash) => {
+                    let Ok(hash) = <[u8; 32]>::try_from(hash.as_slice()) else {
+                        return Ok(None);
+                    };
+
+                    let hash = Hash::new(hash);
+
+                    if !self.0.chain_contains(*slot, &hash).map_err(Error::RollDb)? {
+                        return Ok(None);
+                    }
+
+                    Cursor {
+                        store: self.0.clone(),
+                        first: Some(hash),
+                        after: Some(*slot),
+                    }
+                }
+            };
+
+            Ok(Some(Box::new(cursor)))
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use std::{
     fs::File,
-    io::{BufReader, Read, Seek},
+    io::{BufReader, Read, Seek, SeekFrom},
     path::Path,
 };
 
-use immutable::secondary;
-use tracing::trace;
+use immutable::{primary, secondary};
+use pallas_codec::minicbor;
+use pallas_traverse::{MultiEraBlock, MultiEraHeader};
+use tracing::{trace, warn};
 
 use crate::storage::immutable;
 
@@ -13,10 +15,28 @@ pub type SecondaryIndex = super::secondary::Reader;
 pub type SecondaryEntry = super::secondary::Entry;
 
 pub struct Reader {
+    name: String,
     inner: BufReader<File>,
     index: SecondaryIndex,
     current: Option<Result<SecondaryEntry, secondary::Error>>,
     next: Option<Result<SecondaryEntry, secondary::Error>>,
+    validate: bool,
+}
+
+/// Kind of damage found in a block when validating a chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    /// The CRC32 of the block bytes doesn't match the secondary index
+    ChecksumMismatch,
+    /// The hash of the block header doesn't match the secondary index
+    HeaderHashMismatch,
+    /// The block bytes can't be decoded
+    Undecodable,
+    /// The chunk file ends before the end of the block
+    Truncated,
+    /// The secondary index points to an offset before the end of the
+    /// previous block
+    InvalidOffset,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -27,20 +47,163 @@ pub enum Error {
     CannotReadBlock(std::io::Error),
     #[error(transparent)]
     SecondaryIndexError(secondary::Error),
+    #[error("Cannot decode the era of the block, error: {0}")]
+    CannotDecodeEra(minicbor::decode::Error),
+    #[error("Cannot decode block header, error: {0}")]
+    CannotDecodeHeader(pallas_traverse::Error),
+    #[error("Corrupted block in chunk {chunk} at slot {slot}: {kind:?}")]
+    CorruptedBlock {
+        chunk: String,
+        /// Slot of the block, or its epoch in the case of an EBB
+        slot: u64,
+        kind: Corruption,
+    },
 }
 
 impl Reader {
-    fn open(mut index: SecondaryIndex, chunks: File) -> Self {
+    fn open(name: &str, mut index: SecondaryIndex, chunks: File) -> Self {
         let inner = BufReader::new(chunks);
 
         let current = index.next();
         let next = index.next();
 
         Self {
+            name: name.to_owned(),
             inner,
             index,
             current,
             next,
+            validate: false,
+        }
+    }
+
+    /// Checks each block against its secondary index entry before yielding
+    /// it
+    ///
+    /// The CRC32 of the block bytes and the hash of its header are compared
+    /// with the ones recorded by the node, and the last block of the chunk is
+    /// delimited by its CBOR encoding instead of the end of the file, so that
+    /// a partially written block is detected. The first corrupted block is
+    /// reported as [`Error::CorruptedBlock`] and ends the iteration.
+    pub fn validated(mut self) -> Self {
+        self.validate = true;
+        self
+    }
+
+    fn corrupted(&self, entry: &SecondaryEntry, kind: Corruption) -> Error {
+        Error::CorruptedBlock {
+            chunk: self.name.clone(),
+            slot: u64::from_be_bytes(entry.block_or_ebb),
+            kind,
+        }
+    }
+
+    fn check_block(&self, entry: &SecondaryEntry, block: &[u8]) -> Result<(), Error> {
+        if crc32fast::hash(block) != entry.checksum {
+            return Err(self.corrupted(entry, Corruption::ChecksumMismatch));
+        }
+
+        let decoded = MultiEraBlock::decode(block)
+            .map_err(|_| self.corrupted(entry, Corruption::Undecodable))?;
+
+        if *decoded.hash() != entry.header_hash {
+            return Err(self.corrupted(entry, Corruption::HeaderHashMismatch));
+        }
+
+        Ok(())
+    }
+
+    fn read_validated_block(
+        &mut self,
+        entry: &SecondaryEntry,
+        next_offset: Option<u64>,
+    ) -> Result<Vec<u8>, Error> {
+        let start = self
+            .inner
+            .stream_position()
+            .map_err(Error::CannotReadBlock)?;
+
+        let block = match next_offset {
+            Some(next_offset) => {
+                let delta = next_offset
+                    .checked_sub(start)
+                    .ok_or_else(|| self.corrupted(entry, Corruption::InvalidOffset))?;
+
+                let mut buf = vec![0u8; delta as usize];
+
+                match self.inner.read_exact(&mut buf) {
+                    Ok(_) => buf,
+                    Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                        return Err(self.corrupted(entry, Corruption::Truncated))
+                    }
+                    Err(err) => return Err(Error::CannotReadBlock(err)),
+                }
+            }
+            None => {
+                let mut buf = Self::read_last_block(&mut self.inner)?;
+
+                let mut decoder = minicbor::Decoder::new(&buf);
+
+                match decoder.skip() {
+                    Ok(_) => (),
+                    Err(err) if err.is_end_of_input() => {
+                        return Err(self.corrupted(entry, Corruption::Truncated))
+                    }
+                    Err(_) => return Err(self.corrupted(entry, Corruption::Undecodable)),
+                }
+
+                let len = decoder.position();
+
+                if len < buf.len() {
+                    warn!(
+                        chunk = self.name,
+                        trailing = buf.len() - len,
+                        "ignoring trailing bytes after last block"
+                    );
+                }
+
+                buf.truncate(len);
+                buf
+            }
+        };
+
+        self.check_block(entry, &block)?;
+
+        Ok(block)
+    }
+
+    fn next_validated(&mut self) -> Option<Result<Vec<u8>, Error>> {
+        let current = match self.current.take()? {
+            Ok(x) => x,
+            Err(err) => {
+                self.next = None;
+                return Some(Err(Error::SecondaryIndexError(err)));
+            }
+        };
+
+        let next_offset = match self.next.take() {
+            Some(Ok(next)) => {
+                let offset = next.block_offset;
+                self.next = Some(Ok(next));
+                Some(offset)
+            }
+            Some(Err(err)) => return Some(Err(Error::SecondaryIndexError(err))),
+            None => None,
+        };
+
+        match self.read_validated_block(&current, next_offset) {
+            Ok(block) => {
+                if next_offset.is_some() {
+                    self.current = self.next.take();
+                    self.next = self.index.next();
+                }
+
+                Some(Ok(block))
+            }
+            Err(err) => {
+                self.next = None;
+                Some(Err(err))
+            }
         }
     }
 
@@ -70,6 +233,10 @@ impl Iterator for Reader {
     type Item = Result<Vec<u8>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.validate {
+            return self.next_validated();
+        }
+
         match (self.current.take(), self.next.take()) {
             (None, _) => None,
             (_, Some(Err(next))) => {
@@ -98,18 +265,195 @@ impl Iterator for Reader {
     }
 }
 
+/// Header of a block, with the tags needed to decode it as a
+/// [`MultiEraHeader`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub tag: u8,
+    pub subtag: Option<u8>,
+    pub cbor: Vec<u8>,
+}
+
+impl Header {
+    pub fn decode(&self) -> Result<MultiEraHeader<'_>, pallas_traverse::Error> {
+        MultiEraHeader::decode(self.tag, self.subtag, &self.cbor)
+    }
+}
+
+/// Reads the headers of a chunk, using the position of each header within
+/// its block as recorded in the secondary index
+///
+/// Only the first bytes of each block, where its era is, and the header
+/// bytes are read from the chunk file.
+pub struct HeaderReader {
+    inner: BufReader<File>,
+    index: SecondaryIndex,
+}
+
+fn read_header(file: &mut BufReader<File>, entry: &SecondaryEntry) -> Result<Header, Error> {
+    file.seek(SeekFrom::Start(entry.block_offset))
+        .map_err(Error::CannotReadBlock)?;
+
+    // blocks are wrapped as [era, block], with the era fitting in a byte
+    let mut prefix = [0u8; 2];
+    file.read_exact(&mut prefix)
+        .map_err(Error::CannotReadBlock)?;
+
+    let mut decoder = minicbor::Decoder::new(&prefix);
+    decoder.array().map_err(Error::CannotDecodeEra)?;
+    let era = decoder.u8().map_err(Error::CannotDecodeEra)?;
+
+    // headers are tagged with the index of the era, byron ones with an
+    // extra subtag telling EBBs apart
+    let (tag, subtag) = match era {
+        0 | 1 => (0, Some(era)),
+        x => (x - 1, None),
+    };
+
+    file.seek(SeekFrom::Start(
+        entry.block_offset + entry.header_offset as u64,
+    ))
+    .map_err(Error::CannotReadBlock)?;
+
+    let mut cbor = vec![0u8; entry.header_size as usize];
+    file.read_exact(&mut cbor).map_err(Error::CannotReadBlock)?;
+
+    Ok(Header { tag, subtag, cbor })
+}
+
+impl Iterator for HeaderReader {
+    type Item = Result<Header, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.index.next()? {
+            Ok(entry) => Some(read_header(&mut self.inner, &entry)),
+            Err(err) => Some(Err(Error::SecondaryIndexError(err))),
+        }
+    }
+}
+
+pub fn read_headers(dir: &Path, name: &str) -> Result<HeaderReader, Error> {
+    let index = secondary::read_entries(dir, name).map_err(Error::SecondaryIndexError)?;
+
+    let chunk = dir.join(name).with_extension("chunk");
+    let chunk = std::fs::File::open(chunk).map_err(Error::CannotOpenChunkFile)?;
+
+    Ok(HeaderReader {
+        inner: BufReader::new(chunk),
+        index,
+    })
+}
+
+/// Slots of the first and last blocks of a chunk, `None` if it has no blocks
+///
+/// Only the indexes and the headers of those two blocks are read.
+pub fn read_slot_range(dir: &Path, name: &str) -> Result<Option<(u64, u64)>, Error> {
+    let mut entries = secondary::read_entries(dir, name).map_err(Error::SecondaryIndexError)?;
+
+    let Some(first) = entries.next() else {
+        return Ok(None);
+    };
+
+    let first = first.map_err(Error::SecondaryIndexError)?;
+
+    let last = entries
+        .try_fold(None, |_, x| x.map(Some))
+        .map_err(Error::SecondaryIndexError)?;
+
+    let chunk = dir.join(name).with_extension("chunk");
+    let chunk = std::fs::File::open(chunk).map_err(Error::CannotOpenChunkFile)?;
+    let mut chunk = BufReader::new(chunk);
+
+    let mut slot = |entry: &SecondaryEntry| {
+        let header = read_header(&mut chunk, entry)?;
+        let header = header.decode().map_err(Error::CannotDecodeHeader)?;
+        Ok::<_, Error>(header.slot())
+    };
+
+    let first_slot = slot(&first)?;
+    let last_slot = match last {
+        Some(last) => slot(&last)?,
+        None => first_slot,
+    };
+
+    Ok(Some((first_slot, last_slot)))
+}
+
+/// Reads the block at a relative slot of a chunk by looking up its entry in
+/// the indexes, without going through the blocks that come before it
+///
+/// Returns `None` if the chunk doesn't exist or there's no block at the slot.
+pub fn read_block_at(
+    dir: &Path,
+    name: &str,
+    slot: primary::RelativeSlot,
+) -> Result<Option<(SecondaryEntry, Vec<u8>)>, Error> {
+    let index_error = |x| Error::SecondaryIndexError(secondary::Error::PrimaryIndexError(x));
+
+    let mut primary = match File::open(dir.join(name).with_extension("primary")) {
+        Ok(x) => x,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(Error::SecondaryIndexError(
+                secondary::Error::CannotOpenFile(err),
+            ))
+        }
+    };
+
+    let offset = match primary::read_slot(&mut primary, slot).map_err(index_error)? {
+        Some(primary::Entry::Occupied(_, offset)) => offset,
+        _ => return Ok(None),
+    };
+
+    let mut secondary = File::open(dir.join(name).with_extension("secondary"))
+        .map_err(|x| Error::SecondaryIndexError(secondary::Error::CannotOpenFile(x)))?;
+
+    let (entry, next_offset) =
+        secondary::read_entry_at(&mut secondary, offset).map_err(Error::SecondaryIndexError)?;
+
+    let mut chunk =
+        File::open(dir.join(name).with_extension("chunk")).map_err(Error::CannotOpenChunkFile)?;
+
+    chunk
+        .seek(SeekFrom::Start(entry.block_offset))
+        .map_err(Error::CannotReadBlock)?;
+
+    let block = match next_offset {
+        Some(next_offset) => {
+            trace!(slot, next_offset, "reading block by slot");
+
+            let mut buf = vec![0u8; next_offset.saturating_sub(entry.block_offset) as usize];
+            chunk.read_exact(&mut buf).map_err(Error::CannotReadBlock)?;
+            buf
+        }
+        None => {
+            trace!(slot, "reading last block by slot");
+
+            let mut buf = vec![];
+            chunk
+                .read_to_end(&mut buf)
+                .map_err(Error::CannotReadBlock)?;
+            buf
+        }
+    };
+
+    Ok(Some((entry, block)))
+}
+
 pub fn read_blocks(dir: &Path, name: &str) -> Result<Reader, Error> {
     let secondary = secondary::read_entries(dir, name).map_err(Error::SecondaryIndexError)?;
 
     let chunk = dir.join(name).with_extension("chunk");
     let chunk = std::fs::File::open(chunk).map_err(Error::CannotOpenChunkFile)?;
-    Ok(Reader::open(secondary, chunk))
+    Ok(Reader::open(name, secondary, chunk))
 }
 
 #[cfg(test)]
 mod tests {
     use std::path::Path;
 
+    use super::{Corruption, Error};
+
     #[test]
     fn it_can_decode_all_blocks() {
         let chunk = super::read_blocks(Path::new("../test_data"), "01285").unwrap();
@@ -119,4 +463,54 @@ mod tests {
             pallas_traverse::MultiEraBlock::decode(&block).unwrap();
         }
     }
+
+    #[test]
+    fn validated_reads_match_plain_reads() {
+        let dir = Path::new("../test_data");
+
+        let plain: Vec<_> = super::read_blocks(dir, "01836")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let validated: Vec<_> = super::read_blocks(dir, "01836")
+            .unwrap()
+            .validated()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(plain, validated);
+    }
+
+    #[test]
+    fn validation_detects_truncated_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for extension in ["primary", "secondary"] {
+            let file = Path::new("01836").with_extension(extension);
+            std::fs::copy(
+                Path::new("../test_data").join(&file),
+                dir.path().join(&file),
+            )
+            .unwrap();
+        }
+
+        let chunk = std::fs::read("../test_data/01836.chunk").unwrap();
+        std::fs::write(dir.path().join("01836.chunk"), &chunk[..chunk.len() - 100]).unwrap();
+
+        let mut blocks: Vec<_> = super::read_blocks(dir.path(), "01836")
+            .unwrap()
+            .validated()
+            .collect();
+
+        match blocks.pop().unwrap() {
+            Err(Error::CorruptedBlock { chunk, kind, .. }) => {
+                assert_eq!(chunk, "01836");
+                assert_eq!(kind, Corruption::Truncated);
+            }
+            x => panic!("unexpected result {x:?}"),
+        }
+
+        assert!(blocks.into_iter().all(|x| x.is_ok()));
+    }
 }
@@ -9,8 +9,11 @@ use tap::Tap;
 use tracing::debug;
 
 pub mod chunk;
+pub mod parallel;
 pub mod primary;
+pub mod recovery;
 pub mod secondary;
+pub mod writer;
 
 // TODO: we should make Point accessible in some crate more generic that
 // `network`.
@@ -28,6 +31,8 @@ pub enum Error {
     CannotDecodeBlock(pallas_traverse::Error),
     #[error(transparent)]
     ChunkReadError(chunk::Error),
+    #[error("Worker thread stopped before processing its chunk")]
+    WorkerFailed,
 }
 
 /// Performs a binary search of the given sorted chunks in descending order
@@ -121,9 +126,9 @@ fn iterate_till_point(
     }
 }
 
-fn build_stack_of_chunk_names(dir: &Path) -> Result<ChunkNameSack, Error> {
-    let mut chunks = std::fs::read_dir(dir)
-        .map_err(Error::CannotReadDir)?
+/// Names of all the chunks in the directory, in ascending order
+pub(crate) fn list_chunk_names(dir: &Path) -> Result<ChunkNameSack, std::io::Error> {
+    let mut chunks = std::fs::read_dir(dir)?
         .map_while(|e| e.ok())
         .filter(|e| {
             e.path()
@@ -136,6 +141,13 @@ fn build_stack_of_chunk_names(dir: &Path) -> Result<ChunkNameSack, Error> {
         .collect::<Vec<_>>();
 
     chunks.sort();
+
+    Ok(chunks)
+}
+
+fn build_stack_of_chunk_names(dir: &Path) -> Result<ChunkNameSack, Error> {
+    let mut chunks = list_chunk_names(dir).map_err(Error::CannotReadDir)?;
+
     // According to this docs https://mithril.network/doc/glossary/#immutable-file-number,
     // the last chunk files are not really immutable.
     // So to preserve only immutable data the last chunk files are omitted.
@@ -175,6 +187,127 @@ pub fn read_blocks(dir: &Path) -> Result<impl Iterator<Item = FallibleBlock>, Er
     Ok(iter)
 }
 
+pub type FallibleHeader = Result<chunk::Header, chunk::Error>;
+
+/// Returns an iterator over the headers of the blocks, in chain order.
+///
+/// Only the bytes of each header are read from the chunk files, using the
+/// positions recorded in the secondary index. As in [`read_blocks`], the last
+/// chunk is omitted.
+pub fn read_headers(dir: &Path) -> Result<impl Iterator<Item = FallibleHeader>, Error> {
+    let mut names = build_stack_of_chunk_names(dir)?;
+    let dir = dir.to_owned();
+
+    let iter = std::iter::from_fn(move || {
+        names
+            .pop()
+            .tap(|name| debug!(name, "switched to new chunk"))
+            .map(|name| chunk::read_headers(&dir, &name))
+    })
+    .map_while(Result::ok)
+    .flatten();
+
+    Ok(iter)
+}
+
+/// Chunk name and relative slot where the block of a slot is stored, the
+/// relative slot 0 of each chunk being reserved for the EBB
+fn locate_slot(chunk_size: u64, slot: u64) -> (ChunkName, primary::RelativeSlot) {
+    let name = format!("{:05}", slot / chunk_size);
+    let relative_slot = slot % chunk_size + 1;
+
+    (name, relative_slot as primary::RelativeSlot)
+}
+
+/// Retrieves the block at the given slot, without iterating the chain.
+///
+/// The chunk and the position of the block are computed from the slot, so
+/// the lookup only reads one entry of each index and the block itself. The
+/// `chunk_size` has to match the one used by the node that wrote the db,
+/// which is [`writer::DEFAULT_CHUNK_SIZE`] for mainnet. Unlike the iterators,
+/// the last chunk is also considered.
+///
+/// Returns `None` if there's no block at the slot. EBBs are only reachable
+/// through [`get_block_by_point`], since they share their slot with the first
+/// block of the epoch.
+///
+/// # Errors
+///
+/// * `Error::ChunkReadError` - If the index or chunk files cannot be read.
+pub fn get_block_by_slot(dir: &Path, chunk_size: u64, slot: u64) -> Result<Option<Block>, Error> {
+    let (name, relative_slot) = locate_slot(chunk_size, slot);
+
+    let block = chunk::read_block_at(dir, &name, relative_slot)
+        .map_err(Error::ChunkReadError)?
+        .map(|(_, block)| block);
+
+    Ok(block)
+}
+
+/// Retrieves the block at the given point, without iterating the chain.
+///
+/// The block is looked up as in [`get_block_by_slot`], and its hash is checked
+/// against the header hash recorded in the secondary index. Returns `None` for
+/// the Origin, or if there's no block matching the point.
+///
+/// # Errors
+///
+/// * `Error::ChunkReadError` - If the index or chunk files cannot be read.
+pub fn get_block_by_point(
+    dir: &Path,
+    chunk_size: u64,
+    point: &Point,
+) -> Result<Option<Block>, Error> {
+    let Point::Specific(slot, hash) = point else {
+        return Ok(None);
+    };
+
+    let (name, relative_slot) = locate_slot(chunk_size, *slot);
+
+    // an EBB has the slot of the first block of its epoch
+    let candidates = match slot % chunk_size {
+        0 => vec![relative_slot, 0],
+        _ => vec![relative_slot],
+    };
+
+    for relative_slot in candidates {
+        let found =
+            chunk::read_block_at(dir, &name, relative_slot).map_err(Error::ChunkReadError)?;
+
+        if let Some((entry, block)) = found {
+            if entry.header_hash[..] == hash[..] {
+                return Ok(Some(block));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Same as [`read_blocks`], but each block is validated against the checksum
+/// and header hash recorded in the secondary index.
+///
+/// The iteration stops at the first error, which in the case of a damaged
+/// block is a [`chunk::Error::CorruptedBlock`] with the chunk and slot where
+/// the corruption was found. See [`recovery::recover`] to truncate the db to
+/// its last valid block.
+pub fn read_validated_blocks(dir: &Path) -> Result<impl Iterator<Item = FallibleBlock>, Error> {
+    let names = build_stack_of_chunk_names(dir)?;
+
+    let mut failed = false;
+
+    let iter = ChunkReaders(dir.to_owned(), names)
+        .flat_map(|chunk| -> Box<dyn Iterator<Item = FallibleBlock>> {
+            match chunk {
+                Ok(reader) => Box::new(reader.validated()),
+                Err(err) => Box::new(std::iter::once(Err(err))),
+            }
+        })
+        .take_while(move |block| !std::mem::replace(&mut failed, block.is_err()));
+
+    Ok(iter)
+}
+
 /// Returns an iterator over the chain from the given point if the specific
 /// block is found, otherwise returns an error.
 ///
@@ -235,7 +368,26 @@ pub fn read_blocks_from_point(
     point: Point,
 ) -> Result<Box<dyn Iterator<Item = FallibleBlock> + Send + Sync>, Error> {
     let names = build_stack_of_chunk_names(dir)?;
+    read_stack_from_point(dir, names, point)
+}
+
+/// Same as [`read_blocks_from_point`], but including the last chunk, which
+/// the node may still be appending to.
+pub(crate) fn read_all_blocks_from_point(
+    dir: &Path,
+    point: Point,
+) -> Result<Box<dyn Iterator<Item = FallibleBlock> + Send + Sync>, Error> {
+    let mut names = list_chunk_names(dir).map_err(Error::CannotReadDir)?;
+    names.reverse();
 
+    read_stack_from_point(dir, names, point)
+}
+
+fn read_stack_from_point(
+    dir: &Path,
+    names: ChunkNameSack,
+    point: Point,
+) -> Result<Box<dyn Iterator<Item = FallibleBlock> + Send + Sync>, Error> {
     match point {
         // Establish iterator from the beginning of the chain
         Point::Origin => {
@@ -358,6 +510,26 @@ pub fn get_tip(dir: &Path) -> Result<Option<Point>, Error> {
     }
 }
 
+/// Returns the latest block of the db, including the last chunk, which the
+/// node may still be appending to.
+pub(crate) fn get_last_block(dir: &Path) -> Result<Option<Block>, Error> {
+    let names = list_chunk_names(dir).map_err(Error::CannotReadDir)?;
+
+    for name in names.iter().rev() {
+        let last = chunk::read_blocks(dir, name)
+            .map_err(Error::ChunkReadError)?
+            .last()
+            .transpose()
+            .map_err(Error::ChunkReadError)?;
+
+        if last.is_some() {
+            return Ok(last);
+        }
+    }
+
+    Ok(None)
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
@@ -487,6 +659,73 @@ mod tests {
         assert_eq!(tip, None);
     }
 
+    #[test]
+    fn read_headers_test() {
+        use super::{read_blocks, read_headers};
+
+        let dir = Path::new("../test_data");
+
+        let headers: Vec<_> = read_headers(dir).unwrap().map(Result::unwrap).collect();
+        let blocks: Vec<_> = read_blocks(dir).unwrap().map(Result::unwrap).collect();
+
+        assert_eq!(headers.len(), blocks.len());
+
+        for (header, block) in headers.iter().zip(blocks.iter()) {
+            let header = header.decode().unwrap();
+            let block = MultiEraBlock::decode(block).unwrap();
+
+            assert_eq!(header.hash(), block.hash());
+            assert_eq!(header.slot(), block.slot());
+            assert_eq!(header.cbor(), block.header().cbor());
+        }
+    }
+
+    #[test]
+    fn get_block_by_slot_and_point_test() {
+        use super::{chunk, get_block_by_point, get_block_by_slot, writer::DEFAULT_CHUNK_SIZE};
+
+        let dir = Path::new("../test_data");
+        let mut slots = vec![];
+
+        // the last chunk is included in random access reads
+        for block in chunk::read_blocks(dir, "01836").unwrap() {
+            let block = block.unwrap();
+            let decoded = MultiEraBlock::decode(&block).unwrap();
+            let point = Point::Specific(decoded.slot(), decoded.hash().to_vec());
+
+            let found = get_block_by_slot(dir, DEFAULT_CHUNK_SIZE, decoded.slot()).unwrap();
+            assert_eq!(found.as_ref(), Some(&block));
+
+            let found = get_block_by_point(dir, DEFAULT_CHUNK_SIZE, &point).unwrap();
+            assert_eq!(found.as_ref(), Some(&block));
+
+            let point = Point::Specific(decoded.slot(), vec![0; 32]);
+            let found = get_block_by_point(dir, DEFAULT_CHUNK_SIZE, &point).unwrap();
+            assert_eq!(found, None);
+
+            slots.push(decoded.slot());
+        }
+
+        // a slot without block
+        let empty = (slots[0]..).find(|x| !slots.contains(x)).unwrap();
+        assert_eq!(
+            get_block_by_slot(dir, DEFAULT_CHUNK_SIZE, empty).unwrap(),
+            None
+        );
+
+        // a slot of a chunk that is not in the db
+        let missing = 1000 * DEFAULT_CHUNK_SIZE + 5;
+        assert_eq!(
+            get_block_by_slot(dir, DEFAULT_CHUNK_SIZE, missing).unwrap(),
+            None
+        );
+
+        assert_eq!(
+            get_block_by_point(dir, DEFAULT_CHUNK_SIZE, &Point::Origin).unwrap(),
+            None
+        );
+    }
+
     #[test]
     fn read_blocks_from_point_test() {
         use super::read_blocks_from_point;
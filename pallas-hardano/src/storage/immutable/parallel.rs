@@ -0,0 +1,321 @@
+//! Helpers to process the chunks of an ImmutableDB in parallel
+//!
+//! Chunks are independent from each other, so each of them can be read by a
+//! different thread. [`list_chunks`] hands out the chunks with their slot
+//! ranges for callers that bring their own thread pool, while
+//! [`par_map_blocks`] runs a pool of workers that decode the blocks and
+//! yields the results in chain order.
+
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+};
+
+use pallas_traverse::MultiEraBlock;
+use tracing::debug;
+
+use crate::storage::immutable::{build_stack_of_chunk_names, chunk, ChunkName, Error};
+
+/// A chunk of the db, along with the range of slots of its blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    pub dir: PathBuf,
+    pub name: ChunkName,
+
+    /// Slots of the first and last blocks, `None` if the chunk has no blocks
+    pub slots: Option<(u64, u64)>,
+}
+
+impl ChunkInfo {
+    pub fn read_blocks(&self) -> Result<chunk::Reader, chunk::Error> {
+        chunk::read_blocks(&self.dir, &self.name)
+    }
+
+    pub fn read_headers(&self) -> Result<chunk::HeaderReader, chunk::Error> {
+        chunk::read_headers(&self.dir, &self.name)
+    }
+}
+
+/// Lists the chunks of the db in chain order, each of which can be read on
+/// its own thread
+///
+/// The same chunks as in [`super::read_blocks`] are listed, the last chunk
+/// being omitted. Only the indexes and two headers of each chunk are read to
+/// find its slot range.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use std::thread;
+/// use pallas_hardano::storage::immutable::parallel::list_chunks;
+///
+/// let chunks = list_chunks(Path::new("/path/to/immutable")).unwrap();
+///
+/// let counts: Vec<usize> = thread::scope(|s| {
+///     let handles: Vec<_> = chunks
+///         .iter()
+///         .map(|chunk| s.spawn(move || chunk.read_blocks().unwrap().count()))
+///         .collect();
+///
+///     handles.into_iter().map(|x| x.join().unwrap()).collect()
+/// });
+/// ```
+pub fn list_chunks(dir: &Path) -> Result<Vec<ChunkInfo>, Error> {
+    let mut names = build_stack_of_chunk_names(dir)?;
+    names.reverse();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let slots = chunk::read_slot_range(dir, &name).map_err(Error::ChunkReadError)?;
+
+            Ok(ChunkInfo {
+                dir: dir.to_owned(),
+                name,
+                slots,
+            })
+        })
+        .collect()
+}
+
+type ChunkOutput<T> = Vec<Result<T, Error>>;
+
+type Job<T> = (ChunkName, mpsc::SyncSender<ChunkOutput<T>>);
+
+/// Reads, decodes and maps the blocks of a chunk, stopping at the first error
+fn process_chunk<T, F>(dir: &Path, name: &str, map: &F) -> ChunkOutput<T>
+where
+    F: Fn(MultiEraBlock) -> T,
+{
+    debug!(name, "processing chunk");
+
+    let reader = match chunk::read_blocks(dir, name) {
+        Ok(x) => x,
+        Err(err) => return vec![Err(Error::ChunkReadError(err))],
+    };
+
+    let mut output = vec![];
+
+    for block in reader {
+        let item = block.map_err(Error::ChunkReadError).and_then(|block| {
+            MultiEraBlock::decode(&block)
+                .map(map)
+                .map_err(Error::CannotDecodeBlock)
+        });
+
+        let failed = item.is_err();
+        output.push(item);
+
+        if failed {
+            break;
+        }
+    }
+
+    output
+}
+
+/// Results of [`par_map_blocks`], in chain order
+///
+/// Chunks are handed to the workers a few at a time ahead of the one being
+/// consumed, which bounds the amount of results held in memory. The
+/// iteration stops after the first error.
+pub struct ParallelBlocks<T> {
+    jobs: Option<mpsc::Sender<Job<T>>>,
+    names: std::vec::IntoIter<ChunkName>,
+    pending: VecDeque<mpsc::Receiver<ChunkOutput<T>>>,
+    current: std::vec::IntoIter<Result<T, Error>>,
+    window: usize,
+    failed: bool,
+}
+
+impl<T> ParallelBlocks<T> {
+    fn schedule(&mut self) {
+        let Some(jobs) = &self.jobs else {
+            return;
+        };
+
+        while self.pending.len() < self.window {
+            let Some(name) = self.names.next() else {
+                break;
+            };
+
+            let (reply, receiver) = mpsc::sync_channel(1);
+
+            if jobs.send((name, reply)).is_err() {
+                break;
+            }
+
+            self.pending.push_back(receiver);
+        }
+    }
+}
+
+impl<T> Iterator for ParallelBlocks<T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.failed {
+                return None;
+            }
+
+            if let Some(item) = self.current.next() {
+                self.failed = item.is_err();
+                return Some(item);
+            }
+
+            self.schedule();
+
+            let receiver = self.pending.pop_front()?;
+
+            match receiver.recv() {
+                Ok(output) => self.current = output.into_iter(),
+                Err(_) => {
+                    self.failed = true;
+                    return Some(Err(Error::WorkerFailed));
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for ParallelBlocks<T> {
+    fn drop(&mut self) {
+        // workers stop once the scheduled chunks are done
+        self.jobs.take();
+    }
+}
+
+/// Decodes the blocks of the db on a pool of worker threads, yielding the
+/// output of `map` for each block in chain order
+///
+/// Each worker processes a whole chunk at a time, so `map` should turn the
+/// decoded block into whatever owned data the caller needs. As in
+/// [`super::read_blocks`], the last chunk is omitted.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use pallas_hardano::storage::immutable::parallel::par_map_blocks;
+///
+/// let txs: usize = par_map_blocks(Path::new("/path/to/immutable"), 4, |block| block.tx_count())
+///     .unwrap()
+///     .map(Result::unwrap)
+///     .sum();
+/// ```
+pub fn par_map_blocks<T, F>(dir: &Path, workers: usize, map: F) -> Result<ParallelBlocks<T>, Error>
+where
+    T: Send + 'static,
+    F: Fn(MultiEraBlock) -> T + Send + Sync + 'static,
+{
+    let mut names = build_stack_of_chunk_names(dir)?;
+    names.reverse();
+
+    let workers = workers.max(1);
+    let map = Arc::new(map);
+
+    let (jobs, queue) = mpsc::channel::<Job<T>>();
+    let queue = Arc::new(Mutex::new(queue));
+
+    for _ in 0..workers {
+        let dir = dir.to_owned();
+        let map = map.clone();
+        let queue = queue.clone();
+
+        std::thread::spawn(move || loop {
+            let job = queue.lock().expect("poisoned job queue").recv();
+
+            let Ok((name, reply)) = job else {
+                break;
+            };
+
+            // the receiver is gone if the iterator was dropped
+            let _ = reply.send(process_chunk(&dir, &name, map.as_ref()));
+        });
+    }
+
+    Ok(ParallelBlocks {
+        jobs: Some(jobs),
+        names: names.into_iter(),
+        pending: VecDeque::new(),
+        current: vec![].into_iter(),
+        window: workers * 2,
+        failed: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pallas_traverse::MultiEraBlock;
+
+    use super::{list_chunks, par_map_blocks};
+    use crate::storage::immutable::read_blocks;
+
+    #[test]
+    fn lists_chunks_with_slot_ranges() {
+        let chunks = list_chunks(Path::new("../test_data")).unwrap();
+
+        let names: Vec<_> = chunks.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, vec!["01285", "01836"]);
+
+        for chunk in chunks.iter() {
+            let slots: Vec<_> = chunk
+                .read_blocks()
+                .unwrap()
+                .map(|x| MultiEraBlock::decode(&x.unwrap()).unwrap().slot())
+                .collect();
+
+            let range = (*slots.first().unwrap(), *slots.last().unwrap());
+            assert_eq!(chunk.slots, Some(range));
+        }
+
+        // chunks can be read on independent threads
+        let counts: Vec<_> = std::thread::scope(|s| {
+            let handles: Vec<_> = chunks
+                .iter()
+                .map(|chunk| s.spawn(move || chunk.read_headers().unwrap().count()))
+                .collect();
+
+            handles.into_iter().map(|x| x.join().unwrap()).collect()
+        });
+
+        let total: usize = counts.iter().sum();
+        assert_eq!(
+            total,
+            read_blocks(Path::new("../test_data")).unwrap().count()
+        );
+    }
+
+    #[test]
+    fn yields_blocks_in_chain_order() {
+        let dir = Path::new("../test_data");
+
+        let expected: Vec<_> = read_blocks(dir)
+            .unwrap()
+            .map(|x| MultiEraBlock::decode(&x.unwrap()).unwrap().slot())
+            .collect();
+
+        for workers in [1, 3] {
+            let slots: Vec<_> = par_map_blocks(dir, workers, |x| x.slot())
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+
+            assert_eq!(slots, expected);
+        }
+
+        // dropping the iterator early doesn't block
+        let first = par_map_blocks(dir, 2, |x| x.slot())
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(first, expected[0]);
+    }
+}
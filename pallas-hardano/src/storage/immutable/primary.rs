@@ -1,6 +1,6 @@
 use std::{
     fs::File,
-    io::{BufReader, Read},
+    io::{BufReader, Read, Seek, SeekFrom},
 };
 
 // See https://input-output-hk.github.io/ouroboros-consensus/pdfs/report.pdf, section 8.2.2
@@ -11,6 +11,18 @@ binary_layout::define_layout!(layout, BigEndian, {
 pub type RelativeSlot = u32;
 pub type SecondaryOffset = u32;
 
+/// Version of the primary index files written by the node
+pub const CURRENT_VERSION: u8 = 1;
+
+pub(crate) fn encode_offset(offset: SecondaryOffset) -> Vec<u8> {
+    let mut buf = vec![0u8; layout::SIZE.unwrap()];
+    layout::View::new(&mut buf[..])
+        .secondary_offset_mut()
+        .write(offset);
+
+    buf
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Version missing, cannot read version from primary index file, error: {0}")]
@@ -139,6 +151,34 @@ impl Iterator for Reader {
     }
 }
 
+/// Reads the entry of a single relative slot, seeking directly to its
+/// position in the primary index file
+///
+/// Returns `None` if the slot is past the end of the index.
+pub fn read_slot(file: &mut File, slot: RelativeSlot) -> Result<Option<Entry>, Error> {
+    let size = layout::SIZE.unwrap();
+
+    file.seek(SeekFrom::Start(1 + (size * slot as usize) as u64))
+        .map_err(Error::CannotReadPrimaryIndex)?;
+
+    // the offset of the slot and the one of the next slot
+    let mut buf = vec![0u8; size * 2];
+
+    match file.read_exact(&mut buf) {
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(Error::CannotReadPrimaryIndex(err)),
+        Ok(_) => {
+            let offset = layout::View::new(&buf[..size]).secondary_offset().read();
+            let next = layout::View::new(&buf[size..]).secondary_offset().read();
+
+            match next > offset {
+                true => Ok(Some(Entry::Occupied(slot, offset))),
+                false => Ok(Some(Entry::Empty(slot))),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,310 @@
+use std::{
+    fs::{File, OpenOptions},
+    path::Path,
+};
+
+use pallas_traverse::MultiEraBlock;
+use tracing::{debug, warn};
+
+use crate::storage::immutable::{chunk, list_chunk_names, primary, secondary, Block, Point};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Cannot read directory, error: {0}")]
+    CannotReadDir(std::io::Error),
+    #[error("Cannot read chunk, error: {0}")]
+    CannotReadChunk(chunk::Error),
+    #[error("Cannot truncate chunk files, error: {0}")]
+    CannotTruncate(std::io::Error),
+    #[error("Cannot decode block, error: {0}")]
+    CannotDecodeBlock(pallas_traverse::Error),
+}
+
+/// Outcome of a [`recover`] run
+#[derive(Debug)]
+pub struct Recovery {
+    /// Point of the last valid block, `None` if the db has no valid blocks
+    pub tip: Option<Point>,
+
+    /// Problem that caused the db to be truncated, `None` if it was intact
+    pub corruption: Option<chunk::Error>,
+
+    /// Chunks that were deleted because they came after the corruption
+    pub removed_chunks: Vec<String>,
+}
+
+/// Errors that are caused by damaged files instead of by the environment
+fn is_corruption(err: &chunk::Error) -> bool {
+    matches!(
+        err,
+        chunk::Error::CorruptedBlock { .. }
+            | chunk::Error::SecondaryIndexError(secondary::Error::InconsistentState)
+            | chunk::Error::SecondaryIndexError(secondary::Error::PrimaryIndexError(
+                primary::Error::VersionMissing(_)
+            ))
+    )
+}
+
+/// Relative slots of the blocks of a chunk, as recorded in its primary index
+fn occupied_slots(dir: &Path, name: &str) -> Vec<primary::RelativeSlot> {
+    let reader = File::open(dir.join(name).with_extension("primary"))
+        .ok()
+        .and_then(|x| primary::Reader::open(x).ok());
+
+    let Some(mut reader) = reader else {
+        return vec![];
+    };
+
+    std::iter::from_fn(|| reader.next_occupied())
+        .map_while(Result::ok)
+        .filter_map(|x| match x {
+            primary::Entry::Occupied(slot, _) => Some(slot),
+            primary::Entry::Empty(_) => None,
+        })
+        .collect()
+}
+
+fn shrink_file(path: &Path, len: u64) -> Result<(), Error> {
+    let file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(Error::CannotTruncate)?;
+
+    let current = file.metadata().map_err(Error::CannotTruncate)?.len();
+
+    if current > len {
+        debug!(?path, current, len, "truncating file");
+        file.set_len(len).map_err(Error::CannotTruncate)?;
+    }
+
+    Ok(())
+}
+
+/// Truncates the files of a chunk so that they only hold its first blocks
+fn truncate_chunk(dir: &Path, name: &str, blocks: usize, chunk_len: u64) -> Result<(), Error> {
+    let path = |extension| dir.join(name).with_extension(extension);
+
+    shrink_file(&path("chunk"), chunk_len)?;
+
+    let secondary_len = blocks * secondary::Entry::size();
+    shrink_file(&path("secondary"), secondary_len as u64)?;
+
+    // the primary index holds the offset of each slot plus the initial one,
+    // it's only cut when it points to blocks that are not kept
+    let occupied = occupied_slots(dir, name);
+
+    match blocks.checked_sub(1) {
+        _ if occupied.len() == blocks => (),
+        Some(last) => {
+            let slot = occupied[last] as u64;
+            shrink_file(&path("primary"), 1 + 4 * (slot + 2))?;
+        }
+        None => {
+            let mut primary = vec![primary::CURRENT_VERSION];
+            primary.extend(primary::encode_offset(0));
+            std::fs::write(path("primary"), primary).map_err(Error::CannotTruncate)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_chunk(dir: &Path, name: &str) -> Result<(), Error> {
+    debug!(name, "removing chunk");
+
+    for extension in ["chunk", "primary", "secondary"] {
+        match std::fs::remove_file(dir.join(name).with_extension(extension)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(Error::CannotTruncate(err))
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn block_point(block: &Block) -> Result<Point, Error> {
+    let block = MultiEraBlock::decode(block).map_err(Error::CannotDecodeBlock)?;
+    Ok(Point::Specific(block.slot(), block.hash().to_vec()))
+}
+
+/// Truncates the db to its last valid block, as the node does on startup
+///
+/// Every chunk, including the last one, is read in validation mode. When a
+/// corrupted block is found, the chunk is truncated right before it and all
+/// the chunks that come after it are deleted. Bytes that the indexes don't
+/// account for at the end of the last chunk are dropped as well.
+pub fn recover(dir: &Path) -> Result<Recovery, Error> {
+    let names = list_chunk_names(dir).map_err(Error::CannotReadDir)?;
+
+    let mut tip: Option<Block> = None;
+
+    for (index, name) in names.iter().enumerate() {
+        let mut blocks = 0;
+        let mut chunk_len = 0;
+        let mut corruption = None;
+
+        match chunk::read_blocks(dir, name) {
+            Ok(reader) => {
+                for block in reader.validated() {
+                    match block {
+                        Ok(block) => {
+                            blocks += 1;
+                            chunk_len += block.len() as u64;
+                            tip = Some(block);
+                        }
+                        Err(err) if is_corruption(&err) => {
+                            corruption = Some(err);
+                            break;
+                        }
+                        Err(err) => return Err(Error::CannotReadChunk(err)),
+                    }
+                }
+            }
+            Err(err) if is_corruption(&err) => corruption = Some(err),
+            Err(err) => return Err(Error::CannotReadChunk(err)),
+        }
+
+        let is_last = index + 1 == names.len();
+
+        if corruption.is_some() || is_last {
+            truncate_chunk(dir, name, blocks, chunk_len)?;
+        }
+
+        if let Some(corruption) = corruption {
+            warn!(%corruption, "truncating db to the last valid block");
+
+            let removed_chunks = names[index + 1..].to_vec();
+
+            for name in removed_chunks.iter() {
+                remove_chunk(dir, name)?;
+            }
+
+            return Ok(Recovery {
+                tip: tip.as_ref().map(block_point).transpose()?,
+                corruption: Some(corruption),
+                removed_chunks,
+            });
+        }
+    }
+
+    Ok(Recovery {
+        tip: tip.as_ref().map(block_point).transpose()?,
+        corruption: None,
+        removed_chunks: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::recover;
+    use crate::storage::immutable::{chunk, secondary};
+
+    fn copy_chunks(names: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+
+        for name in names {
+            for extension in ["chunk", "primary", "secondary"] {
+                let file = Path::new(name).with_extension(extension);
+                std::fs::copy(
+                    Path::new("../test_data").join(&file),
+                    dir.path().join(&file),
+                )
+                .unwrap();
+            }
+        }
+
+        dir
+    }
+
+    fn entries(dir: &Path, name: &str) -> Vec<secondary::Entry> {
+        secondary::read_entries(dir, name)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_an_intact_db() {
+        let dir = copy_chunks(&["01285", "01836"]);
+
+        let recovery = recover(dir.path()).unwrap();
+
+        assert!(recovery.corruption.is_none());
+        assert!(recovery.removed_chunks.is_empty());
+
+        let last = entries(dir.path(), "01836").pop().unwrap();
+
+        match recovery.tip.unwrap() {
+            pallas_network::miniprotocols::Point::Specific(slot, hash) => {
+                assert_eq!(slot, u64::from_be_bytes(last.block_or_ebb));
+                assert_eq!(hash, last.header_hash);
+            }
+            x => panic!("unexpected tip {x:?}"),
+        }
+
+        for name in ["01285", "01836"] {
+            for extension in ["chunk", "primary", "secondary"] {
+                let file = Path::new(name).with_extension(extension);
+                assert_eq!(
+                    std::fs::read(dir.path().join(&file)).unwrap(),
+                    std::fs::read(Path::new("../test_data").join(&file)).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn truncates_to_the_last_valid_block() {
+        let dir = copy_chunks(&["01285", "01836", "02019"]);
+
+        let original = entries(dir.path(), "01836");
+        let damaged = &original[10];
+
+        // flip a byte in the middle of the block
+        let path = dir.path().join("01836.chunk");
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[damaged.block_offset as usize + 100] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        let recovery = recover(dir.path()).unwrap();
+
+        match recovery.corruption.unwrap() {
+            chunk::Error::CorruptedBlock { chunk, slot, kind } => {
+                assert_eq!(chunk, "01836");
+                assert_eq!(slot, u64::from_be_bytes(damaged.block_or_ebb));
+                assert_eq!(kind, chunk::Corruption::ChecksumMismatch);
+            }
+            x => panic!("unexpected error {x:?}"),
+        }
+
+        assert_eq!(recovery.removed_chunks, vec!["02019".to_owned()]);
+        assert!(!dir.path().join("02019.chunk").exists());
+
+        match recovery.tip.unwrap() {
+            pallas_network::miniprotocols::Point::Specific(_, hash) => {
+                assert_eq!(hash, original[9].header_hash);
+            }
+            x => panic!("unexpected tip {x:?}"),
+        }
+
+        // the truncated chunk is consistent and valid
+        let kept = entries(dir.path(), "01836");
+        assert_eq!(kept.len(), 10);
+
+        let blocks: Vec<_> = chunk::read_blocks(dir.path(), "01836")
+            .unwrap()
+            .validated()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(blocks.len(), 10);
+
+        // running it again finds nothing to fix
+        let recovery = recover(dir.path()).unwrap();
+        assert!(recovery.corruption.is_none());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     fs::File,
-    io::{BufReader, Read, Seek},
+    io::{BufReader, Read, Seek, SeekFrom},
     path::Path,
 };
 
@@ -54,6 +54,25 @@ impl Entry {
             block_or_ebb: *view.block_or_ebb(),
         }
     }
+
+    /// Size of an encoded entry in the secondary index file
+    pub fn size() -> usize {
+        layout::SIZE.unwrap()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; Self::size()];
+        let mut view = layout::View::new(&mut buf[..]);
+
+        view.block_offset_mut().write(self.block_offset);
+        view.header_offset_mut().write(self.header_offset);
+        view.header_size_mut().write(self.header_size);
+        view.checksum_mut().write(self.checksum);
+        *view.header_hash_mut() = self.header_hash;
+        *view.block_or_ebb_mut() = self.block_or_ebb;
+
+        buf
+    }
 }
 
 pub type SecondaryOffset = u32;
@@ -131,6 +150,39 @@ impl Iterator for Reader {
     }
 }
 
+/// Reads the entry at the given offset of the secondary index, along with the
+/// block offset of the entry that follows it, if there's one
+pub fn read_entry_at(
+    file: &mut File,
+    offset: SecondaryOffset,
+) -> Result<(Entry, Option<u64>), Error> {
+    file.seek(SeekFrom::Start(offset as u64))
+        .map_err(Error::CannotReadSecondaryIndex)?;
+
+    let mut buf = vec![0u8; layout::SIZE.unwrap()];
+
+    match file.read_exact(&mut buf) {
+        Ok(_) => (),
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+            return Err(Error::InconsistentState)
+        }
+        Err(err) => return Err(Error::CannotReadSecondaryIndex(err)),
+    }
+
+    let entry = Entry::from(layout::View::new(&buf));
+
+    // the block offset is the first field of the next entry
+    let mut next = [0u8; 8];
+
+    let next = match file.read_exact(&mut next) {
+        Ok(_) => Some(u64::from_be_bytes(next)),
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => None,
+        Err(err) => return Err(Error::CannotReadSecondaryIndex(err)),
+    };
+
+    Ok((entry, next))
+}
+
 pub fn read_entries(dir: &Path, name: &str) -> Result<Reader, Error> {
     let primary = dir.join(name).with_extension("primary");
     let primary = std::fs::File::open(primary).map_err(Error::CannotOpenFile)?;
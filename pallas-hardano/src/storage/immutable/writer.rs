@@ -0,0 +1,673 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use pallas_traverse::MultiEraBlock;
+use tracing::debug;
+
+use crate::storage::immutable::{primary, secondary};
+
+/// Amount of slots per chunk used by mainnet and the public testnets
+pub const DEFAULT_CHUNK_SIZE: u64 = 21600;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Cannot list chunk files, error: {0}")]
+    CannotReadDir(std::io::Error),
+    #[error("Cannot read chunk files, error: {0}")]
+    CannotReadFile(std::io::Error),
+    #[error("Cannot write chunk files, error: {0}")]
+    CannotWriteFile(std::io::Error),
+    #[error("Cannot decode block, error: {0}")]
+    CannotDecodeBlock(pallas_traverse::Error),
+    #[error("Block at slot {0} doesn't come after the last appended block")]
+    SlotNotIncreasing(u64),
+    #[error("Block header is not part of the block bytes")]
+    HeaderNotFound,
+    #[error("Cannot resume chunk {0}, the size of its index files is not valid")]
+    InconsistentChunk(u64),
+}
+
+fn chunk_path(dir: &Path, number: u64, extension: &str) -> PathBuf {
+    dir.join(format!("{number:05}")).with_extension(extension)
+}
+
+/// Position of a block within the db, the relative slot 0 of each chunk is
+/// reserved for the EBB of the epoch
+type Position = (u64, u64);
+
+/// Position of the last block of a chunk, read from its primary index
+fn read_last_position(dir: &Path, number: u64) -> Result<Option<Position>, Error> {
+    let file = File::open(chunk_path(dir, number, "primary")).map_err(Error::CannotReadFile)?;
+    let index = primary::Reader::open(file).map_err(|_| Error::InconsistentChunk(number))?;
+
+    let mut last = None;
+
+    for entry in index {
+        match entry.map_err(|_| Error::InconsistentChunk(number))? {
+            primary::Entry::Occupied(slot, _) => last = Some((number, slot as u64)),
+            primary::Entry::Empty(_) => (),
+        }
+    }
+
+    Ok(last)
+}
+
+/// Files of the chunk that is currently being appended
+struct ChunkFiles {
+    number: u64,
+    chunk: BufWriter<File>,
+    primary: BufWriter<File>,
+    secondary: BufWriter<File>,
+    chunk_len: u64,
+    secondary_len: u32,
+
+    /// Amount of relative slots that are already covered by the primary index
+    filled_slots: u64,
+}
+
+impl ChunkFiles {
+    fn create(dir: &Path, number: u64) -> Result<Self, Error> {
+        debug!(number, "creating chunk");
+
+        let create = |extension| {
+            File::create(chunk_path(dir, number, extension))
+                .map(BufWriter::new)
+                .map_err(Error::CannotWriteFile)
+        };
+
+        let chunk = create("chunk")?;
+        let mut primary = create("primary")?;
+        let secondary = create("secondary")?;
+
+        primary
+            .write_all(&[primary::CURRENT_VERSION])
+            .and_then(|_| primary.write_all(&primary::encode_offset(0)))
+            .map_err(Error::CannotWriteFile)?;
+
+        Ok(Self {
+            number,
+            chunk,
+            primary,
+            secondary,
+            chunk_len: 0,
+            secondary_len: 0,
+            filled_slots: 0,
+        })
+    }
+
+    fn resume(dir: &Path, number: u64) -> Result<Self, Error> {
+        debug!(number, "resuming chunk");
+
+        let open = |extension| {
+            let file = OpenOptions::new()
+                .append(true)
+                .open(chunk_path(dir, number, extension))
+                .map_err(Error::CannotWriteFile)?;
+
+            let len = file.metadata().map_err(Error::CannotWriteFile)?.len();
+
+            Ok((BufWriter::new(file), len))
+        };
+
+        let (chunk, chunk_len) = open("chunk")?;
+        let (primary, primary_len) = open("primary")?;
+        let (secondary, secondary_len) = open("secondary")?;
+
+        // the version byte, followed by the offset of each slot plus the
+        // initial one
+        let offsets = primary_len.checked_sub(1).filter(|x| x % 4 == 0 && *x > 0);
+        let entry_size = secondary::Entry::size() as u64;
+
+        let (Some(offsets), true) = (offsets, secondary_len % entry_size == 0) else {
+            return Err(Error::InconsistentChunk(number));
+        };
+
+        Ok(Self {
+            number,
+            chunk,
+            primary,
+            secondary,
+            chunk_len,
+            secondary_len: secondary_len as u32,
+            filled_slots: offsets / 4 - 1,
+        })
+    }
+
+    /// Position of the last block of the chunk, if any
+    fn last_position(&self) -> Option<Position> {
+        match self.secondary_len {
+            0 => None,
+            _ => Some((self.number, self.filled_slots - 1)),
+        }
+    }
+
+    /// Marks the slots up to the given one as empty in the primary index
+    fn fill_until(&mut self, relative_slot: u64) -> Result<(), Error> {
+        while self.filled_slots < relative_slot {
+            self.primary
+                .write_all(&primary::encode_offset(self.secondary_len))
+                .map_err(Error::CannotWriteFile)?;
+
+            self.filled_slots += 1;
+        }
+
+        Ok(())
+    }
+
+    fn append(
+        &mut self,
+        relative_slot: u64,
+        block: &[u8],
+        mut entry: secondary::Entry,
+    ) -> Result<(), Error> {
+        self.fill_until(relative_slot)?;
+
+        entry.block_offset = self.chunk_len;
+        let entry = entry.to_bytes();
+
+        self.chunk
+            .write_all(block)
+            .map_err(Error::CannotWriteFile)?;
+
+        self.secondary
+            .write_all(&entry)
+            .map_err(Error::CannotWriteFile)?;
+
+        self.chunk_len += block.len() as u64;
+        self.secondary_len += entry.len() as u32;
+
+        self.primary
+            .write_all(&primary::encode_offset(self.secondary_len))
+            .map_err(Error::CannotWriteFile)?;
+
+        self.filled_slots += 1;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.chunk.flush().map_err(Error::CannotWriteFile)?;
+        self.secondary.flush().map_err(Error::CannotWriteFile)?;
+        self.primary.flush().map_err(Error::CannotWriteFile)?;
+
+        Ok(())
+    }
+
+    /// Backfills the primary index so that it covers every slot of the chunk,
+    /// which is what the node does before moving to the next chunk
+    fn finalize(mut self, chunk_size: u64) -> Result<(), Error> {
+        self.fill_until(chunk_size + 1)?;
+        self.flush()
+    }
+}
+
+/// Appends blocks to an ImmutableDB directory
+///
+/// The chunk, primary and secondary files are written exactly as the node
+/// does: each chunk spans a fixed amount of slots, chunks with no blocks are
+/// still created, and the primary index of a chunk is backfilled up to the
+/// end of the chunk once a block for a later chunk is appended. The last
+/// chunk is left as-is, so that more blocks can be appended to it later.
+pub struct Writer {
+    dir: PathBuf,
+    chunk_size: u64,
+    current: Option<ChunkFiles>,
+    last: Option<Position>,
+}
+
+impl Writer {
+    /// Opens a db for appending, resuming from its last chunk if there's one
+    ///
+    /// The directory is created if it doesn't exist yet.
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        std::fs::create_dir_all(dir).map_err(Error::CannotWriteFile)?;
+
+        let mut chunks: Vec<_> = std::fs::read_dir(dir)
+            .map_err(Error::CannotReadDir)?
+            .map_while(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().map(|e| e == "chunk").unwrap_or_default())
+            .filter_map(|p| p.file_stem()?.to_str()?.parse::<u64>().ok())
+            .collect();
+
+        chunks.sort();
+
+        let current = match chunks.pop() {
+            Some(number) => Some(ChunkFiles::resume(dir, number)?),
+            None => None,
+        };
+
+        let mut last = current.as_ref().and_then(ChunkFiles::last_position);
+
+        // the last chunk might have no blocks yet, in which case the last
+        // block is in one of the previous chunks
+        if current.is_some() {
+            for number in chunks.into_iter().rev() {
+                if last.is_some() {
+                    break;
+                }
+
+                last = read_last_position(dir, number)?;
+            }
+        }
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            current,
+            last,
+        })
+    }
+
+    /// Amount of slots of each chunk, must match the one used by the node
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    fn describe(&self, block: &[u8]) -> Result<(Position, secondary::Entry), Error> {
+        let decoded = MultiEraBlock::decode(block).map_err(Error::CannotDecodeBlock)?;
+
+        let header = decoded.header().cbor();
+
+        // the header is decoded in place, so its bytes are a slice of the
+        // block bytes
+        let header_offset = (header.as_ptr() as usize)
+            .checked_sub(block.as_ptr() as usize)
+            .filter(|x| x + header.len() <= block.len())
+            .ok_or(Error::HeaderNotFound)?;
+
+        let slot = decoded.slot();
+        let chunk = slot / self.chunk_size;
+
+        let (relative_slot, block_or_ebb) = match &decoded {
+            MultiEraBlock::EpochBoundary(x) => (0, x.header.consensus_data.epoch_id),
+            _ => (slot - chunk * self.chunk_size + 1, slot),
+        };
+
+        let entry = secondary::Entry {
+            block_offset: 0,
+            header_offset: header_offset as u16,
+            header_size: header.len() as u16,
+            checksum: crc32fast::hash(block),
+            header_hash: *decoded.hash(),
+            block_or_ebb: block_or_ebb.to_be_bytes(),
+        };
+
+        Ok(((chunk, relative_slot), entry))
+    }
+
+    /// Appends a block, which has to come after the last appended one
+    pub fn append(&mut self, block: &[u8]) -> Result<(), Error> {
+        let (position, entry) = self.describe(block)?;
+
+        let (number, relative_slot) = position;
+
+        // chunks are only ever rotated forward, so a block for a chunk before
+        // the current one is rejected even if the current chunk is empty
+        let behind = matches!(&self.current, Some(current) if number < current.number);
+
+        if behind || matches!(self.last, Some(last) if position <= last) {
+            let slot = u64::from_be_bytes(entry.block_or_ebb);
+            return Err(Error::SlotNotIncreasing(slot));
+        }
+
+        loop {
+            match self.current.take() {
+                Some(current) if current.number == number => {
+                    self.current = Some(current);
+                    break;
+                }
+                Some(current) => {
+                    let next = current.number + 1;
+                    current.finalize(self.chunk_size)?;
+                    self.current = Some(ChunkFiles::create(&self.dir, next)?);
+                }
+                None => self.current = Some(ChunkFiles::create(&self.dir, number)?),
+            }
+        }
+
+        let current = self.current.as_mut().expect("chunk was just opened");
+        current.append(relative_slot, block, entry)?;
+
+        self.last = Some(position);
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        match &mut self.current {
+            Some(x) => x.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes the pending writes and closes the files
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pallas_traverse::MultiEraBlock;
+
+    use super::{Error, Writer, DEFAULT_CHUNK_SIZE};
+    use crate::storage::immutable::{read_blocks, secondary};
+
+    const TEST_CHUNKS: [&str; 3] = ["01285", "01836", "02019"];
+
+    /// Splits the chunk file using the offsets of the secondary index
+    ///
+    /// The last test chunk is truncated, its secondary index has entries past
+    /// the end of the chunk file and its primary index can't be relied upon.
+    fn read_chunk(name: &str) -> Vec<Vec<u8>> {
+        let dir = Path::new("../test_data");
+        let chunk = read_file(dir, name, "chunk");
+
+        let mut offsets: Vec<_> = read_file(dir, name, "secondary")
+            .chunks(secondary::Entry::size())
+            .map(|x| u64::from_be_bytes(x[..8].try_into().unwrap()) as usize)
+            .take_while(|x| *x < chunk.len())
+            .collect();
+
+        offsets.push(chunk.len());
+
+        offsets
+            .windows(2)
+            .map(|x| chunk[x[0]..x[1]].to_vec())
+            .collect()
+    }
+
+    fn read_file(dir: &Path, name: &str, extension: &str) -> Vec<u8> {
+        std::fs::read(dir.join(name).with_extension(extension)).unwrap()
+    }
+
+    fn read_block(name: &str) -> Vec<u8> {
+        let hex = std::fs::read_to_string(Path::new("../test_data").join(name)).unwrap();
+        hex::decode(hex.trim()).unwrap()
+    }
+
+    /// Primary index of a rotated chunk holding a single block, as laid out
+    /// by the node: the offset of each relative slot (plus the final one) into
+    /// the secondary index
+    fn single_block_primary(relative_slot: usize) -> Vec<u8> {
+        let mut primary = vec![1];
+
+        for slot in 0..=DEFAULT_CHUNK_SIZE as usize + 1 {
+            let offset: u32 = if slot <= relative_slot { 0 } else { 56 };
+            primary.extend(offset.to_be_bytes());
+        }
+
+        primary
+    }
+
+    /// Secondary entry of the first block of a chunk, as laid out by the node
+    fn first_secondary_entry(block: &[u8], hash: &[u8], block_or_ebb: u64) -> Vec<u8> {
+        // byron blocks are wrapped in a 2-item array with the era tag, the
+        // header is the first item of the inner 3-item array
+        assert_eq!(&block[..3], &[0x82, block[1], 0x83]);
+
+        let mut header = pallas_codec::minicbor::Decoder::new(&block[3..]);
+        header.skip().unwrap();
+
+        let mut entry = vec![];
+        entry.extend(0u64.to_be_bytes());
+        entry.extend(3u16.to_be_bytes());
+        entry.extend((header.position() as u16).to_be_bytes());
+        entry.extend(crc32fast::hash(block).to_be_bytes());
+        entry.extend(hash);
+        entry.extend(block_or_ebb.to_be_bytes());
+
+        entry
+    }
+
+    #[test]
+    fn writes_byron_chunks_starting_with_an_ebb() {
+        // the mainnet EBB of epoch 0, followed by main blocks of later epochs
+        let ebb = read_block("genesis.block");
+        let main = read_block("byron4.block");
+        let later = read_block("byron8.block");
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = Writer::open(dir.path()).unwrap();
+
+        for block in [&ebb, &main, &later] {
+            writer.append(block).unwrap();
+        }
+
+        writer.finish().unwrap();
+
+        // the EBB takes the relative slot 0 of its chunk and is indexed by
+        // its epoch
+        let ebb_hash =
+            hex::decode("89d9b5a5b8ddc8d7e5a6795e9774d97faf1efea59b2caf7eaf9f8c5b32059df4")
+                .unwrap();
+
+        assert_eq!(read_file(dir.path(), "00000", "chunk"), ebb);
+        assert_eq!(
+            read_file(dir.path(), "00000", "secondary"),
+            first_secondary_entry(&ebb, &ebb_hash, 0)
+        );
+        assert_eq!(
+            read_file(dir.path(), "00000", "primary"),
+            single_block_primary(0)
+        );
+
+        // main blocks take the relative slot after their slot in the epoch
+        let decoded = MultiEraBlock::decode(&main).unwrap();
+        assert_eq!(decoded.slot(), 43191);
+
+        assert_eq!(read_file(dir.path(), "00001", "chunk"), main);
+        assert_eq!(
+            read_file(dir.path(), "00001", "secondary"),
+            first_secondary_entry(&main, decoded.hash().as_ref(), 43191)
+        );
+        assert_eq!(
+            read_file(dir.path(), "00001", "primary"),
+            single_block_primary(21591 + 1)
+        );
+
+        // the last chunk, holding the later block, is left out by the reader
+        let written: Vec<_> = read_blocks(dir.path())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(written, vec![ebb.clone(), main]);
+        assert_eq!(read_file(dir.path(), "00002", "chunk"), later);
+
+        // a main block can't go before the EBB of its epoch
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = Writer::open(dir.path()).unwrap();
+
+        writer.append(&read_block("byron4.block")).unwrap();
+
+        assert!(matches!(
+            writer.append(&ebb),
+            Err(Error::SlotNotIncreasing(0))
+        ));
+    }
+
+    #[test]
+    fn writes_files_identical_to_the_node() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = Writer::open(dir.path()).unwrap();
+
+        for name in TEST_CHUNKS {
+            for block in read_chunk(name) {
+                writer.append(&block).unwrap();
+            }
+        }
+
+        writer.finish().unwrap();
+
+        let original = Path::new("../test_data");
+
+        // rotated chunks include the backfilled primary index
+        for name in &TEST_CHUNKS[..2] {
+            for extension in ["chunk", "primary", "secondary"] {
+                assert_eq!(
+                    read_file(dir.path(), name, extension),
+                    read_file(original, name, extension),
+                    "{name}.{extension} differs"
+                );
+            }
+        }
+
+        // the last test chunk is truncated, only the blocks present in the
+        // chunk file are written
+        let name = TEST_CHUNKS[2];
+        let blocks = read_chunk(name).len();
+
+        assert_eq!(
+            read_file(dir.path(), name, "chunk"),
+            read_file(original, name, "chunk")
+        );
+
+        let secondary = read_file(dir.path(), name, "secondary");
+        assert_eq!(secondary.len(), blocks * secondary::Entry::size());
+        assert!(read_file(original, name, "secondary").starts_with(&secondary));
+
+        // chunks in between are created empty
+        assert!(read_file(dir.path(), "01286", "chunk").is_empty());
+        assert!(read_file(dir.path(), "01286", "secondary").is_empty());
+
+        let written: Vec<_> = read_blocks(dir.path())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let expected: Vec<_> = read_blocks(original).unwrap().map(Result::unwrap).collect();
+
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn resumes_the_last_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let blocks = read_chunk(TEST_CHUNKS[0]);
+        let (first, second) = blocks.split_at(blocks.len() / 2);
+
+        let mut writer = Writer::open(dir.path()).unwrap();
+
+        for block in first {
+            writer.append(block).unwrap();
+        }
+
+        writer.finish().unwrap();
+
+        let mut writer = Writer::open(dir.path()).unwrap();
+
+        // blocks that were already written are rejected
+        assert!(matches!(
+            writer.append(first.last().unwrap()),
+            Err(Error::SlotNotIncreasing(_))
+        ));
+
+        for block in second {
+            writer.append(block).unwrap();
+        }
+
+        writer.finish().unwrap();
+
+        let name = TEST_CHUNKS[0];
+        let original = Path::new("../test_data");
+
+        for extension in ["chunk", "secondary"] {
+            assert_eq!(
+                read_file(dir.path(), name, extension),
+                read_file(original, name, extension)
+            );
+        }
+
+        // the last chunk isn't backfilled
+        let primary = read_file(dir.path(), name, "primary");
+        assert!(read_file(original, name, "primary").starts_with(&primary));
+    }
+
+    #[test]
+    fn resumes_on_an_empty_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let blocks = read_chunk(TEST_CHUNKS[0]);
+        let (first, second) = blocks.split_at(blocks.len() / 2);
+
+        let mut writer = Writer::open(dir.path()).unwrap();
+
+        for block in first {
+            writer.append(block).unwrap();
+        }
+
+        writer.finish().unwrap();
+
+        // an empty chunk after the written one, as left by a crash right
+        // after a rotation
+        for extension in ["chunk", "secondary"] {
+            std::fs::File::create(dir.path().join("01286").with_extension(extension)).unwrap();
+        }
+
+        std::fs::write(
+            dir.path().join("01286.primary"),
+            [super::primary::CURRENT_VERSION, 0, 0, 0, 0],
+        )
+        .unwrap();
+
+        let mut writer = Writer::open(dir.path()).unwrap();
+
+        // blocks of the previous chunks are rejected, whether they were
+        // written or not
+        assert!(matches!(
+            writer.append(first.last().unwrap()),
+            Err(Error::SlotNotIncreasing(_))
+        ));
+
+        assert!(matches!(
+            writer.append(&second[0]),
+            Err(Error::SlotNotIncreasing(_))
+        ));
+
+        assert!(!dir.path().join("01287.chunk").exists());
+
+        // blocks of later chunks are still accepted
+        for name in &TEST_CHUNKS[1..] {
+            for block in read_chunk(name) {
+                writer.append(&block).unwrap();
+            }
+        }
+
+        writer.finish().unwrap();
+
+        // the result is the same as writing the blocks without the crash
+        let reference = tempfile::tempdir().unwrap();
+        let mut writer = Writer::open(reference.path()).unwrap();
+
+        for block in first {
+            writer.append(block).unwrap();
+        }
+
+        for name in &TEST_CHUNKS[1..] {
+            for block in read_chunk(name) {
+                writer.append(&block).unwrap();
+            }
+        }
+
+        writer.finish().unwrap();
+
+        let written: Vec<_> = read_blocks(dir.path())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let expected: Vec<_> = read_blocks(reference.path())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(written, expected);
+    }
+}
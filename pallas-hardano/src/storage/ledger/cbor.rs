@@ -0,0 +1,303 @@
+use std::io::Read;
+
+use super::Error;
+
+/// Max nesting of the items that are skipped, deeper items are rejected
+/// instead of exhausting the stack
+const MAX_DEPTH: usize = 128;
+
+/// Initial byte of a CBOR data item, with its argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Header {
+    Unsigned(u64),
+    Negative(u64),
+    Bytes(Option<u64>),
+    Text(Option<u64>),
+    Array(Option<u64>),
+    Map(Option<u64>),
+    Tag(u64),
+    Simple(u64),
+    Break,
+}
+
+/// Forward-only CBOR decoder over a reader
+///
+/// Snapshots are too big to be loaded in memory, so the structure is walked
+/// item by item, and only the items that need to be decoded are buffered
+/// with [`Decoder::capture`].
+pub struct Decoder<R> {
+    inner: R,
+    offset: u64,
+    depth: usize,
+    record: Option<Vec<u8>>,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            offset: 0,
+            depth: 0,
+            record: None,
+        }
+    }
+
+    /// Amount of bytes consumed so far
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn unexpected(&self, expected: &'static str) -> Error {
+        Error::UnexpectedCbor {
+            expected,
+            offset: self.offset,
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.inner.read_exact(buf).map_err(Error::CannotReadFile)?;
+        self.offset += buf.len() as u64;
+
+        if let Some(record) = &mut self.record {
+            record.extend_from_slice(buf);
+        }
+
+        Ok(())
+    }
+
+    fn discard(&mut self, mut len: u64) -> Result<(), Error> {
+        let mut buf = [0u8; 4096];
+
+        while len > 0 {
+            let chunk = len.min(buf.len() as u64) as usize;
+            self.read_exact(&mut buf[..chunk])?;
+            len -= chunk as u64;
+        }
+
+        Ok(())
+    }
+
+    fn argument(&mut self, info: u8) -> Result<Option<u64>, Error> {
+        let size = match info {
+            0..=23 => return Ok(Some(info as u64)),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            31 => return Ok(None),
+            _ => return Err(self.unexpected("valid initial byte")),
+        };
+
+        let mut buf = [0u8; 8];
+        self.read_exact(&mut buf[8 - size..])?;
+
+        Ok(Some(u64::from_be_bytes(buf)))
+    }
+
+    pub fn header(&mut self) -> Result<Header, Error> {
+        let mut initial = [0u8; 1];
+        self.read_exact(&mut initial)?;
+
+        let major = initial[0] >> 5;
+        let argument = self.argument(initial[0] & 0x1f)?;
+
+        let header = match (major, argument) {
+            (0, Some(x)) => Header::Unsigned(x),
+            (1, Some(x)) => Header::Negative(x),
+            (2, x) => Header::Bytes(x),
+            (3, x) => Header::Text(x),
+            (4, x) => Header::Array(x),
+            (5, x) => Header::Map(x),
+            (6, Some(x)) => Header::Tag(x),
+            (7, Some(x)) => Header::Simple(x),
+            (7, None) => Header::Break,
+            _ => return Err(self.unexpected("valid initial byte")),
+        };
+
+        Ok(header)
+    }
+
+    fn skip_indefinite(&mut self) -> Result<(), Error> {
+        loop {
+            match self.header()? {
+                Header::Break => break Ok(()),
+                header => self.skip_content(header)?,
+            }
+        }
+    }
+
+    /// Skips the rest of an item whose header was already read
+    pub fn skip_content(&mut self, header: Header) -> Result<(), Error> {
+        match header {
+            Header::Unsigned(_) | Header::Negative(_) | Header::Simple(_) => Ok(()),
+            Header::Bytes(Some(len)) | Header::Text(Some(len)) => self.discard(len),
+            Header::Break => Err(self.unexpected("data item")),
+            nested => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(self.unexpected("less deeply nested item"));
+                }
+
+                self.depth += 1;
+                let result = self.skip_nested(nested);
+                self.depth -= 1;
+
+                result
+            }
+        }
+    }
+
+    fn skip_nested(&mut self, header: Header) -> Result<(), Error> {
+        match header {
+            Header::Array(Some(len)) => self.skip_n(len),
+            Header::Map(Some(len)) => match len.checked_mul(2) {
+                Some(items) => self.skip_n(items),
+                None => Err(self.unexpected("map of a valid length")),
+            },
+            Header::Tag(_) => self.skip(),
+            _ => self.skip_indefinite(),
+        }
+    }
+
+    pub fn skip(&mut self) -> Result<(), Error> {
+        let header = self.header()?;
+        self.skip_content(header)
+    }
+
+    pub fn skip_n(&mut self, items: u64) -> Result<(), Error> {
+        for _ in 0..items {
+            self.skip()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next item as raw CBOR bytes
+    pub fn capture(&mut self) -> Result<Vec<u8>, Error> {
+        self.record = Some(vec![]);
+        let result = self.skip();
+        let captured = self.record.take().unwrap_or_default();
+
+        result.map(|_| captured)
+    }
+
+    pub fn array(&mut self) -> Result<u64, Error> {
+        match self.header()? {
+            Header::Array(Some(len)) => Ok(len),
+            _ => Err(self.unexpected("definite array")),
+        }
+    }
+
+    pub fn map(&mut self) -> Result<u64, Error> {
+        match self.header()? {
+            Header::Map(Some(len)) => Ok(len),
+            _ => Err(self.unexpected("definite map")),
+        }
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        match self.header()? {
+            Header::Unsigned(x) => Ok(x),
+            _ => Err(self.unexpected("unsigned integer")),
+        }
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, Error> {
+        match self.header()? {
+            Header::Bytes(Some(mut len)) => {
+                // the length comes from the input, so the buffer grows as
+                // the data arrives instead of being allocated upfront
+                let mut buf = vec![];
+                let mut chunk = [0u8; 4096];
+
+                while len > 0 {
+                    let size = len.min(chunk.len() as u64) as usize;
+                    self.read_exact(&mut chunk[..size])?;
+                    buf.extend_from_slice(&chunk[..size]);
+                    len -= size as u64;
+                }
+
+                Ok(buf)
+            }
+            _ => Err(self.unexpected("definite bytes")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas_codec::minicbor;
+
+    use super::{Decoder, Header};
+
+    #[test]
+    fn skips_and_captures_nested_items() {
+        let mut buf = vec![];
+        let mut encoder = minicbor::Encoder::new(&mut buf);
+
+        encoder
+            .array(3)
+            .unwrap()
+            .u64(500)
+            .unwrap()
+            .begin_map()
+            .unwrap()
+            .str("key")
+            .unwrap()
+            .begin_array()
+            .unwrap()
+            .bytes(&[1, 2, 3])
+            .unwrap()
+            .tag(minicbor::data::Tag::Unassigned(30))
+            .unwrap()
+            .i64(-1)
+            .unwrap()
+            .end()
+            .unwrap()
+            .end()
+            .unwrap()
+            .f64(1.5)
+            .unwrap();
+
+        let mut decoder = Decoder::new(buf.as_slice());
+
+        assert_eq!(decoder.header().unwrap(), Header::Array(Some(3)));
+        assert_eq!(decoder.u64().unwrap(), 500);
+
+        let start = decoder.offset() as usize;
+        let captured = decoder.capture().unwrap();
+        assert_eq!(captured, buf[start..decoder.offset() as usize]);
+
+        decoder.skip().unwrap();
+        assert_eq!(decoder.offset(), buf.len() as u64);
+        assert!(decoder.skip().is_err());
+    }
+
+    #[test]
+    fn rejects_huge_maps() {
+        let buf = [0xbb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+
+        let mut decoder = Decoder::new(buf.as_slice());
+        assert!(decoder.skip().is_err());
+    }
+
+    #[test]
+    fn rejects_deeply_nested_items() {
+        // an array holding an array holding an array, a million times
+        let buf = vec![0x81; 1_000_000];
+
+        let mut decoder = Decoder::new(buf.as_slice());
+        assert!(decoder.skip().is_err());
+        assert!(decoder.offset() < 1_000);
+    }
+
+    #[test]
+    fn rejects_truncated_bytes() {
+        // a byte string claiming to be u64::MAX long, with a few bytes only
+        let buf = [
+            0x5b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 1, 2, 3,
+        ];
+
+        let mut decoder = Decoder::new(buf.as_slice());
+        assert!(decoder.bytes().is_err());
+    }
+}
@@ -0,0 +1,966 @@
+//! Reader for the ledger state snapshots of the Haskell node
+//!
+//! The node periodically writes its `ExtLedgerState` to `db/ledger/<slot>`.
+//! The snapshot is walked as a stream, and the large collections (UTxO set,
+//! reward accounts, pools and stake distribution) are handed one item at a
+//! time to a [`Visitor`] instead of being collected, so that reading mainnet
+//! snapshots stays memory-bounded. Only the Babbage and Conway eras are
+//! supported, and only the in-memory snapshot format, where the UTxO set is
+//! part of the snapshot file.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use pallas_codec::minicbor;
+use pallas_crypto::hash::Hash;
+use pallas_primitives::alonzo::{
+    AddrKeyhash, PoolKeyhash, PoolMetadata, RationalNumber, Relay, StakeCredential,
+    TransactionInput, UnitInterval, VrfKeyhash,
+};
+use pallas_traverse::{Era, MultiEraOutput};
+use tracing::debug;
+
+use crate::storage::immutable::Point;
+
+pub mod cbor;
+
+use cbor::{Decoder, Header};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Cannot read directory, error: {0}")]
+    CannotReadDir(std::io::Error),
+    #[error("Cannot read snapshot file, error: {0}")]
+    CannotReadFile(std::io::Error),
+    #[error("Unexpected CBOR at offset {offset}, expected {expected}")]
+    UnexpectedCbor { expected: &'static str, offset: u64 },
+    #[error("Cannot decode {0}, error: {1}")]
+    CannotDecode(&'static str, minicbor::decode::Error),
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u64),
+    #[error("Snapshots of the {0} era are not supported")]
+    UnsupportedEra(Era),
+    #[error("Unsupported layout of the {0}")]
+    UnsupportedLayout(&'static str),
+}
+
+/// Registration parameters of a stake pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolParams {
+    pub operator: PoolKeyhash,
+    pub vrf_keyhash: VrfKeyhash,
+    pub pledge: u64,
+    pub cost: u64,
+    pub margin: UnitInterval,
+    pub reward_account: Vec<u8>,
+    pub pool_owners: Vec<AddrKeyhash>,
+    pub relays: Vec<Relay>,
+    pub pool_metadata: Option<PoolMetadata>,
+}
+
+/// State of a registered stake credential
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewardAccount {
+    pub credential: StakeCredential,
+    pub rewards: u64,
+    pub deposit: u64,
+
+    /// Pool the credential delegates to, if any
+    pub pool: Option<PoolKeyhash>,
+}
+
+/// Share of the active stake of a pool, as used for leader election in the
+/// current epoch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolStake {
+    pub pool: PoolKeyhash,
+    pub stake: RationalNumber,
+    pub vrf_keyhash: VrfKeyhash,
+}
+
+/// Protocol parameters in effect, the leading fields being the same in all
+/// the supported eras
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolParams {
+    pub minfee_a: u64,
+    pub minfee_b: u64,
+    pub max_block_body_size: u64,
+    pub max_transaction_size: u64,
+    pub max_block_header_size: u64,
+    pub key_deposit: u64,
+    pub pool_deposit: u64,
+    pub maximum_epoch: u64,
+    pub desired_number_of_stake_pools: u64,
+
+    /// CBOR of all the parameters, as encoded by the ledger of the era
+    pub cbor: Vec<u8>,
+}
+
+/// Values of the snapshot that are small enough to be kept in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerState {
+    pub era: Era,
+    pub tip: Option<Point>,
+    pub epoch: u64,
+    pub treasury: u64,
+    pub reserves: u64,
+    pub protocol_params: ProtocolParams,
+
+    /// Nonce of the current epoch, `None` if it's the neutral nonce
+    pub epoch_nonce: Option<Hash<32>>,
+}
+
+/// Receiver of the large collections of a snapshot, in the order in which
+/// they appear in the file
+///
+/// All methods default to a no-op, implementors only need to override the
+/// items they care about.
+pub trait Visitor {
+    fn pool(&mut self, _params: PoolParams) {}
+
+    fn reward_account(&mut self, _account: RewardAccount) {}
+
+    fn utxo(&mut self, _input: TransactionInput, _output: MultiEraOutput<'_>) {}
+
+    fn pool_stake(&mut self, _stake: PoolStake) {}
+}
+
+impl Visitor for () {}
+
+/// Lists the snapshots of a ledger directory by slot, in ascending order
+///
+/// Snapshot files are named after their slot, optionally followed by an
+/// underscore and a suffix.
+pub fn list_snapshots(dir: &Path) -> Result<Vec<(u64, PathBuf)>, Error> {
+    let mut snapshots: Vec<_> = std::fs::read_dir(dir)
+        .map_err(Error::CannotReadDir)?
+        .map_while(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter_map(|p| {
+            let name = p.file_name()?.to_str()?;
+            let slot = name.split('_').next()?.parse::<u64>().ok()?;
+            Some((slot, p))
+        })
+        .collect();
+
+    snapshots.sort();
+
+    Ok(snapshots)
+}
+
+fn decode<'b, T>(what: &'static str, cbor: &'b [u8]) -> Result<T, Error>
+where
+    T: minicbor::Decode<'b, ()>,
+{
+    minicbor::decode(cbor).map_err(|x| Error::CannotDecode(what, x))
+}
+
+fn hash<const BYTES: usize, R: Read>(d: &mut Decoder<R>) -> Result<Hash<BYTES>, Error> {
+    let bytes = d.bytes()?;
+
+    match <[u8; BYTES]>::try_from(bytes.as_slice()) {
+        Ok(x) => Ok(Hash::new(x)),
+        Err(_) => Err(d.unexpected("hash")),
+    }
+}
+
+/// Reads a rational, which the ledger encodes with or without tag 30
+fn rational<R: Read>(d: &mut Decoder<R>) -> Result<RationalNumber, Error> {
+    let len = match d.header()? {
+        Header::Tag(30) => d.array()?,
+        Header::Array(Some(len)) => len,
+        _ => return Err(d.unexpected("rational")),
+    };
+
+    if len != 2 {
+        return Err(d.unexpected("rational"));
+    }
+
+    Ok(RationalNumber {
+        numerator: d.u64()?,
+        denominator: d.u64()?,
+    })
+}
+
+/// Reads a `StrictMaybe`, encoded as a list of zero or one items
+fn strict_maybe<R: Read, T>(
+    d: &mut Decoder<R>,
+    f: impl FnOnce(&mut Decoder<R>) -> Result<T, Error>,
+) -> Result<Option<T>, Error> {
+    match d.array()? {
+        0 => Ok(None),
+        1 => f(d).map(Some),
+        _ => Err(d.unexpected("strict maybe")),
+    }
+}
+
+/// Skips the eras that already ended in a hard fork telescope, leaving the
+/// decoder at the state of the current era
+fn current_era<R: Read>(d: &mut Decoder<R>) -> Result<Era, Error> {
+    let eras = d.array()?;
+    d.skip_n(eras.saturating_sub(1))?;
+
+    // the current era is a pair of its start bound and its state
+    if d.array()? != 2 {
+        return Err(d.unexpected("current era"));
+    }
+
+    d.skip()?;
+
+    u16::try_from(eras)
+        .ok()
+        .and_then(|x| Era::try_from(x).ok())
+        .ok_or(d.unexpected("known era"))
+}
+
+fn read_tip<R: Read>(d: &mut Decoder<R>) -> Result<Option<Point>, Error> {
+    strict_maybe(d, |d| {
+        d.array()?;
+        let slot = d.u64()?;
+        let _block_number = d.u64()?;
+        let hash = d.bytes()?;
+
+        Ok(Point::Specific(slot, hash))
+    })
+}
+
+fn decode_pool_params(cbor: &[u8]) -> Result<PoolParams, minicbor::decode::Error> {
+    let mut d = minicbor::Decoder::new(cbor);
+    d.array()?;
+
+    let operator = d.decode()?;
+    let vrf_keyhash = d.decode()?;
+    let pledge = d.decode()?;
+    let cost = d.decode()?;
+    let margin = d.decode()?;
+    let reward_account = d.bytes()?.to_vec();
+
+    // owners are a set, which newer ledgers tag explicitly
+    if d.datatype()? == minicbor::data::Type::Tag {
+        d.tag()?;
+    }
+
+    let pool_owners = d.array_iter()?.collect::<Result<_, _>>()?;
+    let relays = d.array_iter()?.collect::<Result<_, _>>()?;
+
+    let pool_metadata = match d.datatype()? {
+        minicbor::data::Type::Null => {
+            d.skip()?;
+            None
+        }
+        _ => Some(d.decode()?),
+    };
+
+    Ok(PoolParams {
+        operator,
+        vrf_keyhash,
+        pledge,
+        cost,
+        margin,
+        reward_account,
+        pool_owners,
+        relays,
+        pool_metadata,
+    })
+}
+
+fn read_pools<R: Read>(d: &mut Decoder<R>, visitor: &mut impl Visitor) -> Result<(), Error> {
+    let len = d.array()?;
+
+    let pools = d.map()?;
+    debug!(pools, "reading pool params");
+
+    for _ in 0..pools {
+        d.skip()?;
+        let params = d.capture()?;
+        let params = decode_pool_params(&params).map_err(|x| Error::CannotDecode("pool", x))?;
+        visitor.pool(params);
+    }
+
+    // future params, retirements and deposits
+    d.skip_n(len.saturating_sub(1))
+}
+
+fn read_accounts<R: Read>(d: &mut Decoder<R>, visitor: &mut impl Visitor) -> Result<(), Error> {
+    let len = d.array()?;
+
+    // the unified map of rewards, deposits and delegations, along with the
+    // pointers
+    if d.header()? != Header::Array(Some(2)) {
+        return Err(Error::UnsupportedLayout("delegation state"));
+    }
+
+    let accounts = d.map()?;
+    debug!(accounts, "reading reward accounts");
+
+    for _ in 0..accounts {
+        let credential = d.capture()?;
+        let credential = decode("credential", &credential)?;
+
+        let fields = d.array()?;
+
+        let (rewards, deposit) = strict_maybe(d, |d| {
+            d.array()?;
+            Ok((d.u64()?, d.u64()?))
+        })?
+        .unwrap_or_default();
+
+        // pointers
+        d.skip()?;
+
+        let pool = strict_maybe(d, |d| hash(d))?;
+
+        // the drep delegation, if any
+        d.skip_n(fields.saturating_sub(3))?;
+
+        visitor.reward_account(RewardAccount {
+            credential,
+            rewards,
+            deposit,
+            pool,
+        });
+    }
+
+    // pointers map, genesis delegations and instantaneous rewards
+    d.skip()?;
+    d.skip_n(len.saturating_sub(1))
+}
+
+fn read_utxos<R: Read>(
+    d: &mut Decoder<R>,
+    era: Era,
+    visitor: &mut impl Visitor,
+) -> Result<(), Error> {
+    let utxos = d.map()?;
+    debug!(utxos, "reading utxo set");
+
+    for _ in 0..utxos {
+        let input = d.capture()?;
+        let input = decode("transaction input", &input)?;
+
+        let output = d.capture()?;
+        let output =
+            MultiEraOutput::decode(era, &output).map_err(|x| Error::CannotDecode("output", x))?;
+
+        visitor.utxo(input, output);
+    }
+
+    Ok(())
+}
+
+fn read_protocol_params<R: Read>(d: &mut Decoder<R>) -> Result<ProtocolParams, Error> {
+    let cbor = d.capture()?;
+
+    let mut x = minicbor::Decoder::new(&cbor);
+
+    x.array()
+        .map_err(|e| Error::CannotDecode("protocol params", e))?;
+
+    let mut next = || {
+        x.u64()
+            .map_err(|e| Error::CannotDecode("protocol params", e))
+    };
+
+    Ok(ProtocolParams {
+        minfee_a: next()?,
+        minfee_b: next()?,
+        max_block_body_size: next()?,
+        max_transaction_size: next()?,
+        max_block_header_size: next()?,
+        key_deposit: next()?,
+        pool_deposit: next()?,
+        maximum_epoch: next()?,
+        desired_number_of_stake_pools: next()?,
+        cbor,
+    })
+}
+
+/// Reads the governance state, which holds the protocol params since the
+/// params were moved out of the epoch state
+fn read_gov_state<R: Read>(d: &mut Decoder<R>, era: Era) -> Result<ProtocolParams, Error> {
+    let len = d.array()?;
+
+    // proposals, committee and constitution in Conway, proposals and future
+    // proposals before
+    let skipped = match era {
+        Era::Conway => 3,
+        _ => 2,
+    };
+
+    d.skip_n(skipped)?;
+    let params = read_protocol_params(d)?;
+    d.skip_n(len.saturating_sub(skipped + 1))?;
+
+    Ok(params)
+}
+
+/// Reads the ledger state, returning the protocol params if they are part of
+/// the governance state
+fn read_ledger_state<R: Read>(
+    d: &mut Decoder<R>,
+    era: Era,
+    with_gov_state: bool,
+    visitor: &mut impl Visitor,
+) -> Result<Option<ProtocolParams>, Error> {
+    if d.array()? != 2 {
+        return Err(d.unexpected("ledger state"));
+    }
+
+    match d.array()? {
+        // voting, pool and delegation states
+        3 => {
+            d.skip()?;
+            read_pools(d, visitor)?;
+            read_accounts(d, visitor)?;
+        }
+        // pool and delegation states, before the voting state was added
+        2 => {
+            read_pools(d, visitor)?;
+            read_accounts(d, visitor)?;
+        }
+        _ => return Err(Error::UnsupportedLayout("certificate state")),
+    }
+
+    let len = d.array()?;
+
+    read_utxos(d, era, visitor)?;
+
+    // deposited and fees
+    d.skip_n(2)?;
+
+    let params = match with_gov_state {
+        true => Some(read_gov_state(d, era)?),
+        false => {
+            d.skip()?;
+            None
+        }
+    };
+
+    // stake distribution and donations
+    d.skip_n(len.saturating_sub(4))?;
+
+    Ok(params)
+}
+
+fn read_pool_stakes<R: Read>(d: &mut Decoder<R>, visitor: &mut impl Visitor) -> Result<(), Error> {
+    // newer ledgers pair the map with the total active stake
+    let (pools, with_total) = match d.header()? {
+        Header::Array(Some(2)) => (d.map()?, true),
+        Header::Map(Some(len)) => (len, false),
+        _ => return Err(d.unexpected("pool distribution")),
+    };
+
+    for _ in 0..pools {
+        let pool = hash(d)?;
+
+        let len = d.array()?;
+        let stake = rational(d)?;
+
+        // the compact active stake of the pool
+        d.skip_n(len.saturating_sub(2))?;
+
+        let vrf_keyhash = hash(d)?;
+
+        visitor.pool_stake(PoolStake {
+            pool,
+            stake,
+            vrf_keyhash,
+        });
+    }
+
+    if with_total {
+        d.skip()?;
+    }
+
+    Ok(())
+}
+
+fn read_epoch_nonce<R: Read>(d: &mut Decoder<R>) -> Result<Option<Hash<32>>, Error> {
+    // the header state, made of the annotated tip and the protocol state
+    if d.array()? != 2 {
+        return Err(d.unexpected("header state"));
+    }
+
+    d.skip()?;
+    current_era(d)?;
+
+    // versioned praos state
+    d.array()?;
+    d.u64()?;
+
+    let len = d.array()?;
+
+    // last slot, operational certificate counters, evolving, candidate
+    d.skip_n(4)?;
+
+    let nonce = match d.array()? {
+        1 => {
+            d.u64()?;
+            None
+        }
+        2 => {
+            d.u64()?;
+            Some(hash(d)?)
+        }
+        _ => return Err(d.unexpected("nonce")),
+    };
+
+    // lab and last epoch block nonces
+    d.skip_n(len.saturating_sub(5))?;
+
+    Ok(nonce)
+}
+
+/// Walks a ledger state snapshot, handing the large collections to the
+/// visitor and returning the rest of the values
+pub fn read_snapshot(reader: impl Read, visitor: &mut impl Visitor) -> Result<LedgerState, Error> {
+    let mut d = Decoder::new(reader);
+
+    d.array()?;
+
+    let version = d.u64()?;
+
+    if version != 1 {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    // the extended ledger state, with the ledger and header states
+    if d.array()? != 2 {
+        return Err(d.unexpected("extended ledger state"));
+    }
+
+    let era = current_era(&mut d)?;
+
+    if !matches!(era, Era::Babbage | Era::Conway) {
+        return Err(Error::UnsupportedEra(era));
+    }
+
+    // versioned shelley ledger state
+    d.array()?;
+    d.u64()?;
+    d.array()?;
+
+    let tip = read_tip(&mut d)?;
+
+    // new epoch state
+    d.array()?;
+    let epoch = d.u64()?;
+
+    // blocks made in the previous and current epochs
+    d.skip_n(2)?;
+
+    let len = d.array()?;
+
+    d.array()?;
+    let treasury = d.u64()?;
+    let reserves = d.u64()?;
+
+    let protocol_params = match len {
+        4 => {
+            let params = read_ledger_state(&mut d, era, true, visitor)?;
+
+            // stake snapshots and non-myopic rewards
+            d.skip_n(2)?;
+
+            params.ok_or(Error::UnsupportedLayout("governance state"))?
+        }
+        6 => {
+            d.skip()?;
+            read_ledger_state(&mut d, era, false, visitor)?;
+
+            // previous params come first
+            d.skip()?;
+            let params = read_protocol_params(&mut d)?;
+            d.skip()?;
+
+            params
+        }
+        _ => return Err(Error::UnsupportedLayout("epoch state")),
+    };
+
+    // pulsing reward update
+    d.skip()?;
+
+    read_pool_stakes(&mut d, visitor)?;
+
+    // stashed AVVM addresses and the transition info
+    d.skip_n(2)?;
+
+    let epoch_nonce = read_epoch_nonce(&mut d)?;
+
+    Ok(LedgerState {
+        era,
+        tip,
+        epoch,
+        treasury,
+        reserves,
+        protocol_params,
+        epoch_nonce,
+    })
+}
+
+/// Same as [`read_snapshot`], reading from a snapshot file
+pub fn read_snapshot_file(path: &Path, visitor: &mut impl Visitor) -> Result<LedgerState, Error> {
+    let file = File::open(path).map_err(Error::CannotReadFile)?;
+    read_snapshot(BufReader::new(file), visitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use pallas_codec::minicbor::{self, data::Tag, encode::Error as EncodeError, Encoder};
+    use pallas_crypto::hash::Hash;
+    use pallas_primitives::alonzo::StakeCredential;
+    use pallas_traverse::{Era, MultiEraOutput};
+
+    use super::{
+        list_snapshots, read_snapshot, PoolParams, PoolStake, RewardAccount, TransactionInput,
+        Visitor,
+    };
+    use crate::storage::immutable::Point;
+
+    type Result = std::result::Result<(), EncodeError<Infallible>>;
+
+    const POOL: [u8; 28] = [1; 28];
+    const VRF: [u8; 32] = [2; 32];
+    const STAKE: [u8; 28] = [3; 28];
+    const TIP: [u8; 32] = [4; 32];
+    const NONCE: [u8; 32] = [5; 32];
+
+    /// Eras that ended before the current one, as `[start, end]` bounds
+    fn past_eras(e: &mut Encoder<&mut Vec<u8>>, count: u64) -> Result {
+        for _ in 0..count {
+            e.array(2)?;
+            e.array(3)?.u64(0)?.u64(0)?.u64(0)?;
+            e.array(3)?.u64(0)?.u64(0)?.u64(0)?;
+        }
+
+        Ok(())
+    }
+
+    fn pool_params(e: &mut Encoder<&mut Vec<u8>>) -> Result {
+        e.array(9)?;
+        e.bytes(&POOL)?.bytes(&VRF)?.u64(500)?.u64(340)?;
+        e.tag(Tag::Unassigned(30))?.array(2)?.u64(1)?.u64(100)?;
+        e.bytes(&[0xe1; 29])?;
+        e.tag(Tag::Unassigned(258))?.array(1)?.bytes(&STAKE)?;
+        e.array(1)?.array(4)?.u8(0)?.u16(3001)?.null()?.null()?;
+        e.null()?;
+
+        Ok(())
+    }
+
+    fn protocol_params(e: &mut Encoder<&mut Vec<u8>>) -> Result {
+        e.array(10)?;
+
+        for x in [
+            44,
+            155381,
+            90112,
+            16384,
+            1100,
+            2_000_000,
+            500_000_000,
+            18,
+            500,
+            0,
+        ] {
+            e.u64(x)?;
+        }
+
+        Ok(())
+    }
+
+    fn ledger_state(e: &mut Encoder<&mut Vec<u8>>, era: Era) -> Result {
+        e.array(2)?;
+
+        // certificate state, with pool and delegation states, preceded by the
+        // voting state in Conway
+        match era {
+            Era::Conway => {
+                e.array(3)?;
+                e.array(0)?;
+            }
+            _ => {
+                e.array(2)?;
+            }
+        }
+
+        e.array(4)?;
+        e.map(1)?.bytes(&POOL)?;
+        pool_params(e)?;
+        e.map(0)?.map(0)?.map(0)?;
+
+        e.array(4)?;
+        e.array(2)?;
+        e.map(2)?;
+        e.array(2)?.u8(0)?.bytes(&STAKE)?;
+        e.array(4)?;
+        e.array(1)?.array(2)?.u64(1000)?.u64(2_000_000)?;
+        e.array(0)?;
+        e.array(1)?.bytes(&POOL)?;
+        e.array(0)?;
+        e.array(2)?.u8(1)?.bytes(&[6; 28])?;
+        e.array(4)?;
+        e.array(0)?.array(0)?.array(0)?.array(0)?;
+        e.map(0)?;
+        e.map(0)?.map(0)?;
+        e.array(4)?.map(0)?.map(0)?.u64(0)?.u64(0)?;
+
+        // utxo state, with a legacy and a post-alonzo output
+        e.array(match era {
+            Era::Conway => 6,
+            _ => 5,
+        })?;
+        e.map(2)?;
+        e.array(2)?.bytes(&[7; 32])?.u64(0)?;
+        e.array(2)?.bytes(&[0x61; 29])?.u64(1_500_000)?;
+        e.array(2)?.bytes(&[7; 32])?.u64(1)?;
+        e.map(2)?.u8(0)?.bytes(&[0x61; 29])?.u8(1)?.u64(2_500_000)?;
+        e.u64(2_000_000)?.u64(180_000)?;
+
+        match era {
+            Era::Conway => {
+                // governance state, the current params being the fourth item
+                e.array(7)?;
+                e.array(0)?.array(0)?.array(0)?;
+                protocol_params(e)?;
+                e.array(0)?.array(0)?.array(0)?;
+
+                e.map(0)?.u64(0)?;
+            }
+            _ => {
+                // protocol param updates
+                e.array(2)?.map(0)?.map(0)?;
+
+                e.map(0)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn snapshot(era: Era) -> Vec<u8> {
+        let eras = match era {
+            Era::Conway => 7,
+            _ => 6,
+        };
+
+        let mut buf = vec![];
+        let e = &mut Encoder::new(&mut buf);
+
+        let result: Result = (|| {
+            e.array(2)?.u64(1)?;
+            e.array(2)?;
+
+            // ledger telescope
+            e.array(eras)?;
+            past_eras(e, eras - 1)?;
+            e.array(2)?.array(3)?.u64(0)?.u64(0)?.u64(0)?;
+            e.array(2)?.u64(2)?.array(3)?;
+
+            e.array(1)?
+                .array(3)?
+                .u64(72_316_896)?
+                .u64(9_000_000)?
+                .bytes(&TIP)?;
+
+            // new epoch state
+            e.array(7)?.u64(432)?.map(0)?.map(0)?;
+
+            // epoch state, with the params moved to the governance state in
+            // Conway
+            match era {
+                Era::Conway => {
+                    e.array(4)?;
+                    e.array(2)?.u64(1_000_000_000)?.u64(8_000_000_000)?;
+                    ledger_state(e, era)?;
+                    e.array(0)?.array(0)?;
+                }
+                _ => {
+                    e.array(6)?;
+                    e.array(2)?.u64(1_000_000_000)?.u64(8_000_000_000)?;
+                    e.array(0)?;
+                    ledger_state(e, era)?;
+                    e.array(0)?;
+                    protocol_params(e)?;
+                    e.array(0)?;
+                }
+            }
+
+            e.array(0)?;
+
+            e.array(2)?.map(1)?.bytes(&POOL)?;
+            e.array(3)?;
+            e.tag(Tag::Unassigned(30))?.array(2)?.u64(1)?.u64(3)?;
+            e.u64(1000)?.bytes(&VRF)?;
+            e.u64(3000)?;
+
+            e.map(0)?;
+
+            // transition info
+            e.array(0)?;
+
+            // header state
+            e.array(2)?.array(0)?;
+            e.array(eras)?;
+            past_eras(e, eras - 1)?;
+            e.array(2)?.array(3)?.u64(0)?.u64(0)?.u64(0)?;
+            e.array(2)?.u64(0)?.array(7)?;
+            e.array(0)?.map(0)?.array(1)?.u8(0)?.array(1)?.u8(0)?;
+            e.array(2)?.u8(1)?.bytes(&NONCE)?;
+            e.array(1)?.u8(0)?.array(1)?.u8(0)?;
+
+            Ok(())
+        })();
+
+        result.unwrap();
+
+        buf
+    }
+
+    #[derive(Default)]
+    struct Collected {
+        pools: Vec<PoolParams>,
+        accounts: Vec<RewardAccount>,
+        utxos: Vec<(TransactionInput, u64)>,
+        stakes: Vec<PoolStake>,
+    }
+
+    impl Visitor for Collected {
+        fn pool(&mut self, params: PoolParams) {
+            self.pools.push(params);
+        }
+
+        fn reward_account(&mut self, account: RewardAccount) {
+            self.accounts.push(account);
+        }
+
+        fn utxo(&mut self, input: TransactionInput, output: MultiEraOutput<'_>) {
+            self.utxos.push((input, output.lovelace_amount()));
+        }
+
+        fn pool_stake(&mut self, stake: PoolStake) {
+            self.stakes.push(stake);
+        }
+    }
+
+    fn check_snapshot(era: Era) {
+        let snapshot = snapshot(era);
+
+        let mut collected = Collected::default();
+        let state = read_snapshot(snapshot.as_slice(), &mut collected).unwrap();
+
+        assert_eq!(state.era, era);
+        assert_eq!(state.tip, Some(Point::Specific(72_316_896, TIP.to_vec())));
+        assert_eq!(state.epoch, 432);
+        assert_eq!(state.treasury, 1_000_000_000);
+        assert_eq!(state.reserves, 8_000_000_000);
+        assert_eq!(state.epoch_nonce, Some(Hash::new(NONCE)));
+
+        let params = &state.protocol_params;
+        assert_eq!(params.minfee_a, 44);
+        assert_eq!(params.minfee_b, 155381);
+        assert_eq!(params.key_deposit, 2_000_000);
+        assert_eq!(params.desired_number_of_stake_pools, 500);
+        assert_eq!(
+            minicbor::decode::<Vec<u64>>(&params.cbor).unwrap().len(),
+            10
+        );
+
+        assert_eq!(collected.pools.len(), 1);
+        assert_eq!(collected.pools[0].operator, Hash::new(POOL));
+        assert_eq!(collected.pools[0].pledge, 500);
+        assert_eq!(collected.pools[0].pool_owners, vec![Hash::new(STAKE)]);
+        assert_eq!(collected.pools[0].relays.len(), 1);
+        assert!(collected.pools[0].pool_metadata.is_none());
+
+        assert_eq!(collected.accounts.len(), 2);
+        assert_eq!(
+            collected.accounts[0],
+            RewardAccount {
+                credential: StakeCredential::AddrKeyhash(STAKE.into()),
+                rewards: 1000,
+                deposit: 2_000_000,
+                pool: Some(POOL.into()),
+            }
+        );
+        assert_eq!(collected.accounts[1].rewards, 0);
+        assert_eq!(collected.accounts[1].pool, None);
+
+        let amounts: Vec<_> = collected.utxos.iter().map(|(_, x)| *x).collect();
+        assert_eq!(amounts, vec![1_500_000, 2_500_000]);
+        assert_eq!(collected.utxos[1].0.index, 1);
+
+        assert_eq!(collected.stakes.len(), 1);
+        assert_eq!(collected.stakes[0].stake.denominator, 3);
+        assert_eq!(collected.stakes[0].vrf_keyhash, Hash::new(VRF));
+
+        // a truncated snapshot fails instead of returning partial values
+        let truncated = &snapshot[..snapshot.len() - 10];
+        assert!(read_snapshot(truncated, &mut ()).is_err());
+    }
+
+    #[test]
+    fn reads_a_conway_snapshot() {
+        check_snapshot(Era::Conway);
+    }
+
+    #[test]
+    fn reads_a_babbage_snapshot() {
+        check_snapshot(Era::Babbage);
+    }
+
+    #[test]
+    fn lists_snapshots_by_slot() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for name in ["72316896", "4492800_db-analyser", "100000000", "clean"] {
+            std::fs::write(dir.path().join(name), []).unwrap();
+        }
+
+        std::fs::create_dir(dir.path().join("5000")).unwrap();
+
+        let slots: Vec<_> = list_snapshots(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|(slot, _)| slot)
+            .collect();
+
+        assert_eq!(slots, vec![4_492_800, 72_316_896, 100_000_000]);
+    }
+
+    /// Reads a snapshot taken by cardano-node on the preview network, pointed
+    /// at by `PALLAS_PREVIEW_LEDGER_SNAPSHOT`
+    ///
+    /// Real snapshots are too big to be kept in the repo, so this only runs
+    /// when asked for, eg: before supporting the layout of a new node release.
+    #[test]
+    #[ignore]
+    fn reads_a_preview_node_snapshot() {
+        let path = std::env::var("PALLAS_PREVIEW_LEDGER_SNAPSHOT")
+            .expect("PALLAS_PREVIEW_LEDGER_SNAPSHOT must point to a snapshot file");
+
+        let mut collected = Collected::default();
+        let state = super::read_snapshot_file(path.as_ref(), &mut collected).unwrap();
+
+        assert!(state.tip.is_some());
+        assert!(state.epoch_nonce.is_some());
+
+        // values set by the preview genesis and never updated since
+        assert_eq!(state.protocol_params.key_deposit, 2_000_000);
+        assert_eq!(state.protocol_params.pool_deposit, 500_000_000);
+
+        assert!(!collected.utxos.is_empty());
+        assert!(collected.utxos.iter().all(|(_, lovelace)| *lovelace > 0));
+
+        assert!(!collected.pools.is_empty());
+        assert!(collected.pools.iter().all(|x| x.cost > 0));
+        assert!(!collected.stakes.is_empty());
+    }
+}
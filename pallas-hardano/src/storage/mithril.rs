@@ -0,0 +1,383 @@
+//! Import of the node db from Mithril snapshot archives
+//!
+//! A Mithril snapshot is a `tar.zst` archive of the db directory of the node,
+//! certified by the digest of its immutable files. The import works from local
+//! files only: the archive is unpacked, the digest is recomputed the way the
+//! Mithril signers compute it and checked against the manifest of the
+//! snapshot. Verifying the certificate chain that signs the digest is left to
+//! the caller, the certificate hash is kept in the [`Manifest`] for that.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+
+use crate::storage::immutable::{self, Point};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Cannot read manifest, error: {0}")]
+    CannotReadManifest(std::io::Error),
+    #[error("Cannot parse manifest, error: {0}")]
+    CannotParseManifest(serde_json::Error),
+    #[error("Cannot unpack archive, error: {0}")]
+    CannotUnpack(std::io::Error),
+    #[error("Cannot read directory, error: {0}")]
+    CannotReadDir(std::io::Error),
+    #[error("Cannot hash immutable file, error: {0}")]
+    CannotHashFile(std::io::Error),
+    #[error("Immutable files up to {expected} are required, found up to {found:?}")]
+    MissingImmutableFiles { expected: u64, found: Option<u64> },
+    #[error("Digest mismatch, expected {expected}, computed {computed}")]
+    DigestMismatch { expected: String, computed: String },
+    #[error("Cannot read immutable db, error: {0}")]
+    CannotReadImmutable(immutable::Error),
+}
+
+#[derive(Debug, Deserialize)]
+struct BeaconJson {
+    network: Option<String>,
+    epoch: u64,
+    immutable_file_number: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestJson {
+    digest: String,
+    network: Option<String>,
+    beacon: BeaconJson,
+    certificate_hash: Option<String>,
+}
+
+/// Description of a snapshot, as served by a Mithril aggregator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// Hex digest of the immutable files of the snapshot
+    pub digest: String,
+
+    /// Name of the network, e.g. `mainnet` or `preprod`
+    pub network: String,
+    pub epoch: u64,
+
+    /// Number of the last immutable file covered by the digest
+    pub immutable_file_number: u64,
+
+    /// Hash of the certificate that signs the digest, not verified here
+    pub certificate_hash: Option<String>,
+}
+
+impl Manifest {
+    /// Parses the JSON of the snapshot artifact of an aggregator
+    ///
+    /// Both the older layout, where the network is part of the beacon, and
+    /// the newer one, where it's a top-level field, are accepted.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let raw: ManifestJson = serde_json::from_str(json).map_err(Error::CannotParseManifest)?;
+
+        let network = raw.network.or(raw.beacon.network).ok_or_else(|| {
+            Error::CannotParseManifest(serde::de::Error::missing_field("network"))
+        })?;
+
+        Ok(Self {
+            digest: raw.digest,
+            network,
+            epoch: raw.beacon.epoch,
+            immutable_file_number: raw.beacon.immutable_file_number,
+            certificate_hash: raw.certificate_hash,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let json = std::fs::read_to_string(path).map_err(Error::CannotReadManifest)?;
+        Self::from_json(&json)
+    }
+
+    fn beacon_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.network.as_bytes());
+        hasher.update(self.epoch.to_be_bytes());
+        hasher.update(self.immutable_file_number.to_be_bytes());
+
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Lists the chunk, primary and secondary files of the db ordered by number
+/// and then by name, leaving out the files of the chunk still being written
+fn list_completed_files(dir: &Path) -> Result<Vec<(u64, PathBuf)>, Error> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)
+        .map_err(Error::CannotReadDir)?
+        .map_while(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.extension()
+                .and_then(|x| x.to_str())
+                .map(|x| matches!(x, "chunk" | "primary" | "secondary"))
+                .unwrap_or(false)
+        })
+        .filter_map(|p| {
+            let number = p.file_stem()?.to_str()?.parse::<u64>().ok()?;
+            Some((number, p))
+        })
+        .collect();
+
+    files.sort();
+
+    if let Some((last, _)) = files.last().cloned() {
+        files.retain(|(number, _)| *number < last);
+    }
+
+    Ok(files)
+}
+
+fn hash_file(path: &Path) -> Result<[u8; 32], Error> {
+    let mut file = File::open(path).map_err(Error::CannotHashFile)?;
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(Error::CannotHashFile)?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// Computes the Mithril digest of the immutable files of a db, up to the
+/// file number of the manifest
+///
+/// As in the immutable digester of the Mithril signers, the hex beacon hash is
+/// hashed along with the raw sha256 of each file.
+pub fn compute_digest(immutable_dir: &Path, manifest: &Manifest) -> Result<String, Error> {
+    let files = list_completed_files(immutable_dir)?;
+
+    let found = files.last().map(|(number, _)| *number);
+
+    match found {
+        Some(x) if x >= manifest.immutable_file_number => (),
+        _ => {
+            return Err(Error::MissingImmutableFiles {
+                expected: manifest.immutable_file_number,
+                found,
+            })
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(manifest.beacon_hash().as_bytes());
+
+    for (_, path) in files
+        .iter()
+        .take_while(|(number, _)| *number <= manifest.immutable_file_number)
+    {
+        debug!(?path, "hashing immutable file");
+        hasher.update(hash_file(path)?);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Checks that the immutable files of a db match the digest of the manifest
+pub fn verify(db_dir: &Path, manifest: &Manifest) -> Result<(), Error> {
+    let computed = compute_digest(&db_dir.join("immutable"), manifest)?;
+
+    if computed != manifest.digest {
+        return Err(Error::DigestMismatch {
+            expected: manifest.digest.clone(),
+            computed,
+        });
+    }
+
+    Ok(())
+}
+
+/// Unpacks a `tar.zst` snapshot archive into a directory
+pub fn unpack(archive: &Path, target: &Path) -> Result<(), Error> {
+    let file = File::open(archive).map_err(Error::CannotUnpack)?;
+    let decoder = zstd::Decoder::new(BufReader::new(file)).map_err(Error::CannotUnpack)?;
+
+    tar::Archive::new(decoder)
+        .unpack(target)
+        .map_err(Error::CannotUnpack)
+}
+
+/// A db restored from a verified snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedDb {
+    pub dir: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl ImportedDb {
+    /// Directory of the ImmutableDB, to be used with the readers of
+    /// [`immutable`]
+    pub fn immutable_dir(&self) -> PathBuf {
+        self.dir.join("immutable")
+    }
+
+    pub fn read_blocks(&self) -> Result<impl Iterator<Item = immutable::FallibleBlock>, Error> {
+        immutable::read_blocks(&self.immutable_dir()).map_err(Error::CannotReadImmutable)
+    }
+
+    pub fn get_tip(&self) -> Result<Option<Point>, Error> {
+        immutable::get_tip(&self.immutable_dir()).map_err(Error::CannotReadImmutable)
+    }
+}
+
+/// Unpacks a snapshot archive into a directory and verifies its immutable
+/// files against the manifest
+///
+/// The unpacked files are left in place when the verification fails, it's up
+/// to the caller to discard them.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use pallas_hardano::storage::mithril::{import, Manifest};
+///
+/// let manifest = Manifest::read(Path::new("snapshot.json")).unwrap();
+/// let db = import(Path::new("snapshot.tar.zst"), &manifest, Path::new("db")).unwrap();
+///
+/// for block in db.read_blocks().unwrap() {
+///     println!("{}", block.unwrap().len());
+/// }
+/// ```
+pub fn import(archive: &Path, manifest: &Manifest, target: &Path) -> Result<ImportedDb, Error> {
+    info!(?archive, ?target, "unpacking snapshot");
+    unpack(archive, target)?;
+
+    verify(target, manifest)?;
+    info!(digest = manifest.digest, "snapshot verified");
+
+    Ok(ImportedDb {
+        dir: target.to_owned(),
+        manifest: manifest.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{compute_digest, import, Error, Manifest};
+
+    const NAMES: [&str; 3] = ["01285", "01836", "02019"];
+
+    fn manifest(digest: &str) -> Manifest {
+        Manifest {
+            digest: digest.to_owned(),
+            network: "preview".to_owned(),
+            epoch: 539,
+            immutable_file_number: 1836,
+            certificate_hash: None,
+        }
+    }
+
+    fn build_archive(dir: &Path) -> std::path::PathBuf {
+        let path = dir.join("snapshot.tar.zst");
+        let file = std::fs::File::create(&path).unwrap();
+        let encoder = zstd::Encoder::new(file, 3).unwrap();
+        let mut builder = tar::Builder::new(encoder);
+
+        for name in NAMES {
+            for extension in ["chunk", "primary", "secondary"] {
+                let file = Path::new(name).with_extension(extension);
+                builder
+                    .append_path_with_name(
+                        Path::new("../test_data").join(&file),
+                        Path::new("immutable").join(&file),
+                    )
+                    .unwrap();
+            }
+        }
+
+        builder.into_inner().unwrap().finish().unwrap();
+
+        path
+    }
+
+    #[test]
+    fn parses_aggregator_manifests() {
+        let json = r#"{
+            "digest": "abcd",
+            "network": "preview",
+            "beacon": { "epoch": 539, "immutable_file_number": 1836 },
+            "certificate_hash": "ef01",
+            "size": 1000,
+            "locations": ["https://example.com/snapshot.tar.zst"],
+            "compression_algorithm": "zstandard"
+        }"#;
+
+        let mut expected = manifest("abcd");
+        expected.certificate_hash = Some("ef01".to_owned());
+
+        assert_eq!(Manifest::from_json(json).unwrap(), expected);
+
+        let json = r#"{
+            "digest": "abcd",
+            "beacon": { "network": "preview", "epoch": 539, "immutable_file_number": 1836 }
+        }"#;
+
+        assert_eq!(Manifest::from_json(json).unwrap(), manifest("abcd"));
+    }
+
+    #[test]
+    fn computes_the_mithril_digest() {
+        let digest = compute_digest(Path::new("../test_data"), &manifest("")).unwrap();
+
+        // computed with Python's hashlib, following the immutable digester of
+        // mithril-common step by step
+        assert_eq!(
+            digest,
+            "c2297e6797ce33869365c32f69e33399b63c75d5abb1b2ec57d0d5aad422bb5d"
+        );
+
+        let mut missing = manifest("");
+        missing.immutable_file_number = 2019;
+
+        match compute_digest(Path::new("../test_data"), &missing) {
+            Err(Error::MissingImmutableFiles { expected, found }) => {
+                assert_eq!(expected, 2019);
+                assert_eq!(found, Some(1836));
+            }
+            x => panic!("unexpected result {x:?}"),
+        }
+    }
+
+    #[test]
+    fn imports_a_verified_snapshot() {
+        let work = tempfile::tempdir().unwrap();
+        let archive = build_archive(work.path());
+
+        let digest = compute_digest(Path::new("../test_data"), &manifest("")).unwrap();
+
+        let target = work.path().join("db");
+        let db = import(&archive, &manifest(&digest), &target).unwrap();
+
+        let expected = crate::storage::immutable::read_blocks(Path::new("../test_data"))
+            .unwrap()
+            .count();
+
+        assert_eq!(db.read_blocks().unwrap().count(), expected);
+        assert!(db.get_tip().unwrap().is_some());
+
+        // a damaged file is detected
+        let path = target.join("immutable/01285.chunk");
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[1000] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        match import(&archive, &manifest("0000"), &work.path().join("other")) {
+            Err(Error::DigestMismatch { computed, .. }) => assert_eq!(computed, digest),
+            x => panic!("unexpected result {x:?}"),
+        }
+
+        assert!(matches!(
+            super::verify(&target, &manifest(&digest)),
+            Err(Error::DigestMismatch { .. })
+        ));
+    }
+}
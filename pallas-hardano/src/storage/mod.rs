@@ -1,3 +1,7 @@
 //! Storage compatible with the Haskell Cardano node implementation
 
 pub mod immutable;
+pub mod ledger;
+#[cfg(feature = "mithril")]
+pub mod mithril;
+pub mod volatile;
@@ -0,0 +1,652 @@
+//! Reader for the VolatileDB of the Haskell node
+//!
+//! The volatile db holds the most recent blocks, the ones that can still be
+//! rolled back, in `blocks-N.dat` files. Each file is a plain concatenation
+//! of the CBOR of the blocks in the order they were received, without any
+//! index, and it may contain blocks of several forks as well as blocks that
+//! were already copied to the ImmutableDB. The chain is rebuilt from the
+//! links between each block and its predecessor.
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    ffi::OsStr,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use pallas_codec::minicbor;
+use pallas_traverse::MultiEraBlock;
+use tracing::{debug, warn};
+
+use crate::storage::immutable::{self, chunk, Block, Point};
+
+pub type BlockHash = [u8; 32];
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Cannot find block by the provided point: {0:?}")]
+    CannotFindBlock(Point),
+    #[error("Cannot read directory, error: {0}")]
+    CannotReadDir(std::io::Error),
+    #[error("Cannot read blocks file, error: {0}")]
+    CannotReadFile(std::io::Error),
+    #[error("Cannot decode block, error: {0}")]
+    CannotDecodeBlock(pallas_traverse::Error),
+    #[error(transparent)]
+    ImmutableError(immutable::Error),
+    #[error(transparent)]
+    ChunkReadError(chunk::Error),
+}
+
+/// Location and chain data of a block stored in the volatile db
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub hash: BlockHash,
+    pub prev_hash: Option<BlockHash>,
+    pub slot: u64,
+    pub number: u64,
+
+    /// Number of the `blocks-N.dat` file that holds the block
+    pub file: u64,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl BlockInfo {
+    pub fn point(&self) -> Point {
+        Point::Specific(self.slot, self.hash.to_vec())
+    }
+}
+
+fn file_path(dir: &Path, number: u64) -> PathBuf {
+    dir.join(format!("blocks-{number}.dat"))
+}
+
+/// Numbers of the blocks files in the directory, in ascending order
+fn list_file_numbers(dir: &Path) -> Result<Vec<u64>, Error> {
+    let mut numbers: Vec<_> = std::fs::read_dir(dir)
+        .map_err(Error::CannotReadDir)?
+        .map_while(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension() == Some(OsStr::new("dat")))
+        .filter_map(|p| {
+            p.file_stem()?
+                .to_str()?
+                .strip_prefix("blocks-")?
+                .parse::<u64>()
+                .ok()
+        })
+        .collect();
+
+    numbers.sort();
+
+    Ok(numbers)
+}
+
+/// Parses the blocks of a single blocks file
+///
+/// A block that was only partially written when the node stopped ends the
+/// file, the node discards it the same way on startup.
+fn parse_file(dir: &Path, number: u64) -> Result<Vec<BlockInfo>, Error> {
+    let data = std::fs::read(file_path(dir, number)).map_err(Error::CannotReadFile)?;
+
+    let mut blocks = vec![];
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let mut decoder = minicbor::Decoder::new(&data[offset..]);
+
+        if decoder.skip().is_err() {
+            warn!(number, offset, "ignoring invalid data at the end of file");
+            break;
+        }
+
+        let size = decoder.position();
+
+        let Ok(block) = MultiEraBlock::decode(&data[offset..offset + size]) else {
+            warn!(number, offset, "ignoring invalid block at the end of file");
+            break;
+        };
+
+        let header = block.header();
+
+        blocks.push(BlockInfo {
+            hash: *block.hash(),
+            prev_hash: header.previous_hash().map(|x| *x),
+            slot: block.slot(),
+            number: block.number(),
+            file: number,
+            offset: offset as u64,
+            size: size as u64,
+        });
+
+        offset += size;
+    }
+
+    Ok(blocks)
+}
+
+/// In-memory index of the blocks in the volatile db
+pub struct VolatileDb {
+    dir: PathBuf,
+    blocks: HashMap<BlockHash, BlockInfo>,
+    successors: HashMap<Option<BlockHash>, Vec<BlockHash>>,
+}
+
+impl VolatileDb {
+    fn new(dir: &Path, blocks: Vec<BlockInfo>) -> Self {
+        let mut successors: HashMap<_, Vec<_>> = HashMap::new();
+
+        for block in blocks.iter() {
+            successors
+                .entry(block.prev_hash)
+                .or_default()
+                .push(block.hash);
+        }
+
+        Self {
+            dir: dir.to_owned(),
+            blocks: blocks.into_iter().map(|x| (x.hash, x)).collect(),
+            successors,
+        }
+    }
+
+    /// Builds the index by parsing all the blocks files of the directory
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        let mut blocks = vec![];
+
+        for number in list_file_numbers(dir)? {
+            debug!(number, "parsing volatile blocks file");
+            blocks.extend(parse_file(dir, number)?);
+        }
+
+        Ok(Self::new(dir, blocks))
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn block_info(&self, hash: &BlockHash) -> Option<&BlockInfo> {
+        self.blocks.get(hash)
+    }
+
+    /// Hashes of the blocks that extend the given one, `None` standing for
+    /// the genesis
+    pub fn successors(&self, hash: Option<&BlockHash>) -> &[BlockHash] {
+        self.successors
+            .get(&hash.copied())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Reads the CBOR of the given block from its blocks file
+    pub fn read_block(&self, info: &BlockInfo) -> Result<Block, Error> {
+        let mut file =
+            File::open(file_path(&self.dir, info.file)).map_err(Error::CannotReadFile)?;
+
+        file.seek(SeekFrom::Start(info.offset))
+            .map_err(Error::CannotReadFile)?;
+
+        let mut buf = vec![0u8; info.size as usize];
+        file.read_exact(&mut buf).map_err(Error::CannotReadFile)?;
+
+        Ok(buf)
+    }
+
+    pub fn get_block(&self, hash: &BlockHash) -> Result<Option<Block>, Error> {
+        self.blocks
+            .get(hash)
+            .map(|x| self.read_block(x))
+            .transpose()
+    }
+
+    /// Blocks that can start a chain extending the given one; without an
+    /// anchor, every block whose predecessor is not in the db
+    fn roots(&self, anchor: Option<&BlockHash>) -> Vec<BlockHash> {
+        match anchor {
+            Some(anchor) => self.successors(Some(anchor)).to_vec(),
+            None => self
+                .blocks
+                .values()
+                .filter(|x| !matches!(x.prev_hash, Some(prev) if self.blocks.contains_key(&prev)))
+                .map(|x| x.hash)
+                .collect(),
+        }
+    }
+
+    /// Selects the best chain that extends the given block
+    ///
+    /// The longest chain is selected. The node breaks ties between chains of
+    /// the same length with the Praos chain order, which looks at the
+    /// operational certificates and VRF outputs of the tip headers; that's
+    /// not done here, ties are broken in favor of the chain whose tip has the
+    /// lowest slot, which may not be the chain the node selected. Use
+    /// [`VolatileDb::best_tips`] to get every candidate. Without an anchor,
+    /// every block whose predecessor is not in the db is a candidate to start
+    /// the chain.
+    pub fn best_chain(&self, anchor: Option<&BlockHash>) -> Vec<&BlockInfo> {
+        // successors have a higher block number, or a higher slot in the case
+        // of EBBs, so they are always ranked before their predecessor
+        let mut order: Vec<_> = self.blocks.values().collect();
+        order.sort_by_key(|x| Reverse((x.number, x.slot)));
+
+        // length, tip slot and next block of the best chain from each block
+        let mut best: HashMap<BlockHash, (u64, Reverse<u64>, Option<BlockHash>)> = HashMap::new();
+
+        let pick = |best: &HashMap<_, (u64, Reverse<u64>, _)>, hashes: &[BlockHash]| {
+            hashes
+                .iter()
+                .filter_map(|x| best.get(x).map(|(len, slot, _)| ((*len, *slot), *x)))
+                .max_by_key(|(rank, hash)| (*rank, Reverse(*hash)))
+        };
+
+        for block in order {
+            let entry = match pick(&best, self.successors(Some(&block.hash))) {
+                Some(((len, slot), next)) => (len + 1, slot, Some(next)),
+                None => (1, Reverse(block.slot), None),
+            };
+
+            best.insert(block.hash, entry);
+        }
+
+        let roots = self.roots(anchor);
+
+        let mut chain = vec![];
+        let mut next = pick(&best, &roots).map(|(_, hash)| hash);
+
+        while let Some(hash) = next {
+            chain.push(&self.blocks[&hash]);
+            next = best[&hash].2;
+        }
+
+        chain
+    }
+
+    /// Tips of all the longest chains that extend the given block, in no
+    /// particular order
+    ///
+    /// When there's more than one, the chains have the same length and the
+    /// one selected by the node can't be told from the block lengths alone,
+    /// see [`VolatileDb::best_chain`].
+    pub fn best_tips(&self, anchor: Option<&BlockHash>) -> Vec<&BlockInfo> {
+        let mut order: Vec<_> = self.blocks.values().collect();
+        order.sort_by_key(|x| Reverse((x.number, x.slot)));
+
+        // length of the longest chain from each block
+        let mut lengths: HashMap<BlockHash, u64> = HashMap::new();
+
+        for block in order {
+            let len = self
+                .successors(Some(&block.hash))
+                .iter()
+                .filter_map(|x| lengths.get(x))
+                .max()
+                .map_or(1, |x| x + 1);
+
+            lengths.insert(block.hash, len);
+        }
+
+        let longest = |hashes: &[BlockHash]| {
+            let max = hashes.iter().filter_map(|x| lengths.get(x)).max().copied();
+
+            hashes
+                .iter()
+                .filter(|x| lengths.get(*x).copied() == max)
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        let mut tips = vec![];
+        let mut pending = longest(&self.roots(anchor));
+
+        while let Some(hash) = pending.pop() {
+            match lengths[&hash] {
+                1 => tips.push(&self.blocks[&hash]),
+                _ => pending.extend(longest(self.successors(Some(&hash)))),
+            }
+        }
+
+        tips
+    }
+}
+
+pub type FallibleBlock = Result<Block, Error>;
+
+/// The last block of the ImmutableDB, including the last chunk
+fn load_immutable_tip(immutable: &Path) -> Result<Option<BlockInfo>, Error> {
+    immutable::get_last_block(immutable)
+        .map_err(Error::ImmutableError)?
+        .map(|block| {
+            MultiEraBlock::decode(&block).map(|x| BlockInfo {
+                hash: *x.hash(),
+                prev_hash: x.header().previous_hash().map(|x| *x),
+                slot: x.slot(),
+                number: x.number(),
+                file: 0,
+                offset: 0,
+                size: block.len() as u64,
+            })
+        })
+        .transpose()
+        .map_err(Error::CannotDecodeBlock)
+}
+
+/// The immutable tip, including the last chunk, and the best volatile chain
+/// that extends it
+fn load_chain(
+    immutable: &Path,
+    volatile: &Path,
+) -> Result<(Option<BlockInfo>, VolatileDb, Vec<BlockInfo>), Error> {
+    let last = load_immutable_tip(immutable)?;
+
+    let db = VolatileDb::open(volatile)?;
+
+    let chain = db
+        .best_chain(last.as_ref().map(|x| &x.hash))
+        .into_iter()
+        .cloned()
+        .collect();
+
+    Ok((last, db, chain))
+}
+
+/// Retrieves the tip of the chain selected by the node
+///
+/// It's the tip of the best volatile chain that extends the immutable tip,
+/// or the immutable tip itself if there's none. Unlike
+/// [`immutable::get_tip`], the last chunk of the ImmutableDB is considered.
+///
+/// When several volatile chains have the same length, the node's choice
+/// can't be told apart and one of them is picked, see
+/// [`VolatileDb::best_chain`] and [`get_tips`].
+pub fn get_tip(immutable: &Path, volatile: &Path) -> Result<Option<Point>, Error> {
+    let (last, _, chain) = load_chain(immutable, volatile)?;
+
+    Ok(chain.last().or(last.as_ref()).map(BlockInfo::point))
+}
+
+/// Retrieves the tips of all the longest volatile chains that extend the
+/// immutable tip, one of which is the tip selected by the node
+///
+/// Returns the immutable tip alone if no volatile block extends it.
+pub fn get_tips(immutable: &Path, volatile: &Path) -> Result<Vec<Point>, Error> {
+    let last = load_immutable_tip(immutable)?;
+    let db = VolatileDb::open(volatile)?;
+
+    let tips = db.best_tips(last.as_ref().map(|x| &x.hash));
+
+    if tips.is_empty() {
+        Ok(last.iter().map(BlockInfo::point).collect())
+    } else {
+        Ok(tips.into_iter().map(BlockInfo::point).collect())
+    }
+}
+
+/// Returns an iterator over the chain selected by the node from the given
+/// point, going through the ImmutableDB first and the VolatileDB after it
+///
+/// Points are looked up the same way as in
+/// [`immutable::read_blocks_from_point`], including fuzzy searches for
+/// points without hash.
+pub fn read_blocks_from_point(
+    immutable: &Path,
+    volatile: &Path,
+    point: Point,
+) -> Result<Box<dyn Iterator<Item = FallibleBlock> + Send + Sync>, Error> {
+    let (last, db, chain) = load_chain(immutable, volatile)?;
+
+    let immutable_slot = last.as_ref().map(|x| x.slot);
+
+    let start = match &point {
+        Point::Origin => None,
+        Point::Specific(slot, hash) if hash.is_empty() => match immutable_slot {
+            Some(tip) if *slot <= tip => None,
+            _ => Some(chain.iter().position(|x| x.slot >= *slot)),
+        },
+        Point::Specific(slot, hash) => match immutable_slot {
+            Some(tip) if *slot <= tip => None,
+            _ => Some(chain.iter().position(|x| x.hash[..] == hash[..])),
+        },
+    };
+
+    match start {
+        Some(None) => Err(Error::CannotFindBlock(point)),
+        Some(Some(index)) => {
+            let chain = chain.into_iter().skip(index);
+            Ok(Box::new(chain.map(move |x| db.read_block(&x))))
+        }
+        None => {
+            let blocks = match immutable::read_all_blocks_from_point(immutable, point) {
+                Ok(x) => x.map(|x| x.map_err(Error::ChunkReadError)),
+                Err(immutable::Error::CannotFindBlock(x)) => return Err(Error::CannotFindBlock(x)),
+                Err(err) => return Err(Error::ImmutableError(err)),
+            };
+
+            let chain = chain.into_iter().map(move |x| db.read_block(&x));
+
+            Ok(Box::new(blocks.chain(chain)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pallas_traverse::MultiEraBlock;
+
+    use super::{BlockInfo, VolatileDb};
+    use crate::storage::immutable::{self, writer::Writer, Point};
+
+    fn block(hash: u8, prev: Option<u8>, number: u64) -> BlockInfo {
+        BlockInfo {
+            hash: [hash; 32],
+            prev_hash: prev.map(|x| [x; 32]),
+            slot: number * 10,
+            number,
+            file: 0,
+            offset: 0,
+            size: 0,
+        }
+    }
+
+    fn hashes(chain: Vec<&BlockInfo>) -> Vec<u8> {
+        chain.into_iter().map(|x| x.hash[0]).collect()
+    }
+
+    #[test]
+    fn selects_the_longest_fork() {
+        // 1 - 2 - 3 - 4
+        //      \
+        //       5 - 6 - 7 - 8
+        let db = VolatileDb::new(
+            Path::new("."),
+            vec![
+                block(1, Some(0), 1),
+                block(2, Some(1), 2),
+                block(3, Some(2), 3),
+                block(4, Some(3), 4),
+                block(5, Some(2), 3),
+                block(6, Some(5), 4),
+                block(7, Some(6), 5),
+                block(8, Some(7), 6),
+                // orphan blocks are not part of any chain
+                block(9, Some(42), 7),
+            ],
+        );
+
+        assert_eq!(db.successors(Some(&[2; 32])).len(), 2);
+
+        assert_eq!(
+            hashes(db.best_chain(Some(&[0; 32]))),
+            vec![1, 2, 5, 6, 7, 8]
+        );
+        assert_eq!(hashes(db.best_chain(Some(&[3; 32]))), vec![4]);
+        assert!(db.best_chain(Some(&[8; 32])).is_empty());
+        assert_eq!(hashes(db.best_chain(Some(&[42; 32]))), vec![9]);
+    }
+
+    #[test]
+    fn breaks_ties_by_tip_slot() {
+        let mut late = block(3, Some(1), 2);
+        late.slot = 25;
+
+        let db = VolatileDb::new(
+            Path::new("."),
+            vec![block(1, None, 1), late, block(2, Some(1), 2)],
+        );
+
+        assert_eq!(hashes(db.best_chain(None)), vec![1, 2]);
+
+        // both tips are candidates for the node's selection
+        let mut tips = hashes(db.best_tips(None));
+        tips.sort();
+        assert_eq!(tips, vec![2, 3]);
+    }
+
+    #[test]
+    fn returns_the_tips_of_all_the_longest_forks() {
+        // 1 - 2 - 3 - 4
+        //              //       5 - 6 - 7
+        //                //         8 - 9
+        let db = VolatileDb::new(
+            Path::new("."),
+            vec![
+                block(1, Some(0), 1),
+                block(2, Some(1), 2),
+                block(3, Some(2), 3),
+                block(4, Some(3), 4),
+                block(5, Some(2), 3),
+                block(6, Some(5), 4),
+                block(7, Some(6), 5),
+                block(8, Some(5), 4),
+                block(9, Some(8), 5),
+            ],
+        );
+
+        let mut tips = hashes(db.best_tips(Some(&[0; 32])));
+        tips.sort();
+        assert_eq!(tips, vec![7, 9]);
+
+        assert_eq!(hashes(db.best_tips(Some(&[3; 32]))), vec![4]);
+        assert!(db.best_tips(Some(&[9; 32])).is_empty());
+    }
+
+    /// Writes the first blocks of a chunk to an immutable db, and an
+    /// overlapping range to volatile blocks files, along with unrelated blocks
+    fn build_node_db() -> (tempfile::TempDir, Vec<Vec<u8>>) {
+        let blocks: Vec<_> = immutable::chunk::read_blocks(Path::new("../test_data"), "01836")
+            .unwrap()
+            .take(40)
+            .map(Result::unwrap)
+            .collect();
+
+        let dir = tempfile::tempdir().unwrap();
+        let volatile = dir.path().join("volatile");
+        std::fs::create_dir(&volatile).unwrap();
+
+        let mut writer = Writer::open(&dir.path().join("immutable")).unwrap();
+
+        for block in &blocks[..20] {
+            writer.append(block).unwrap();
+        }
+
+        writer.finish().unwrap();
+
+        // blocks that were already copied to the immutable db are kept by the
+        // node until they are garbage collected
+        std::fs::write(volatile.join("blocks-0.dat"), blocks[15..30].concat()).unwrap();
+        std::fs::write(volatile.join("blocks-1.dat"), blocks[30..].concat()).unwrap();
+
+        let unrelated: Vec<_> = immutable::chunk::read_blocks(Path::new("../test_data"), "01285")
+            .unwrap()
+            .take(5)
+            .map(Result::unwrap)
+            .collect();
+
+        // a trailing partial block is ignored
+        let mut data = unrelated.concat();
+        data.extend_from_slice(&blocks[0][..100]);
+        std::fs::write(volatile.join("blocks-2.dat"), data).unwrap();
+
+        (dir, blocks)
+    }
+
+    fn point(block: &[u8]) -> Point {
+        let block = MultiEraBlock::decode(block).unwrap();
+        Point::Specific(block.slot(), block.hash().to_vec())
+    }
+
+    #[test]
+    fn reads_the_node_tip() {
+        let (dir, blocks) = build_node_db();
+        let immutable = dir.path().join("immutable");
+        let volatile = dir.path().join("volatile");
+
+        let db = VolatileDb::open(&volatile).unwrap();
+        assert_eq!(db.len(), 30);
+
+        let tip = super::get_tip(&immutable, &volatile).unwrap();
+        assert_eq!(tip, Some(point(blocks.last().unwrap())));
+
+        let tips = super::get_tips(&immutable, &volatile).unwrap();
+        assert_eq!(tips, vec![point(blocks.last().unwrap())]);
+
+        // with an empty volatile db, the tip is in the last chunk
+        let empty = tempfile::tempdir().unwrap();
+        let tip = super::get_tip(&immutable, empty.path()).unwrap();
+        assert_eq!(tip, Some(point(&blocks[19])));
+
+        let tips = super::get_tips(&immutable, empty.path()).unwrap();
+        assert_eq!(tips, vec![point(&blocks[19])]);
+
+        // starting in the immutable db
+        let read: Vec<_> = super::read_blocks_from_point(&immutable, &volatile, point(&blocks[5]))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(read, blocks[5..]);
+
+        // starting in the volatile db
+        let read: Vec<_> = super::read_blocks_from_point(&immutable, &volatile, point(&blocks[25]))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(read, blocks[25..]);
+
+        // fuzzy search past the immutable tip
+        let Point::Specific(slot, _) = point(&blocks[32]) else {
+            unreachable!()
+        };
+
+        let read =
+            super::read_blocks_from_point(&immutable, &volatile, Point::Specific(slot, vec![]))
+                .unwrap()
+                .count();
+
+        assert_eq!(read, 8);
+
+        // blocks outside of the selected chain are not found
+        let unknown = point(
+            &immutable::read_blocks(Path::new("../test_data"))
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap(),
+        );
+
+        assert!(matches!(
+            super::read_blocks_from_point(&immutable, &volatile, unknown),
+            Err(super::Error::CannotFindBlock(_))
+        ));
+    }
+}
@@ -1,6 +1,8 @@
 use std::net::SocketAddr;
 use std::path::Path;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tracing::{debug, error};
 
@@ -12,15 +14,21 @@ use tokio::net::{unix::SocketAddr as UnixSocketAddr, UnixListener};
 use crate::miniprotocols::handshake::{n2c, n2n, Confirmation, VersionNumber};
 
 use crate::miniprotocols::{
-    blockfetch, chainsync, handshake, keepalive, localstate, localtxsubmission, txmonitor,
-    txsubmission, PROTOCOL_N2C_CHAIN_SYNC, PROTOCOL_N2C_HANDSHAKE, PROTOCOL_N2C_STATE_QUERY,
-    PROTOCOL_N2C_TX_MONITOR, PROTOCOL_N2C_TX_SUBMISSION, PROTOCOL_N2N_BLOCK_FETCH,
-    PROTOCOL_N2N_CHAIN_SYNC, PROTOCOL_N2N_HANDSHAKE, PROTOCOL_N2N_KEEP_ALIVE,
-    PROTOCOL_N2N_TX_SUBMISSION,
+    blockfetch, chainsync, handshake, keepalive, localstate, localtxsubmission, peersharing,
+    txmonitor, txsubmission, PROTOCOL_N2C_CHAIN_SYNC, PROTOCOL_N2C_HANDSHAKE,
+    PROTOCOL_N2C_STATE_QUERY, PROTOCOL_N2C_TX_MONITOR, PROTOCOL_N2C_TX_SUBMISSION,
+    PROTOCOL_N2N_BLOCK_FETCH, PROTOCOL_N2N_CHAIN_SYNC, PROTOCOL_N2N_HANDSHAKE,
+    PROTOCOL_N2N_KEEP_ALIVE, PROTOCOL_N2N_PEER_SHARING, PROTOCOL_N2N_TX_SUBMISSION,
 };
 
 use crate::multiplexer::{self, Bearer, RunningPlexer};
 
+pub mod follower;
+pub mod manager;
+
+pub use follower::ChainFollower;
+pub use manager::PeerManager;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("error in multiplexer")]
@@ -46,14 +54,41 @@ pub const DEFAULT_KEEP_ALIVE_INTERVAL_SEC: u64 = 20;
 
 pub type KeepAliveHandle = tokio::task::JoinHandle<Result<(), Error>>;
 
+/// Round-trip time of the latest keepalive exchange with a peer
+#[derive(Debug, Clone, Default)]
+pub struct KeepAliveLatency(Arc<AtomicU64>);
+
+impl KeepAliveLatency {
+    pub fn get(&self) -> Option<Duration> {
+        match self.0.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    fn set(&self, value: Duration) {
+        // zero is reserved for "not measured yet"
+        let micros = (value.as_micros() as u64).max(1);
+        self.0.store(micros, Ordering::Relaxed);
+    }
+}
+
 pub enum KeepAliveLoop {
-    Client(keepalive::Client, Duration),
+    Client(keepalive::Client, Duration, KeepAliveLatency),
     Server(keepalive::Server),
 }
 
 impl KeepAliveLoop {
     pub fn client(client: keepalive::Client, interval: Duration) -> Self {
-        Self::Client(client, interval)
+        Self::Client(client, interval, KeepAliveLatency::default())
+    }
+
+    /// Handle to the round-trip time measured by a client loop
+    pub fn latency(&self) -> Option<KeepAliveLatency> {
+        match self {
+            KeepAliveLoop::Client(_, _, latency) => Some(latency.clone()),
+            KeepAliveLoop::Server(_) => None,
+        }
     }
 
     pub fn server(server: keepalive::Server) -> Self {
@@ -63,6 +98,7 @@ impl KeepAliveLoop {
     pub async fn run_client(
         mut client: keepalive::Client,
         interval: Duration,
+        latency: KeepAliveLatency,
     ) -> Result<(), Error> {
         let mut interval = tokio::time::interval(interval);
 
@@ -70,10 +106,14 @@ impl KeepAliveLoop {
             interval.tick().await;
             debug!("sending keepalive request");
 
+            let start = Instant::now();
+
             client
                 .keepalive_roundtrip()
                 .await
                 .map_err(Error::KeepAliveClientLoop)?;
+
+            latency.set(start.elapsed());
         }
     }
 
@@ -90,8 +130,8 @@ impl KeepAliveLoop {
 
     pub fn spawn(self) -> KeepAliveHandle {
         match self {
-            KeepAliveLoop::Client(client, interval) => {
-                tokio::spawn(Self::run_client(client, interval))
+            KeepAliveLoop::Client(client, interval, latency) => {
+                tokio::spawn(Self::run_client(client, interval, latency))
             }
             KeepAliveLoop::Server(server) => tokio::spawn(Self::run_server(server)),
         }
@@ -102,9 +142,12 @@ impl KeepAliveLoop {
 pub struct PeerClient {
     pub plexer: RunningPlexer,
     pub keepalive: KeepAliveHandle,
+    pub latency: KeepAliveLatency,
     pub chainsync: chainsync::N2NClient,
     pub blockfetch: blockfetch::Client,
     pub txsubmission: txsubmission::Client,
+    pub peersharing: peersharing::Client,
+    version: (VersionNumber, n2n::VersionData),
 }
 
 impl PeerClient {
@@ -113,6 +156,33 @@ impl PeerClient {
             .await
             .map_err(Error::ConnectFailure)?;
 
+        Self::connect_bearer(bearer, magic).await
+    }
+
+    /// Connects using a custom handshake negotiation policy
+    pub async fn connect_with_policy(
+        addr: impl ToSocketAddrs,
+        policy: &handshake::N2NPolicy,
+    ) -> Result<Self, Error> {
+        let bearer = Bearer::connect_tcp(addr)
+            .await
+            .map_err(Error::ConnectFailure)?;
+
+        Self::connect_bearer_with_policy(bearer, policy).await
+    }
+
+    /// Runs the handshake over an already connected bearer
+    pub async fn connect_bearer(bearer: Bearer, magic: u64) -> Result<Self, Error> {
+        let policy = handshake::N2NPolicy::v7_and_above(magic);
+        Self::connect_bearer_with_policy(bearer, &policy).await
+    }
+
+    /// Runs the handshake over an already connected bearer using a custom
+    /// negotiation policy
+    pub async fn connect_bearer_with_policy(
+        bearer: Bearer,
+        policy: &handshake::N2NPolicy,
+    ) -> Result<Self, Error> {
         let mut plexer = multiplexer::Plexer::new(bearer);
 
         let channel = plexer.subscribe_client(PROTOCOL_N2N_HANDSHAKE);
@@ -121,36 +191,52 @@ impl PeerClient {
         let cs_channel = plexer.subscribe_client(PROTOCOL_N2N_CHAIN_SYNC);
         let bf_channel = plexer.subscribe_client(PROTOCOL_N2N_BLOCK_FETCH);
         let txsub_channel = plexer.subscribe_client(PROTOCOL_N2N_TX_SUBMISSION);
+        let ps_channel = plexer.subscribe_client(PROTOCOL_N2N_PEER_SHARING);
 
         let channel = plexer.subscribe_client(PROTOCOL_N2N_KEEP_ALIVE);
         let keepalive = keepalive::Client::new(channel);
 
         let plexer = plexer.spawn();
 
-        let versions = handshake::n2n::VersionTable::v7_and_above(magic);
-
         let handshake = handshake
-            .handshake(versions)
+            .handshake_with_policy(policy)
             .await
             .map_err(Error::HandshakeProtocol)?;
 
-        if let handshake::Confirmation::Rejected(reason) = handshake {
-            error!(?reason, "handshake refused");
-            return Err(Error::IncompatibleVersion);
-        }
+        let version = match handshake {
+            handshake::Confirmation::Accepted(version, data) => (version, data),
+            handshake::Confirmation::Rejected(reason) => {
+                error!(?reason, "handshake refused");
+                plexer.abort().await;
+                return Err(Error::IncompatibleVersion);
+            }
+            handshake::Confirmation::QueryReply(_) => {
+                error!("handshake query reply when we expected a version");
+                plexer.abort().await;
+                return Err(Error::HandshakeProtocol(handshake::Error::InvalidInbound));
+            }
+        };
 
         let keepalive = KeepAliveLoop::client(
             keepalive,
             Duration::from_secs(DEFAULT_KEEP_ALIVE_INTERVAL_SEC),
-        )
-        .spawn();
+        );
+
+        let latency = keepalive.latency().unwrap_or_default();
+        let keepalive = keepalive.spawn();
+
+        let mut chainsync = chainsync::Client::new(cs_channel);
+        chainsync.set_timeouts(chainsync::default_timeouts());
 
         let client = Self {
             plexer,
             keepalive,
-            chainsync: chainsync::Client::new(cs_channel),
+            latency,
+            chainsync,
             blockfetch: blockfetch::Client::new(bf_channel),
             txsubmission: txsubmission::Client::new(txsub_channel),
+            peersharing: peersharing::Client::new(ps_channel),
+            version,
         };
 
         Ok(client)
@@ -177,6 +263,20 @@ impl PeerClient {
         &mut self.txsubmission
     }
 
+    pub fn peersharing(&mut self) -> &mut peersharing::Client {
+        &mut self.peersharing
+    }
+
+    /// Version number and params agreed during the handshake
+    pub fn negotiated_version(&self) -> &(VersionNumber, n2n::VersionData) {
+        &self.version
+    }
+
+    /// Round-trip time of the latest keepalive exchange with the peer
+    pub fn keepalive_latency(&self) -> Option<Duration> {
+        self.latency.get()
+    }
+
     pub async fn abort(self) {
         self.plexer.abort().await
     }
@@ -190,6 +290,7 @@ pub struct PeerServer {
     pub blockfetch: blockfetch::Server,
     pub txsubmission: txsubmission::Server,
     pub keepalive: keepalive::Server,
+    pub peersharing: peersharing::Server,
     accepted_address: Option<SocketAddr>,
     accepted_version: Option<(u64, n2n::VersionData)>,
 }
@@ -203,12 +304,15 @@ impl PeerServer {
         let bf_channel = plexer.subscribe_server(PROTOCOL_N2N_BLOCK_FETCH);
         let txsub_channel = plexer.subscribe_server(PROTOCOL_N2N_TX_SUBMISSION);
         let keepalive_channel = plexer.subscribe_server(PROTOCOL_N2N_KEEP_ALIVE);
+        let ps_channel = plexer.subscribe_server(PROTOCOL_N2N_PEER_SHARING);
 
         let hs = handshake::N2NServer::new(hs_channel);
-        let cs = chainsync::N2NServer::new(cs_channel);
+        let mut cs = chainsync::N2NServer::new(cs_channel);
+        cs.set_timeouts(chainsync::default_timeouts());
         let bf = blockfetch::Server::new(bf_channel);
         let txsub = txsubmission::Server::new(txsub_channel);
         let keepalive = keepalive::Server::new(keepalive_channel);
+        let peersharing = peersharing::Server::new(ps_channel);
 
         let plexer = plexer.spawn();
 
@@ -219,6 +323,7 @@ impl PeerServer {
             blockfetch: bf,
             txsubmission: txsub,
             keepalive,
+            peersharing,
             accepted_address: None,
             accepted_version: None,
         }
@@ -229,16 +334,33 @@ impl PeerServer {
             .await
             .map_err(Error::ConnectFailure)?;
 
+        let mut client = Self::accept_bearer(bearer, magic).await?;
+        client.accepted_address = Some(address);
+
+        Ok(client)
+    }
+
+    /// Runs the handshake over an already accepted bearer
+    pub async fn accept_bearer(bearer: Bearer, magic: u64) -> Result<Self, Error> {
+        let policy = handshake::N2NPolicy::v7_and_above(magic);
+        Self::accept_bearer_with_policy(bearer, &policy).await
+    }
+
+    /// Runs the handshake over an already accepted bearer using a custom
+    /// negotiation policy
+    pub async fn accept_bearer_with_policy(
+        bearer: Bearer,
+        policy: &handshake::N2NPolicy,
+    ) -> Result<Self, Error> {
         let mut client = Self::new(bearer);
 
         let accepted_version = client
             .handshake()
-            .handshake(n2n::VersionTable::v7_and_above(magic))
+            .handshake_with_policy(policy)
             .await
             .map_err(Error::HandshakeProtocol)?;
 
         if let Some((version, data)) = accepted_version {
-            client.accepted_address = Some(address);
             client.accepted_version = Some((version, data));
             Ok(client)
         } else {
@@ -267,6 +389,10 @@ impl PeerServer {
         &mut self.keepalive
     }
 
+    pub fn peersharing(&mut self) -> &mut peersharing::Server {
+        &mut self.peersharing
+    }
+
     pub fn accepted_address(&self) -> Option<&SocketAddr> {
         self.accepted_address.as_ref()
     }
@@ -288,6 +414,7 @@ pub struct NodeClient {
     statequery: localstate::Client,
     submission: localtxsubmission::Client,
     monitor: txmonitor::Client,
+    version: Option<(VersionNumber, n2c::VersionData)>,
 }
 
 impl NodeClient {
@@ -309,31 +436,55 @@ impl NodeClient {
             statequery: localstate::Client::new(sq_channel),
             submission: localtxsubmission::Client::new(tx_channel),
             monitor: txmonitor::Client::new(mo_channel),
+            version: None,
         }
     }
 
-    #[cfg(unix)]
-    pub async fn connect(path: impl AsRef<Path>, magic: u64) -> Result<Self, Error> {
-        let bearer = Bearer::connect_unix(path)
-            .await
-            .map_err(Error::ConnectFailure)?;
+    /// Runs the handshake over an already connected bearer
+    pub async fn connect_bearer(bearer: Bearer, magic: u64) -> Result<Self, Error> {
+        let policy = handshake::N2CPolicy::v10_and_above(magic);
+        Self::connect_bearer_with_policy(bearer, &policy).await
+    }
 
+    /// Runs the handshake over an already connected bearer using a custom
+    /// negotiation policy
+    pub async fn connect_bearer_with_policy(
+        bearer: Bearer,
+        policy: &handshake::N2CPolicy,
+    ) -> Result<Self, Error> {
         let mut client = Self::new(bearer);
 
-        let versions = handshake::n2c::VersionTable::v10_and_above(magic);
-
         let handshake = client
             .handshake()
-            .handshake(versions)
+            .handshake_with_policy(policy)
             .await
             .map_err(Error::HandshakeProtocol)?;
 
-        if let handshake::Confirmation::Rejected(reason) = handshake {
-            error!(?reason, "handshake refused");
-            return Err(Error::IncompatibleVersion);
+        match handshake {
+            handshake::Confirmation::Accepted(version, data) => {
+                client.version = Some((version, data));
+                Ok(client)
+            }
+            handshake::Confirmation::Rejected(reason) => {
+                error!(?reason, "handshake refused");
+                client.abort().await;
+                Err(Error::IncompatibleVersion)
+            }
+            handshake::Confirmation::QueryReply(_) => {
+                error!("handshake query reply when we expected a version");
+                client.abort().await;
+                Err(Error::HandshakeProtocol(handshake::Error::InvalidInbound))
+            }
         }
+    }
 
-        Ok(client)
+    #[cfg(unix)]
+    pub async fn connect(path: impl AsRef<Path>, magic: u64) -> Result<Self, Error> {
+        let bearer = Bearer::connect_unix(path)
+            .await
+            .map_err(Error::ConnectFailure)?;
+
+        Self::connect_bearer(bearer, magic).await
     }
 
     #[cfg(windows)]
@@ -348,22 +499,7 @@ impl NodeClient {
             .expect("can't join tokio thread")
             .map_err(Error::ConnectFailure)?;
 
-        let mut client = Self::new(bearer);
-
-        let versions = handshake::n2c::VersionTable::v10_and_above(magic);
-
-        let handshake = client
-            .handshake()
-            .handshake(versions)
-            .await
-            .map_err(Error::HandshakeProtocol)?;
-
-        if let handshake::Confirmation::Rejected(reason) = handshake {
-            error!(?reason, "handshake refused");
-            return Err(Error::IncompatibleVersion);
-        }
-
-        Ok(client)
+        Self::connect_bearer(bearer, magic).await
     }
 
     #[cfg(unix)]
@@ -405,6 +541,12 @@ impl NodeClient {
         &mut self.handshake
     }
 
+    /// Version number and params agreed during the handshake, if it was run
+    /// by one of the `connect` functions
+    pub fn negotiated_version(&self) -> Option<&(VersionNumber, n2c::VersionData)> {
+        self.version.as_ref()
+    }
+
     pub fn chainsync(&mut self) -> &mut chainsync::N2CClient {
         &mut self.chainsync
     }
@@ -433,6 +575,8 @@ pub struct NodeServer {
     pub handshake: handshake::N2CServer,
     pub chainsync: chainsync::N2CServer,
     pub statequery: localstate::Server,
+    pub submission: localtxsubmission::Server,
+    pub monitor: txmonitor::Server,
     accepted_address: Option<UnixSocketAddr>,
     accpeted_version: Option<(VersionNumber, n2c::VersionData)>,
 }
@@ -445,10 +589,14 @@ impl NodeServer {
         let hs_channel = plexer.subscribe_server(PROTOCOL_N2C_HANDSHAKE);
         let cs_channel = plexer.subscribe_server(PROTOCOL_N2C_CHAIN_SYNC);
         let sq_channel = plexer.subscribe_server(PROTOCOL_N2C_STATE_QUERY);
+        let tx_channel = plexer.subscribe_server(PROTOCOL_N2C_TX_SUBMISSION);
+        let mo_channel = plexer.subscribe_server(PROTOCOL_N2C_TX_MONITOR);
 
         let server_hs = handshake::Server::<n2c::VersionData>::new(hs_channel);
         let server_cs = chainsync::N2CServer::new(cs_channel);
         let server_sq = localstate::Server::new(sq_channel);
+        let server_tx = localtxsubmission::Server::new(tx_channel);
+        let server_mo = txmonitor::Server::new(mo_channel);
 
         let plexer = plexer.spawn();
 
@@ -457,6 +605,8 @@ impl NodeServer {
             handshake: server_hs,
             chainsync: server_cs,
             statequery: server_sq,
+            submission: server_tx,
+            monitor: server_mo,
             accepted_address: None,
             accpeted_version: None,
         }
@@ -467,16 +617,33 @@ impl NodeServer {
             .await
             .map_err(Error::ConnectFailure)?;
 
+        let mut client = Self::accept_bearer(bearer, magic).await?;
+        client.accepted_address = Some(address);
+
+        Ok(client)
+    }
+
+    /// Runs the handshake over an already accepted bearer
+    pub async fn accept_bearer(bearer: Bearer, magic: u64) -> Result<Self, Error> {
+        let policy = handshake::N2CPolicy::v10_and_above(magic);
+        Self::accept_bearer_with_policy(bearer, &policy).await
+    }
+
+    /// Runs the handshake over an already accepted bearer using a custom
+    /// negotiation policy
+    pub async fn accept_bearer_with_policy(
+        bearer: Bearer,
+        policy: &handshake::N2CPolicy,
+    ) -> Result<Self, Error> {
         let mut client = Self::new(bearer).await;
 
         let accepted_version = client
             .handshake()
-            .handshake(n2c::VersionTable::v10_and_above(magic))
+            .handshake_with_policy(policy)
             .await
             .map_err(Error::HandshakeProtocol)?;
 
         if let Some(version) = accepted_version {
-            client.accepted_address = Some(address);
             client.accpeted_version = Some(version);
             Ok(client)
         } else {
@@ -497,6 +664,14 @@ impl NodeServer {
         &mut self.statequery
     }
 
+    pub fn submission(&mut self) -> &mut localtxsubmission::Server {
+        &mut self.submission
+    }
+
+    pub fn monitor(&mut self) -> &mut txmonitor::Server {
+        &mut self.monitor
+    }
+
     pub fn accepted_address(&self) -> Option<&UnixSocketAddr> {
         self.accepted_address.as_ref()
     }
@@ -0,0 +1,211 @@
+//! A chain follower that yields full blocks with rollback handling
+//!
+//! The [`ChainFollower`] wraps the loop that every chain consumer ends up
+//! writing: find an intersection, request the next chain-sync update, decode
+//! the header to find its point, fetch the block body and keep enough history
+//! to absorb rollbacks. Blocks are only yielded once they reach the configured
+//! confirmation depth, which means that rollbacks within that depth never
+//! reach the consumer.
+
+use std::collections::{HashMap, VecDeque};
+
+use pallas_traverse::{MultiEraBlock, MultiEraHeader};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::miniprotocols::chainsync::{
+    BlockContent, HeaderContent, NextResponse, RollbackBuffer, RollbackEffect,
+};
+use crate::miniprotocols::{blockfetch, chainsync, Point};
+
+use super::{NodeClient, PeerClient};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("chain-sync error")]
+    ChainSync(#[source] chainsync::ClientError),
+
+    #[error("block-fetch error")]
+    BlockFetch(#[source] blockfetch::ClientError),
+
+    #[error("can't decode header or block")]
+    InvalidContent(#[source] pallas_traverse::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// CBOR of a block that reached the confirmation depth, and its point
+    RollForward(Vec<u8>, Point),
+
+    /// Rollback to a point that was already yielded, or to the intersection
+    RollBack(Point),
+}
+
+enum Source {
+    Peer(PeerClient),
+    Node(NodeClient),
+}
+
+fn header_point(header: &HeaderContent) -> Result<Point, Error> {
+    let subtag = header.byron_prefix.map(|(x, _)| x);
+
+    let header = MultiEraHeader::decode(header.variant, subtag, &header.cbor)
+        .map_err(Error::InvalidContent)?;
+
+    Ok(Point::Specific(header.slot(), header.hash().to_vec()))
+}
+
+fn block_point(block: &BlockContent) -> Result<Point, Error> {
+    let block = MultiEraBlock::decode(&block.0).map_err(Error::InvalidContent)?;
+
+    Ok(Point::Specific(block.slot(), block.hash().to_vec()))
+}
+
+/// Follows the chain of a peer (headers + block-fetch) or a node (blocks)
+pub struct ChainFollower {
+    source: Source,
+    confirmations: usize,
+    buffer: RollbackBuffer,
+    bodies: HashMap<Point, Vec<u8>>,
+    ready: VecDeque<ChainEvent>,
+}
+
+impl ChainFollower {
+    fn new(source: Source) -> Self {
+        Self {
+            source,
+            confirmations: 0,
+            buffer: RollbackBuffer::new(),
+            bodies: HashMap::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Follows a N2N peer, fetching the body of each header via block-fetch
+    pub fn peer(client: PeerClient) -> Self {
+        Self::new(Source::Peer(client))
+    }
+
+    /// Follows a N2C node, which streams full blocks through chain-sync
+    pub fn node(client: NodeClient) -> Self {
+        Self::new(Source::Node(client))
+    }
+
+    /// Amount of blocks that need to be on top of a block before yielding it
+    pub fn with_confirmations(mut self, depth: usize) -> Self {
+        self.confirmations = depth;
+        self
+    }
+
+    pub async fn find_intersect(&mut self, points: Vec<Point>) -> Result<Option<Point>, Error> {
+        let (point, _) = match &mut self.source {
+            Source::Peer(client) => client.chainsync().find_intersect(points).await,
+            Source::Node(client) => client.chainsync().find_intersect(points).await,
+        }
+        .map_err(Error::ChainSync)?;
+
+        Ok(point)
+    }
+
+    /// Waits for the next event of the chain
+    pub async fn next(&mut self) -> Result<ChainEvent, Error> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Ok(event);
+            }
+
+            self.advance().await?;
+        }
+    }
+
+    async fn advance(&mut self) -> Result<(), Error> {
+        match &mut self.source {
+            Source::Peer(client) => {
+                match client
+                    .chainsync()
+                    .request_or_await_next()
+                    .await
+                    .map_err(Error::ChainSync)?
+                {
+                    NextResponse::RollForward(header, _) => {
+                        let point = header_point(&header)?;
+                        self.buffer.roll_forward(point);
+                    }
+                    NextResponse::RollBackward(point, _) => self.roll_back(point),
+                    NextResponse::Await => (),
+                }
+            }
+            Source::Node(client) => {
+                match client
+                    .chainsync()
+                    .request_or_await_next()
+                    .await
+                    .map_err(Error::ChainSync)?
+                {
+                    NextResponse::RollForward(block, _) => {
+                        let point = block_point(&block)?;
+                        self.bodies.insert(point.clone(), block.0);
+                        self.buffer.roll_forward(point);
+                    }
+                    NextResponse::RollBackward(point, _) => self.roll_back(point),
+                    NextResponse::Await => (),
+                }
+            }
+        }
+
+        self.confirm().await
+    }
+
+    fn roll_back(&mut self, point: Point) {
+        match self.buffer.roll_back(&point) {
+            RollbackEffect::Handled => {
+                debug!(?point, "rollback handled within confirmation depth");
+
+                let buffer = &self.buffer;
+                self.bodies.retain(|x, _| buffer.position(x).is_some());
+            }
+            RollbackEffect::OutOfScope => {
+                self.bodies.clear();
+                self.ready.push_back(ChainEvent::RollBack(point));
+            }
+        }
+    }
+
+    /// Yields the blocks that reached the confirmation depth
+    ///
+    /// Points are only taken out of the buffer once their body is at hand, so
+    /// that a failed fetch is retried on the next call instead of skipping
+    /// the block.
+    async fn confirm(&mut self) -> Result<(), Error> {
+        while self.buffer.size() > self.confirmations {
+            let point = self.buffer.oldest().cloned().expect("buffer isn't empty");
+
+            let body = match &mut self.source {
+                // block-fetch is delayed until confirmation, so that blocks
+                // that are rolled back are never downloaded
+                Source::Peer(client) => client
+                    .blockfetch()
+                    .fetch_single(point.clone())
+                    .await
+                    .map_err(Error::BlockFetch)?,
+                Source::Node(_) => self
+                    .bodies
+                    .remove(&point)
+                    .expect("buffered point without body"),
+            };
+
+            self.buffer.pop_oldest();
+            self.ready.push_back(ChainEvent::RollForward(body, point));
+        }
+
+        Ok(())
+    }
+
+    /// Stops following and closes the underlying connection
+    pub async fn abort(self) {
+        match self.source {
+            Source::Peer(client) => client.abort().await,
+            Source::Node(client) => client.abort().await,
+        }
+    }
+}
@@ -0,0 +1,644 @@
+//! Management of a set of N2N peers
+//!
+//! The [`PeerManager`] keeps a connection open to each of the known peers,
+//! reconnecting with exponential backoff when a connection fails. Peers are
+//! ranked by the round-trip time of their keepalive exchanges, which is used to
+//! pick the peer that serves each request.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::miniprotocols::chainsync::{HeaderContent, NextResponse, Tip};
+use crate::miniprotocols::handshake::{self, n2n};
+use crate::miniprotocols::peersharing::PeerAddress;
+use crate::miniprotocols::{blockfetch, chainsync, peersharing, Point};
+use crate::multiplexer::RunningPlexer;
+
+use super::{KeepAliveHandle, KeepAliveLatency, PeerClient};
+
+/// How often a connection is checked for a finished keepalive loop
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Capacity of the event channel of a chain follower
+const FOLLOW_BUFFER_SIZE: usize = 50;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no connected peers available")]
+    NoPeersAvailable,
+
+    #[error("block not found in any of the connected peers")]
+    BlockNotFound,
+
+    #[error("timeout waiting for peers to connect")]
+    Timeout,
+}
+
+#[derive(Debug, Clone)]
+pub struct PeerManagerConfig {
+    pub magic: u64,
+
+    /// Max time to wait for the bearer and the handshake of a connection
+    pub connect_timeout: Duration,
+
+    /// Delay before the first reconnection attempt
+    pub min_backoff: Duration,
+
+    /// Upper bound for the delay between reconnection attempts
+    pub max_backoff: Duration,
+
+    /// Amount of peers to request from each new connection via peer sharing,
+    /// `None` disables discovery
+    ///
+    /// Peer sharing is proposed during the handshake when enabled, and peers
+    /// are only asked for addresses if they agree to it.
+    pub peer_sharing: Option<peersharing::Amount>,
+
+    /// Max amount of peers to manage, including the ones discovered
+    pub max_peers: usize,
+}
+
+impl PeerManagerConfig {
+    pub fn new(magic: u64) -> Self {
+        Self {
+            magic,
+            connect_timeout: Duration::from_secs(10),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            peer_sharing: None,
+            max_peers: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerStatus {
+    Connecting,
+    Connected,
+
+    /// Waiting the given delay before the next connection attempt
+    Backoff(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub address: String,
+    pub status: PeerStatus,
+    pub latency: Option<Duration>,
+}
+
+/// The live resources of a connection to a peer
+struct Connection {
+    plexer: RunningPlexer,
+    keepalive: KeepAliveHandle,
+    latency: KeepAliveLatency,
+    chainsync: tokio::sync::Mutex<chainsync::N2NClient>,
+    blockfetch: tokio::sync::Mutex<blockfetch::Client>,
+    broken: AtomicBool,
+    broken_notify: Notify,
+
+    /// Whether the peer agreed to share peers during the handshake
+    shares_peers: bool,
+}
+
+impl Connection {
+    fn new(client: PeerClient) -> (Self, peersharing::Client) {
+        let (_, version) = client.negotiated_version();
+        let shares_peers = version.peer_sharing() == Some(n2n::PEER_SHARING_ENABLED);
+
+        let connection = Self {
+            plexer: client.plexer,
+            keepalive: client.keepalive,
+            latency: client.latency,
+            chainsync: tokio::sync::Mutex::new(client.chainsync),
+            blockfetch: tokio::sync::Mutex::new(client.blockfetch),
+            broken: AtomicBool::new(false),
+            broken_notify: Notify::new(),
+            shares_peers,
+        };
+
+        (connection, client.peersharing)
+    }
+
+    /// Signals the supervisor that the connection needs to be replaced
+    ///
+    /// The connection is left out of the ranking from now on, so that it's not
+    /// handed out again while the supervisor replaces it.
+    fn mark_broken(&self) {
+        self.broken.store(true, Ordering::Release);
+        self.broken_notify.notify_one();
+    }
+
+    fn is_broken(&self) -> bool {
+        self.broken.load(Ordering::Acquire) || self.keepalive.is_finished()
+    }
+
+    async fn wait_broken(&self) {
+        loop {
+            tokio::select! {
+                _ = self.broken_notify.notified() => return,
+                _ = tokio::time::sleep(HEALTH_CHECK_INTERVAL) => {
+                    if self.keepalive.is_finished() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.keepalive.abort();
+        self.plexer.abort_tasks();
+    }
+}
+
+struct Peer {
+    address: String,
+    status: RwLock<PeerStatus>,
+    connection: RwLock<Option<Arc<Connection>>>,
+}
+
+impl Peer {
+    fn new(address: String) -> Self {
+        Self {
+            address,
+            status: RwLock::new(PeerStatus::Connecting),
+            connection: RwLock::new(None),
+        }
+    }
+
+    fn set_status(&self, status: PeerStatus) {
+        *self.status.write().expect("poisoned peer") = status;
+    }
+
+    fn set_connection(&self, connection: Option<Arc<Connection>>) {
+        *self.connection.write().expect("poisoned peer") = connection;
+    }
+
+    fn connection(&self) -> Option<Arc<Connection>> {
+        self.connection.read().expect("poisoned peer").clone()
+    }
+
+    fn info(&self) -> PeerInfo {
+        PeerInfo {
+            address: self.address.clone(),
+            status: self.status.read().expect("poisoned peer").clone(),
+            latency: self.connection().and_then(|x| x.latency.get()),
+        }
+    }
+}
+
+struct Inner {
+    config: PeerManagerConfig,
+    peers: RwLock<HashMap<String, Arc<Peer>>>,
+    supervisors: Mutex<Vec<JoinHandle<()>>>,
+    connected: Notify,
+}
+
+impl Inner {
+    fn add_peer(self: &Arc<Self>, address: String) -> bool {
+        let peer = {
+            let mut peers = self.peers.write().expect("poisoned manager");
+
+            if peers.contains_key(&address) || peers.len() >= self.config.max_peers {
+                return false;
+            }
+
+            let peer = Arc::new(Peer::new(address.clone()));
+            peers.insert(address, peer.clone());
+            peer
+        };
+
+        let supervisor = tokio::spawn(supervise(self.clone(), peer));
+
+        self.supervisors
+            .lock()
+            .expect("poisoned manager")
+            .push(supervisor);
+
+        true
+    }
+
+    /// Live connections, sorted from the lowest to the highest latency
+    ///
+    /// Connections that haven't completed a keepalive exchange yet go last,
+    /// broken ones are skipped until they are replaced.
+    fn ranked_connections(&self) -> Vec<(Arc<Peer>, Arc<Connection>)> {
+        let mut ranked: Vec<_> = self
+            .peers
+            .read()
+            .expect("poisoned manager")
+            .values()
+            .filter_map(|peer| peer.connection().map(|x| (peer.clone(), x)))
+            .filter(|(_, connection)| !connection.is_broken())
+            .collect();
+
+        ranked.sort_by_key(|(_, connection)| match connection.latency.get() {
+            Some(latency) => (false, latency),
+            None => (true, Duration::ZERO),
+        });
+
+        ranked
+    }
+
+    async fn wait_connected(&self) {
+        loop {
+            let connected = self.connected.notified();
+
+            if !self.ranked_connections().is_empty() {
+                return;
+            }
+
+            connected.await;
+        }
+    }
+}
+
+/// Handshake policy of the connections, proposing peer sharing on the
+/// versions that support it when discovery is enabled
+fn handshake_policy(config: &PeerManagerConfig) -> handshake::N2NPolicy {
+    let mut versions = n2n::VersionTable::v7_and_above(config.magic);
+
+    if config.peer_sharing.is_some() {
+        for data in versions.values.values_mut() {
+            if data.peer_sharing().is_some() {
+                *data = n2n::VersionData::new(
+                    data.network_magic(),
+                    data.initiator_only_diffusion_mode(),
+                    Some(n2n::PEER_SHARING_ENABLED),
+                    data.query(),
+                );
+            }
+        }
+    }
+
+    handshake::N2NPolicy::new(versions).with_acceptor(n2n::VersionData::negotiate)
+}
+
+async fn connect(
+    config: &PeerManagerConfig,
+    address: &str,
+) -> Result<(Connection, peersharing::Client), super::Error> {
+    let policy = handshake_policy(config);
+
+    let client = tokio::time::timeout(
+        config.connect_timeout,
+        PeerClient::connect_with_policy(address, &policy),
+    )
+    .await
+    .map_err(|_| {
+        super::Error::ConnectFailure(tokio::io::Error::new(
+            tokio::io::ErrorKind::TimedOut,
+            "connection timeout",
+        ))
+    })??;
+
+    Ok(Connection::new(client))
+}
+
+fn peer_address_to_string(address: PeerAddress) -> String {
+    match address {
+        PeerAddress::V4(ip, port) => SocketAddr::from((ip, port)).to_string(),
+        PeerAddress::V6(ip, port) => SocketAddr::from((ip, port)).to_string(),
+    }
+}
+
+async fn discover(
+    inner: &Arc<Inner>,
+    connection: &Connection,
+    client: &mut peersharing::Client,
+    amount: u8,
+) {
+    let addresses = match tokio::time::timeout(
+        inner.config.connect_timeout,
+        client.request_peers(amount),
+    )
+    .await
+    {
+        Ok(Ok(x)) => x,
+        Ok(Err(err)) => {
+            debug!(?err, "peer sharing request failed");
+            return;
+        }
+        Err(_) => {
+            // the reply might still arrive, which would break the protocol
+            // state, so the connection is replaced
+            warn!("peer sharing request timed out, reconnecting");
+            connection.mark_broken();
+            return;
+        }
+    };
+
+    for address in addresses.into_iter().map(peer_address_to_string) {
+        if inner.add_peer(address.clone()) {
+            info!(address, "discovered peer");
+        }
+    }
+}
+
+/// Keeps a connection to the peer alive for as long as the manager exists
+async fn supervise(inner: Arc<Inner>, peer: Arc<Peer>) {
+    let config = &inner.config;
+    let mut backoff = config.min_backoff;
+
+    loop {
+        peer.set_status(PeerStatus::Connecting);
+
+        match connect(config, &peer.address).await {
+            Ok((connection, mut peersharing)) => {
+                info!(address = peer.address, "peer connected");
+                backoff = config.min_backoff;
+
+                let connection = Arc::new(connection);
+                peer.set_connection(Some(connection.clone()));
+                peer.set_status(PeerStatus::Connected);
+                inner.connected.notify_waiters();
+
+                match config.peer_sharing {
+                    Some(amount) if connection.shares_peers => {
+                        discover(&inner, &connection, &mut peersharing, amount).await;
+                    }
+                    Some(_) => debug!(address = peer.address, "peer doesn't share peers"),
+                    None => (),
+                }
+
+                connection.wait_broken().await;
+                warn!(address = peer.address, "peer disconnected");
+
+                peer.set_connection(None);
+                connection.plexer.abort_tasks();
+            }
+            Err(err) => {
+                warn!(address = peer.address, ?err, "peer connection failed");
+            }
+        }
+
+        debug!(
+            address = peer.address,
+            ?backoff,
+            "waiting before reconnecting"
+        );
+        peer.set_status(PeerStatus::Backoff(backoff));
+        tokio::time::sleep(backoff).await;
+
+        backoff = (backoff * 2).min(config.max_backoff);
+    }
+}
+
+#[derive(Debug)]
+pub enum FollowEvent {
+    RollForward(HeaderContent, Tip),
+    RollBackward(Point, Tip),
+
+    /// The follower switched to a new peer, the next event is the rollback to
+    /// the intersection with the latest checkpoint
+    PeerChanged(String),
+}
+
+/// A chain-sync session that moves across peers when they fail
+pub struct ChainFollow {
+    events: mpsc::Receiver<FollowEvent>,
+    checkpoint: Arc<Mutex<Vec<Point>>>,
+    task: JoinHandle<()>,
+}
+
+impl ChainFollow {
+    pub async fn next(&mut self) -> Option<FollowEvent> {
+        self.events.recv().await
+    }
+
+    /// Sets the points used to find the intersection when switching peers
+    ///
+    /// Consumers should call this as they process events, otherwise the
+    /// follower re-syncs from the points it was started with.
+    pub fn checkpoint(&self, points: Vec<Point>) {
+        *self.checkpoint.lock().expect("poisoned follower") = points;
+    }
+
+    pub fn abort(self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for ChainFollow {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Follows the chain of a single peer until it fails or the receiver is gone
+async fn follow_peer(
+    client: &mut chainsync::N2NClient,
+    points: Vec<Point>,
+    events: &mpsc::Sender<FollowEvent>,
+) -> Result<(), chainsync::ClientError> {
+    // a previous follower might have been dropped mid-exchange
+    if client.state() != &chainsync::State::Idle {
+        return Err(chainsync::ClientError::AgencyIsTheirs);
+    }
+
+    let (point, _) = client.find_intersect(points).await?;
+
+    if point.is_none() {
+        warn!("no intersection found, following from origin");
+    }
+
+    loop {
+        let event = match client.request_or_await_next().await? {
+            NextResponse::RollForward(header, tip) => FollowEvent::RollForward(header, tip),
+            NextResponse::RollBackward(point, tip) => FollowEvent::RollBackward(point, tip),
+            NextResponse::Await => continue,
+        };
+
+        if events.send(event).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+async fn follow(
+    inner: Arc<Inner>,
+    checkpoint: Arc<Mutex<Vec<Point>>>,
+    events: mpsc::Sender<FollowEvent>,
+) {
+    loop {
+        let Some((peer, connection)) = inner.ranked_connections().into_iter().next() else {
+            inner.wait_connected().await;
+            continue;
+        };
+
+        if events
+            .send(FollowEvent::PeerChanged(peer.address.clone()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        let points = checkpoint.lock().expect("poisoned follower").clone();
+        let mut client = connection.chainsync.lock().await;
+
+        match follow_peer(&mut client, points, &events).await {
+            Ok(()) => return,
+            Err(err) => {
+                warn!(
+                    address = peer.address,
+                    ?err,
+                    "chain follow failed, switching peer"
+                );
+                connection.mark_broken();
+            }
+        }
+    }
+}
+
+/// Owns the connections to a set of N2N peers
+pub struct PeerManager(Arc<Inner>);
+
+impl PeerManager {
+    pub fn new(config: PeerManagerConfig) -> Self {
+        let inner = Inner {
+            config,
+            peers: Default::default(),
+            supervisors: Default::default(),
+            connected: Notify::new(),
+        };
+
+        Self(Arc::new(inner))
+    }
+
+    /// Creates a manager that connects to a static list of peers
+    pub fn with_peers(
+        config: PeerManagerConfig,
+        addresses: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let manager = Self::new(config);
+
+        for address in addresses {
+            manager.add_peer(address);
+        }
+
+        manager
+    }
+
+    /// Starts managing a peer, returns false if it was already known or the
+    /// max amount of peers was reached
+    pub fn add_peer(&self, address: impl Into<String>) -> bool {
+        self.0.add_peer(address.into())
+    }
+
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        self.0
+            .peers
+            .read()
+            .expect("poisoned manager")
+            .values()
+            .map(|x| x.info())
+            .collect()
+    }
+
+    /// Address of the connected peer with the lowest latency
+    pub fn best_peer(&self) -> Option<String> {
+        self.0
+            .ranked_connections()
+            .into_iter()
+            .next()
+            .map(|(peer, _)| peer.address.clone())
+    }
+
+    /// Waits until at least one of the peers is connected
+    pub async fn wait_connected(&self, timeout: Duration) -> Result<(), Error> {
+        tokio::time::timeout(timeout, self.0.wait_connected())
+            .await
+            .map_err(|_| Error::Timeout)
+    }
+
+    /// Fetches a block from the best peer, falling back to the next ones when
+    /// the block is missing or the peer fails
+    pub async fn fetch_block(&self, point: Point) -> Result<blockfetch::Body, Error> {
+        let ranked = self.0.ranked_connections();
+
+        if ranked.is_empty() {
+            return Err(Error::NoPeersAvailable);
+        }
+
+        let mut not_found = false;
+
+        for (peer, connection) in ranked {
+            let mut client = connection.blockfetch.lock().await;
+
+            match client.fetch_single(point.clone()).await {
+                Ok(body) => return Ok(body),
+                Err(blockfetch::ClientError::NoBlocks) => {
+                    debug!(address = peer.address, "block not found in peer");
+                    not_found = true;
+                }
+                Err(err) => {
+                    warn!(address = peer.address, ?err, "block fetch failed");
+                    connection.mark_broken();
+                }
+            }
+        }
+
+        match not_found {
+            true => Err(Error::BlockNotFound),
+            false => Err(Error::NoPeersAvailable),
+        }
+    }
+
+    /// Follows the chain from the best available peer, starting at the
+    /// intersection with the given points
+    ///
+    /// If the peer fails, the follower moves to the next best one, emitting a
+    /// [`FollowEvent::PeerChanged`] event.
+    pub fn follow_chain(&self, points: Vec<Point>) -> ChainFollow {
+        let (sender, events) = mpsc::channel(FOLLOW_BUFFER_SIZE);
+        let checkpoint = Arc::new(Mutex::new(points));
+
+        let task = tokio::spawn(follow(self.0.clone(), checkpoint.clone(), sender));
+
+        ChainFollow {
+            events,
+            checkpoint,
+            task,
+        }
+    }
+
+    /// Stops the supervisors and closes all of the connections
+    pub fn shutdown(&self) {
+        for supervisor in self
+            .0
+            .supervisors
+            .lock()
+            .expect("poisoned manager")
+            .drain(..)
+        {
+            supervisor.abort();
+        }
+
+        for peer in self.0.peers.read().expect("poisoned manager").values() {
+            if let Some(connection) = peer.connection() {
+                connection.keepalive.abort();
+                connection.plexer.abort_tasks();
+            }
+
+            peer.set_connection(None);
+        }
+    }
+}
+
+impl Drop for PeerManager {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -1,10 +1,12 @@
+use std::time::Instant;
+
 use thiserror::Error;
 use tracing::{debug, warn};
 
-use crate::miniprotocols::common::Point;
+use crate::miniprotocols::common::{Point, StateTimeouts};
 use crate::multiplexer;
 
-use super::{Message, State};
+use super::{default_timeouts, Message, State};
 
 #[derive(Error, Debug)]
 pub enum ClientError {
@@ -23,6 +25,9 @@ pub enum ClientError {
     #[error("requested range doesn't contain any blocks")]
     NoBlocks,
 
+    #[error("received blocks don't match the requested range")]
+    RangeMismatch,
+
     #[error("error while sending or receiving data through the multiplexer")]
     Plexer(multiplexer::Error),
 }
@@ -38,7 +43,7 @@ pub type HasBlocks = Option<()>;
 /// This struct is used to interact with the Cardano network and fetch blocks
 /// from a remote node. It handles the state transitions and message exchange
 /// required to communicate with the network using the BlockFetch mini-protocol.
-pub struct Client(State, multiplexer::ChannelBuffer);
+pub struct Client(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
 
 impl Client {
     /// Create a new BlockFetch client from a multiplexer agent channel.
@@ -48,7 +53,21 @@ impl Client {
     /// * `channel` - A multiplexer agent channel used for communication with
     ///   the remote node.
     pub fn new(channel: multiplexer::AgentChannel) -> Self {
-        Self(State::Idle, multiplexer::ChannelBuffer::new(channel))
+        Self(
+            State::Idle,
+            multiplexer::ChannelBuffer::new(channel),
+            default_timeouts(),
+        )
+    }
+
+    /// Set the max time to wait for the server in each of its states.
+    /// Defaults to the limits of the network spec.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeouts` - The timeouts for the `Busy` and `Streaming` states.
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
     }
 
     /// Get the current state of the client.
@@ -121,6 +140,7 @@ impl Client {
 
     pub async fn recv_message(&mut self) -> Result<Message, ClientError> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(ClientError::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
@@ -157,10 +177,19 @@ impl Client {
     ///
     /// * `range` - A tuple of two `Point` instances representing the start and
     ///   end of the requested block range.
+    ///
+    /// The time until the server starts the batch (or reports that there are
+    /// no blocks) is reported as the round-trip time of the exchange.
     pub async fn request_range(&mut self, range: Range) -> Result<HasBlocks, ClientError> {
+        let start = Instant::now();
+
         self.send_request_range(range).await?;
         debug!("range requested");
-        self.recv_while_busy().await
+        let has_blocks = self.recv_while_busy().await?;
+
+        self.1.record_round_trip(start.elapsed());
+
+        Ok(has_blocks)
     }
 
     /// Receive blocks while the client is in the `Streaming` state.
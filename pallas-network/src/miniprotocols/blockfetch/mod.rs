@@ -3,8 +3,10 @@
 mod client;
 mod codec;
 mod protocol;
+mod scheduler;
 mod server;
 
 pub use client::*;
 pub use protocol::*;
+pub use scheduler::*;
 pub use server::*;
@@ -1,4 +1,4 @@
-use crate::miniprotocols::Point;
+use crate::miniprotocols::{Point, StateTimeouts, LONG_WAIT};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum State {
@@ -8,6 +8,13 @@ pub enum State {
     Done,
 }
 
+/// Time limits that the network spec sets on the states of the protocol
+pub fn default_timeouts() -> StateTimeouts<State> {
+    StateTimeouts::new()
+        .with(&State::Busy, LONG_WAIT)
+        .with(&State::Streaming, LONG_WAIT)
+}
+
 #[derive(Debug)]
 pub enum Message {
     RequestRange { range: (Point, Point) },
@@ -0,0 +1,387 @@
+//! Scheduling of block ranges across the block-fetch clients of several peers
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use pallas_traverse::MultiEraBlock;
+use thiserror::Error;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::miniprotocols::Point;
+
+use super::{Body, Client, ClientError, Range};
+
+#[derive(Error, Debug)]
+pub enum SchedulerError {
+    #[error("no peers to fetch blocks from")]
+    NoPeers,
+
+    #[error("range {0:?} couldn't be fetched from any peer")]
+    RangeFailed(Range, #[source] ClientError),
+
+    #[error("range {0:?} has no peers left to retry it")]
+    PeersExhausted(Range),
+}
+
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// Amount of blocks requested in each range
+    pub range_size: usize,
+
+    /// Max amount of ranges that can be fetched ahead of the consumer, at
+    /// least one is always fetched
+    pub max_in_flight: usize,
+
+    /// Max amount of peers that are asked for the same range
+    pub max_attempts: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            range_size: 50,
+            max_in_flight: 10,
+            max_attempts: 3,
+        }
+    }
+}
+
+struct Job {
+    index: usize,
+    points: Vec<Point>,
+    tried: Vec<usize>,
+}
+
+impl Job {
+    fn range(&self) -> Range {
+        let first = self.points.first().cloned().unwrap_or(Point::Origin);
+        let last = self.points.last().cloned().unwrap_or(Point::Origin);
+        (first, last)
+    }
+}
+
+type JobResult = (usize, Result<Vec<(Point, Body)>, SchedulerError>);
+
+struct Queue {
+    pending: VecDeque<Job>,
+    outstanding: usize,
+    next_emit: usize,
+    live: Vec<bool>,
+    closed: bool,
+}
+
+impl Queue {
+    fn live_peers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.live
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x)
+            .map(|(i, _)| i)
+    }
+
+    fn can_retry(&self, job: &Job, max_attempts: usize) -> bool {
+        job.tried.len() < max_attempts && self.live_peers().any(|x| !job.tried.contains(&x))
+    }
+
+    fn requeue(&mut self, job: Job) {
+        let position = self
+            .pending
+            .iter()
+            .position(|x| x.index > job.index)
+            .unwrap_or(self.pending.len());
+
+        self.pending.insert(position, job);
+    }
+
+    fn take_for(&mut self, peer: usize, max_in_flight: usize) -> Option<Job> {
+        let limit = self.next_emit + max_in_flight.max(1);
+
+        let position = self
+            .pending
+            .iter()
+            .position(|x| x.index < limit && !x.tried.contains(&peer))?;
+
+        self.pending.remove(position)
+    }
+}
+
+struct Shared {
+    config: SchedulerConfig,
+    queue: Mutex<Queue>,
+    progress: Notify,
+    results: mpsc::UnboundedSender<JobResult>,
+}
+
+impl Shared {
+    fn complete(&self, queue: &mut Queue, result: JobResult) {
+        queue.outstanding -= 1;
+        let _ = self.results.send(result);
+    }
+
+    /// Fails the queued jobs that none of the remaining peers can retry
+    fn fail_orphans(&self, queue: &mut Queue) {
+        let max_attempts = self.config.max_attempts;
+
+        let (orphans, pending) = std::mem::take(&mut queue.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|x| !queue.can_retry(x, max_attempts));
+
+        queue.pending = pending.into();
+
+        for job in orphans {
+            let error = SchedulerError::PeersExhausted(job.range());
+            self.complete(queue, (job.index, Err(error)));
+        }
+    }
+}
+
+/// Checks that a block body is the one identified by the point
+fn is_block_at(point: &Point, body: &[u8]) -> bool {
+    let Point::Specific(slot, hash) = point else {
+        return false;
+    };
+
+    match MultiEraBlock::decode(body) {
+        Ok(block) => block.slot() == *slot && block.hash().as_slice() == hash.as_slice(),
+        Err(_) => false,
+    }
+}
+
+async fn fetch_job(client: &mut Client, job: &Job) -> Result<Vec<(Point, Body)>, ClientError> {
+    let bodies = client.fetch_range(job.range()).await?;
+
+    if bodies.len() != job.points.len() {
+        warn!(
+            expected = job.points.len(),
+            received = bodies.len(),
+            "peer returned a range with the wrong amount of blocks"
+        );
+
+        return Err(ClientError::RangeMismatch);
+    }
+
+    let blocks: Vec<_> = job.points.iter().cloned().zip(bodies).collect();
+
+    if let Some((point, _)) = blocks.iter().find(|(x, body)| !is_block_at(x, body)) {
+        warn!(?point, "peer returned a block that doesn't match the point");
+        return Err(ClientError::RangeMismatch);
+    }
+
+    Ok(blocks)
+}
+
+async fn run_worker(shared: Arc<Shared>, peer: usize, mut client: Client) -> Option<Client> {
+    loop {
+        let progress = shared.progress.notified();
+
+        let job = {
+            let mut queue = shared.queue.lock().expect("poisoned scheduler");
+
+            if queue.closed || queue.outstanding == 0 {
+                return Some(client);
+            }
+
+            queue.take_for(peer, shared.config.max_in_flight)
+        };
+
+        let Some(mut job) = job else {
+            progress.await;
+            continue;
+        };
+
+        debug!(peer, index = job.index, "fetching range");
+
+        let result = fetch_job(&mut client, &job).await;
+
+        let mut queue = shared.queue.lock().expect("poisoned scheduler");
+
+        match result {
+            Ok(blocks) => {
+                shared.complete(&mut queue, (job.index, Ok(blocks)));
+            }
+            Err(error) => {
+                warn!(peer, index = job.index, ?error, "range fetch failed");
+
+                // a missing range leaves the client idle, anything else means
+                // we can't trust this peer anymore
+                let usable = matches!(error, ClientError::NoBlocks);
+
+                if !usable {
+                    queue.live[peer] = false;
+                }
+
+                job.tried.push(peer);
+
+                if queue.can_retry(&job, shared.config.max_attempts) {
+                    queue.requeue(job);
+                } else {
+                    let error = SchedulerError::RangeFailed(job.range(), error);
+                    shared.complete(&mut queue, (job.index, Err(error)));
+                }
+
+                shared.fail_orphans(&mut queue);
+
+                if !usable {
+                    drop(queue);
+                    shared.progress.notify_waiters();
+                    return None;
+                }
+            }
+        }
+
+        drop(queue);
+        shared.progress.notify_waiters();
+    }
+}
+
+/// Fetches the blocks of a header chain using the clients of several peers
+///
+/// The chain is split in ranges of consecutive points that are assigned to
+/// whichever peer is free. Ranges that fail are retried on other peers.
+/// Blocks are yielded in the order of the chain, regardless of the order in
+/// which their ranges complete.
+pub struct FetchScheduler {
+    shared: Arc<Shared>,
+    results: mpsc::UnboundedReceiver<JobResult>,
+    workers: Vec<JoinHandle<Option<Client>>>,
+    total: usize,
+    completed: BTreeMap<usize, Result<Vec<(Point, Body)>, SchedulerError>>,
+    ready: VecDeque<(Point, Body)>,
+    failed: bool,
+}
+
+impl FetchScheduler {
+    /// Starts fetching the blocks of the given chain, in ascending order
+    pub fn start(
+        clients: Vec<Client>,
+        chain: Vec<Point>,
+        config: SchedulerConfig,
+    ) -> Result<Self, SchedulerError> {
+        if clients.is_empty() {
+            return Err(SchedulerError::NoPeers);
+        }
+
+        let pending: VecDeque<_> = chain
+            .chunks(config.range_size.max(1))
+            .enumerate()
+            .map(|(index, points)| Job {
+                index,
+                points: points.to_vec(),
+                tried: vec![],
+            })
+            .collect();
+
+        let total = pending.len();
+        let (sender, results) = mpsc::unbounded_channel();
+
+        let queue = Queue {
+            outstanding: total,
+            pending,
+            next_emit: 0,
+            live: vec![true; clients.len()],
+            closed: false,
+        };
+
+        let shared = Arc::new(Shared {
+            config,
+            queue: Mutex::new(queue),
+            progress: Notify::new(),
+            results: sender,
+        });
+
+        let workers = clients
+            .into_iter()
+            .enumerate()
+            .map(|(peer, client)| tokio::spawn(run_worker(shared.clone(), peer, client)))
+            .collect();
+
+        Ok(Self {
+            shared,
+            results,
+            workers,
+            total,
+            completed: BTreeMap::new(),
+            ready: VecDeque::new(),
+            failed: false,
+        })
+    }
+
+    fn advance(&mut self) {
+        let mut queue = self.shared.queue.lock().expect("poisoned scheduler");
+
+        queue.next_emit += 1;
+
+        drop(queue);
+        self.shared.progress.notify_waiters();
+    }
+
+    /// Next block of the chain, or `None` once all the blocks were yielded
+    ///
+    /// After an error, no more blocks are yielded.
+    pub async fn next(&mut self) -> Option<Result<(Point, Body), SchedulerError>> {
+        loop {
+            if let Some(block) = self.ready.pop_front() {
+                return Some(Ok(block));
+            }
+
+            if self.failed {
+                return None;
+            }
+
+            let next_emit = self
+                .shared
+                .queue
+                .lock()
+                .expect("poisoned scheduler")
+                .next_emit;
+
+            if next_emit == self.total {
+                return None;
+            }
+
+            match self.completed.remove(&next_emit) {
+                Some(Ok(blocks)) => {
+                    self.ready.extend(blocks);
+                    self.advance();
+                    continue;
+                }
+                Some(Err(error)) => {
+                    self.failed = true;
+                    return Some(Err(error));
+                }
+                None => (),
+            }
+
+            let (index, result) = self.results.recv().await?;
+            self.completed.insert(index, result);
+        }
+    }
+
+    fn close(&self) {
+        self.shared.queue.lock().expect("poisoned scheduler").closed = true;
+        self.shared.progress.notify_waiters();
+    }
+
+    /// Stops fetching and returns the clients that are still usable
+    pub async fn finish(mut self) -> Vec<Client> {
+        self.close();
+
+        let mut clients = vec![];
+
+        for worker in std::mem::take(&mut self.workers) {
+            if let Ok(Some(client)) = worker.await {
+                clients.push(client);
+            }
+        }
+
+        clients
+    }
+}
+
+impl Drop for FetchScheduler {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
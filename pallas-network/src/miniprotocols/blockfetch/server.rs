@@ -1,8 +1,9 @@
 use thiserror::Error;
 
+use crate::miniprotocols::StateTimeouts;
 use crate::multiplexer;
 
-use super::{Body, Message, Range, State};
+use super::{default_timeouts, Body, Message, Range, State};
 
 #[derive(Error, Debug)]
 pub enum ServerError {
@@ -26,7 +27,7 @@ pub enum ServerError {
 pub struct BlockRequest(pub Range);
 
 /// Represents the server for the BlockFetch mini-protocol.
-pub struct Server(State, multiplexer::ChannelBuffer);
+pub struct Server(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
 
 impl Server {
     /// Create a new BlockFetch server from a multiplexer agent channel.
@@ -36,7 +37,16 @@ impl Server {
     /// * `channel` - A multiplexer agent channel used for communication with
     ///   the server.
     pub fn new(channel: multiplexer::AgentChannel) -> Self {
-        Self(State::Idle, multiplexer::ChannelBuffer::new(channel))
+        Self(
+            State::Idle,
+            multiplexer::ChannelBuffer::new(channel),
+            default_timeouts(),
+        )
+    }
+
+    /// Sets the max time to wait for the client in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
     }
 
     /// Get the current state of the server.
@@ -109,6 +119,7 @@ impl Server {
 
     pub async fn recv_message(&mut self) -> Result<Message, ServerError> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(ServerError::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
@@ -79,6 +79,11 @@ impl RollbackBuffer {
         self.points.front()
     }
 
+    /// Removes the oldest point in the buffer
+    pub fn pop_oldest(&mut self) -> Option<Point> {
+        self.points.pop_front()
+    }
+
     /// Unwind the buffer up to a certain point, clearing orphaned items
     ///
     /// If the buffer contains the rollback point, we can safely discard from
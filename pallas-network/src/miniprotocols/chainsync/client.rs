@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 use thiserror::Error;
 use tracing::debug;
 
-use crate::miniprotocols::Point;
+use crate::miniprotocols::{Point, StateTimeouts};
 use crate::multiplexer;
 
 use super::{BlockContent, HeaderContent, IntersectResponse, Message, State, Tip};
@@ -36,7 +36,12 @@ pub enum NextResponse<CONTENT> {
     Await,
 }
 
-pub struct Client<O>(State, multiplexer::ChannelBuffer, PhantomData<O>)
+pub struct Client<O>(
+    State,
+    multiplexer::ChannelBuffer,
+    PhantomData<O>,
+    StateTimeouts<State>,
+)
 where
     Message<O>: Fragment;
 
@@ -55,9 +60,22 @@ where
             State::Idle,
             multiplexer::ChannelBuffer::new(channel),
             PhantomData {},
+            StateTimeouts::default(),
         )
     }
 
+    /// Sets the max time to wait for the server in each of its states. There
+    /// are no limits by default, see [`super::default_timeouts`] for the
+    /// node-to-node ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeouts` - The timeouts for the `Intersect`, `CanAwait` and
+    ///   `MustReply` states.
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.3 = timeouts;
+    }
+
     /// Returns the current state of the client.
     pub fn state(&self) -> &State {
         &self.0
@@ -148,6 +166,7 @@ where
     pub async fn recv_message(&mut self) -> Result<Message<O>, ClientError> {
         self.assert_agency_is_theirs()?;
 
+        self.1.set_timeout(self.3.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(ClientError::Plexer)?;
 
         self.assert_inbound_state(&msg)?;
@@ -1,6 +1,8 @@
 use std::{fmt::Debug, ops::Deref};
 
-use crate::miniprotocols::Point;
+use std::time::Duration;
+
+use crate::miniprotocols::{Point, StateTimeouts, SHORT_WAIT};
 
 #[derive(Debug, Clone)]
 pub struct Tip(pub Point, pub u64);
@@ -16,6 +18,19 @@ pub enum State {
     Done,
 }
 
+/// Time limits that the node-to-node spec sets on the states of the protocol
+///
+/// The spec picks a random `MustReply` limit between 135 and 269 seconds, we
+/// use the upper bound. Node-to-client chain-sync has no limits, so agents
+/// start without any and the node-to-node facades apply these.
+pub fn default_timeouts() -> StateTimeouts<State> {
+    StateTimeouts::new()
+        .with(&State::Idle, Duration::from_secs(3673))
+        .with(&State::Intersect, SHORT_WAIT)
+        .with(&State::CanAwait, SHORT_WAIT)
+        .with(&State::MustReply, Duration::from_secs(269))
+}
+
 /// A generic chain-sync message for either header or block content
 #[derive(Debug)]
 pub enum Message<C> {
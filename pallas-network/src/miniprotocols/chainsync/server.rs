@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 use thiserror::Error;
 use tracing::debug;
 
-use crate::miniprotocols::Point;
+use crate::miniprotocols::{Point, StateTimeouts};
 use crate::multiplexer;
 
 use super::{BlockContent, HeaderContent, Message, State, Tip};
@@ -32,7 +32,12 @@ pub enum ClientRequest {
     RequestNext,
 }
 
-pub struct Server<O>(State, multiplexer::ChannelBuffer, PhantomData<O>)
+pub struct Server<O>(
+    State,
+    multiplexer::ChannelBuffer,
+    PhantomData<O>,
+    StateTimeouts<State>,
+)
 where
     Message<O>: Fragment;
 
@@ -51,9 +56,15 @@ where
             State::Idle,
             multiplexer::ChannelBuffer::new(channel),
             PhantomData {},
+            StateTimeouts::default(),
         )
     }
 
+    /// Sets the max time to wait for the client in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.3 = timeouts;
+    }
+
     /// Returns the current state of the server.
     pub fn state(&self) -> &State {
         &self.0
@@ -144,6 +155,7 @@ where
     async fn recv_message(&mut self) -> Result<Message<O>, ServerError> {
         self.assert_agency_is_theirs()?;
 
+        self.1.set_timeout(self.3.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(ServerError::Plexer)?;
 
         self.assert_inbound_state(&msg)?;
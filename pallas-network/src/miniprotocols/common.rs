@@ -1,4 +1,6 @@
 use std::fmt::Debug;
+use std::mem::{discriminant, Discriminant};
+use std::time::Duration;
 
 use pallas_codec::minicbor::{decode, encode, Decode, Decoder, Encode, Encoder};
 
@@ -51,6 +53,9 @@ pub const PROTOCOL_N2N_TX_SUBMISSION: u16 = 4;
 /// Protocol channel number for node-to-node Keep-alive
 pub const PROTOCOL_N2N_KEEP_ALIVE: u16 = 8;
 
+/// Protocol channel number for node-to-node peer-sharing
+pub const PROTOCOL_N2N_PEER_SHARING: u16 = 10;
+
 /// Protocol channel number for node-to-client handshakes
 pub const PROTOCOL_N2C_HANDSHAKE: u16 = 0;
 
@@ -129,3 +134,46 @@ impl<'b> Decode<'b, ()> for Point {
         }
     }
 }
+
+/// Time limit of the states where the peer is expected to reply right away,
+/// as defined by the network spec
+pub const SHORT_WAIT: Duration = Duration::from_secs(10);
+
+/// Time limit of the states where the peer might need to do some work before
+/// replying, as defined by the network spec
+pub const LONG_WAIT: Duration = Duration::from_secs(60);
+
+/// Max time to wait for the peer while in each of the states where it has
+/// agency
+///
+/// States are matched by variant, regardless of the data they carry.
+#[derive(Debug, Clone)]
+pub struct StateTimeouts<S>(Vec<(Discriminant<S>, Duration)>);
+
+impl<S> StateTimeouts<S> {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn with(mut self, state: &S, timeout: Duration) -> Self {
+        self.set(state, timeout);
+        self
+    }
+
+    pub fn set(&mut self, state: &S, timeout: Duration) {
+        let key = discriminant(state);
+        self.0.retain(|(x, _)| *x != key);
+        self.0.push((key, timeout));
+    }
+
+    pub fn get(&self, state: &S) -> Option<Duration> {
+        let key = discriminant(state);
+        self.0.iter().find(|(x, _)| *x == key).map(|(_, t)| *t)
+    }
+}
+
+impl<S> Default for StateTimeouts<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,9 +1,12 @@
 use pallas_codec::Fragment;
 use std::fmt::Debug;
 use std::marker::PhantomData;
-use tracing::debug;
+use tracing::{debug, warn};
 
-use super::{Error, Message, RefuseReason, State, VersionNumber, VersionTable};
+use super::{
+    default_timeouts, Error, Message, Policy, RefuseReason, State, VersionNumber, VersionTable,
+};
+use crate::miniprotocols::StateTimeouts;
 use crate::multiplexer;
 
 #[derive(Debug)]
@@ -13,7 +16,12 @@ pub enum Confirmation<D: Debug + Clone> {
     QueryReply(VersionTable<D>),
 }
 
-pub struct Client<D>(State, multiplexer::ChannelBuffer, PhantomData<D>);
+pub struct Client<D>(
+    State,
+    multiplexer::ChannelBuffer,
+    PhantomData<D>,
+    StateTimeouts<State>,
+);
 
 impl<D> Client<D>
 where
@@ -25,9 +33,15 @@ where
             State::Propose,
             multiplexer::ChannelBuffer::new(channel),
             PhantomData {},
+            default_timeouts(),
         )
     }
 
+    /// Sets the max time to wait for the server in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.3 = timeouts;
+    }
+
     pub fn state(&self) -> &State {
         &self.0
     }
@@ -86,6 +100,7 @@ where
 
     pub async fn recv_message(&mut self) -> Result<Message<D>, Error> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.3.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(Error::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
@@ -130,6 +145,28 @@ where
         self.recv_while_confirm().await
     }
 
+    /// Proposes the versions of the policy and checks the accepted params
+    ///
+    /// If the server accepts a version with params that the policy refuses,
+    /// the handshake is reported as rejected.
+    pub async fn handshake_with_policy(
+        &mut self,
+        policy: &Policy<D>,
+    ) -> Result<Confirmation<D>, Error> {
+        match self.handshake(policy.versions().clone()).await? {
+            Confirmation::Accepted(version, data) => match policy.accept(version, &data) {
+                Ok(data) => Ok(Confirmation::Accepted(version, data)),
+                Err(reason) => {
+                    warn!(version, reason, "accepted params refused by policy");
+                    Ok(Confirmation::Rejected(RefuseReason::Refused(
+                        version, reason,
+                    )))
+                }
+            },
+            other => Ok(other),
+        }
+    }
+
     pub fn unwrap(self) -> multiplexer::AgentChannel {
         self.1.unwrap()
     }
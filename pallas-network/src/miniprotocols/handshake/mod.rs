@@ -1,4 +1,5 @@
 mod client;
+mod policy;
 mod protocol;
 mod server;
 
@@ -6,5 +7,6 @@ pub mod n2c;
 pub mod n2n;
 
 pub use client::*;
+pub use policy::*;
 pub use protocol::*;
 pub use server::*;
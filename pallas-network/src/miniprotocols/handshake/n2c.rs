@@ -3,7 +3,8 @@ use std::collections::HashMap;
 use pallas_codec::minicbor::data::Type;
 use pallas_codec::minicbor::{decode, encode, Decode, Decoder, Encode, Encoder};
 
-use super::protocol::NetworkMagic;
+use super::protocol::{NetworkMagic, VersionNumber};
+use super::Policy;
 
 pub type VersionTable = super::protocol::VersionTable<VersionData>;
 
@@ -23,6 +24,9 @@ const PROTOCOL_V13: u64 = 32781;
 const PROTOCOL_V14: u64 = 32782;
 const PROTOCOL_V15: u64 = 32783;
 const PROTOCOL_V16: u64 = 32784;
+const PROTOCOL_V17: u64 = 32785;
+const PROTOCOL_V18: u64 = 32786;
+const PROTOCOL_V19: u64 = 32787;
 
 impl VersionTable {
     pub fn v1_and_above(network_magic: u64) -> VersionTable {
@@ -74,6 +78,20 @@ impl VersionTable {
         VersionTable { values }
     }
 
+    /// Conway-era versions
+    pub fn v16_and_above(network_magic: u64) -> VersionTable {
+        let values = vec![
+            (PROTOCOL_V16, VersionData(network_magic, Some(false))),
+            (PROTOCOL_V17, VersionData(network_magic, Some(false))),
+            (PROTOCOL_V18, VersionData(network_magic, Some(false))),
+            (PROTOCOL_V19, VersionData(network_magic, Some(false))),
+        ]
+        .into_iter()
+        .collect::<HashMap<u64, VersionData>>();
+
+        VersionTable { values }
+    }
+
     pub fn v15_with_query(network_magic: u64) -> VersionTable {
         let values = vec![(PROTOCOL_V15, VersionData(network_magic, Some(true)))]
             .into_iter()
@@ -83,6 +101,15 @@ impl VersionTable {
     }
 }
 
+impl Policy<VersionData> {
+    /// Proposes (or supports) v10 and above, negotiating the params as
+    /// described in the network spec
+    pub fn v10_and_above(network_magic: u64) -> Self {
+        Policy::new(VersionTable::v10_and_above(network_magic))
+            .with_acceptor(VersionData::negotiate)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct VersionData(NetworkMagic, Option<bool>);
 
@@ -90,6 +117,36 @@ impl VersionData {
     pub fn new(magic: NetworkMagic, param: Option<bool>) -> Self {
         Self(magic, param)
     }
+
+    pub fn network_magic(&self) -> NetworkMagic {
+        self.0
+    }
+
+    /// Whether the proposer only wants to query the supported versions,
+    /// only present from v15 onwards
+    pub fn query(&self) -> Option<bool> {
+        self.1
+    }
+
+    /// Agrees on the params of a version, following the node's rules
+    ///
+    /// The network magic must match and the query flag is kept if any of the
+    /// sides sets it.
+    pub fn negotiate(_version: VersionNumber, ours: &Self, theirs: &Self) -> Result<Self, String> {
+        if ours.0 != theirs.0 {
+            return Err(format!(
+                "network magic mismatch: {} != {}",
+                ours.0, theirs.0
+            ));
+        }
+
+        let query = match (ours.1, theirs.1) {
+            (Some(a), Some(b)) => Some(a || b),
+            _ => None,
+        };
+
+        Ok(Self(ours.0, query))
+    }
 }
 
 impl Encode<()> for VersionData {
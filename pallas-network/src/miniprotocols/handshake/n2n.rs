@@ -2,6 +2,8 @@ use std::collections::HashMap;
 
 use pallas_codec::minicbor::{decode, encode, Decode, Decoder, Encode, Encoder};
 
+use super::{Policy, VersionNumber};
+
 pub type VersionTable = super::protocol::VersionTable<VersionData>;
 
 const PROTOCOL_V7: u64 = 7;
@@ -11,8 +13,14 @@ const PROTOCOL_V10: u64 = 10;
 const PROTOCOL_V11: u64 = 11;
 const PROTOCOL_V12: u64 = 12;
 const PROTOCOL_V13: u64 = 13;
+const PROTOCOL_V14: u64 = 14;
+
+/// The node doesn't take part in peer sharing
+pub const PEER_SHARING_DISABLED: u8 = 0;
 
-const PEER_SHARING_DISABLED: u8 = 0;
+/// The node shares its public peers (v13 onwards, before that this value meant
+/// "private" sharing)
+pub const PEER_SHARING_ENABLED: u8 = 1;
 
 impl VersionTable {
     #[deprecated(note = "no longer supported by spec")]
@@ -99,6 +107,15 @@ impl VersionTable {
                     Some(false),
                 ),
             ),
+            (
+                PROTOCOL_V14,
+                VersionData::new(
+                    network_magic,
+                    true,
+                    Some(PEER_SHARING_DISABLED),
+                    Some(false),
+                ),
+            ),
         ]
         .into_iter()
         .collect::<HashMap<u64, VersionData>>();
@@ -135,6 +152,43 @@ impl VersionTable {
                     Some(false),
                 ),
             ),
+            (
+                PROTOCOL_V14,
+                VersionData::new(
+                    network_magic,
+                    true,
+                    Some(PEER_SHARING_DISABLED),
+                    Some(false),
+                ),
+            ),
+        ]
+        .into_iter()
+        .collect::<HashMap<u64, VersionData>>();
+
+        VersionTable { values }
+    }
+
+    /// Versions that share the v13 semantics for the peer sharing flag
+    pub fn v13_and_above(network_magic: u64) -> VersionTable {
+        let values = vec![
+            (
+                PROTOCOL_V13,
+                VersionData::new(
+                    network_magic,
+                    true,
+                    Some(PEER_SHARING_DISABLED),
+                    Some(false),
+                ),
+            ),
+            (
+                PROTOCOL_V14,
+                VersionData::new(
+                    network_magic,
+                    true,
+                    Some(PEER_SHARING_DISABLED),
+                    Some(false),
+                ),
+            ),
         ]
         .into_iter()
         .collect::<HashMap<u64, VersionData>>();
@@ -143,6 +197,14 @@ impl VersionTable {
     }
 }
 
+impl Policy<VersionData> {
+    /// Proposes (or supports) v7 and above, negotiating the params as
+    /// described in the network spec
+    pub fn v7_and_above(network_magic: u64) -> Self {
+        Policy::new(VersionTable::v7_and_above(network_magic)).with_acceptor(VersionData::negotiate)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct VersionData {
     network_magic: u64,
@@ -165,6 +227,57 @@ impl VersionData {
             query,
         }
     }
+
+    pub fn network_magic(&self) -> u64 {
+        self.network_magic
+    }
+
+    pub fn initiator_only_diffusion_mode(&self) -> bool {
+        self.initiator_only_diffusion_mode
+    }
+
+    /// Peer sharing flag, only present from v11 onwards
+    pub fn peer_sharing(&self) -> Option<u8> {
+        self.peer_sharing
+    }
+
+    /// Whether the proposer only wants to query the supported versions,
+    /// only present from v11 onwards
+    pub fn query(&self) -> Option<bool> {
+        self.query
+    }
+
+    /// Agrees on the params of a version, following the node's rules
+    ///
+    /// The network magic must match. The connection is initiator-only if any
+    /// of the sides asks for it, peer sharing is only enabled if both sides
+    /// enable it, and the query flag is kept if any of the sides sets it.
+    pub fn negotiate(_version: VersionNumber, ours: &Self, theirs: &Self) -> Result<Self, String> {
+        if ours.network_magic != theirs.network_magic {
+            return Err(format!(
+                "network magic mismatch: {} != {}",
+                ours.network_magic, theirs.network_magic
+            ));
+        }
+
+        let peer_sharing = match (ours.peer_sharing, theirs.peer_sharing) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            _ => None,
+        };
+
+        let query = match (ours.query, theirs.query) {
+            (Some(a), Some(b)) => Some(a || b),
+            _ => None,
+        };
+
+        Ok(Self {
+            network_magic: ours.network_magic,
+            initiator_only_diffusion_mode: ours.initiator_only_diffusion_mode
+                || theirs.initiator_only_diffusion_mode,
+            peer_sharing,
+            query,
+        })
+    }
 }
 
 impl Encode<()> for VersionData {
@@ -0,0 +1,105 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::{VersionNumber, VersionTable};
+
+/// Decides if the version data proposed by the other side is acceptable
+///
+/// Receives the version number being negotiated, our version data and the
+/// data proposed by the other side. Returns the version data both sides agree
+/// on, or the reason for refusing it.
+pub type Acceptor<D> = Arc<dyn Fn(VersionNumber, &D, &D) -> Result<D, String> + Send + Sync>;
+
+/// Rules used to pick a version during the handshake
+#[derive(Clone)]
+pub struct Policy<D>
+where
+    D: Debug + Clone,
+{
+    versions: VersionTable<D>,
+    preferred: Option<VersionNumber>,
+    acceptor: Acceptor<D>,
+}
+
+impl<D> Policy<D>
+where
+    D: Debug + Clone + PartialEq + 'static,
+{
+    /// A policy that supports the given versions, picks the highest one in
+    /// common and requires both sides to propose the same version data
+    pub fn new(versions: VersionTable<D>) -> Self {
+        Self {
+            versions,
+            preferred: None,
+            acceptor: Arc::new(|_, ours, theirs| match ours == theirs {
+                true => Ok(ours.clone()),
+                false => Err("Proposed extra params don't match".into()),
+            }),
+        }
+    }
+}
+
+impl<D> Policy<D>
+where
+    D: Debug + Clone,
+{
+    /// Version to pick over the highest one, as long as both sides support it
+    pub fn with_preferred(mut self, version: VersionNumber) -> Self {
+        self.preferred = Some(version);
+        self
+    }
+
+    pub fn with_acceptor(
+        mut self,
+        acceptor: impl Fn(VersionNumber, &D, &D) -> Result<D, String> + Send + Sync + 'static,
+    ) -> Self {
+        self.acceptor = Arc::new(acceptor);
+        self
+    }
+
+    pub fn versions(&self) -> &VersionTable<D> {
+        &self.versions
+    }
+
+    pub fn preferred(&self) -> Option<VersionNumber> {
+        self.preferred
+    }
+
+    /// Supported version numbers, from the most to the least desirable
+    pub fn ranked_versions(&self) -> Vec<VersionNumber> {
+        let mut ranked: Vec<_> = self.versions.values.keys().copied().collect();
+        ranked.sort_by(|a, b| b.cmp(a));
+
+        if let Some(preferred) = self.preferred {
+            if let Some(position) = ranked.iter().position(|x| *x == preferred) {
+                ranked.remove(position);
+                ranked.insert(0, preferred);
+            }
+        }
+
+        ranked
+    }
+
+    /// Picks the best version from the ones proposed by the other side
+    pub fn select(&self, proposed: &VersionTable<D>) -> Option<(VersionNumber, D)> {
+        self.ranked_versions().into_iter().find_map(|version| {
+            let theirs = proposed.values.get(&version)?;
+            Some((version, theirs.clone()))
+        })
+    }
+
+    /// Checks the version data proposed (or accepted) by the other side
+    pub fn accept(&self, version: VersionNumber, theirs: &D) -> Result<D, String> {
+        let ours = self
+            .versions
+            .values
+            .get(&version)
+            .ok_or_else(|| format!("version {version} not supported"))?;
+
+        (self.acceptor)(version, ours, theirs)
+    }
+}
+
+pub type N2NPolicy = Policy<super::n2n::VersionData>;
+
+pub type N2CPolicy = Policy<super::n2c::VersionData>;
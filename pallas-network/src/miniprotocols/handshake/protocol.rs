@@ -3,6 +3,7 @@ use pallas_codec::minicbor::{decode, encode, Decode, Decoder, Encode, Encoder};
 use std::{collections::HashMap, fmt::Debug};
 use thiserror::*;
 
+use crate::miniprotocols::{StateTimeouts, SHORT_WAIT};
 use crate::multiplexer;
 
 #[derive(Error, Debug)]
@@ -160,6 +161,14 @@ pub enum State {
     Done,
 }
 
+/// Time limits that the network spec sets on the states of the protocol,
+/// both for node-to-node and node-to-client connections
+pub fn default_timeouts() -> StateTimeouts<State> {
+    StateTimeouts::new()
+        .with(&State::Propose, SHORT_WAIT)
+        .with(&State::Confirm, SHORT_WAIT)
+}
+
 #[derive(Debug)]
 pub enum RefuseReason {
     VersionMismatch(Vec<VersionNumber>),
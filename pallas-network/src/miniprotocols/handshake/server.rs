@@ -3,14 +3,22 @@ use std::marker::PhantomData;
 use pallas_codec::Fragment;
 use tracing::{debug, warn};
 
-use super::{Error, Message, RefuseReason, State, VersionNumber, VersionTable};
+use super::{
+    default_timeouts, Error, Message, Policy, RefuseReason, State, VersionNumber, VersionTable,
+};
+use crate::miniprotocols::StateTimeouts;
 use crate::multiplexer;
 
-pub struct Server<D>(State, multiplexer::ChannelBuffer, PhantomData<D>);
+pub struct Server<D>(
+    State,
+    multiplexer::ChannelBuffer,
+    PhantomData<D>,
+    StateTimeouts<State>,
+);
 
 impl<D> Server<D>
 where
-    D: std::fmt::Debug + Clone + std::cmp::PartialEq,
+    D: std::fmt::Debug + Clone + std::cmp::PartialEq + 'static,
     Message<D>: Fragment,
 {
     pub fn new(channel: multiplexer::AgentChannel) -> Self {
@@ -18,9 +26,15 @@ where
             State::Propose,
             multiplexer::ChannelBuffer::new(channel),
             PhantomData {},
+            default_timeouts(),
         )
     }
 
+    /// Sets the max time to wait for the client in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.3 = timeouts;
+    }
+
     pub fn state(&self) -> &State {
         &self.0
     }
@@ -74,6 +88,7 @@ where
 
     pub async fn recv_message(&mut self) -> Result<Message<D>, Error> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.3.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(Error::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
@@ -113,65 +128,55 @@ where
     /// Perform a handshake with the client
     ///
     /// Performs a full handshake with the client, where `versions` are the
-    /// acceptable versions supported by the server.
+    /// acceptable versions supported by the server. The highest version in
+    /// common is accepted if the client proposed the same extra params.
     pub async fn handshake(
         &mut self,
         versions: VersionTable<D>,
     ) -> Result<Option<(VersionNumber, D)>, Error> {
-        // receive proposed versions
-        let client_versions = self
-            .receive_proposed_versions()
-            .await?
-            .values
-            .into_iter()
-            .collect::<Vec<(u64, D)>>();
-
-        // find highest intersect with our version table (TODO: improve)
-        let mut versions = versions.values.into_iter().collect::<Vec<(u64, D)>>();
-
-        versions.sort_by(|a, b| b.0.cmp(&a.0));
-
-        for (ver_num, ver_data) in versions.clone() {
-            for (client_ver_num, client_ver_data) in client_versions.clone() {
-                if ver_num == client_ver_num {
-                    if ver_data == client_ver_data {
-                        // found a version number and extra data match
-                        debug!("accepting hs with ({}, {:?})", ver_num, ver_data);
-
-                        self.accept_version(ver_num, ver_data.clone()).await?;
-
-                        return Ok(Some((ver_num, ver_data)));
-                    } else {
-                        warn!(
-                            "rejecting hs as params not acceptable - server: {:?}, client: {:?}",
-                            ver_data, client_ver_data
-                        );
-
-                        // found version number match but extra data not acceptable
-                        self.refuse(RefuseReason::Refused(
-                            ver_num,
-                            "Proposed extra params don't match".into(),
-                        ))
-                        .await?;
-
-                        return Ok(None);
-                    }
-                }
-            }
-        }
+        self.handshake_with_policy(&Policy::new(versions)).await
+    }
+
+    /// Perform a handshake with the client, following the given policy
+    pub async fn handshake_with_policy(
+        &mut self,
+        policy: &Policy<D>,
+    ) -> Result<Option<(VersionNumber, D)>, Error> {
+        let proposed = self.receive_proposed_versions().await?;
 
-        warn!(
-            "rejecting hs as no version intersect found - server: {:?}, client: {:?}",
-            versions, client_versions
-        );
+        let Some((version, client_data)) = policy.select(&proposed) else {
+            warn!(
+                "rejecting hs as no version intersect found - server: {:?}, client: {:?}",
+                policy.versions(),
+                proposed
+            );
 
-        // failed to find a version number intersection
-        self.refuse(RefuseReason::VersionMismatch(
-            versions.into_iter().map(|(num, _)| num).collect(),
-        ))
-        .await?;
+            // failed to find a version number intersection
+            self.refuse(RefuseReason::VersionMismatch(policy.ranked_versions()))
+                .await?;
 
-        Ok(None)
+            return Ok(None);
+        };
+
+        match policy.accept(version, &client_data) {
+            Ok(data) => {
+                debug!("accepting hs with ({}, {:?})", version, data);
+
+                self.accept_version(version, data.clone()).await?;
+
+                Ok(Some((version, data)))
+            }
+            Err(reason) => {
+                warn!(
+                    "rejecting hs as params not acceptable - client: {:?}, reason: {}",
+                    client_data, reason
+                );
+
+                self.refuse(RefuseReason::Refused(version, reason)).await?;
+
+                Ok(None)
+            }
+        }
     }
 
     pub fn unwrap(self) -> multiplexer::AgentChannel {
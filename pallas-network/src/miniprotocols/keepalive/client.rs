@@ -1,9 +1,11 @@
 use rand::Rng;
 use std::fmt::Debug;
+use std::time::Instant;
 use thiserror::*;
 use tracing::debug;
 
 use super::protocol::*;
+use crate::miniprotocols::StateTimeouts;
 use crate::multiplexer;
 
 #[derive(Error, Debug)]
@@ -27,11 +29,21 @@ pub enum ClientError {
     Plexer(multiplexer::Error),
 }
 
-pub struct Client(State, multiplexer::ChannelBuffer);
+pub struct Client(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
 
 impl Client {
     pub fn new(channel: multiplexer::AgentChannel) -> Self {
-        Self(State::Client, multiplexer::ChannelBuffer::new(channel))
+        Self(
+            State::Client,
+            multiplexer::ChannelBuffer::new(channel),
+            default_timeouts(),
+        )
+    }
+
+    /// Sets the max time to wait for the server in each of its states,
+    /// defaults to the limits of the network spec
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
     }
 
     pub fn state(&self) -> &State {
@@ -94,6 +106,7 @@ impl Client {
 
     pub async fn recv_message(&mut self) -> Result<Message, ClientError> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(ClientError::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
@@ -129,9 +142,13 @@ impl Client {
     }
 
     pub async fn keepalive_roundtrip(&mut self) -> Result<(), ClientError> {
+        let start = Instant::now();
+
         self.send_keepalive_request().await?;
         self.recv_keepalive_response().await?;
 
+        self.1.record_round_trip(start.elapsed());
+
         Ok(())
     }
 }
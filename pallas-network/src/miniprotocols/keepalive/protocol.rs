@@ -1,3 +1,7 @@
+use std::time::Duration;
+
+use crate::miniprotocols::{StateTimeouts, LONG_WAIT};
+
 pub type Cookie = u16;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -7,6 +11,13 @@ pub enum State {
     Done,
 }
 
+/// Time limits that the network spec sets on the states of the protocol
+pub fn default_timeouts() -> StateTimeouts<State> {
+    StateTimeouts::new()
+        .with(&State::Client, Duration::from_secs(97))
+        .with(&State::Server(0), LONG_WAIT)
+}
+
 #[derive(Debug, Clone)]
 pub enum Message {
     KeepAlive(Cookie),
@@ -3,6 +3,7 @@ use thiserror::*;
 use tracing::debug;
 
 use super::protocol::*;
+use crate::miniprotocols::StateTimeouts;
 use crate::multiplexer;
 
 #[derive(Error, Debug)]
@@ -23,11 +24,20 @@ pub enum ServerError {
     Plexer(multiplexer::Error),
 }
 
-pub struct Server(State, multiplexer::ChannelBuffer);
+pub struct Server(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
 
 impl Server {
     pub fn new(channel: multiplexer::AgentChannel) -> Self {
-        Self(State::Client, multiplexer::ChannelBuffer::new(channel))
+        Self(
+            State::Client,
+            multiplexer::ChannelBuffer::new(channel),
+            default_timeouts(),
+        )
+    }
+
+    /// Sets the max time to wait for the client in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
     }
 
     pub fn state(&self) -> &State {
@@ -90,6 +100,7 @@ impl Server {
 
     pub async fn recv_message(&mut self) -> Result<Message, ServerError> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(ServerError::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
@@ -3,7 +3,7 @@ use std::fmt::Debug;
 use thiserror::*;
 
 use super::{AcquireFailure, Message, State};
-use crate::miniprotocols::Point;
+use crate::miniprotocols::{Point, StateTimeouts};
 use crate::multiplexer;
 
 #[derive(Error, Debug)]
@@ -42,11 +42,20 @@ impl From<AcquireFailure> for ClientError {
     }
 }
 
-pub struct GenericClient(State, multiplexer::ChannelBuffer);
+pub struct GenericClient(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
 
 impl GenericClient {
     pub fn new(channel: multiplexer::AgentChannel) -> Self {
-        Self(State::Idle, multiplexer::ChannelBuffer::new(channel))
+        Self(
+            State::Idle,
+            multiplexer::ChannelBuffer::new(channel),
+            StateTimeouts::default(),
+        )
+    }
+
+    /// Sets the max time to wait for the server in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
     }
 
     pub fn state(&self) -> &State {
@@ -115,6 +124,7 @@ impl GenericClient {
 
     pub async fn recv_message(&mut self) -> Result<Message, ClientError> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(ClientError::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
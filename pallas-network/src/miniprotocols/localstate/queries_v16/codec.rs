@@ -1,5 +1,8 @@
 use super::*;
-use pallas_codec::minicbor::{data::Tag, decode, encode, Decode, Decoder, Encode, Encoder};
+use pallas_codec::minicbor::{
+    data::{Tag, Type},
+    decode, encode, Decode, Decoder, Encode, Encoder,
+};
 
 impl Encode<()> for BlockQuery {
     fn encode<W: encode::Write>(
@@ -16,10 +19,10 @@ impl Encode<()> for BlockQuery {
                 e.array(1)?;
                 e.u16(1)?;
             }
-            BlockQuery::GetNonMyopicMemberRewards(x) => {
+            BlockQuery::GetNonMyopicMemberRewards(amounts) => {
                 e.array(2)?;
                 e.u16(2)?;
-                e.encode(x)?;
+                e.encode(amounts)?;
             }
             BlockQuery::GetCurrentPParams => {
                 e.array(1)?;
@@ -50,10 +53,10 @@ impl Encode<()> for BlockQuery {
                 e.u16(9)?;
                 e.encode(x)?;
             }
-            BlockQuery::GetFilteredDelegationsAndRewardAccounts(x) => {
+            BlockQuery::GetFilteredDelegationsAndRewardAccounts(addrs) => {
                 e.array(2)?;
                 e.u16(10)?;
-                e.encode(x)?;
+                e.encode(addrs)?;
             }
             BlockQuery::GetGenesisConfig => {
                 e.array(1)?;
@@ -71,54 +74,84 @@ impl Encode<()> for BlockQuery {
                 e.array(1)?;
                 e.u16(14)?;
             }
-            BlockQuery::GetUTxOByTxIn(_) => {
+            BlockQuery::GetUTxOByTxIn(txins) => {
                 e.array(2)?;
                 e.u16(15)?;
-                e.encode(2)?;
+                e.encode(txins)?;
             }
             BlockQuery::GetStakePools => {
                 e.array(1)?;
                 e.u16(16)?;
             }
-            BlockQuery::GetStakePoolParams(x) => {
+            BlockQuery::GetStakePoolParams(pools) => {
                 e.array(2)?;
                 e.u16(17)?;
-                e.encode(x)?;
+                encode_pool_set(e, pools)?;
             }
             BlockQuery::GetRewardInfoPools => {
                 e.array(1)?;
                 e.u16(18)?;
             }
-            BlockQuery::GetPoolState(x) => {
+            BlockQuery::GetPoolState(pools) => {
                 e.array(2)?;
                 e.u16(19)?;
-                e.encode(x)?;
+                encode_maybe_pool_set(e, pools)?;
             }
             BlockQuery::GetStakeSnapshots(pools) => {
                 e.array(2)?;
                 e.u16(20)?;
-
-                if !pools.is_empty() {
-                    e.array(1)?;
-                    e.tag(Tag::Unassigned(258))?;
-                }
-
-                e.encode(pools)?;
+                encode_maybe_pool_set(e, pools)?;
             }
-            BlockQuery::GetPoolDistr(x) => {
+            BlockQuery::GetPoolDistr(pools) => {
                 e.array(2)?;
                 e.u16(21)?;
-                e.encode(x)?;
+                encode_maybe_pool_set(e, pools)?;
             }
-            BlockQuery::GetStakeDelegDeposits(x) => {
+            BlockQuery::GetStakeDelegDeposits(addrs) => {
                 e.array(2)?;
                 e.u16(22)?;
-                e.encode(x)?;
+                e.encode(addrs)?;
             }
-            BlockQuery::GetConstitutionHash => {
+            #[allow(deprecated)]
+            BlockQuery::GetConstitutionHash | BlockQuery::GetConstitution => {
                 e.array(1)?;
                 e.u16(23)?;
             }
+            BlockQuery::GetGovState => {
+                e.array(1)?;
+                e.u16(24)?;
+            }
+            BlockQuery::GetDRepState(creds) => {
+                e.array(2)?;
+                e.u16(25)?;
+                e.encode(creds)?;
+            }
+            BlockQuery::GetDRepStakeDistr(dreps) => {
+                e.array(2)?;
+                e.u16(26)?;
+                e.encode(dreps)?;
+            }
+            BlockQuery::GetCommitteeMembersState(cold, hot, statuses) => {
+                e.array(4)?;
+                e.u16(27)?;
+                e.encode(cold)?;
+                e.encode(hot)?;
+                e.encode(statuses)?;
+            }
+            BlockQuery::GetFilteredVoteDelegatees(creds) => {
+                e.array(2)?;
+                e.u16(28)?;
+                e.encode(creds)?;
+            }
+            BlockQuery::GetAccountState => {
+                e.array(1)?;
+                e.u16(29)?;
+            }
+            BlockQuery::GetSPOStakeDistr(pools) => {
+                e.array(2)?;
+                e.u16(30)?;
+                encode_pool_set(e, pools)?;
+            }
         }
         Ok(())
     }
@@ -131,33 +164,87 @@ impl<'b> Decode<'b, ()> for BlockQuery {
         match d.u16()? {
             0 => Ok(Self::GetLedgerTip),
             1 => Ok(Self::GetEpochNo),
-            // 2 => Ok(Self::GetNonMyopicMemberRewards(())),
+            2 => Ok(Self::GetNonMyopicMemberRewards(d.decode()?)),
             3 => Ok(Self::GetCurrentPParams),
             4 => Ok(Self::GetProposedPParamsUpdates),
             5 => Ok(Self::GetStakeDistribution),
             6 => Ok(Self::GetUTxOByAddress(d.decode()?)),
-            // 7 => Ok(Self::GetUTxOWhole),
-            // 8 => Ok(Self::DebugEpochState),
+            7 => Ok(Self::GetUTxOWhole),
+            8 => Ok(Self::DebugEpochState),
             9 => Ok(Self::GetCBOR(d.decode()?)),
-            // 10 => Ok(Self::GetFilteredDelegationsAndRewardAccounts(())),
+            10 => Ok(Self::GetFilteredDelegationsAndRewardAccounts(d.decode()?)),
             11 => Ok(Self::GetGenesisConfig),
-            // 12 => Ok(Self::DebugNewEpochState),
+            12 => Ok(Self::DebugNewEpochState),
             13 => Ok(Self::DebugChainDepState),
             14 => Ok(Self::GetRewardProvenance),
-            // 15 => Ok(Self::GetUTxOByTxIn(())),
+            15 => Ok(Self::GetUTxOByTxIn(d.decode()?)),
             16 => Ok(Self::GetStakePools),
-            // 17 => Ok(Self::GetStakePoolParams(())),
+            17 => Ok(Self::GetStakePoolParams(decode_pool_set(d)?)),
             18 => Ok(Self::GetRewardInfoPools),
-            // 19 => Ok(Self::GetPoolState(())),
-            20 => Ok(Self::GetStakeSnapshots(d.decode()?)),
-            // 21 => Ok(Self::GetPoolDistr(())),
-            // 22 => Ok(Self::GetStakeDelegDeposits(())),
-            // 23 => Ok(Self::GetConstitutionHash),
-            _ => unreachable!(),
+            19 => Ok(Self::GetPoolState(decode_maybe_pool_set(d)?)),
+            20 => Ok(Self::GetStakeSnapshots(decode_maybe_pool_set(d)?)),
+            21 => Ok(Self::GetPoolDistr(decode_maybe_pool_set(d)?)),
+            22 => Ok(Self::GetStakeDelegDeposits(d.decode()?)),
+            23 => Ok(Self::GetConstitution),
+            24 => Ok(Self::GetGovState),
+            25 => Ok(Self::GetDRepState(d.decode()?)),
+            26 => Ok(Self::GetDRepStakeDistr(d.decode()?)),
+            27 => Ok(Self::GetCommitteeMembersState(
+                d.decode()?,
+                d.decode()?,
+                d.decode()?,
+            )),
+            28 => Ok(Self::GetFilteredVoteDelegatees(d.decode()?)),
+            29 => Ok(Self::GetAccountState),
+            30 => Ok(Self::GetSPOStakeDistr(decode_pool_set(d)?)),
+            _ => Err(decode::Error::message("unknown block query")),
         }
     }
 }
 
+/// Encodes a set of pool ids, using the optional set tag.
+fn encode_pool_set<W: encode::Write>(
+    e: &mut Encoder<W>,
+    pools: &Pools,
+) -> Result<(), encode::Error<W::Error>> {
+    e.tag(Tag::Unassigned(258))?;
+    e.encode(pools)?;
+
+    Ok(())
+}
+
+/// Encodes an optional set of pool ids, where an empty set means that all
+/// pools are queried (encoded as `Nothing`).
+fn encode_maybe_pool_set<W: encode::Write>(
+    e: &mut Encoder<W>,
+    pools: &Pools,
+) -> Result<(), encode::Error<W::Error>> {
+    if pools.is_empty() {
+        e.array(0)?;
+    } else {
+        e.array(1)?;
+        encode_pool_set(e, pools)?;
+    }
+
+    Ok(())
+}
+
+fn decode_pool_set(d: &mut Decoder) -> Result<Pools, decode::Error> {
+    if d.datatype()? == Type::Tag {
+        d.tag()?;
+    }
+
+    d.decode()
+}
+
+fn decode_maybe_pool_set(d: &mut Decoder) -> Result<Pools, decode::Error> {
+    match d.array()? {
+        Some(0) => Ok(Pools::new()),
+        Some(1) => decode_pool_set(d),
+        _ => Err(decode::Error::message("invalid length for maybe pool set")),
+    }
+}
+
 impl Encode<()> for HardForkQuery {
     fn encode<W: encode::Write>(
         &self,
@@ -369,3 +456,648 @@ impl<C> minicbor::encode::Encode<C> for TransactionOutput {
         Ok(())
     }
 }
+
+impl<'b, C> minicbor::decode::Decode<'b, C> for StakeAddr {
+    fn decode(d: &mut minicbor::Decoder<'b>, ctx: &mut C) -> Result<Self, minicbor::decode::Error> {
+        d.array()?;
+
+        match d.u16()? {
+            0 => Ok(StakeAddr::AddrKeyhash(d.decode_with(ctx)?)),
+            1 => Ok(StakeAddr::Scripthash(d.decode_with(ctx)?)),
+            _ => Err(minicbor::decode::Error::message(
+                "invalid variant id for StakeAddr",
+            )),
+        }
+    }
+}
+
+impl<C> minicbor::encode::Encode<C> for StakeAddr {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.array(2)?;
+
+        match self {
+            StakeAddr::AddrKeyhash(x) => {
+                e.u16(0)?;
+                e.encode_with(x, ctx)?;
+            }
+            StakeAddr::Scripthash(x) => {
+                e.u16(1)?;
+                e.encode_with(x, ctx)?;
+            }
+        };
+
+        Ok(())
+    }
+}
+
+impl<'b, C> minicbor::decode::Decode<'b, C> for Amount {
+    fn decode(d: &mut minicbor::Decoder<'b>, ctx: &mut C) -> Result<Self, minicbor::decode::Error> {
+        d.array()?;
+
+        match d.u16()? {
+            0 => Ok(Amount::Coin(d.decode_with(ctx)?)),
+            1 => Ok(Amount::StakeAddr(d.decode_with(ctx)?)),
+            _ => Err(minicbor::decode::Error::message(
+                "invalid variant id for Amount",
+            )),
+        }
+    }
+}
+
+impl<C> minicbor::encode::Encode<C> for Amount {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.array(2)?;
+
+        match self {
+            Amount::Coin(x) => {
+                e.u16(0)?;
+                e.encode_with(x, ctx)?;
+            }
+            Amount::StakeAddr(x) => {
+                e.u16(1)?;
+                e.encode_with(x, ctx)?;
+            }
+        };
+
+        Ok(())
+    }
+}
+
+impl<'b, C> minicbor::decode::Decode<'b, C> for Relay {
+    fn decode(d: &mut minicbor::Decoder<'b>, ctx: &mut C) -> Result<Self, minicbor::decode::Error> {
+        d.array()?;
+
+        match d.u16()? {
+            0 => Ok(Relay::SingleHostAddr(
+                d.decode_with(ctx)?,
+                d.decode_with(ctx)?,
+                d.decode_with(ctx)?,
+            )),
+            1 => Ok(Relay::SingleHostName(
+                d.decode_with(ctx)?,
+                d.decode_with(ctx)?,
+            )),
+            2 => Ok(Relay::MultiHostName(d.decode_with(ctx)?)),
+            _ => Err(minicbor::decode::Error::message(
+                "invalid variant id for Relay",
+            )),
+        }
+    }
+}
+
+impl<C> minicbor::encode::Encode<C> for Relay {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        match self {
+            Relay::SingleHostAddr(port, ipv4, ipv6) => {
+                e.array(4)?;
+                e.u16(0)?;
+                e.encode_with(port, ctx)?;
+                e.encode_with(ipv4, ctx)?;
+                e.encode_with(ipv6, ctx)?;
+            }
+            Relay::SingleHostName(port, dns) => {
+                e.array(3)?;
+                e.u16(1)?;
+                e.encode_with(port, ctx)?;
+                e.encode_with(dns, ctx)?;
+            }
+            Relay::MultiHostName(dns) => {
+                e.array(2)?;
+                e.u16(2)?;
+                e.encode_with(dns, ctx)?;
+            }
+        };
+
+        Ok(())
+    }
+}
+
+impl<'b, C> minicbor::decode::Decode<'b, C> for HotCredentialAuthStatus {
+    fn decode(d: &mut minicbor::Decoder<'b>, ctx: &mut C) -> Result<Self, minicbor::decode::Error> {
+        d.array()?;
+
+        match d.u16()? {
+            0 => Ok(HotCredentialAuthStatus::Authorized(d.decode_with(ctx)?)),
+            1 => Ok(HotCredentialAuthStatus::NotAuthorized),
+            2 => Ok(HotCredentialAuthStatus::Resigned(d.decode_with(ctx)?)),
+            _ => Err(minicbor::decode::Error::message(
+                "invalid variant id for HotCredentialAuthStatus",
+            )),
+        }
+    }
+}
+
+impl<C> minicbor::encode::Encode<C> for HotCredentialAuthStatus {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        match self {
+            HotCredentialAuthStatus::Authorized(cred) => {
+                e.array(2)?;
+                e.u16(0)?;
+                e.encode_with(cred, ctx)?;
+            }
+            HotCredentialAuthStatus::NotAuthorized => {
+                e.array(1)?;
+                e.u16(1)?;
+            }
+            HotCredentialAuthStatus::Resigned(anchor) => {
+                e.array(2)?;
+                e.u16(2)?;
+                e.encode_with(anchor, ctx)?;
+            }
+        };
+
+        Ok(())
+    }
+}
+
+impl<'b, C> minicbor::decode::Decode<'b, C> for NextEpochChange {
+    fn decode(d: &mut minicbor::Decoder<'b>, ctx: &mut C) -> Result<Self, minicbor::decode::Error> {
+        d.array()?;
+
+        match d.u16()? {
+            0 => Ok(NextEpochChange::ToBeEnacted),
+            1 => Ok(NextEpochChange::ToBeRemoved),
+            2 => Ok(NextEpochChange::NoChangeExpected),
+            3 => Ok(NextEpochChange::ToBeExpired),
+            4 => Ok(NextEpochChange::TermAdjusted(d.decode_with(ctx)?)),
+            _ => Err(minicbor::decode::Error::message(
+                "invalid variant id for NextEpochChange",
+            )),
+        }
+    }
+}
+
+impl<C> minicbor::encode::Encode<C> for NextEpochChange {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        match self {
+            NextEpochChange::ToBeEnacted => {
+                e.array(1)?;
+                e.u16(0)?;
+            }
+            NextEpochChange::ToBeRemoved => {
+                e.array(1)?;
+                e.u16(1)?;
+            }
+            NextEpochChange::NoChangeExpected => {
+                e.array(1)?;
+                e.u16(2)?;
+            }
+            NextEpochChange::ToBeExpired => {
+                e.array(1)?;
+                e.u16(3)?;
+            }
+            NextEpochChange::TermAdjusted(epoch) => {
+                e.array(2)?;
+                e.u16(4)?;
+                e.encode_with(epoch, ctx)?;
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use pallas_codec::minicbor;
+
+    use super::*;
+
+    fn roundtrip<T>(value: T)
+    where
+        T: Encode<()> + for<'b> Decode<'b, ()> + PartialEq + Debug,
+    {
+        let bytes = minicbor::to_vec(&value).unwrap();
+        let decoded: T = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(decoded, value);
+    }
+
+    fn pool_id(byte: u8) -> Bytes {
+        vec![byte; 28].into()
+    }
+
+    fn stake_addrs() -> StakeAddrs {
+        Set::from(vec![
+            StakeAddr::AddrKeyhash(Hash::new([1; 28])),
+            StakeAddr::Scripthash(Hash::new([2; 28])),
+        ])
+    }
+
+    fn pool_params() -> PoolParams {
+        PoolParams {
+            operator: pool_id(1),
+            vrf_keyhash: Hash::new([3; 32]),
+            pledge: AnyUInt::U32(500_000_000),
+            cost: AnyUInt::U32(340_000_000),
+            margin: RationalNumber {
+                numerator: 1,
+                denominator: 100,
+            },
+            reward_account: vec![0xe1; 29].into(),
+            pool_owners: Set::from(vec![Hash::new([4; 28])]),
+            relays: vec![
+                Relay::SingleHostAddr(
+                    Nullable::Some(3001),
+                    Nullable::Some(vec![127, 0, 0, 1].into()),
+                    Nullable::Null,
+                ),
+                Relay::SingleHostName(Nullable::Null, "relay.example.com".into()),
+                Relay::MultiHostName("pool.example.com".into()),
+            ],
+            pool_metadata: Nullable::Some(PoolMetadata {
+                url: "https://example.com/pool.json".into(),
+                hash: Hash::new([5; 32]),
+            }),
+        }
+    }
+
+    fn block_query(query: BlockQuery) -> Request {
+        Request::LedgerQuery(LedgerQuery::BlockQuery(5, query))
+    }
+
+    #[test]
+    fn typed_block_queries_roundtrip() {
+        let pools: Pools = [pool_id(1), pool_id(2)].into_iter().collect();
+
+        let queries = vec![
+            BlockQuery::GetNonMyopicMemberRewards(Set::from(vec![
+                Amount::Coin(AnyUInt::U32(1_000_000)),
+                Amount::StakeAddr(StakeAddr::AddrKeyhash(Hash::new([1; 28]))),
+            ])),
+            BlockQuery::GetProposedPParamsUpdates,
+            BlockQuery::GetFilteredDelegationsAndRewardAccounts(stake_addrs()),
+            BlockQuery::GetRewardProvenance,
+            BlockQuery::GetUTxOByTxIn(Set::from(vec![UTxO {
+                transaction_id: Hash::new([6; 32]),
+                index: AnyUInt::MajorByte(1),
+            }])),
+            BlockQuery::GetStakePools,
+            BlockQuery::GetStakePoolParams(pools.clone()),
+            BlockQuery::GetRewardInfoPools,
+            BlockQuery::GetPoolState(pools.clone()),
+            BlockQuery::GetPoolState(Pools::new()),
+            BlockQuery::GetStakeSnapshots(pools.clone()),
+            BlockQuery::GetStakeSnapshots(Pools::new()),
+            BlockQuery::GetPoolDistr(pools),
+            BlockQuery::GetPoolDistr(Pools::new()),
+            BlockQuery::GetStakeDelegDeposits(stake_addrs()),
+        ];
+
+        for query in queries {
+            roundtrip(block_query(query));
+        }
+    }
+
+    #[test]
+    fn maybe_pool_set_encodes_as_zero_or_one_array() {
+        let all = minicbor::to_vec(BlockQuery::GetPoolDistr(Pools::new())).unwrap();
+        assert_eq!(hex::encode(all), "821580");
+
+        let pools = [Bytes::from(vec![0xaa])].into_iter().collect();
+        let some = minicbor::to_vec(BlockQuery::GetPoolDistr(pools)).unwrap();
+        assert_eq!(hex::encode(some), "821581d901028141aa");
+    }
+
+    #[test]
+    fn stake_addr_encodes_as_credential() {
+        let addr = StakeAddr::Scripthash(Hash::new([0; 28]));
+        let bytes = minicbor::to_vec(&addr).unwrap();
+
+        assert_eq!(hex::encode(&bytes[..4]), "8201581c");
+    }
+
+    #[test]
+    fn typed_query_responses_roundtrip() {
+        roundtrip(NonMyopicMemberRewards {
+            rewards: KeyValuePairs::from(vec![(
+                Amount::Coin(AnyUInt::U32(1_000_000)),
+                KeyValuePairs::from(vec![(pool_id(1), AnyUInt::U16(1_234))]),
+            )]),
+        });
+
+        roundtrip(ProposedPParamsUpdates {
+            updates: KeyValuePairs::from(vec![(
+                pool_id(7),
+                ProtocolParamUpdate {
+                    minfee_a: Some(44),
+                    minfee_b: None,
+                    max_block_body_size: None,
+                    max_transaction_size: None,
+                    max_block_header_size: None,
+                    key_deposit: None,
+                    pool_deposit: None,
+                    maximum_epoch: None,
+                    desired_number_of_stake_pools: Some(500),
+                    pool_pledge_influence: None,
+                    expansion_rate: None,
+                    treasury_growth_rate: None,
+                    protocol_version: Some((8, 0)),
+                    min_pool_cost: None,
+                    ada_per_utxo_byte: None,
+                    cost_models_for_script_languages: None,
+                    execution_costs: None,
+                    max_tx_ex_units: None,
+                    max_block_ex_units: None,
+                    max_value_size: None,
+                    collateral_percentage: None,
+                    max_collateral_inputs: None,
+                },
+            )]),
+        });
+
+        roundtrip(FilteredDelegationsAndRewardAccounts {
+            delegations: KeyValuePairs::from(vec![(
+                StakeAddr::AddrKeyhash(Hash::new([1; 28])),
+                pool_id(1),
+            )]),
+            rewards: KeyValuePairs::from(vec![(
+                StakeAddr::AddrKeyhash(Hash::new([1; 28])),
+                AnyUInt::U32(2_000_000),
+            )]),
+        });
+
+        roundtrip(StakePoolParams {
+            pools: KeyValuePairs::from(vec![(pool_id(1), pool_params())]),
+        });
+
+        roundtrip(PoolState {
+            stake_pool_params: KeyValuePairs::from(vec![(pool_id(1), pool_params())]),
+            future_stake_pool_params: KeyValuePairs::from(vec![]),
+            retiring: KeyValuePairs::from(vec![(pool_id(1), 300)]),
+            deposits: KeyValuePairs::from(vec![(pool_id(1), AnyUInt::U32(500_000_000))]),
+        });
+
+        roundtrip(PoolDistr {
+            pools: KeyValuePairs::from(vec![(
+                pool_id(1),
+                Pool {
+                    stakes: Fraction { num: 1, dem: 3 },
+                    hashes: vec![8; 32].into(),
+                },
+            )]),
+        });
+
+        roundtrip(StakeDelegDeposits {
+            deposits: KeyValuePairs::from(vec![(
+                StakeAddr::Scripthash(Hash::new([2; 28])),
+                AnyUInt::U32(2_000_000),
+            )]),
+        });
+
+        roundtrip(RewardProvenance {
+            slots_per_epoch: 432_000,
+            blocks: KeyValuePairs::from(vec![(pool_id(1), 12)]),
+            max_lovelace_supply: AnyUInt::U64(45_000_000_000_000_000),
+            delta_r1: AnyUInt::U32(1_000),
+            delta_r2: AnyUInt::U32(2_000),
+            r: AnyUInt::U32(3_000),
+            total_stake: AnyUInt::U64(30_000_000_000_000_000),
+            blocks_count: 21_600,
+            d: Fraction { num: 0, dem: 1 },
+            expected_blocks: 21_600,
+            eta: Fraction { num: 1, dem: 1 },
+            reward_pot: AnyUInt::U32(4_000),
+            delta_t1: AnyUInt::U32(5_000),
+            active_stake: AnyUInt::U64(20_000_000_000_000_000),
+            pools: KeyValuePairs::from(vec![(
+                pool_id(1),
+                RewardProvenancePool {
+                    pool_blocks: 12,
+                    sigma: Fraction { num: 1, dem: 1000 },
+                    sigma_a: Fraction { num: 1, dem: 900 },
+                    owner_stake: AnyUInt::U32(500_000_000),
+                    pool_params: pool_params(),
+                    pledge_ratio: Fraction { num: 1, dem: 10 },
+                    max_pool: AnyUInt::U32(60_000_000),
+                    apparent_performance: Fraction { num: 9, dem: 10 },
+                    pool_rewards: AnyUInt::U32(50_000_000),
+                    leader_reward: AnyUInt::U32(10_000_000),
+                },
+            )]),
+            desirabilities: KeyValuePairs::from(vec![(
+                pool_id(1),
+                Desirability {
+                    score: 0.5,
+                    potential: 1.25,
+                },
+            )]),
+        });
+
+        roundtrip(RewardInfoPools {
+            reward_params: RewardParams {
+                desired_number_of_stake_pools: 500,
+                pool_pledge_influence: RationalNumber {
+                    numerator: 3,
+                    denominator: 10,
+                },
+                reward_pot: AnyUInt::U32(4_000),
+                total_stake: AnyUInt::U64(30_000_000_000_000_000),
+            },
+            pools: KeyValuePairs::from(vec![(
+                pool_id(1),
+                RewardInfoPool {
+                    stake: AnyUInt::U32(1_000_000_000),
+                    owner_pledge: AnyUInt::U32(500_000_000),
+                    owner_stake: AnyUInt::U32(500_000_000),
+                    cost: AnyUInt::U32(340_000_000),
+                    margin: RationalNumber {
+                        numerator: 1,
+                        denominator: 100,
+                    },
+                    performance_estimate: 0.95,
+                },
+            )]),
+        });
+    }
+
+    fn anchor() -> Anchor {
+        Anchor {
+            url: "https://example.com/anchor.json".into(),
+            content_hash: Hash::new([9; 32]),
+        }
+    }
+
+    fn gov_action_id() -> GovActionId {
+        GovActionId {
+            transaction_id: Hash::new([10; 32]),
+            action_index: 0,
+        }
+    }
+
+    #[test]
+    fn governance_queries_roundtrip() {
+        let cred = StakeCredential::AddrKeyhash(Hash::new([1; 28]));
+        let pools: Pools = [pool_id(1)].into_iter().collect();
+
+        let queries = vec![
+            BlockQuery::GetConstitution,
+            BlockQuery::GetGovState,
+            BlockQuery::GetDRepState(Set::from(vec![cred.clone()])),
+            BlockQuery::GetDRepStakeDistr(Set::from(vec![
+                DRep::Key(Hash::new([1; 28])),
+                DRep::Abstain,
+            ])),
+            BlockQuery::GetCommitteeMembersState(
+                Set::from(vec![cred.clone()]),
+                Set::from(vec![]),
+                Set::from(vec![MemberStatus::Active, MemberStatus::Expired]),
+            ),
+            BlockQuery::GetFilteredVoteDelegatees(Set::from(vec![cred])),
+            BlockQuery::GetAccountState,
+            BlockQuery::GetSPOStakeDistr(pools),
+        ];
+
+        for query in queries {
+            roundtrip(block_query(query));
+        }
+    }
+
+    #[test]
+    fn drep_state_accepts_missing_delegators() {
+        let state = DRepState {
+            expiry: 200,
+            anchor: Some(anchor()).into(),
+            deposit: AnyUInt::U32(500_000_000),
+            delegators: None,
+        };
+
+        let bytes = minicbor::to_vec(&state).unwrap();
+        assert_eq!(hex::encode(&bytes[..1]), "83");
+        roundtrip(state);
+
+        roundtrip(DRepState {
+            expiry: 200,
+            anchor: None.into(),
+            deposit: AnyUInt::U32(500_000_000),
+            delegators: Some(Set::from(vec![StakeCredential::Scripthash(Hash::new(
+                [2; 28],
+            ))])),
+        });
+    }
+
+    #[test]
+    fn governance_responses_roundtrip() {
+        let cold = StakeCredential::AddrKeyhash(Hash::new([1; 28]));
+        let hot = StakeCredential::Scripthash(Hash::new([2; 28]));
+
+        let constitution = Constitution {
+            anchor: anchor(),
+            guardrail_script: Nullable::Some(Hash::new([3; 28])),
+        };
+
+        roundtrip(GovState {
+            proposals: Proposals {
+                roots: ProposalRoots {
+                    pparam_update: None.into(),
+                    hard_fork: Some(gov_action_id()).into(),
+                    committee: None.into(),
+                    constitution: None.into(),
+                },
+                actions: vec![GovActionState {
+                    id: gov_action_id(),
+                    committee_votes: KeyValuePairs::from(vec![(hot.clone(), Vote::Yes)]),
+                    drep_votes: KeyValuePairs::from(vec![(cold.clone(), Vote::No)]),
+                    spo_votes: KeyValuePairs::from(vec![(Hash::new([4; 28]), Vote::Abstain)]),
+                    proposal_procedure: ProposalProcedure {
+                        deposit: 100_000_000_000,
+                        reward_account: vec![0xe1; 29].into(),
+                        gov_action: pallas_primitives::conway::GovAction::Information,
+                        anchor: anchor(),
+                    },
+                    proposed_in: 500,
+                    expires_after: 506,
+                }],
+            },
+            committee: Some(Committee {
+                members: KeyValuePairs::from(vec![(cold.clone(), 600)]),
+                threshold: RationalNumber {
+                    numerator: 2,
+                    denominator: 3,
+                },
+            })
+            .into(),
+            constitution: constitution.clone(),
+            current_pparams: AnyCbor::from_encode(0u8),
+            previous_pparams: AnyCbor::from_encode(0u8),
+            future_pparams: AnyCbor::from_encode((0u8,)),
+            drep_pulsing_state: AnyCbor::from_encode(0u8),
+        });
+
+        roundtrip(DRepStakeDistr {
+            dreps: KeyValuePairs::from(vec![
+                (DRep::Script(Hash::new([5; 28])), AnyUInt::U32(1_000_000)),
+                (DRep::NoConfidence, AnyUInt::U32(2_000_000)),
+            ]),
+        });
+
+        roundtrip(CommitteeMembersState {
+            members: KeyValuePairs::from(vec![(
+                cold.clone(),
+                CommitteeMemberState {
+                    hot_credential_status: HotCredentialAuthStatus::Authorized(hot),
+                    status: MemberStatus::Active,
+                    expiration: Some(600).into(),
+                    next_epoch_change: NextEpochChange::TermAdjusted(610),
+                },
+            )]),
+            threshold: Some(RationalNumber {
+                numerator: 2,
+                denominator: 3,
+            })
+            .into(),
+            epoch: 505,
+        });
+
+        roundtrip(CommitteeMemberState {
+            hot_credential_status: HotCredentialAuthStatus::Resigned(Some(anchor()).into()),
+            status: MemberStatus::Unrecognized,
+            expiration: None.into(),
+            next_epoch_change: NextEpochChange::NoChangeExpected,
+        });
+
+        roundtrip(FilteredVoteDelegatees {
+            delegatees: KeyValuePairs::from(vec![(cold, DRep::Abstain)]),
+        });
+
+        roundtrip(AccountState {
+            treasury: AnyUInt::U64(1_500_000_000_000_000),
+            reserves: AnyUInt::U64(7_000_000_000_000_000),
+        });
+
+        roundtrip(SPOStakeDistr {
+            pools: KeyValuePairs::from(vec![(pool_id(1), AnyUInt::U32(3_000_000))]),
+        });
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn constitution_hash_is_an_alias() {
+        let alias = minicbor::to_vec(block_query(BlockQuery::GetConstitutionHash)).unwrap();
+        let query = minicbor::to_vec(block_query(BlockQuery::GetConstitution)).unwrap();
+
+        assert_eq!(alias, query);
+    }
+}
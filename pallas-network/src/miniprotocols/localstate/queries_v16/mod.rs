@@ -6,10 +6,13 @@ use std::hash::Hash as StdHash;
 // required for derive attrs to work
 use pallas_codec::minicbor::{self};
 
-use pallas_codec::utils::{AnyUInt, Bytes, KeyValuePairs, TagWrap};
-use pallas_codec::{
-    minicbor::{Decode, Encode},
-    utils::AnyCbor,
+use pallas_codec::minicbor::{Decode, Encode};
+use pallas_codec::utils::{
+    AnyCbor, AnyUInt, Bytes, KeyValuePairs, Nullable, Set, TagWrap, ZeroOrOneArray,
+};
+use pallas_primitives::conway::{
+    Anchor, CommitteeColdCredential, CommitteeHotCredential, Constitution, DRep, DRepCredential,
+    GovActionId, ProposalProcedure, StakeCredential, Vote,
 };
 
 use crate::miniprotocols::Point;
@@ -24,7 +27,7 @@ mod codec;
 pub enum BlockQuery {
     GetLedgerTip,
     GetEpochNo,
-    GetNonMyopicMemberRewards(AnyCbor),
+    GetNonMyopicMemberRewards(Amounts),
     GetCurrentPParams,
     GetProposedPParamsUpdates,
     GetStakeDistribution,
@@ -32,20 +35,33 @@ pub enum BlockQuery {
     GetUTxOWhole,
     DebugEpochState,
     GetCBOR(Box<BlockQuery>),
-    GetFilteredDelegationsAndRewardAccounts(AnyCbor),
+    GetFilteredDelegationsAndRewardAccounts(StakeAddrs),
     GetGenesisConfig,
     DebugNewEpochState,
     DebugChainDepState,
     GetRewardProvenance,
-    GetUTxOByTxIn(AnyCbor),
+    GetUTxOByTxIn(TxIns),
     GetStakePools,
-    GetStakePoolParams(AnyCbor),
+    GetStakePoolParams(Pools),
     GetRewardInfoPools,
-    GetPoolState(AnyCbor),
+    GetPoolState(Pools),
     GetStakeSnapshots(Pools),
-    GetPoolDistr(AnyCbor),
-    GetStakeDelegDeposits(AnyCbor),
+    GetPoolDistr(Pools),
+    GetStakeDelegDeposits(StakeAddrs),
+    #[deprecated(note = "use `GetConstitution`, the query returns the whole constitution")]
     GetConstitutionHash,
+    GetConstitution,
+    GetGovState,
+    GetDRepState(DRepCredentials),
+    GetDRepStakeDistr(DReps),
+    GetCommitteeMembersState(
+        CommitteeColdCredentials,
+        CommitteeHotCredentials,
+        MemberStatuses,
+    ),
+    GetFilteredVoteDelegatees(StakeCredentials),
+    GetAccountState,
+    GetSPOStakeDistr(Pools),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -79,7 +95,7 @@ pub enum Value {
     Multiasset(Coin, Multiasset<Coin>),
 }
 
-#[derive(Debug, Encode, Decode, PartialEq)]
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
 pub struct SystemStart {
     #[n(0)]
     pub year: u32,
@@ -351,6 +367,527 @@ pub struct Genesis {
     pub max_lovelace_supply: Coin,
 }
 
+pub type AddrKeyhash = Hash<28>;
+
+pub type Scripthash = Hash<28>;
+
+/// A stake credential, either a key hash or a script hash.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, StdHash)]
+pub enum StakeAddr {
+    AddrKeyhash(AddrKeyhash),
+    Scripthash(Scripthash),
+}
+
+pub type StakeAddrs = Set<StakeAddr>;
+
+/// Either an amount of stake or a stake credential, used to ask for the
+/// non-myopic rewards of a (hypothetical) delegator.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum Amount {
+    Coin(Coin),
+    StakeAddr(StakeAddr),
+}
+
+pub type Amounts = Set<Amount>;
+
+pub type TxIns = Set<UTxO>;
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct NonMyopicMemberRewards {
+    #[n(0)]
+    pub rewards: KeyValuePairs<Amount, KeyValuePairs<Bytes, Coin>>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+#[cbor(map)]
+pub struct ProtocolParamUpdate {
+    #[n(0)]
+    pub minfee_a: Option<u32>,
+    #[n(1)]
+    pub minfee_b: Option<u32>,
+    #[n(2)]
+    pub max_block_body_size: Option<u32>,
+    #[n(3)]
+    pub max_transaction_size: Option<u32>,
+    #[n(4)]
+    pub max_block_header_size: Option<u32>,
+    #[n(5)]
+    pub key_deposit: Option<Coin>,
+    #[n(6)]
+    pub pool_deposit: Option<Coin>,
+    #[n(7)]
+    pub maximum_epoch: Option<Epoch>,
+    #[n(8)]
+    pub desired_number_of_stake_pools: Option<u32>,
+    #[n(9)]
+    pub pool_pledge_influence: Option<RationalNumber>,
+    #[n(10)]
+    pub expansion_rate: Option<UnitInterval>,
+    #[n(11)]
+    pub treasury_growth_rate: Option<UnitInterval>,
+    #[n(14)]
+    pub protocol_version: Option<(ProtocolVersionMajor, ProtocolVersionMinor)>,
+    #[n(16)]
+    pub min_pool_cost: Option<Coin>,
+    #[n(17)]
+    pub ada_per_utxo_byte: Option<Coin>,
+    #[n(18)]
+    pub cost_models_for_script_languages: Option<CostMdls>,
+    #[n(19)]
+    pub execution_costs: Option<ExUnitPrices>,
+    #[n(20)]
+    pub max_tx_ex_units: Option<ExUnits>,
+    #[n(21)]
+    pub max_block_ex_units: Option<ExUnits>,
+    #[n(22)]
+    pub max_value_size: Option<u32>,
+    #[n(23)]
+    pub collateral_percentage: Option<u32>,
+    #[n(24)]
+    pub max_collateral_inputs: Option<u32>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct ProposedPParamsUpdates {
+    #[n(0)]
+    pub updates: KeyValuePairs<Bytes, ProtocolParamUpdate>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct FilteredDelegationsAndRewardAccounts {
+    #[n(0)]
+    pub delegations: KeyValuePairs<StakeAddr, Bytes>,
+
+    #[n(1)]
+    pub rewards: KeyValuePairs<StakeAddr, Coin>,
+}
+
+pub type UTxOByTxIn = UTxOByAddress;
+
+pub type Port = u32;
+
+pub type IPv4 = Bytes;
+
+pub type IPv6 = Bytes;
+
+pub type DnsName = String;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Relay {
+    SingleHostAddr(Nullable<Port>, Nullable<IPv4>, Nullable<IPv6>),
+    SingleHostName(Nullable<Port>, DnsName),
+    MultiHostName(DnsName),
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Eq, Clone)]
+pub struct PoolMetadata {
+    #[n(0)]
+    pub url: String,
+
+    #[n(1)]
+    pub hash: Hash<32>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Eq, Clone)]
+pub struct PoolParams {
+    #[n(0)]
+    pub operator: Bytes,
+
+    #[n(1)]
+    pub vrf_keyhash: Hash<32>,
+
+    #[n(2)]
+    pub pledge: Coin,
+
+    #[n(3)]
+    pub cost: Coin,
+
+    #[n(4)]
+    pub margin: UnitInterval,
+
+    #[n(5)]
+    pub reward_account: Bytes,
+
+    #[n(6)]
+    pub pool_owners: Set<AddrKeyhash>,
+
+    #[n(7)]
+    pub relays: Vec<Relay>,
+
+    #[n(8)]
+    pub pool_metadata: Nullable<PoolMetadata>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct StakePoolParams {
+    #[n(0)]
+    pub pools: KeyValuePairs<Bytes, PoolParams>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct PoolState {
+    #[n(0)]
+    pub stake_pool_params: KeyValuePairs<Bytes, PoolParams>,
+
+    #[n(1)]
+    pub future_stake_pool_params: KeyValuePairs<Bytes, PoolParams>,
+
+    #[n(2)]
+    pub retiring: KeyValuePairs<Bytes, Epoch>,
+
+    #[n(3)]
+    pub deposits: KeyValuePairs<Bytes, Coin>,
+}
+
+pub type PoolDistr = StakeDistribution;
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct StakeDelegDeposits {
+    #[n(0)]
+    pub deposits: KeyValuePairs<StakeAddr, Coin>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct RewardProvenancePool {
+    #[n(0)]
+    pub pool_blocks: u64,
+
+    #[n(1)]
+    pub sigma: Fraction,
+
+    #[n(2)]
+    pub sigma_a: Fraction,
+
+    #[n(3)]
+    pub owner_stake: Coin,
+
+    #[n(4)]
+    pub pool_params: PoolParams,
+
+    #[n(5)]
+    pub pledge_ratio: Fraction,
+
+    #[n(6)]
+    pub max_pool: Coin,
+
+    #[n(7)]
+    pub apparent_performance: Fraction,
+
+    #[n(8)]
+    pub pool_rewards: Coin,
+
+    #[n(9)]
+    pub leader_reward: Coin,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct Desirability {
+    #[n(0)]
+    pub score: f64,
+
+    #[n(1)]
+    pub potential: f64,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct RewardProvenance {
+    #[n(0)]
+    pub slots_per_epoch: u64,
+
+    #[n(1)]
+    pub blocks: KeyValuePairs<Bytes, u64>,
+
+    #[n(2)]
+    pub max_lovelace_supply: Coin,
+
+    #[n(3)]
+    pub delta_r1: Coin,
+
+    #[n(4)]
+    pub delta_r2: Coin,
+
+    #[n(5)]
+    pub r: Coin,
+
+    #[n(6)]
+    pub total_stake: Coin,
+
+    #[n(7)]
+    pub blocks_count: u64,
+
+    #[n(8)]
+    pub d: Fraction,
+
+    #[n(9)]
+    pub expected_blocks: u64,
+
+    #[n(10)]
+    pub eta: Fraction,
+
+    #[n(11)]
+    pub reward_pot: Coin,
+
+    #[n(12)]
+    pub delta_t1: Coin,
+
+    #[n(13)]
+    pub active_stake: Coin,
+
+    #[n(14)]
+    pub pools: KeyValuePairs<Bytes, RewardProvenancePool>,
+
+    #[n(15)]
+    pub desirabilities: KeyValuePairs<Bytes, Desirability>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct RewardParams {
+    #[n(0)]
+    pub desired_number_of_stake_pools: u64,
+
+    #[n(1)]
+    pub pool_pledge_influence: RationalNumber,
+
+    #[n(2)]
+    pub reward_pot: Coin,
+
+    #[n(3)]
+    pub total_stake: Coin,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct RewardInfoPool {
+    #[n(0)]
+    pub stake: Coin,
+
+    #[n(1)]
+    pub owner_pledge: Coin,
+
+    #[n(2)]
+    pub owner_stake: Coin,
+
+    #[n(3)]
+    pub cost: Coin,
+
+    #[n(4)]
+    pub margin: UnitInterval,
+
+    #[n(5)]
+    pub performance_estimate: f64,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct RewardInfoPools {
+    #[n(0)]
+    pub reward_params: RewardParams,
+
+    #[n(1)]
+    pub pools: KeyValuePairs<Bytes, RewardInfoPool>,
+}
+
+pub type DRepCredentials = Set<DRepCredential>;
+
+pub type DReps = Set<DRep>;
+
+pub type CommitteeColdCredentials = Set<CommitteeColdCredential>;
+
+pub type CommitteeHotCredentials = Set<CommitteeHotCredential>;
+
+pub type StakeCredentials = Set<StakeCredential>;
+
+#[derive(Debug, Encode, Decode, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[cbor(index_only)]
+pub enum MemberStatus {
+    #[n(0)]
+    Active,
+
+    #[n(1)]
+    Expired,
+
+    #[n(2)]
+    Unrecognized,
+}
+
+pub type MemberStatuses = Set<MemberStatus>;
+
+/// The previously enacted governance action of each purpose, which new
+/// proposals of the same purpose have to reference.
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct ProposalRoots {
+    #[n(0)]
+    pub pparam_update: ZeroOrOneArray<GovActionId>,
+
+    #[n(1)]
+    pub hard_fork: ZeroOrOneArray<GovActionId>,
+
+    #[n(2)]
+    pub committee: ZeroOrOneArray<GovActionId>,
+
+    #[n(3)]
+    pub constitution: ZeroOrOneArray<GovActionId>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct GovActionState {
+    #[n(0)]
+    pub id: GovActionId,
+
+    #[n(1)]
+    pub committee_votes: KeyValuePairs<CommitteeHotCredential, Vote>,
+
+    #[n(2)]
+    pub drep_votes: KeyValuePairs<DRepCredential, Vote>,
+
+    #[n(3)]
+    pub spo_votes: KeyValuePairs<AddrKeyhash, Vote>,
+
+    #[n(4)]
+    pub proposal_procedure: ProposalProcedure,
+
+    #[n(5)]
+    pub proposed_in: Epoch,
+
+    #[n(6)]
+    pub expires_after: Epoch,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct Proposals {
+    #[n(0)]
+    pub roots: ProposalRoots,
+
+    #[n(1)]
+    pub actions: Vec<GovActionState>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct Committee {
+    #[n(0)]
+    pub members: KeyValuePairs<CommitteeColdCredential, Epoch>,
+
+    #[n(1)]
+    pub threshold: UnitInterval,
+}
+
+/// The Conway governance state. Protocol parameters and the DRep pulsing
+/// state are left as raw CBOR, use [`AnyCbor::into_decode`] to inspect them.
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct GovState {
+    #[n(0)]
+    pub proposals: Proposals,
+
+    #[n(1)]
+    pub committee: ZeroOrOneArray<Committee>,
+
+    #[n(2)]
+    pub constitution: Constitution,
+
+    #[n(3)]
+    pub current_pparams: AnyCbor,
+
+    #[n(4)]
+    pub previous_pparams: AnyCbor,
+
+    #[n(5)]
+    pub future_pparams: AnyCbor,
+
+    #[n(6)]
+    pub drep_pulsing_state: AnyCbor,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct DRepState {
+    #[n(0)]
+    pub expiry: Epoch,
+
+    #[n(1)]
+    pub anchor: ZeroOrOneArray<Anchor>,
+
+    #[n(2)]
+    pub deposit: Coin,
+
+    /// Only reported by nodes running ledger versions that track delegators.
+    #[n(3)]
+    pub delegators: Option<Set<StakeCredential>>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct DRepStates {
+    #[n(0)]
+    pub dreps: KeyValuePairs<DRepCredential, DRepState>,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct DRepStakeDistr {
+    #[n(0)]
+    pub dreps: KeyValuePairs<DRep, Coin>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum HotCredentialAuthStatus {
+    Authorized(CommitteeHotCredential),
+    NotAuthorized,
+    Resigned(ZeroOrOneArray<Anchor>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum NextEpochChange {
+    ToBeEnacted,
+    ToBeRemoved,
+    NoChangeExpected,
+    ToBeExpired,
+    TermAdjusted(Epoch),
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct CommitteeMemberState {
+    #[n(0)]
+    pub hot_credential_status: HotCredentialAuthStatus,
+
+    #[n(1)]
+    pub status: MemberStatus,
+
+    #[n(2)]
+    pub expiration: ZeroOrOneArray<Epoch>,
+
+    #[n(3)]
+    pub next_epoch_change: NextEpochChange,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct CommitteeMembersState {
+    #[n(0)]
+    pub members: KeyValuePairs<CommitteeColdCredential, CommitteeMemberState>,
+
+    #[n(1)]
+    pub threshold: ZeroOrOneArray<UnitInterval>,
+
+    #[n(2)]
+    pub epoch: Epoch,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct FilteredVoteDelegatees {
+    #[n(0)]
+    pub delegatees: KeyValuePairs<StakeCredential, DRep>,
+}
+
+/// The treasury and reserves pots.
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct AccountState {
+    #[n(0)]
+    pub treasury: Coin,
+
+    #[n(1)]
+    pub reserves: Coin,
+}
+
+#[derive(Debug, Encode, Decode, PartialEq, Clone)]
+pub struct SPOStakeDistr {
+    #[n(0)]
+    pub pools: KeyValuePairs<Bytes, Coin>,
+}
+
 /// Get the current tip of the ledger.
 pub async fn get_chain_point(client: &mut Client) -> Result<Point, ClientError> {
     let query = Request::GetChainPoint;
@@ -477,3 +1014,269 @@ pub async fn get_genesis_config(
 
     Ok(result)
 }
+
+/// Get the non-myopic member rewards for the given amounts or stake
+/// credentials.
+pub async fn get_non_myopic_member_rewards(
+    client: &mut Client,
+    era: u16,
+    amounts: Amounts,
+) -> Result<NonMyopicMemberRewards, ClientError> {
+    let query = BlockQuery::GetNonMyopicMemberRewards(amounts);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the protocol parameter updates proposed by the genesis delegates.
+pub async fn get_proposed_pparams_updates(
+    client: &mut Client,
+    era: u16,
+) -> Result<ProposedPParamsUpdates, ClientError> {
+    let query = BlockQuery::GetProposedPParamsUpdates;
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the delegations and reward account balances of the given stake
+/// credentials.
+pub async fn get_filtered_delegations_and_reward_accounts(
+    client: &mut Client,
+    era: u16,
+    addrs: StakeAddrs,
+) -> Result<FilteredDelegationsAndRewardAccounts, ClientError> {
+    let query = BlockQuery::GetFilteredDelegationsAndRewardAccounts(addrs);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (_,) = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the reward provenance for the current epoch.
+pub async fn get_reward_provenance(
+    client: &mut Client,
+    era: u16,
+) -> Result<RewardProvenance, ClientError> {
+    let query = BlockQuery::GetRewardProvenance;
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (_,) = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the UTxOs for the given transaction inputs.
+pub async fn get_utxo_by_txin(
+    client: &mut Client,
+    era: u16,
+    txins: TxIns,
+) -> Result<UTxOByTxIn, ClientError> {
+    let query = BlockQuery::GetUTxOByTxIn(txins);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the ids of all the registered stake pools.
+pub async fn get_stake_pools(client: &mut Client, era: u16) -> Result<Pools, ClientError> {
+    let query = BlockQuery::GetStakePools;
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (Set<Bytes>,) = client.query(query).await?;
+
+    Ok(result.to_vec().into_iter().collect())
+}
+
+/// Get the registration parameters of the given stake pools.
+pub async fn get_stake_pool_params(
+    client: &mut Client,
+    era: u16,
+    pools: Pools,
+) -> Result<StakePoolParams, ClientError> {
+    let query = BlockQuery::GetStakePoolParams(pools);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the reward parameters and the per-pool reward information.
+pub async fn get_reward_info_pools(
+    client: &mut Client,
+    era: u16,
+) -> Result<RewardInfoPools, ClientError> {
+    let query = BlockQuery::GetRewardInfoPools;
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (_,) = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the pool state for the given stake pools.
+/// If `pools` are empty, all pools are queried.
+pub async fn get_pool_state(
+    client: &mut Client,
+    era: u16,
+    pools: Pools,
+) -> Result<PoolState, ClientError> {
+    let query = BlockQuery::GetPoolState(pools);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (_,) = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the stake distribution for the given stake pools.
+/// If `pools` are empty, all pools are queried.
+pub async fn get_pool_distr(
+    client: &mut Client,
+    era: u16,
+    pools: Pools,
+) -> Result<PoolDistr, ClientError> {
+    let query = BlockQuery::GetPoolDistr(pools);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the key deposits of the given stake credentials.
+pub async fn get_stake_deleg_deposits(
+    client: &mut Client,
+    era: u16,
+    addrs: StakeAddrs,
+) -> Result<StakeDelegDeposits, ClientError> {
+    let query = BlockQuery::GetStakeDelegDeposits(addrs);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the current constitution.
+pub async fn get_constitution(client: &mut Client, era: u16) -> Result<Constitution, ClientError> {
+    let query = BlockQuery::GetConstitution;
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (_,) = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the governance state: proposals, committee, constitution and
+/// protocol parameters.
+pub async fn get_gov_state(client: &mut Client, era: u16) -> Result<GovState, ClientError> {
+    let query = BlockQuery::GetGovState;
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (_,) = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the state of the given DReps, or of all of them if the set is empty.
+pub async fn get_drep_state(
+    client: &mut Client,
+    era: u16,
+    dreps: DRepCredentials,
+) -> Result<DRepStates, ClientError> {
+    let query = BlockQuery::GetDRepState(dreps);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the stake delegated to the given DReps, or to all of them if the set
+/// is empty.
+pub async fn get_drep_stake_distr(
+    client: &mut Client,
+    era: u16,
+    dreps: DReps,
+) -> Result<DRepStakeDistr, ClientError> {
+    let query = BlockQuery::GetDRepStakeDistr(dreps);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the state of the committee members matching the given cold
+/// credentials, hot credentials and statuses. Empty sets match everything.
+pub async fn get_committee_members_state(
+    client: &mut Client,
+    era: u16,
+    cold: CommitteeColdCredentials,
+    hot: CommitteeHotCredentials,
+    statuses: MemberStatuses,
+) -> Result<CommitteeMembersState, ClientError> {
+    let query = BlockQuery::GetCommitteeMembersState(cold, hot, statuses);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (_,) = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the DReps the given stake credentials delegate their votes to.
+pub async fn get_filtered_vote_delegatees(
+    client: &mut Client,
+    era: u16,
+    creds: StakeCredentials,
+) -> Result<FilteredVoteDelegatees, ClientError> {
+    let query = BlockQuery::GetFilteredVoteDelegatees(creds);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the key deposits of the given stake credentials.
+#[deprecated(note = "use `get_stake_deleg_deposits`, both run the same query")]
+pub async fn get_accounts_deposits(
+    client: &mut Client,
+    era: u16,
+    addrs: StakeAddrs,
+) -> Result<StakeDelegDeposits, ClientError> {
+    get_stake_deleg_deposits(client, era, addrs).await
+}
+
+/// Get the treasury and reserves pots.
+pub async fn get_account_state(client: &mut Client, era: u16) -> Result<AccountState, ClientError> {
+    let query = BlockQuery::GetAccountState;
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let (result,): (_,) = client.query(query).await?;
+
+    Ok(result)
+}
+
+/// Get the stake of the given pools, or of all of them if the set is empty.
+pub async fn get_spo_stake_distr(
+    client: &mut Client,
+    era: u16,
+    pools: Pools,
+) -> Result<SPOStakeDistr, ClientError> {
+    let query = BlockQuery::GetSPOStakeDistr(pools);
+    let query = LedgerQuery::BlockQuery(era, query);
+    let query = Request::LedgerQuery(query);
+    let result = client.query(query).await?;
+
+    Ok(result)
+}
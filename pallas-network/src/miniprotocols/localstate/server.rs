@@ -3,7 +3,7 @@ use std::fmt::Debug;
 use thiserror::*;
 
 use super::{AcquireFailure, Message, State};
-use crate::miniprotocols::Point;
+use crate::miniprotocols::{Point, StateTimeouts};
 use crate::multiplexer;
 
 #[derive(Error, Debug)]
@@ -31,11 +31,20 @@ pub enum ClientQueryRequest {
     Release,
 }
 
-pub struct GenericServer(State, multiplexer::ChannelBuffer);
+pub struct GenericServer(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
 
 impl GenericServer {
     pub fn new(channel: multiplexer::AgentChannel) -> Self {
-        Self(State::Idle, multiplexer::ChannelBuffer::new(channel))
+        Self(
+            State::Idle,
+            multiplexer::ChannelBuffer::new(channel),
+            StateTimeouts::default(),
+        )
+    }
+
+    /// Sets the max time to wait for the client in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
     }
 
     pub fn state(&self) -> &State {
@@ -96,6 +105,7 @@ impl GenericServer {
 
     pub async fn recv_message(&mut self) -> Result<Message, Error> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(Error::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
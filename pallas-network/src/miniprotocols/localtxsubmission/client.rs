@@ -6,6 +6,7 @@ use tracing::debug;
 use pallas_codec::Fragment;
 
 use crate::miniprotocols::localtxsubmission::{EraTx, Message, RejectReason, State};
+use crate::miniprotocols::StateTimeouts;
 use crate::multiplexer;
 
 /// Cardano specific instantiation of LocalTxSubmission client.
@@ -16,6 +17,7 @@ pub type Client = GenericClient<EraTx, RejectReason>;
 pub struct GenericClient<Tx, Reject> {
     state: State,
     muxer: multiplexer::ChannelBuffer,
+    timeouts: StateTimeouts<State>,
     pd_tx: PhantomData<Tx>,
     pd_reject: PhantomData<Reject>,
 }
@@ -33,11 +35,17 @@ where
         Self {
             state: State::Idle,
             muxer: multiplexer::ChannelBuffer::new(channel),
+            timeouts: StateTimeouts::default(),
             pd_tx: Default::default(),
             pd_reject: Default::default(),
         }
     }
 
+    /// Sets the max time to wait for the server in each of its states.
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.timeouts = timeouts;
+    }
+
     /// Submits the given `tx` to the server.
     ///
     /// # Arguments
@@ -136,6 +144,7 @@ where
     async fn recv_message(&mut self) -> Result<Message<Tx, Reject>, Error> {
         self.assert_agency_is_theirs()?;
 
+        self.muxer.set_timeout(self.timeouts.get(&self.state));
         let msg = self
             .muxer
             .recv_full_msg()
@@ -1,6 +1,8 @@
 pub use client::*;
 pub use protocol::*;
+pub use server::*;
 
 mod client;
 mod codec;
 mod protocol;
+mod server;
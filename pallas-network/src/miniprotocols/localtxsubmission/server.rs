@@ -0,0 +1,210 @@
+use std::marker::PhantomData;
+
+use thiserror::Error;
+use tracing::debug;
+
+use pallas_codec::Fragment;
+
+use crate::miniprotocols::localtxsubmission::{EraTx, Message, RejectReason, State};
+use crate::miniprotocols::StateTimeouts;
+use crate::multiplexer;
+
+/// Cardano specific instantiation of LocalTxSubmission server.
+pub type Server = GenericServer<EraTx, RejectReason>;
+
+/// A generic Ouroboros server that receives generic transactions from a
+/// client and replies with either an acceptance or a generic rejection.
+pub struct GenericServer<Tx, Reject> {
+    state: State,
+    muxer: multiplexer::ChannelBuffer,
+    timeouts: StateTimeouts<State>,
+    pd_tx: PhantomData<Tx>,
+    pd_reject: PhantomData<Reject>,
+}
+
+impl<Tx, Reject> GenericServer<Tx, Reject>
+where
+    Message<Tx, Reject>: Fragment,
+{
+    /// Constructs a new LocalTxSubmission `Server` instance.
+    ///
+    /// # Arguments
+    /// * `channel` - An instance of `multiplexer::AgentChannel` to be used for
+    ///   communication.
+    pub fn new(channel: multiplexer::AgentChannel) -> Self {
+        Self {
+            state: State::Idle,
+            muxer: multiplexer::ChannelBuffer::new(channel),
+            timeouts: StateTimeouts::default(),
+            pd_tx: Default::default(),
+            pd_reject: Default::default(),
+        }
+    }
+
+    /// Sets the max time to wait for the client in each of its states.
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.timeouts = timeouts;
+    }
+
+    /// Returns the current state of the server.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Checks if the server is done.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Checks if the server has agency.
+    fn has_agency(&self) -> bool {
+        match self.state() {
+            State::Busy => true,
+            State::Idle | State::Done => false,
+        }
+    }
+
+    fn assert_agency_is_ours(&self) -> Result<(), ServerError> {
+        if !self.has_agency() {
+            Err(ServerError::AgencyIsTheirs)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn assert_agency_is_theirs(&self) -> Result<(), ServerError> {
+        if self.has_agency() {
+            Err(ServerError::AgencyIsOurs)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn assert_outbound_state(&self, msg: &Message<Tx, Reject>) -> Result<(), ServerError> {
+        match (&self.state, msg) {
+            (State::Busy, Message::AcceptTx | Message::RejectTx(_)) => Ok(()),
+            _ => Err(ServerError::InvalidOutbound),
+        }
+    }
+
+    fn assert_inbound_state(&self, msg: &Message<Tx, Reject>) -> Result<(), ServerError> {
+        match (&self.state, msg) {
+            (State::Idle, Message::SubmitTx(_) | Message::Done) => Ok(()),
+            _ => Err(ServerError::InvalidInbound),
+        }
+    }
+
+    /// Sends a message to the client
+    ///
+    /// # Errors
+    /// Returns an error if the agency is not ours or if the outbound state is
+    /// invalid.
+    async fn send_message(&mut self, msg: &Message<Tx, Reject>) -> Result<(), ServerError> {
+        self.assert_agency_is_ours()?;
+        self.assert_outbound_state(msg)?;
+
+        self.muxer
+            .send_msg_chunks(msg)
+            .await
+            .map_err(ServerError::ChannelError)?;
+
+        Ok(())
+    }
+
+    /// Receives the next message from the client.
+    ///
+    /// # Errors
+    /// Returns an error if the agency is not theirs or if the inbound state is
+    /// invalid.
+    async fn recv_message(&mut self) -> Result<Message<Tx, Reject>, ServerError> {
+        self.assert_agency_is_theirs()?;
+
+        self.muxer.set_timeout(self.timeouts.get(&self.state));
+        let msg = self
+            .muxer
+            .recv_full_msg()
+            .await
+            .map_err(ServerError::ChannelError)?;
+
+        self.assert_inbound_state(&msg)?;
+
+        Ok(msg)
+    }
+
+    /// Waits for the client to submit a transaction.
+    ///
+    /// Returns the submitted transaction and moves the server to the `Busy`
+    /// state, where it's expected to reply with either `accept_tx` or
+    /// `reject_tx`. If the client terminates the protocol instead, returns
+    /// `None` and moves the server to the `Done` state.
+    ///
+    /// # Errors
+    /// Returns an error if the agency is not theirs or if the inbound message
+    /// is invalid.
+    pub async fn recv_submit_tx(&mut self) -> Result<Option<Tx>, ServerError> {
+        debug!("waiting for SubmitTx");
+
+        match self.recv_message().await? {
+            Message::SubmitTx(tx) => {
+                self.state = State::Busy;
+                Ok(Some(tx))
+            }
+            Message::Done => {
+                self.state = State::Done;
+                Ok(None)
+            }
+            _ => Err(ServerError::InvalidInbound),
+        }
+    }
+
+    /// Notifies the client that the submitted transaction was accepted.
+    ///
+    /// # Errors
+    /// Returns an error if the agency is not ours or if the outbound state is
+    /// invalid.
+    pub async fn accept_tx(&mut self) -> Result<(), ServerError> {
+        let msg = Message::AcceptTx;
+        self.send_message(&msg).await?;
+        self.state = State::Idle;
+
+        debug!("sent AcceptTx");
+
+        Ok(())
+    }
+
+    /// Notifies the client that the submitted transaction was rejected.
+    ///
+    /// # Arguments
+    /// * `reason` - the reason why the transaction was rejected.
+    ///
+    /// # Errors
+    /// Returns an error if the agency is not ours or if the outbound state is
+    /// invalid.
+    pub async fn reject_tx(&mut self, reason: Reject) -> Result<(), ServerError> {
+        let msg = Message::RejectTx(reason);
+        self.send_message(&msg).await?;
+        self.state = State::Idle;
+
+        debug!("sent RejectTx");
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("attempted to receive message while agency is ours")]
+    AgencyIsOurs,
+
+    #[error("attempted to send message while agency is theirs")]
+    AgencyIsTheirs,
+
+    #[error("inbound message is not valid for current state")]
+    InvalidInbound,
+
+    #[error("outbound message is not valid for current state")]
+    InvalidOutbound,
+
+    #[error("error while sending or receiving data through the channel")]
+    ChannelError(multiplexer::Error),
+}
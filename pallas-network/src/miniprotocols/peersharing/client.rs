@@ -0,0 +1,156 @@
+use std::fmt::Debug;
+use thiserror::*;
+use tracing::debug;
+
+use super::protocol::*;
+use crate::miniprotocols::StateTimeouts;
+use crate::multiplexer;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("attempted to receive message while agency is ours")]
+    AgencyIsOurs,
+
+    #[error("attempted to send message while agency is theirs")]
+    AgencyIsTheirs,
+
+    #[error("inbound message is not valid for current state")]
+    InvalidInbound,
+
+    #[error("outbound message is not valid for current state")]
+    InvalidOutbound,
+
+    #[error("received more peer addresses than requested")]
+    TooManyAddresses,
+
+    #[error("error while sending or receiving data through the channel")]
+    Plexer(multiplexer::Error),
+}
+
+/// Represents the client for the PeerSharing mini-protocol.
+///
+/// The client asks the remote peer for a number of addresses of other peers
+/// it knows about, which is useful to discover relays without a static
+/// topology.
+pub struct Client(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
+
+impl Client {
+    pub fn new(channel: multiplexer::AgentChannel) -> Self {
+        Self(
+            State::Idle,
+            multiplexer::ChannelBuffer::new(channel),
+            default_timeouts(),
+        )
+    }
+
+    /// Sets the max time to wait for the server in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
+    }
+
+    pub fn state(&self) -> &State {
+        &self.0
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.0 == State::Done
+    }
+
+    fn has_agency(&self) -> bool {
+        match &self.0 {
+            State::Idle => true,
+            State::Busy(..) => false,
+            State::Done => false,
+        }
+    }
+
+    fn assert_agency_is_ours(&self) -> Result<(), ClientError> {
+        if !self.has_agency() {
+            Err(ClientError::AgencyIsTheirs)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn assert_agency_is_theirs(&self) -> Result<(), ClientError> {
+        if self.has_agency() {
+            Err(ClientError::AgencyIsOurs)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn assert_outbound_state(&self, msg: &Message) -> Result<(), ClientError> {
+        match (&self.0, msg) {
+            (State::Idle, Message::ShareRequest(..)) => Ok(()),
+            (State::Idle, Message::Done) => Ok(()),
+            _ => Err(ClientError::InvalidOutbound),
+        }
+    }
+
+    fn assert_inbound_state(&self, msg: &Message) -> Result<(), ClientError> {
+        match (&self.0, msg) {
+            (State::Busy(..), Message::SharePeers(..)) => Ok(()),
+            _ => Err(ClientError::InvalidInbound),
+        }
+    }
+
+    pub async fn send_message(&mut self, msg: &Message) -> Result<(), ClientError> {
+        self.assert_agency_is_ours()?;
+        self.assert_outbound_state(msg)?;
+        self.1
+            .send_msg_chunks(msg)
+            .await
+            .map_err(ClientError::Plexer)?;
+
+        Ok(())
+    }
+
+    pub async fn recv_message(&mut self) -> Result<Message, ClientError> {
+        self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
+        let msg = self.1.recv_full_msg().await.map_err(ClientError::Plexer)?;
+        self.assert_inbound_state(&msg)?;
+
+        Ok(msg)
+    }
+
+    pub async fn send_share_request(&mut self, amount: Amount) -> Result<(), ClientError> {
+        let msg = Message::ShareRequest(amount);
+        self.send_message(&msg).await?;
+        self.0 = State::Busy(amount);
+        debug!("requested {} peer addresses", amount);
+
+        Ok(())
+    }
+
+    pub async fn recv_peer_addresses(&mut self) -> Result<Vec<PeerAddress>, ClientError> {
+        match self.recv_message().await? {
+            Message::SharePeers(addresses) => match self.state() {
+                State::Busy(amount) if addresses.len() <= *amount as usize => {
+                    debug!("received {} peer addresses", addresses.len());
+                    self.0 = State::Idle;
+                    Ok(addresses)
+                }
+                State::Busy(..) => Err(ClientError::TooManyAddresses),
+                _ => unreachable!(),
+            },
+            _ => Err(ClientError::InvalidInbound),
+        }
+    }
+
+    /// Ask the remote peer for (at most) `amount` peer addresses and wait for
+    /// its reply.
+    pub async fn request_peers(&mut self, amount: Amount) -> Result<Vec<PeerAddress>, ClientError> {
+        self.send_share_request(amount).await?;
+        self.recv_peer_addresses().await
+    }
+
+    pub async fn send_done(&mut self) -> Result<(), ClientError> {
+        let msg = Message::Done;
+        self.send_message(&msg).await?;
+        self.0 = State::Done;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use pallas_codec::minicbor::{decode, encode, Decode, Decoder, Encode, Encoder};
+
+use super::protocol::*;
+
+impl Encode<()> for PeerAddress {
+    fn encode<W: encode::Write>(
+        &self,
+        e: &mut Encoder<W>,
+        _ctx: &mut (),
+    ) -> Result<(), encode::Error<W::Error>> {
+        match self {
+            PeerAddress::V4(address, port) => {
+                e.array(3)?.u16(0)?;
+                e.u32(u32::from(*address))?;
+                e.u16(*port)?;
+            }
+            PeerAddress::V6(address, port) => {
+                e.array(6)?.u16(1)?;
+
+                for word in address.octets().chunks_exact(4) {
+                    e.u32(u32::from_be_bytes([word[0], word[1], word[2], word[3]]))?;
+                }
+
+                e.u16(*port)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'b> Decode<'b, ()> for PeerAddress {
+    fn decode(d: &mut Decoder<'b>, _ctx: &mut ()) -> Result<Self, decode::Error> {
+        d.array()?;
+        let label = d.u16()?;
+
+        match label {
+            0 => {
+                let address = Ipv4Addr::from(d.u32()?);
+                let port = d.u16()?;
+                Ok(PeerAddress::V4(address, port))
+            }
+            1 => {
+                let mut octets = [0u8; 16];
+
+                for word in octets.chunks_exact_mut(4) {
+                    word.copy_from_slice(&d.u32()?.to_be_bytes());
+                }
+
+                let port = d.u16()?;
+                Ok(PeerAddress::V6(Ipv6Addr::from(octets), port))
+            }
+            _ => Err(decode::Error::message("can't decode PeerAddress")),
+        }
+    }
+}
+
+impl Encode<()> for Message {
+    fn encode<W: encode::Write>(
+        &self,
+        e: &mut Encoder<W>,
+        _ctx: &mut (),
+    ) -> Result<(), encode::Error<W::Error>> {
+        match self {
+            Message::ShareRequest(amount) => {
+                e.array(2)?.u16(0)?;
+                e.u8(*amount)?;
+            }
+            Message::SharePeers(addresses) => {
+                e.array(2)?.u16(1)?;
+                e.array(addresses.len() as u64)?;
+                for address in addresses {
+                    e.encode(address)?;
+                }
+            }
+            Message::Done => {
+                e.array(1)?.u16(2)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'b> Decode<'b, ()> for Message {
+    fn decode(d: &mut Decoder<'b>, _ctx: &mut ()) -> Result<Self, decode::Error> {
+        d.array()?;
+        let label = d.u16()?;
+
+        match label {
+            0 => {
+                let amount = d.u8()?;
+                Ok(Message::ShareRequest(amount))
+            }
+            1 => {
+                let addresses = d.decode()?;
+                Ok(Message::SharePeers(addresses))
+            }
+            2 => Ok(Message::Done),
+            _ => Err(decode::Error::message(
+                "unknown variant for peersharing message",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use pallas_codec::minicbor;
+
+    use super::*;
+
+    #[test]
+    fn share_peers_roundtrip() {
+        let msg = Message::SharePeers(vec![
+            PeerAddress::V4(Ipv4Addr::new(192, 168, 0, 1), 3001),
+            PeerAddress::V6(
+                Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0xff00, 0x42, 0x8329),
+                6000,
+            ),
+        ]);
+
+        let bytes = minicbor::to_vec(&msg).unwrap();
+        let decoded: Message = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn ipv4_address_is_encoded_as_network_order_word() {
+        let address = PeerAddress::V4(Ipv4Addr::new(1, 2, 3, 4), 3001);
+        let bytes = minicbor::to_vec(&address).unwrap();
+
+        assert_eq!(hex::encode(bytes), "83001a01020304190bb9");
+    }
+}
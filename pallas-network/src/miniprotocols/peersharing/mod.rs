@@ -0,0 +1,10 @@
+//! PeerSharing mini-protocol implementation
+
+mod client;
+mod codec;
+mod protocol;
+mod server;
+
+pub use client::*;
+pub use protocol::*;
+pub use server::*;
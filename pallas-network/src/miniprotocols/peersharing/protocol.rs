@@ -0,0 +1,32 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::miniprotocols::{StateTimeouts, LONG_WAIT};
+
+pub type Amount = u8;
+
+pub type Port = u16;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PeerAddress {
+    V4(Ipv4Addr, Port),
+    V6(Ipv6Addr, Port),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum State {
+    Idle,
+    Busy(Amount),
+    Done,
+}
+
+/// Time limits that the network spec sets on the states of the protocol
+pub fn default_timeouts() -> StateTimeouts<State> {
+    StateTimeouts::new().with(&State::Busy(0), LONG_WAIT)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Message {
+    ShareRequest(Amount),
+    SharePeers(Vec<PeerAddress>),
+    Done,
+}
@@ -0,0 +1,155 @@
+use std::fmt::Debug;
+use thiserror::*;
+use tracing::debug;
+
+use super::protocol::*;
+use crate::miniprotocols::StateTimeouts;
+use crate::multiplexer;
+
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("attempted to receive message while agency is ours")]
+    AgencyIsOurs,
+
+    #[error("attempted to send message while agency is theirs")]
+    AgencyIsTheirs,
+
+    #[error("inbound message is not valid for current state")]
+    InvalidInbound,
+
+    #[error("outbound message is not valid for current state")]
+    InvalidOutbound,
+
+    #[error("attempted to send more peer addresses than requested")]
+    TooManyAddresses,
+
+    #[error("error while sending or receiving data through the channel")]
+    Plexer(multiplexer::Error),
+}
+
+/// Represents the server for the PeerSharing mini-protocol.
+pub struct Server(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
+
+impl Server {
+    pub fn new(channel: multiplexer::AgentChannel) -> Self {
+        Self(
+            State::Idle,
+            multiplexer::ChannelBuffer::new(channel),
+            default_timeouts(),
+        )
+    }
+
+    /// Sets the max time to wait for the client in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
+    }
+
+    pub fn state(&self) -> &State {
+        &self.0
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.0 == State::Done
+    }
+
+    fn has_agency(&self) -> bool {
+        match &self.0 {
+            State::Idle => false,
+            State::Busy(..) => true,
+            State::Done => false,
+        }
+    }
+
+    fn assert_agency_is_ours(&self) -> Result<(), ServerError> {
+        if !self.has_agency() {
+            Err(ServerError::AgencyIsTheirs)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn assert_agency_is_theirs(&self) -> Result<(), ServerError> {
+        if self.has_agency() {
+            Err(ServerError::AgencyIsOurs)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn assert_outbound_state(&self, msg: &Message) -> Result<(), ServerError> {
+        match (&self.0, msg) {
+            (State::Busy(..), Message::SharePeers(..)) => Ok(()),
+            _ => Err(ServerError::InvalidOutbound),
+        }
+    }
+
+    fn assert_inbound_state(&self, msg: &Message) -> Result<(), ServerError> {
+        match (&self.0, msg) {
+            (State::Idle, Message::ShareRequest(..)) => Ok(()),
+            (State::Idle, Message::Done) => Ok(()),
+            _ => Err(ServerError::InvalidInbound),
+        }
+    }
+
+    pub async fn send_message(&mut self, msg: &Message) -> Result<(), ServerError> {
+        self.assert_agency_is_ours()?;
+        self.assert_outbound_state(msg)?;
+        self.1
+            .send_msg_chunks(msg)
+            .await
+            .map_err(ServerError::Plexer)?;
+
+        Ok(())
+    }
+
+    pub async fn recv_message(&mut self) -> Result<Message, ServerError> {
+        self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
+        let msg = self.1.recv_full_msg().await.map_err(ServerError::Plexer)?;
+        self.assert_inbound_state(&msg)?;
+
+        Ok(msg)
+    }
+
+    /// Receive a message from the client while the miniprotocol is in the
+    /// `Idle` state.
+    ///
+    /// If the message is a `ShareRequest`, return the requested amount of
+    /// addresses and progress the server state to `Busy`. If the message is a
+    /// `Done`, return None and progress the server state to `Done`.
+    pub async fn recv_while_idle(&mut self) -> Result<Option<Amount>, ServerError> {
+        match self.recv_message().await? {
+            Message::ShareRequest(amount) => {
+                debug!("client requested {} peer addresses", amount);
+                self.0 = State::Busy(amount);
+                Ok(Some(amount))
+            }
+            Message::Done => {
+                debug!("client sent done message in peersharing protocol");
+                self.0 = State::Done;
+                Ok(None)
+            }
+            _ => Err(ServerError::InvalidInbound),
+        }
+    }
+
+    /// Reply to the pending `ShareRequest` with a list of peer addresses.
+    ///
+    /// The list must not be longer than the amount requested by the client.
+    pub async fn send_peer_addresses(
+        &mut self,
+        addresses: Vec<PeerAddress>,
+    ) -> Result<(), ServerError> {
+        if let State::Busy(amount) = self.state() {
+            if addresses.len() > *amount as usize {
+                return Err(ServerError::TooManyAddresses);
+            }
+        }
+
+        let msg = Message::SharePeers(addresses);
+        self.send_message(&msg).await?;
+        self.0 = State::Idle;
+
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use std::fmt::Debug;
 use thiserror::*;
 
 use super::protocol::*;
+use crate::miniprotocols::StateTimeouts;
 use crate::multiplexer;
 
 #[derive(Error, Debug)]
@@ -22,11 +23,20 @@ pub enum Error {
     Plexer(multiplexer::Error),
 }
 
-pub struct Client(State, multiplexer::ChannelBuffer);
+pub struct Client(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
 
 impl Client {
     pub fn new(channel: multiplexer::AgentChannel) -> Self {
-        Self(State::Idle, multiplexer::ChannelBuffer::new(channel))
+        Self(
+            State::Idle,
+            multiplexer::ChannelBuffer::new(channel),
+            StateTimeouts::default(),
+        )
+    }
+
+    /// Sets the max time to wait for the server in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
     }
 
     pub fn state(&self) -> &State {
@@ -68,9 +78,11 @@ impl Client {
             (State::Idle, Message::Acquire) => Ok(()),
             (State::Idle, Message::Done) => Ok(()),
             (State::Acquired, Message::Acquire) => Ok(()),
+            (State::Acquired, Message::AwaitAcquire) => Ok(()),
             (State::Acquired, Message::RequestHasTx(..)) => Ok(()),
             (State::Acquired, Message::RequestNextTx) => Ok(()),
             (State::Acquired, Message::RequestSizeAndCapacity) => Ok(()),
+            (State::Acquired, Message::Release) => Ok(()),
             _ => Err(Error::InvalidOutbound),
         }
     }
@@ -95,6 +107,7 @@ impl Client {
 
     pub async fn recv_message(&mut self) -> Result<Message, Error> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(Error::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
@@ -124,6 +137,21 @@ impl Client {
         self.recv_while_acquiring().await
     }
 
+    async fn send_await_acquire(&mut self) -> Result<(), Error> {
+        let msg = Message::AwaitAcquire;
+        self.send_message(&msg).await?;
+        self.0 = State::Acquiring;
+
+        Ok(())
+    }
+
+    /// Acquire a new snapshot once the mempool changes from the one currently
+    /// held
+    pub async fn await_acquire(&mut self) -> Result<Slot, Error> {
+        self.send_await_acquire().await?;
+        self.recv_while_acquiring().await
+    }
+
     async fn send_request_has_tx(&mut self, id: TxId) -> Result<(), Error> {
         let msg = Message::RequestHasTx(id);
         self.send_message(&msg).await?;
@@ -1,6 +1,8 @@
 mod client;
 mod codec;
 mod protocol;
+mod server;
 
 pub use client::*;
 pub use protocol::*;
+pub use server::*;
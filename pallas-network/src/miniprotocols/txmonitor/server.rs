@@ -0,0 +1,305 @@
+use std::fmt::Debug;
+use std::future::Future;
+use thiserror::*;
+use tracing::debug;
+
+use super::protocol::*;
+use crate::miniprotocols::StateTimeouts;
+use crate::multiplexer;
+
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("attempted to receive message while agency is ours")]
+    AgencyIsOurs,
+
+    #[error("attempted to send message while agency is theirs")]
+    AgencyIsTheirs,
+
+    #[error("inbound message is not valid for current state")]
+    InvalidInbound,
+
+    #[error("outbound message is not valid for current state")]
+    InvalidOutbound,
+
+    #[error("error while sending or receiving data through the channel")]
+    Plexer(multiplexer::Error),
+}
+
+/// Request received from the client when in the Acquired state
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ClientRequest {
+    ReAcquire,
+    AwaitAcquire,
+    HasTx(TxId),
+    NextTx,
+    SizeAndCapacity,
+    Release,
+}
+
+/// A fixed view of the mempool, taken when a client acquires it.
+///
+/// The snapshot keeps track of the txs that have already been handed out to
+/// the client through `next_tx`, each call should return the following tx or
+/// `None` once all of them have been seen.
+pub trait MempoolSnapshot {
+    /// The slot number at which the snapshot was taken
+    fn slot(&self) -> Slot;
+
+    /// Whether the tx with the given id is present in the snapshot
+    fn has_tx(&self, id: &TxId) -> bool;
+
+    /// The next tx in the snapshot not yet seen by the client
+    fn next_tx(&mut self) -> Option<Tx>;
+
+    /// Size and capacity of the mempool at the time of the snapshot
+    fn size_and_capacity(&self) -> MempoolSizeAndCapacity;
+}
+
+/// A source of mempool snapshots to serve to clients
+pub trait Mempool {
+    type Snapshot: MempoolSnapshot;
+
+    fn snapshot(&self) -> Self::Snapshot;
+
+    /// Resolves once the content of the mempool differs from the given
+    /// snapshot
+    ///
+    /// Used to answer await-acquire requests, which must block until there's a
+    /// new snapshot to hand out.
+    fn wait_for_change(&self, since: &Self::Snapshot) -> impl Future<Output = ()> + Send;
+}
+
+pub struct Server(State, multiplexer::ChannelBuffer, StateTimeouts<State>);
+
+impl Server {
+    pub fn new(channel: multiplexer::AgentChannel) -> Self {
+        Self(
+            State::Idle,
+            multiplexer::ChannelBuffer::new(channel),
+            StateTimeouts::default(),
+        )
+    }
+
+    /// Sets the max time to wait for the client in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.2 = timeouts;
+    }
+
+    pub fn state(&self) -> &State {
+        &self.0
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.0 == State::Done
+    }
+
+    fn has_agency(&self) -> bool {
+        match &self.0 {
+            State::Idle => false,
+            State::Acquiring => true,
+            State::Acquired => false,
+            State::Busy => true,
+            State::Done => false,
+        }
+    }
+
+    fn assert_agency_is_ours(&self) -> Result<(), ServerError> {
+        if !self.has_agency() {
+            Err(ServerError::AgencyIsTheirs)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn assert_agency_is_theirs(&self) -> Result<(), ServerError> {
+        if self.has_agency() {
+            Err(ServerError::AgencyIsOurs)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn assert_outbound_state(&self, msg: &Message) -> Result<(), ServerError> {
+        match (&self.0, msg) {
+            (State::Acquiring, Message::Acquired(..)) => Ok(()),
+            (State::Busy, Message::ResponseHasTx(..)) => Ok(()),
+            (State::Busy, Message::ResponseNextTx(..)) => Ok(()),
+            (State::Busy, Message::ResponseSizeAndCapacity(..)) => Ok(()),
+            _ => Err(ServerError::InvalidOutbound),
+        }
+    }
+
+    fn assert_inbound_state(&self, msg: &Message) -> Result<(), ServerError> {
+        match (&self.0, msg) {
+            (State::Idle, Message::Acquire) => Ok(()),
+            (State::Idle, Message::Done) => Ok(()),
+            (State::Acquired, Message::Acquire) => Ok(()),
+            (State::Acquired, Message::AwaitAcquire) => Ok(()),
+            (State::Acquired, Message::RequestHasTx(..)) => Ok(()),
+            (State::Acquired, Message::RequestNextTx) => Ok(()),
+            (State::Acquired, Message::RequestSizeAndCapacity) => Ok(()),
+            (State::Acquired, Message::Release) => Ok(()),
+            _ => Err(ServerError::InvalidInbound),
+        }
+    }
+
+    pub async fn send_message(&mut self, msg: &Message) -> Result<(), ServerError> {
+        self.assert_agency_is_ours()?;
+        self.assert_outbound_state(msg)?;
+        self.1
+            .send_msg_chunks(msg)
+            .await
+            .map_err(ServerError::Plexer)?;
+
+        Ok(())
+    }
+
+    pub async fn recv_message(&mut self) -> Result<Message, ServerError> {
+        self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.2.get(&self.0));
+        let msg = self.1.recv_full_msg().await.map_err(ServerError::Plexer)?;
+        self.assert_inbound_state(&msg)?;
+
+        Ok(msg)
+    }
+
+    /// Receive a message from the client when the protocol is in the Idle
+    /// state
+    ///
+    /// Returns true if the client requested to acquire a snapshot of the
+    /// mempool or false if a Done message was received from the client causing
+    /// the protocol to finish.
+    pub async fn recv_while_idle(&mut self) -> Result<bool, ServerError> {
+        match self.recv_message().await? {
+            Message::Acquire => {
+                self.0 = State::Acquiring;
+                Ok(true)
+            }
+            Message::Done => {
+                self.0 = State::Done;
+                Ok(false)
+            }
+            _ => Err(ServerError::InvalidInbound),
+        }
+    }
+
+    /// Receive a message from the client when the protocol is in the Acquired
+    /// state
+    pub async fn recv_while_acquired(&mut self) -> Result<ClientRequest, ServerError> {
+        match self.recv_message().await? {
+            Message::Acquire => {
+                self.0 = State::Acquiring;
+                Ok(ClientRequest::ReAcquire)
+            }
+            Message::AwaitAcquire => {
+                self.0 = State::Acquiring;
+                Ok(ClientRequest::AwaitAcquire)
+            }
+            Message::RequestHasTx(id) => {
+                self.0 = State::Busy;
+                Ok(ClientRequest::HasTx(id))
+            }
+            Message::RequestNextTx => {
+                self.0 = State::Busy;
+                Ok(ClientRequest::NextTx)
+            }
+            Message::RequestSizeAndCapacity => {
+                self.0 = State::Busy;
+                Ok(ClientRequest::SizeAndCapacity)
+            }
+            Message::Release => {
+                self.0 = State::Idle;
+                Ok(ClientRequest::Release)
+            }
+            _ => Err(ServerError::InvalidInbound),
+        }
+    }
+
+    pub async fn send_acquired(&mut self, slot: Slot) -> Result<(), ServerError> {
+        let msg = Message::Acquired(slot);
+        self.send_message(&msg).await?;
+        self.0 = State::Acquired;
+
+        Ok(())
+    }
+
+    pub async fn send_has_tx(&mut self, has: bool) -> Result<(), ServerError> {
+        let msg = Message::ResponseHasTx(has);
+        self.send_message(&msg).await?;
+        self.0 = State::Acquired;
+
+        Ok(())
+    }
+
+    pub async fn send_next_tx(&mut self, tx: Option<Tx>) -> Result<(), ServerError> {
+        let msg = Message::ResponseNextTx(tx);
+        self.send_message(&msg).await?;
+        self.0 = State::Acquired;
+
+        Ok(())
+    }
+
+    pub async fn send_size_and_capacity(
+        &mut self,
+        sizes: MempoolSizeAndCapacity,
+    ) -> Result<(), ServerError> {
+        let msg = Message::ResponseSizeAndCapacity(sizes);
+        self.send_message(&msg).await?;
+        self.0 = State::Acquired;
+
+        Ok(())
+    }
+
+    /// Serve the client requests until it releases the acquired snapshot.
+    ///
+    /// Must be called right after the client acquired a snapshot (the
+    /// protocol is in the Acquiring state). Re-acquire requests are answered
+    /// right away with a fresh snapshot from the mempool, await-acquire
+    /// requests only once the mempool differs from the current snapshot.
+    pub async fn serve_snapshot<M: Mempool>(&mut self, mempool: &M) -> Result<(), ServerError> {
+        let mut snapshot = mempool.snapshot();
+        self.send_acquired(snapshot.slot()).await?;
+
+        loop {
+            match self.recv_while_acquired().await? {
+                ClientRequest::ReAcquire => {
+                    snapshot = mempool.snapshot();
+                    self.send_acquired(snapshot.slot()).await?;
+                }
+                ClientRequest::AwaitAcquire => {
+                    mempool.wait_for_change(&snapshot).await;
+                    snapshot = mempool.snapshot();
+                    self.send_acquired(snapshot.slot()).await?;
+                }
+                ClientRequest::HasTx(id) => {
+                    let has = snapshot.has_tx(&id);
+                    self.send_has_tx(has).await?;
+                }
+                ClientRequest::NextTx => {
+                    let tx = snapshot.next_tx();
+                    self.send_next_tx(tx).await?;
+                }
+                ClientRequest::SizeAndCapacity => {
+                    let sizes = snapshot.size_and_capacity();
+                    self.send_size_and_capacity(sizes).await?;
+                }
+                ClientRequest::Release => {
+                    debug!("client released mempool snapshot");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Serve mempool snapshots to the client until it's done with the
+    /// protocol.
+    pub async fn serve<M: Mempool>(&mut self, mempool: &M) -> Result<(), ServerError> {
+        while self.recv_while_idle().await? {
+            self.serve_snapshot(mempool).await?;
+        }
+
+        debug!("client sent done message in txmonitor protocol");
+
+        Ok(())
+    }
+}
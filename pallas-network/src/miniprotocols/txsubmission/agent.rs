@@ -0,0 +1,212 @@
+//! Client-side agent that feeds a queue of txs to a tx-submission server
+
+use std::collections::VecDeque;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use super::{Client, EraTxBody, EraTxId, Error, Request, State, TxCount, TxIdAndSize};
+
+/// A transaction waiting to be announced to the server
+#[derive(Debug, Clone)]
+pub struct QueuedTx {
+    pub id: EraTxId,
+    pub body: EraTxBody,
+}
+
+impl QueuedTx {
+    fn id_and_size(&self) -> TxIdAndSize<EraTxId> {
+        TxIdAndSize(self.id.clone(), self.body.1.len() as u32)
+    }
+}
+
+/// The client side of the exchange, driven by the requests of the server
+struct AgentLoop {
+    client: Client,
+    incoming: mpsc::Receiver<QueuedTx>,
+    acknowledged: mpsc::Sender<EraTxId>,
+
+    /// Txs received from the channel that weren't announced yet
+    pending: VecDeque<QueuedTx>,
+
+    /// Txs announced to the server that weren't acknowledged yet, in the order
+    /// in which they were announced
+    unacked: VecDeque<QueuedTx>,
+
+    closed: bool,
+}
+
+impl AgentLoop {
+    fn drain_incoming(&mut self) {
+        loop {
+            match self.incoming.try_recv() {
+                Ok(tx) => self.pending.push_back(tx),
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    fn acknowledge(&mut self, count: TxCount) -> Result<(), Error> {
+        let count = count as usize;
+
+        if count > self.unacked.len() {
+            warn!(
+                count,
+                unacked = self.unacked.len(),
+                "server acknowledged more txs than announced"
+            );
+
+            return Err(Error::InvalidInbound);
+        }
+
+        for tx in self.unacked.drain(..count) {
+            // acknowledgements are only reported on a best-effort basis, ids
+            // are dropped if nobody is reading them or the buffer is full
+            if let Err(mpsc::error::TrySendError::Full(id)) = self.acknowledged.try_send(tx.id) {
+                debug!(?id, "acknowledged ids buffer is full, dropping id");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn announce(&mut self, count: TxCount) -> Vec<TxIdAndSize<EraTxId>> {
+        let count = self.pending.len().min(count as usize);
+        let announced: Vec<_> = self.pending.drain(..count).collect();
+
+        let ids = announced.iter().map(QueuedTx::id_and_size).collect();
+        self.unacked.extend(announced);
+
+        ids
+    }
+
+    fn bodies(&self, ids: &[EraTxId]) -> Vec<EraTxBody> {
+        ids.iter()
+            .filter_map(|id| match self.unacked.iter().find(|tx| tx.id == *id) {
+                Some(tx) => Some(tx.body.clone()),
+                None => {
+                    warn!(?id, "server requested a tx that wasn't announced");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    async fn run(mut self) -> Result<(), Error> {
+        if *self.client.state() == State::Init {
+            self.client.send_init().await?;
+        }
+
+        loop {
+            match self.client.next_request().await? {
+                Request::TxIds(ack, count) => {
+                    self.acknowledge(ack)?;
+                    self.drain_incoming();
+
+                    // a blocking request can't be answered with an empty list,
+                    // we wait for the next tx or for the queue to be closed
+                    if self.pending.is_empty() && !self.closed {
+                        debug!("waiting for txs to answer blocking request");
+
+                        match self.incoming.recv().await {
+                            Some(tx) => self.pending.push_back(tx),
+                            None => self.closed = true,
+                        }
+                    }
+
+                    if self.pending.is_empty() {
+                        debug!("tx queue closed, ending tx-submission");
+                        self.client.send_done().await?;
+                        return Ok(());
+                    }
+
+                    let ids = self.announce(count);
+                    self.client.reply_tx_ids(ids).await?;
+                }
+                Request::TxIdsNonBlocking(ack, count) => {
+                    self.acknowledge(ack)?;
+                    self.drain_incoming();
+
+                    let ids = self.announce(count);
+                    self.client.reply_tx_ids(ids).await?;
+                }
+                Request::Txs(ids) => {
+                    let bodies = self.bodies(&ids);
+                    self.client.reply_txs(bodies).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Serves the tx-submission requests of a server from a local queue
+///
+/// Txs fed through [`TxSubmissionAgent::submit`] (or a cloned
+/// [`TxSubmissionAgent::sender`]) are announced to the server as it requests
+/// them. Once the server acknowledges a tx, its id is reported through
+/// [`TxSubmissionAgent::next_acknowledged`]; up to `capacity` ids are kept
+/// for the caller, later ones are dropped until it catches up. When all the
+/// senders are dropped and the queue is empty, the agent ends the protocol on
+/// the next blocking request.
+pub struct TxSubmissionAgent {
+    sender: mpsc::Sender<QueuedTx>,
+    acknowledged: mpsc::Receiver<EraTxId>,
+    task: JoinHandle<Result<(), Error>>,
+}
+
+impl TxSubmissionAgent {
+    /// Starts serving requests, buffering up to `capacity` submitted txs and
+    /// as many acknowledged ids
+    pub fn spawn(client: Client, capacity: usize) -> Self {
+        let (sender, incoming) = mpsc::channel(capacity);
+        let (acknowledged_tx, acknowledged) = mpsc::channel(capacity);
+
+        let agent = AgentLoop {
+            client,
+            incoming,
+            acknowledged: acknowledged_tx,
+            pending: VecDeque::new(),
+            unacked: VecDeque::new(),
+            closed: false,
+        };
+
+        Self {
+            sender,
+            acknowledged,
+            task: tokio::spawn(agent.run()),
+        }
+    }
+
+    pub fn sender(&self) -> mpsc::Sender<QueuedTx> {
+        self.sender.clone()
+    }
+
+    pub async fn submit(
+        &self,
+        id: EraTxId,
+        body: EraTxBody,
+    ) -> Result<(), mpsc::error::SendError<QueuedTx>> {
+        self.sender.send(QueuedTx { id, body }).await
+    }
+
+    /// Id of the next tx acknowledged by the server, in announcement order
+    pub async fn next_acknowledged(&mut self) -> Option<EraTxId> {
+        self.acknowledged.recv().await
+    }
+
+    /// Closes the queue and waits for the agent to end the protocol
+    pub async fn finish(self) -> Result<(), Error> {
+        drop(self.sender);
+
+        self.task.await.expect("tx-submission agent panicked")
+    }
+
+    pub fn abort(self) {
+        self.task.abort();
+    }
+}
@@ -1,10 +1,11 @@
 use std::marker::PhantomData;
 
+use crate::miniprotocols::StateTimeouts;
 use crate::multiplexer;
 use pallas_codec::Fragment;
 
 use super::{
-    protocol::{Error, Message, State, TxIdAndSize},
+    protocol::{default_timeouts, Error, Message, State, TxIdAndSize},
     EraTxBody, EraTxId,
 };
 
@@ -21,6 +22,7 @@ pub struct GenericClient<TxId, TxBody>(
     multiplexer::ChannelBuffer,
     PhantomData<TxId>,
     PhantomData<TxBody>,
+    StateTimeouts<State>,
 )
 where
     Message<TxId, TxBody>: Fragment;
@@ -38,9 +40,15 @@ where
             multiplexer::ChannelBuffer::new(channel),
             PhantomData {},
             PhantomData {},
+            default_timeouts(),
         )
     }
 
+    /// Sets the max time to wait for the server in each of its states
+    pub fn set_timeouts(&mut self, timeouts: StateTimeouts<State>) {
+        self.4 = timeouts;
+    }
+
     pub fn state(&self) -> &State {
         &self.0
     }
@@ -100,6 +108,7 @@ where
 
     pub async fn recv_message(&mut self) -> Result<Message<TxId, TxBody>, Error> {
         self.assert_agency_is_theirs()?;
+        self.1.set_timeout(self.4.get(&self.0));
         let msg = self.1.recv_full_msg().await.map_err(Error::Plexer)?;
         self.assert_inbound_state(&msg)?;
 
@@ -1,8 +1,10 @@
+mod agent;
 mod client;
 mod codec;
 mod protocol;
 mod server;
 
+pub use agent::*;
 pub use client::*;
 pub use protocol::*;
 pub use server::*;
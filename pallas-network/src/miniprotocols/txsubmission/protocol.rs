@@ -1,5 +1,6 @@
 use thiserror::Error;
 
+use crate::miniprotocols::{StateTimeouts, SHORT_WAIT};
 use crate::multiplexer;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -12,6 +13,16 @@ pub enum State {
     Done,
 }
 
+/// Time limits that the network spec sets on the states of the protocol
+///
+/// A blocking request for tx ids can wait until the client has new txs, so
+/// it has no limit.
+pub fn default_timeouts() -> StateTimeouts<State> {
+    StateTimeouts::new()
+        .with(&State::TxIdsNonBlocking, SHORT_WAIT)
+        .with(&State::Txs, SHORT_WAIT)
+}
+
 pub type Blocking = bool;
 
 pub type TxCount = u16;
@@ -19,7 +30,7 @@ pub type TxCount = u16;
 pub type TxSizeInBytes = u32;
 
 // The bytes of a txId, tagged with an era number
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct EraTxId(pub u16, pub Vec<u8>);
 
 // The bytes of a transaction, with an era number and some raw CBOR
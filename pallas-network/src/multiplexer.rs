@@ -1,6 +1,9 @@
 //! A multiplexer of several mini-protocols through a single bearer
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use byteorder::{ByteOrder, NetworkEndian};
 use pallas_codec::{minicbor, Fragment};
@@ -13,6 +16,9 @@ use tracing::{debug, error, trace, warn};
 
 type IOResult<T> = tokio::io::Result<T>;
 
+pub mod metrics;
+pub mod recording;
+
 use tokio::net as tcp;
 
 #[cfg(unix)]
@@ -26,12 +32,20 @@ use tokio::io::{ReadHalf, WriteHalf};
 
 const HEADER_LEN: usize = 8;
 
+/// Max amount of bytes in-flight on each direction of an in-memory bearer
+const DUPLEX_BUFFER_SIZE: usize = 1024 * 1024;
+
 pub type Timestamp = u32;
 
 pub type Payload = Vec<u8>;
 
 pub type Protocol = u16;
 
+/// Strips the direction bit, leaving the mini-protocol number
+fn mini_protocol(protocol: Protocol) -> Protocol {
+    protocol & !0x8000
+}
+
 #[derive(Debug)]
 pub struct Header {
     pub protocol: Protocol,
@@ -77,6 +91,15 @@ pub enum Bearer {
 
     #[cfg(windows)]
     NamedPipe(NamedPipeClient),
+
+    /// An in-memory bearer, one of the ends of a [`Bearer::pair`]
+    Duplex(tokio::io::DuplexStream),
+
+    /// A bearer that records every segment going through the inner one
+    Recording(Box<Bearer>, recording::Recorder),
+
+    /// A bearer that plays back a recorded session
+    Replay(recording::Replay),
 }
 
 impl Bearer {
@@ -134,6 +157,25 @@ impl Bearer {
         Ok(Self::NamedPipe(client))
     }
 
+    /// Creates two in-memory bearers connected to each other
+    ///
+    /// Useful for wiring a client and a server together in the same process,
+    /// without going through the OS network stack.
+    pub fn pair() -> (Self, Self) {
+        let (a, b) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+        (Self::Duplex(a), Self::Duplex(b))
+    }
+
+    /// Wraps the bearer so that every segment going through it is recorded
+    pub fn recorded(self, recorder: recording::Recorder) -> Self {
+        Self::Recording(Box::new(self), recorder)
+    }
+
+    /// Creates a bearer that plays back a recorded session
+    pub fn replay(replay: recording::Replay) -> Self {
+        Self::Replay(replay)
+    }
+
     pub fn into_split(self) -> (BearerReadHalf, BearerWriteHalf) {
         match self {
             Bearer::Tcp(x) => {
@@ -155,6 +197,31 @@ impl Bearer {
 
                 (reader, writer)
             }
+
+            Bearer::Duplex(x) => {
+                let (r, w) = tokio::io::split(x);
+                (BearerReadHalf::Duplex(r), BearerWriteHalf::Duplex(w))
+            }
+
+            Bearer::Recording(inner, recorder) => {
+                let (r, w) = inner.into_split();
+
+                let r_tap =
+                    recording::SegmentTap::new(recorder.clone(), recording::Direction::Inbound);
+                let w_tap = recording::SegmentTap::new(recorder, recording::Direction::Outbound);
+
+                (
+                    BearerReadHalf::Recording(Box::new(r), r_tap),
+                    BearerWriteHalf::Recording(Box::new(w), w_tap),
+                )
+            }
+
+            Bearer::Replay(replay) => {
+                let r = recording::ReplayReader::new(replay.clone());
+                let w = recording::ReplayWriter::new(replay);
+
+                (BearerReadHalf::Replay(r), BearerWriteHalf::Replay(w))
+            }
         }
     }
 }
@@ -167,6 +234,12 @@ pub enum BearerReadHalf {
 
     #[cfg(windows)]
     NamedPipe(ReadHalf<NamedPipeClient>),
+
+    Duplex(tokio::io::ReadHalf<tokio::io::DuplexStream>),
+
+    Recording(Box<BearerReadHalf>, recording::SegmentTap),
+
+    Replay(recording::ReplayReader),
 }
 
 impl BearerReadHalf {
@@ -179,6 +252,16 @@ impl BearerReadHalf {
 
             #[cfg(windows)]
             BearerReadHalf::NamedPipe(x) => x.read_exact(buf).await,
+
+            BearerReadHalf::Duplex(x) => x.read_exact(buf).await,
+
+            BearerReadHalf::Recording(x, tap) => {
+                let len = Box::pin(x.read_exact(buf)).await?;
+                tap.tap(&buf[..len])?;
+                Ok(len)
+            }
+
+            BearerReadHalf::Replay(x) => x.read_exact(buf).await,
         }
     }
 }
@@ -191,6 +274,12 @@ pub enum BearerWriteHalf {
 
     #[cfg(windows)]
     NamedPipe(WriteHalf<NamedPipeClient>),
+
+    Duplex(tokio::io::WriteHalf<tokio::io::DuplexStream>),
+
+    Recording(Box<BearerWriteHalf>, recording::SegmentTap),
+
+    Replay(recording::ReplayWriter),
 }
 
 impl BearerWriteHalf {
@@ -203,6 +292,15 @@ impl BearerWriteHalf {
 
             #[cfg(windows)]
             Self::NamedPipe(x) => x.write_all(buf).await,
+
+            Self::Duplex(x) => x.write_all(buf).await,
+
+            Self::Recording(x, tap) => {
+                Box::pin(x.write_all(buf)).await?;
+                tap.tap(buf)
+            }
+
+            Self::Replay(x) => x.write_all(buf),
         }
     }
 
@@ -215,6 +313,12 @@ impl BearerWriteHalf {
 
             #[cfg(windows)]
             Self::NamedPipe(x) => x.flush().await,
+
+            Self::Duplex(x) => x.flush().await,
+
+            Self::Recording(x, _) => Box::pin(x.flush()).await,
+
+            Self::Replay(_) => Ok(()),
         }
     }
 }
@@ -247,19 +351,63 @@ pub enum Error {
 
     #[error("failure to abort the plexer threads")]
     AbortFailure,
+
+    #[error("ingress limit of {1} bytes exceeded for protocol {0}")]
+    IngressLimitExceeded(Protocol, usize),
+
+    #[error("timeout after {1:?} waiting for message of protocol {0}")]
+    Timeout(Protocol, Duration),
+}
+
+/// Bytes of a protocol that were read from the bearer but not yet dequeued
+/// by its agent
+#[derive(Debug, Default)]
+pub struct IngressUsage {
+    queued: AtomicUsize,
+    exceeded: AtomicBool,
+}
+
+impl IngressUsage {
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+}
+
+struct EgressChannel {
+    sender: tokio::sync::mpsc::Sender<Payload>,
+    limit: Option<usize>,
+    usage: Arc<IngressUsage>,
 }
 
-type EgressChannel = tokio::sync::mpsc::Sender<Payload>;
 type Egress = HashMap<Protocol, EgressChannel>;
 
+type IngressLimits = HashMap<Protocol, usize>;
+
+type SharedMetrics = Arc<dyn metrics::Metrics>;
+
 const EGRESS_MSG_QUEUE_BUFFER: usize = 100;
 
-pub struct Demuxer(BearerReadHalf, Egress);
+pub struct Demuxer(BearerReadHalf, Egress, IngressLimits, SharedMetrics);
 
 impl Demuxer {
     pub fn new(bearer: BearerReadHalf) -> Self {
         let egress = HashMap::new();
-        Self(bearer, egress)
+        let limits = HashMap::new();
+        Self(bearer, egress, limits, Arc::new(metrics::NoMetrics))
+    }
+
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.3 = metrics;
+    }
+
+    /// Sets the max amount of bytes that can be queued for a protocol before
+    /// its agent consumes them. Applies to subscriptions made afterwards.
+    pub fn set_ingress_limit(&mut self, protocol: Protocol, bytes: usize) {
+        self.2.insert(protocol, bytes);
     }
 
     pub async fn read_segment(&mut self) -> Result<(Protocol, Payload), Error> {
@@ -273,14 +421,34 @@ impl Demuxer {
         let mut buf = vec![0u8; segment_size];
         self.0.read_exact(&mut buf).await.map_err(Error::BearerIo)?;
 
+        self.3
+            .segment_received(mini_protocol(header.protocol), segment_size);
+
         Ok((header.protocol, buf))
     }
 
     async fn demux(&mut self, protocol: Protocol, payload: Payload) -> Result<(), Error> {
         let channel = self.1.get(&protocol);
 
-        if let Some(sender) = channel {
-            sender
+        if let Some(channel) = channel {
+            let len = payload.len();
+            let queued = channel.usage.queued.fetch_add(len, Ordering::Relaxed) + len;
+
+            if let Some(limit) = channel.limit {
+                if queued > limit {
+                    warn!(protocol, queued, limit, "ingress limit exceeded");
+                    channel.usage.exceeded.store(true, Ordering::Relaxed);
+
+                    // dropping the sender makes the agent fail once it drains
+                    // what was queued, the other protocols keep running
+                    self.1.remove(&protocol);
+
+                    return Ok(());
+                }
+            }
+
+            channel
+                .sender
                 .send(payload)
                 .await
                 .map_err(|err| Error::PlexerDemux(protocol, err.0))?;
@@ -292,13 +460,27 @@ impl Demuxer {
     }
 
     pub fn subscribe(&mut self, protocol: Protocol) -> tokio::sync::mpsc::Receiver<Payload> {
+        self.subscribe_tracked(protocol).0
+    }
+
+    fn subscribe_tracked(&mut self, protocol: Protocol) -> (FromPlexerPort, IngressTracking) {
         let (sender, recv) = tokio::sync::mpsc::channel(EGRESS_MSG_QUEUE_BUFFER);
 
+        // limits are set per mini-protocol, regardless of the direction bit
+        let limit = self.2.get(&mini_protocol(protocol)).copied();
+        let usage = Arc::new(IngressUsage::default());
+
         // keep track of the sender
-        self.1.insert(protocol, sender);
+        let channel = EgressChannel {
+            sender,
+            limit,
+            usage: usage.clone(),
+        };
+
+        self.1.insert(protocol, channel);
 
         // return the receiver for the agent
-        recv
+        (recv, (limit, usage))
     }
 
     pub async fn tick(&mut self) -> Result<(), Error> {
@@ -325,13 +507,17 @@ type Clock = Instant;
 
 const INGRESS_MSG_QUEUE_BUFFER: usize = 100;
 
-pub struct Muxer(BearerWriteHalf, Clock, Ingress);
+pub struct Muxer(BearerWriteHalf, Clock, Ingress, SharedMetrics);
 
 impl Muxer {
     pub fn new(bearer: BearerWriteHalf) -> Self {
         let ingress = tokio::sync::mpsc::channel(INGRESS_MSG_QUEUE_BUFFER);
         let clock = Instant::now();
-        Self(bearer, clock, ingress)
+        Self(bearer, clock, ingress, Arc::new(metrics::NoMetrics))
+    }
+
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.3 = metrics;
     }
 
     async fn write_segment(&mut self, protocol: u16, payload: &[u8]) -> Result<(), std::io::Error> {
@@ -341,6 +527,10 @@ impl Muxer {
             payload_len: payload.len() as u16,
         };
 
+        // recorded upfront, so that the peer never sees a segment before it's
+        // accounted for
+        self.3.segment_sent(mini_protocol(protocol), payload.len());
+
         let buf: [u8; 8] = header.into();
         self.0.write_all(&buf).await?;
         self.0.write_all(payload).await?;
@@ -392,11 +582,15 @@ impl Muxer {
 
 type ToPlexerPort = tokio::sync::mpsc::Sender<(Protocol, Payload)>;
 type FromPlexerPort = tokio::sync::mpsc::Receiver<Payload>;
+type IngressTracking = (Option<usize>, Arc<IngressUsage>);
 
 pub struct AgentChannel {
     protocol: Protocol,
     to_plexer: ToPlexerPort,
     from_plexer: FromPlexerPort,
+    ingress_limit: Option<usize>,
+    ingress_usage: Arc<IngressUsage>,
+    metrics: SharedMetrics,
 }
 
 impl AgentChannel {
@@ -409,6 +603,9 @@ impl AgentChannel {
             protocol,
             from_plexer,
             to_plexer,
+            ingress_limit: None,
+            ingress_usage: Default::default(),
+            metrics: Arc::new(metrics::NoMetrics),
         }
     }
 
@@ -421,6 +618,9 @@ impl AgentChannel {
             protocol,
             from_plexer,
             to_plexer,
+            ingress_limit: None,
+            ingress_usage: Default::default(),
+            metrics: Arc::new(metrics::NoMetrics),
         }
     }
 
@@ -431,8 +631,44 @@ impl AgentChannel {
             .map_err(|SendError((protocol, payload))| Error::AgentEnqueue(protocol, payload))
     }
 
+    fn with_ingress(self, (limit, usage): IngressTracking) -> Self {
+        Self {
+            ingress_limit: limit,
+            ingress_usage: usage,
+            ..self
+        }
+    }
+
+    fn with_metrics(self, metrics: SharedMetrics) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// The mini-protocol number of the channel, without the direction bit
+    pub fn mini_protocol(&self) -> Protocol {
+        mini_protocol(self.protocol)
+    }
+
+    /// The max amount of bytes the peer can have in-flight for this channel
+    pub fn ingress_limit(&self) -> Option<usize> {
+        self.ingress_limit
+    }
+
     pub async fn dequeue_chunk(&mut self) -> Result<Payload, Error> {
-        self.from_plexer.recv().await.ok_or(Error::AgentDequeue)
+        match self.from_plexer.recv().await {
+            Some(chunk) => {
+                self.ingress_usage
+                    .queued
+                    .fetch_sub(chunk.len(), Ordering::Relaxed);
+
+                Ok(chunk)
+            }
+            None => match self.ingress_limit {
+                Some(limit) if self.ingress_usage.is_exceeded() => {
+                    Err(Error::IngressLimitExceeded(self.mini_protocol(), limit))
+                }
+                _ => Err(Error::AgentDequeue),
+            },
+        }
     }
 }
 
@@ -443,6 +679,12 @@ pub struct RunningPlexer {
 
 impl RunningPlexer {
     pub async fn abort(self) {
+        self.abort_tasks();
+    }
+
+    /// Aborts the plexer tasks without consuming the handle, usable from sync
+    /// contexts such as a `Drop` impl
+    pub(crate) fn abort_tasks(&self) {
         self.demuxer.abort();
         self.muxer.abort();
     }
@@ -463,16 +705,37 @@ impl Plexer {
         }
     }
 
+    /// Sets the max amount of bytes that the peer can have in-flight for a
+    /// mini-protocol. Must be called before subscribing to the protocol.
+    ///
+    /// Going over the limit fails the agent of the protocol with
+    /// [`Error::IngressLimitExceeded`], the rest of the protocols are not
+    /// affected.
+    pub fn set_ingress_limit(&mut self, protocol: Protocol, bytes: usize) {
+        self.demuxer.set_ingress_limit(protocol, bytes);
+    }
+
+    /// Sets the receiver of the metrics of the plexer and its agents. Must be
+    /// called before subscribing the agents.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn metrics::Metrics>) {
+        self.demuxer.set_metrics(metrics.clone());
+        self.muxer.set_metrics(metrics);
+    }
+
     pub fn subscribe_client(&mut self, protocol: Protocol) -> AgentChannel {
         let to_plexer = self.muxer.clone_sender();
-        let from_plexer = self.demuxer.subscribe(protocol ^ 0x8000);
+        let (from_plexer, ingress) = self.demuxer.subscribe_tracked(protocol ^ 0x8000);
         AgentChannel::for_client(protocol, to_plexer, from_plexer)
+            .with_ingress(ingress)
+            .with_metrics(self.demuxer.3.clone())
     }
 
     pub fn subscribe_server(&mut self, protocol: Protocol) -> AgentChannel {
         let to_plexer = self.muxer.clone_sender();
-        let from_plexer = self.demuxer.subscribe(protocol);
+        let (from_plexer, ingress) = self.demuxer.subscribe_tracked(protocol);
         AgentChannel::for_server(protocol ^ 0x8000, to_plexer, from_plexer)
+            .with_ingress(ingress)
+            .with_metrics(self.demuxer.3.clone())
     }
 
     pub fn spawn(self) -> RunningPlexer {
@@ -489,7 +752,10 @@ impl Plexer {
 /// Protocol value that defines max segment length
 pub const MAX_SEGMENT_PAYLOAD_LENGTH: usize = 65535;
 
-fn try_decode_message<M>(buffer: &mut Vec<u8>) -> Result<Option<M>, Error>
+/// Decodes the next message in the buffer, along with its tag
+fn try_decode_message<M>(
+    buffer: &mut Vec<u8>,
+) -> Result<Option<(M, Option<metrics::MessageTag>)>, Error>
 where
     M: Fragment,
 {
@@ -499,8 +765,9 @@ where
     match maybe_msg {
         Ok(msg) => {
             let pos = decoder.position();
+            let tag = metrics::message_tag(&buffer[..pos]);
             buffer.drain(0..pos);
-            Ok(Some(msg))
+            Ok(Some((msg, tag)))
         }
         Err(err) if err.is_end_of_input() => Ok(None),
         Err(err) => {
@@ -515,16 +782,42 @@ where
 pub struct ChannelBuffer {
     channel: AgentChannel,
     temp: Vec<u8>,
+    ingress_limit: Option<usize>,
+    timeout: Option<Duration>,
+
+    /// When the first message since the last one from the peer was sent
+    awaiting_since: Option<Instant>,
 }
 
 impl ChannelBuffer {
     pub fn new(channel: AgentChannel) -> Self {
+        let ingress_limit = channel.ingress_limit();
+
         Self {
             channel,
             temp: Vec::new(),
+            ingress_limit,
+            timeout: None,
+            awaiting_since: None,
         }
     }
 
+    /// Sets the max amount of bytes that can be buffered while waiting for a
+    /// complete message. Defaults to the ingress limit of the channel.
+    pub fn set_ingress_limit(&mut self, bytes: Option<usize>) {
+        self.ingress_limit = bytes;
+    }
+
+    /// Sets the max time to wait for a complete message on the next calls to
+    /// `recv_full_msg`. Agents usually update it on each state transition.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
     /// Enqueues a msg as a sequence payload chunks
     pub async fn send_msg_chunks<M>(&mut self, msg: &M) -> Result<(), Error>
     where
@@ -539,11 +832,46 @@ impl ChannelBuffer {
             self.channel.enqueue_chunk(Vec::from(chunk)).await?;
         }
 
+        let protocol = self.channel.mini_protocol();
+        let tag = metrics::message_tag(&payload);
+        self.channel.metrics.message_sent(protocol, tag);
+        self.awaiting_since.get_or_insert_with(Instant::now);
+
         Ok(())
     }
 
     /// Reads from the channel until a complete message is found
+    ///
+    /// Fails if the message isn't complete before the timeout elapses or if
+    /// its partial payload exceeds the ingress limit.
     pub async fn recv_full_msg<M>(&mut self) -> Result<M, Error>
+    where
+        M: Fragment,
+    {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.recv_until_full_msg())
+                .await
+                .map_err(|_| Error::Timeout(self.channel.mini_protocol(), timeout))?,
+            None => self.recv_until_full_msg().await,
+        }
+    }
+
+    /// Reports the round-trip time of an exchange measured by the agent
+    pub fn record_round_trip(&self, elapsed: Duration) {
+        let protocol = self.channel.mini_protocol();
+        self.channel.metrics.round_trip(protocol, elapsed);
+    }
+
+    fn on_msg_received(&mut self, tag: Option<metrics::MessageTag>) {
+        let protocol = self.channel.mini_protocol();
+        self.channel.metrics.message_received(protocol, tag);
+
+        if let Some(since) = self.awaiting_since.take() {
+            self.channel.metrics.agency_wait(protocol, since.elapsed());
+        }
+    }
+
+    async fn recv_until_full_msg<M>(&mut self) -> Result<M, Error>
     where
         M: Fragment,
     {
@@ -552,8 +880,9 @@ impl ChannelBuffer {
         if !self.temp.is_empty() {
             trace!("buffer has data from previous payload");
 
-            if let Some(msg) = try_decode_message::<M>(&mut self.temp)? {
+            if let Some((msg, tag)) = try_decode_message::<M>(&mut self.temp)? {
                 debug!("decoding done");
+                self.on_msg_received(tag);
                 return Ok(msg);
             }
         }
@@ -562,8 +891,19 @@ impl ChannelBuffer {
             let chunk = self.channel.dequeue_chunk().await?;
             self.temp.extend(chunk);
 
-            if let Some(msg) = try_decode_message::<M>(&mut self.temp)? {
+            if let Some(limit) = self.ingress_limit {
+                if self.temp.len() > limit {
+                    warn!(len = self.temp.len(), limit, "ingress limit exceeded");
+                    return Err(Error::IngressLimitExceeded(
+                        self.channel.mini_protocol(),
+                        limit,
+                    ));
+                }
+            }
+
+            if let Some((msg, tag)) = try_decode_message::<M>(&mut self.temp)? {
                 debug!("decoding done");
+                self.on_msg_received(tag);
                 return Ok(msg);
             }
 
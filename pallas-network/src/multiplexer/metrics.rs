@@ -0,0 +1,150 @@
+//! Hooks to collect metrics about the traffic that goes through a plexer
+//!
+//! A [`Metrics`] implementation is handed to the [`super::Plexer`] before
+//! subscribing the agents. It receives the segments muxed and demuxed by the
+//! plexer, the messages sent and received by each agent and the timings
+//! measured along the way. Protocols are always reported as mini-protocol
+//! numbers, without the direction bit.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use pallas_codec::minicbor;
+
+use super::Protocol;
+
+/// Tag of a mini-protocol message, the first item of its CBOR array
+///
+/// Each message tag identifies a state transition of the mini-protocol.
+pub type MessageTag = u64;
+
+/// Receiver of the events observed by a plexer and its agents
+///
+/// All methods default to a no-op, implementors only need to override the
+/// events they care about. Methods are called from the plexer tasks and from
+/// the agents, so they should be cheap and never block.
+pub trait Metrics: Send + Sync {
+    /// A segment was read from the bearer
+    fn segment_received(&self, _protocol: Protocol, _bytes: usize) {}
+
+    /// A segment is about to be written to the bearer
+    fn segment_sent(&self, _protocol: Protocol, _bytes: usize) {}
+
+    /// An agent received a complete message
+    fn message_received(&self, _protocol: Protocol, _tag: Option<MessageTag>) {}
+
+    /// An agent sent a complete message
+    fn message_sent(&self, _protocol: Protocol, _tag: Option<MessageTag>) {}
+
+    /// Time between the last message sent by an agent and the next message
+    /// received from the peer
+    fn agency_wait(&self, _protocol: Protocol, _elapsed: Duration) {}
+
+    /// Latency of a request / response exchange, as measured by the clients
+    /// of the protocols that have one (keepalive and block-fetch)
+    fn round_trip(&self, _protocol: Protocol, _elapsed: Duration) {}
+}
+
+/// Metrics that are discarded, used when none are set
+#[derive(Debug, Default)]
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics {}
+
+/// Reads the tag of an encoded mini-protocol message
+pub(crate) fn message_tag(bytes: &[u8]) -> Option<MessageTag> {
+    let mut decoder = minicbor::Decoder::new(bytes);
+    decoder.array().ok()?;
+    decoder.u64().ok()
+}
+
+/// Stats accumulated for a single mini-protocol
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProtocolStats {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub segments_in: u64,
+    pub segments_out: u64,
+
+    /// Amount of received messages, by message tag
+    pub messages_in: BTreeMap<MessageTag, u64>,
+
+    /// Amount of sent messages, by message tag
+    pub messages_out: BTreeMap<MessageTag, u64>,
+
+    /// Messages that couldn't be tagged, in both directions
+    pub untagged_messages: u64,
+
+    pub agency_wait: Duration,
+    pub round_trips: Vec<Duration>,
+}
+
+/// Metrics kept in memory, mostly useful to make assertions in tests
+#[derive(Debug, Default)]
+pub struct MemoryMetrics(Mutex<HashMap<Protocol, ProtocolStats>>);
+
+impl MemoryMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&self, protocol: Protocol, f: impl FnOnce(&mut ProtocolStats)) {
+        let mut stats = self.0.lock().expect("poisoned metrics");
+        f(stats.entry(protocol).or_default());
+    }
+
+    /// Stats of a mini-protocol, empty if no traffic was seen for it
+    pub fn protocol(&self, protocol: Protocol) -> ProtocolStats {
+        let stats = self.0.lock().expect("poisoned metrics");
+        stats.get(&protocol).cloned().unwrap_or_default()
+    }
+
+    /// Mini-protocols with some recorded traffic, in ascending order
+    pub fn protocols(&self) -> Vec<Protocol> {
+        let stats = self.0.lock().expect("poisoned metrics");
+        let mut protocols: Vec<_> = stats.keys().copied().collect();
+        protocols.sort();
+        protocols
+    }
+}
+
+fn count_message(stats: &mut ProtocolStats, inbound: bool, tag: Option<MessageTag>) {
+    match (tag, inbound) {
+        (Some(tag), true) => *stats.messages_in.entry(tag).or_default() += 1,
+        (Some(tag), false) => *stats.messages_out.entry(tag).or_default() += 1,
+        (None, _) => stats.untagged_messages += 1,
+    }
+}
+
+impl Metrics for MemoryMetrics {
+    fn segment_received(&self, protocol: Protocol, bytes: usize) {
+        self.update(protocol, |x| {
+            x.bytes_in += bytes as u64;
+            x.segments_in += 1;
+        });
+    }
+
+    fn segment_sent(&self, protocol: Protocol, bytes: usize) {
+        self.update(protocol, |x| {
+            x.bytes_out += bytes as u64;
+            x.segments_out += 1;
+        });
+    }
+
+    fn message_received(&self, protocol: Protocol, tag: Option<MessageTag>) {
+        self.update(protocol, |x| count_message(x, true, tag));
+    }
+
+    fn message_sent(&self, protocol: Protocol, tag: Option<MessageTag>) {
+        self.update(protocol, |x| count_message(x, false, tag));
+    }
+
+    fn agency_wait(&self, protocol: Protocol, elapsed: Duration) {
+        self.update(protocol, |x| x.agency_wait += elapsed);
+    }
+
+    fn round_trip(&self, protocol: Protocol, elapsed: Duration) {
+        self.update(protocol, |x| x.round_trips.push(elapsed));
+    }
+}
@@ -0,0 +1,488 @@
+//! Recording and replay of the segments that go through a bearer
+//!
+//! A recording is a sequence of CBOR-encoded [`RecordedSegment`] values, one
+//! per muxed segment, in the order in which they were seen by the bearer.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use pallas_codec::minicbor::{self, Decode, Encode};
+use pallas_codec::utils::Bytes;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, oneshot, Notify};
+use tracing::{error, trace, warn};
+
+use super::{Header, Protocol, Segment, Timestamp, HEADER_LEN};
+
+type IOResult<T> = tokio::io::Result<T>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+#[cbor(index_only)]
+pub enum Direction {
+    /// Segment received from the peer
+    #[n(0)]
+    Inbound,
+
+    /// Segment sent to the peer
+    #[n(1)]
+    Outbound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct RecordedSegment {
+    #[n(0)]
+    pub direction: Direction,
+
+    #[n(1)]
+    pub timestamp: Timestamp,
+
+    #[n(2)]
+    pub protocol: Protocol,
+
+    #[n(3)]
+    pub payload: Bytes,
+}
+
+impl RecordedSegment {
+    fn new(direction: Direction, segment: Segment) -> Self {
+        Self {
+            direction,
+            timestamp: segment.header.timestamp,
+            protocol: segment.header.protocol,
+            payload: segment.payload.into(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let header = Header {
+            protocol: self.protocol,
+            timestamp: self.timestamp,
+            payload_len: self.payload.len() as u16,
+        };
+
+        let header: [u8; HEADER_LEN] = header.into();
+
+        let mut out = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        out.extend_from_slice(&header);
+        out.extend_from_slice(&self.payload);
+        out
+    }
+}
+
+/// Reads all the segments of a recording file
+pub fn read_recording(path: impl AsRef<Path>) -> IOResult<Vec<RecordedSegment>> {
+    let data = std::fs::read(path)?;
+    decode_recording(&data)
+}
+
+/// Decodes all the segments of an in-memory recording
+pub fn decode_recording(data: &[u8]) -> IOResult<Vec<RecordedSegment>> {
+    let mut decoder = minicbor::Decoder::new(data);
+    let mut segments = Vec::new();
+
+    while decoder.position() < data.len() {
+        let segment = decoder
+            .decode()
+            .map_err(|err| tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, err))?;
+
+        segments.push(segment);
+    }
+
+    Ok(segments)
+}
+
+/// Splits the bytes that go through a bearer half into segments
+#[derive(Debug, Default)]
+struct SegmentParser(Vec<u8>);
+
+impl SegmentParser {
+    fn push(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn next_segment(&mut self) -> Option<Segment> {
+        if self.0.len() < HEADER_LEN {
+            return None;
+        }
+
+        let header = Header::from(&self.0[..HEADER_LEN]);
+        let end = HEADER_LEN + header.payload_len as usize;
+
+        if self.0.len() < end {
+            return None;
+        }
+
+        let payload = self.0[HEADER_LEN..end].to_vec();
+        self.0.drain(..end);
+
+        Some(Segment { header, payload })
+    }
+}
+
+enum Command {
+    Record(Vec<u8>),
+    Sync(oneshot::Sender<()>),
+}
+
+/// Writes the segments handed by the recorder, so that the bearer never
+/// waits on the sink
+async fn write_recording(
+    mut sink: impl AsyncWrite + Send + Unpin,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+) {
+    while let Some(command) = commands.recv().await {
+        match command {
+            Command::Record(data) => {
+                // flush every segment so that the recording survives a crash,
+                // which is usually what we're trying to debug
+                let result = match sink.write_all(&data).await {
+                    Ok(_) => sink.flush().await,
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = result {
+                    error!(?err, "can't write recording, stopping");
+                    return;
+                }
+            }
+            Command::Sync(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// A sink for the segments of a recording, shared by both halves of a bearer
+///
+/// Segments are written by a background task, which requires the recorder to
+/// be created within a tokio runtime.
+#[derive(Clone)]
+pub struct Recorder(mpsc::UnboundedSender<Command>);
+
+impl Recorder {
+    pub fn new(sink: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_recording(sink, receiver));
+
+        Self(sender)
+    }
+
+    /// Creates (or truncates) a recording file
+    pub async fn create(path: impl AsRef<Path>) -> IOResult<Self> {
+        let file = tokio::fs::File::create(path).await?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+
+    fn send(&self, command: Command) -> IOResult<()> {
+        self.0.send(command).map_err(|_| {
+            tokio::io::Error::new(tokio::io::ErrorKind::BrokenPipe, "recording stopped")
+        })
+    }
+
+    fn record(&self, segment: &RecordedSegment) -> IOResult<()> {
+        let data = minicbor::to_vec(segment)
+            .map_err(|err| tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, err))?;
+
+        self.send(Command::Record(data))
+    }
+
+    /// Waits until all the segments recorded so far are written to the sink
+    pub async fn sync(&self) -> IOResult<()> {
+        let (done, wait) = oneshot::channel();
+        self.send(Command::Sync(done))?;
+
+        wait.await.map_err(|_| {
+            tokio::io::Error::new(tokio::io::ErrorKind::BrokenPipe, "recording stopped")
+        })
+    }
+}
+
+/// Records the segments of one direction of a bearer
+pub struct SegmentTap {
+    recorder: Recorder,
+    direction: Direction,
+    parser: SegmentParser,
+}
+
+impl SegmentTap {
+    pub(super) fn new(recorder: Recorder, direction: Direction) -> Self {
+        Self {
+            recorder,
+            direction,
+            parser: SegmentParser::default(),
+        }
+    }
+
+    pub(super) fn tap(&mut self, bytes: &[u8]) -> IOResult<()> {
+        self.parser.push(bytes);
+
+        while let Some(segment) = self.parser.next_segment() {
+            trace!(direction = ?self.direction, protocol = segment.header.protocol, "recording segment");
+            let segment = RecordedSegment::new(self.direction, segment);
+            self.recorder.record(&segment)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ReplayState {
+    pending: Mutex<VecDeque<RecordedSegment>>,
+    progress: Notify,
+}
+
+/// A recorded session that can be played back through a bearer
+///
+/// Inbound segments are handed to the reader in their recorded order, but
+/// only after all the outbound segments that preceded them in the recording
+/// were written. Outbound segments must match the recording, otherwise the
+/// write fails. This means that the agents on top of the replayed bearer
+/// must behave deterministically (eg: no random keep-alive cookies).
+#[derive(Clone)]
+pub struct Replay(Arc<ReplayState>);
+
+impl Replay {
+    pub fn new(segments: impl IntoIterator<Item = RecordedSegment>) -> Self {
+        let state = ReplayState {
+            pending: Mutex::new(segments.into_iter().collect()),
+            progress: Notify::new(),
+        };
+
+        Self(Arc::new(state))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> IOResult<Self> {
+        let segments = read_recording(path)?;
+        Ok(Self::new(segments))
+    }
+
+    /// Number of recorded segments that haven't been replayed yet
+    pub fn remaining(&self) -> usize {
+        self.0.pending.lock().expect("poisoned replay").len()
+    }
+
+    async fn next_inbound(&self) -> Option<RecordedSegment> {
+        loop {
+            // the notification needs to be registered before checking the
+            // state, otherwise we could miss progress from the writer
+            let progress = self.0.progress.notified();
+
+            {
+                let mut pending = self.0.pending.lock().expect("poisoned replay");
+
+                match pending.front() {
+                    None => return None,
+                    Some(x) if x.direction == Direction::Inbound => return pending.pop_front(),
+                    Some(_) => (),
+                }
+            }
+
+            trace!("waiting for outbound segments before replaying inbound");
+            progress.await;
+        }
+    }
+
+    fn match_outbound(&self, segment: Segment) -> IOResult<()> {
+        let mut pending = self.0.pending.lock().expect("poisoned replay");
+
+        let next = pending
+            .iter()
+            .position(|x| x.direction == Direction::Outbound);
+
+        let Some(index) = next else {
+            warn!(
+                protocol = segment.header.protocol,
+                "outbound segment after the end of the recording"
+            );
+
+            return Ok(());
+        };
+
+        let expected = &pending[index];
+
+        if expected.protocol != segment.header.protocol
+            || expected.payload.as_slice() != segment.payload.as_slice()
+        {
+            return Err(tokio::io::Error::new(
+                tokio::io::ErrorKind::InvalidData,
+                format!(
+                    "replay diverged from recording on protocol {}",
+                    segment.header.protocol
+                ),
+            ));
+        }
+
+        pending.remove(index);
+        drop(pending);
+
+        self.0.progress.notify_waiters();
+
+        Ok(())
+    }
+}
+
+/// The read half of a replayed bearer
+pub struct ReplayReader {
+    replay: Replay,
+    buffer: VecDeque<u8>,
+}
+
+impl ReplayReader {
+    pub(super) fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    pub(super) async fn read_exact(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        while self.buffer.len() < buf.len() {
+            let segment = self.replay.next_inbound().await.ok_or_else(|| {
+                tokio::io::Error::new(tokio::io::ErrorKind::UnexpectedEof, "end of recording")
+            })?;
+
+            self.buffer.extend(segment.to_bytes());
+        }
+
+        let len = buf.len();
+
+        for (target, source) in buf.iter_mut().zip(self.buffer.drain(..len)) {
+            *target = source;
+        }
+
+        Ok(len)
+    }
+}
+
+/// The write half of a replayed bearer
+pub struct ReplayWriter {
+    replay: Replay,
+    parser: SegmentParser,
+}
+
+impl ReplayWriter {
+    pub(super) fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            parser: SegmentParser::default(),
+        }
+    }
+
+    pub(super) fn write_all(&mut self, buf: &[u8]) -> IOResult<()> {
+        self.parser.push(buf);
+
+        while let Some(segment) = self.parser.next_segment() {
+            self.replay.match_outbound(segment)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(direction: Direction, protocol: Protocol, payload: &[u8]) -> RecordedSegment {
+        RecordedSegment {
+            direction,
+            timestamp: 0,
+            protocol,
+            payload: payload.to_vec().into(),
+        }
+    }
+
+    #[test]
+    fn recording_roundtrip() {
+        let segments = vec![
+            segment(Direction::Outbound, 2, &[0x81, 0x00]),
+            segment(Direction::Inbound, 0x8002, &[0x81, 0x01]),
+        ];
+
+        let mut data = Vec::new();
+
+        for segment in segments.iter() {
+            data.extend(minicbor::to_vec(segment).unwrap());
+        }
+
+        assert_eq!(decode_recording(&data).unwrap(), segments);
+    }
+
+    #[test]
+    fn parser_splits_segments_across_writes() {
+        let bytes = [
+            segment(Direction::Outbound, 2, &[1, 2, 3]).to_bytes(),
+            segment(Direction::Outbound, 3, &[4]).to_bytes(),
+        ]
+        .concat();
+
+        let mut parser = SegmentParser::default();
+        let mut found = vec![];
+
+        for chunk in bytes.chunks(5) {
+            parser.push(chunk);
+
+            while let Some(segment) = parser.next_segment() {
+                found.push((segment.header.protocol, segment.payload));
+            }
+        }
+
+        assert_eq!(found, vec![(2, vec![1, 2, 3]), (3, vec![4])]);
+    }
+
+    #[tokio::test]
+    async fn inbound_waits_for_preceding_outbound() {
+        let replay = Replay::new(vec![
+            segment(Direction::Outbound, 2, &[0x81, 0x00]),
+            segment(Direction::Inbound, 0x8002, &[0x81, 0x01]),
+        ]);
+
+        let mut reader = ReplayReader::new(replay.clone());
+        let mut writer = ReplayWriter::new(replay.clone());
+
+        let read = tokio::spawn(async move {
+            let mut buf = [0u8; HEADER_LEN + 2];
+            reader.read_exact(&mut buf).await.unwrap();
+            buf
+        });
+
+        tokio::task::yield_now().await;
+        assert!(!read.is_finished());
+
+        writer
+            .write_all(&segment(Direction::Outbound, 2, &[0x81, 0x00]).to_bytes())
+            .unwrap();
+
+        let buf = read.await.unwrap();
+        assert_eq!(&buf[HEADER_LEN..], &[0x81, 0x01]);
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn recorder_writes_in_the_background() {
+        let (sink, mut source) = tokio::io::duplex(1024);
+        let recorder = Recorder::new(sink);
+
+        let recorded = segment(Direction::Inbound, 0x8002, &[0x81, 0x01]);
+        recorder.record(&recorded).unwrap();
+        recorder.sync().await.unwrap();
+        drop(recorder);
+
+        let mut data = vec![];
+        tokio::io::AsyncReadExt::read_to_end(&mut source, &mut data)
+            .await
+            .unwrap();
+
+        assert_eq!(decode_recording(&data).unwrap(), vec![recorded]);
+    }
+
+    #[test]
+    fn diverging_outbound_fails() {
+        let replay = Replay::new(vec![segment(Direction::Outbound, 2, &[0x81, 0x00])]);
+        let mut writer = ReplayWriter::new(replay);
+
+        let result = writer.write_all(&segment(Direction::Outbound, 2, &[0x81, 0x02]).to_bytes());
+        assert!(result.is_err());
+    }
+}
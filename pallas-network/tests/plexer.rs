@@ -1,25 +1,37 @@
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
 
-use pallas_network::multiplexer::{Bearer, Plexer};
+use pallas_codec::utils::Bytes;
+use pallas_network::miniprotocols::{
+    blockfetch, chainsync, keepalive, Point, StateTimeouts, LONG_WAIT,
+};
+use pallas_network::multiplexer::metrics::MemoryMetrics;
+use pallas_network::multiplexer::{Bearer, ChannelBuffer, Error, Plexer};
 use rand::{distributions::Uniform, Rng};
-use tokio::net::TcpListener;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 
-async fn setup_passive_muxer<const P: u16>() -> Plexer {
-    let server = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, P))
+/// Binds a listener to a free local port
+async fn bind_local() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
         .await
         .unwrap();
 
-    println!("listening for connections on port {P}");
+    let address = listener.local_addr().unwrap();
+    println!("listening for connections on {address}");
 
-    let (bearer, _) = Bearer::accept_tcp(&server).await.unwrap();
+    (listener, address)
+}
+
+async fn setup_passive_muxer(listener: TcpListener) -> Plexer {
+    let (bearer, _) = Bearer::accept_tcp(&listener).await.unwrap();
 
     Plexer::new(bearer)
 }
 
-async fn setup_active_muxer<const P: u16>() -> Plexer {
-    let bearer = Bearer::connect_tcp(SocketAddrV4::new(Ipv4Addr::LOCALHOST, P))
-        .await
-        .unwrap();
+async fn setup_active_muxer(address: SocketAddr) -> Plexer {
+    let bearer = Bearer::connect_tcp(address).await.unwrap();
 
     println!("active plexer connected");
 
@@ -33,13 +45,10 @@ fn random_payload(size: usize) -> Vec<u8> {
 
 #[tokio::test]
 async fn one_way_small_sequence_of_payloads() {
-    let passive = tokio::task::spawn(setup_passive_muxer::<50301>());
+    let (listener, address) = bind_local().await;
+    let passive = tokio::task::spawn(setup_passive_muxer(listener));
 
-    // HACK: a small sleep seems to be required for Github actions runner to
-    // formally expose the port
-    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-
-    let mut active = setup_active_muxer::<50301>().await;
+    let mut active = setup_active_muxer(address).await;
 
     let mut passive = passive.await.unwrap();
 
@@ -60,3 +69,309 @@ async fn one_way_small_sequence_of_payloads() {
     passive.abort().await;
     active.abort().await;
 }
+
+/// A peer that writes raw segments to the bearer, bypassing any protocol
+/// state machine
+struct MaliciousPeer(TcpStream);
+
+impl MaliciousPeer {
+    async fn connect(address: SocketAddr) -> Self {
+        let stream = TcpStream::connect(address).await.unwrap();
+        Self(stream)
+    }
+
+    async fn accept(listener: &TcpListener) -> Self {
+        let (stream, _) = listener.accept().await.unwrap();
+        Self(stream)
+    }
+
+    async fn send_segment(&mut self, protocol: u16, payload: &[u8]) {
+        let mut header = [0u8; 8];
+        header[4..6].copy_from_slice(&protocol.to_be_bytes());
+        header[6..8].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+
+        self.0.write_all(&header).await.unwrap();
+        self.0.write_all(payload).await.unwrap();
+        self.0.flush().await.unwrap();
+    }
+
+    async fn read_segment(&mut self) -> Vec<u8> {
+        let mut header = [0u8; 8];
+        self.0.read_exact(&mut header).await.unwrap();
+
+        let len = u16::from_be_bytes([header[6], header[7]]) as usize;
+        let mut payload = vec![0u8; len];
+        self.0.read_exact(&mut payload).await.unwrap();
+
+        payload
+    }
+}
+
+#[tokio::test]
+async fn ingress_limit_rejects_never_ending_message() {
+    let (listener, address) = bind_local().await;
+    let passive = tokio::task::spawn(setup_passive_muxer(listener));
+
+    let mut peer = MaliciousPeer::connect(address).await;
+
+    let mut passive = passive.await.unwrap();
+    passive.set_ingress_limit(3, 1024);
+    let mut channel = ChannelBuffer::new(passive.subscribe_server(3));
+    let passive = passive.spawn();
+
+    // announce a 1MB bytestring and then trickle its content
+    let mut first = vec![0x5a, 0x00, 0x10, 0x00, 0x00];
+    first.extend(random_payload(300));
+    peer.send_segment(3, &first).await;
+
+    for _ in 0..5 {
+        peer.send_segment(3, &random_payload(300)).await;
+    }
+
+    let result = channel.recv_full_msg::<Bytes>().await;
+    assert!(matches!(result, Err(Error::IngressLimitExceeded(_, 1024))));
+
+    passive.abort().await;
+}
+
+#[tokio::test]
+async fn ingress_limit_rejects_unconsumed_segments() {
+    let (listener, address) = bind_local().await;
+    let passive = tokio::task::spawn(setup_passive_muxer(listener));
+
+    let mut peer = MaliciousPeer::connect(address).await;
+
+    let mut passive = passive.await.unwrap();
+    passive.set_ingress_limit(3, 1024);
+    let mut channel = passive.subscribe_server(3);
+    let mut marker = passive.subscribe_server(2);
+    let passive = passive.spawn();
+
+    // the agent doesn't consume anything until the peer is done flooding
+    for _ in 0..3 {
+        peer.send_segment(3, &random_payload(512)).await;
+    }
+
+    // segments are demuxed in order, once the marker sent after the flood
+    // comes through, the flood went through the ingress limit too
+    peer.send_segment(2, &random_payload(8)).await;
+
+    let marker = tokio::time::timeout(Duration::from_secs(5), marker.dequeue_chunk()).await;
+    assert_eq!(marker.unwrap().unwrap().len(), 8);
+
+    assert_eq!(channel.dequeue_chunk().await.unwrap().len(), 512);
+    assert_eq!(channel.dequeue_chunk().await.unwrap().len(), 512);
+
+    let result = tokio::time::timeout(Duration::from_secs(5), channel.dequeue_chunk()).await;
+    assert!(matches!(
+        result,
+        Ok(Err(Error::IngressLimitExceeded(3, 1024)))
+    ));
+
+    passive.abort().await;
+}
+
+#[tokio::test]
+async fn ingress_limit_is_scoped_to_protocol() {
+    let (listener, address) = bind_local().await;
+    let passive = tokio::task::spawn(setup_passive_muxer(listener));
+
+    let mut peer = MaliciousPeer::connect(address).await;
+
+    let mut passive = passive.await.unwrap();
+    passive.set_ingress_limit(3, 16);
+    let mut channel = ChannelBuffer::new(passive.subscribe_server(2));
+    let passive = passive.spawn();
+
+    let payload: Bytes = random_payload(512).into();
+    let cbor = pallas_codec::minicbor::to_vec(&payload).unwrap();
+    peer.send_segment(2, &cbor).await;
+
+    let msg = channel.recv_full_msg::<Bytes>().await.unwrap();
+    assert_eq!(msg, payload);
+
+    passive.abort().await;
+}
+
+#[tokio::test]
+async fn ingress_limit_breach_keeps_other_protocols() {
+    let (listener, address) = bind_local().await;
+    let passive = tokio::task::spawn(setup_passive_muxer(listener));
+
+    let mut peer = MaliciousPeer::connect(address).await;
+
+    let mut passive = passive.await.unwrap();
+    passive.set_ingress_limit(3, 1024);
+    let mut flooded = passive.subscribe_server(3);
+    let mut channel = ChannelBuffer::new(passive.subscribe_server(2));
+    let passive = passive.spawn();
+
+    for _ in 0..3 {
+        peer.send_segment(3, &random_payload(512)).await;
+    }
+
+    let payload: Bytes = random_payload(64).into();
+    let cbor = pallas_codec::minicbor::to_vec(&payload).unwrap();
+    peer.send_segment(2, &cbor).await;
+
+    let msg = channel.recv_full_msg::<Bytes>().await.unwrap();
+    assert_eq!(msg, payload);
+
+    flooded.dequeue_chunk().await.unwrap();
+    flooded.dequeue_chunk().await.unwrap();
+
+    let result = flooded.dequeue_chunk().await;
+    assert!(matches!(result, Err(Error::IngressLimitExceeded(3, 1024))));
+
+    passive.abort().await;
+}
+
+#[tokio::test]
+async fn state_timeout_on_silent_peer() {
+    let (listener, address) = bind_local().await;
+
+    let peer = tokio::spawn(async move {
+        let mut peer = MaliciousPeer::accept(&listener).await;
+
+        // swallow the find-intersect and never reply
+        peer.read_segment().await;
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    });
+
+    let bearer = Bearer::connect_tcp(address).await.unwrap();
+
+    let mut plexer = Plexer::new(bearer);
+    let mut client = chainsync::N2NClient::new(plexer.subscribe_client(2));
+    let plexer = plexer.spawn();
+
+    let timeouts = StateTimeouts::new()
+        .with(&chainsync::State::Intersect, Duration::from_millis(200))
+        .with(&chainsync::State::MustReply, Duration::from_secs(60));
+
+    client.set_timeouts(timeouts);
+
+    client
+        .send_find_intersect(vec![Point::Origin])
+        .await
+        .unwrap();
+
+    let result = client.recv_message().await;
+
+    assert!(matches!(
+        result,
+        Err(chainsync::ClientError::Plexer(Error::Timeout(2, _)))
+    ));
+
+    plexer.abort().await;
+    peer.abort();
+}
+
+#[tokio::test]
+async fn metrics_track_traffic_and_round_trips() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let client_metrics = Arc::new(MemoryMetrics::new());
+    let server_metrics = Arc::new(MemoryMetrics::new());
+
+    let mut client_plexer = Plexer::new(client_bearer);
+    client_plexer.set_metrics(client_metrics.clone());
+    let mut keepalive_client = keepalive::Client::new(client_plexer.subscribe_client(8));
+    let mut blockfetch_client = blockfetch::Client::new(client_plexer.subscribe_client(3));
+    let client_plexer = client_plexer.spawn();
+
+    let mut server_plexer = Plexer::new(server_bearer);
+    server_plexer.set_metrics(server_metrics.clone());
+    let mut keepalive_server = keepalive::Server::new(server_plexer.subscribe_server(8));
+    let mut blockfetch_server = blockfetch::Server::new(server_plexer.subscribe_server(3));
+    let server_plexer = server_plexer.spawn();
+
+    let server = tokio::spawn(async move {
+        keepalive_server.keepalive_roundtrip().await.unwrap();
+
+        blockfetch_server.recv_while_idle().await.unwrap().unwrap();
+        blockfetch_server
+            .send_block_range(vec![vec![1; 100]])
+            .await
+            .unwrap();
+    });
+
+    keepalive_client.keepalive_roundtrip().await.unwrap();
+
+    let body = blockfetch_client
+        .fetch_single(Point::Specific(1, vec![2; 32]))
+        .await
+        .unwrap();
+
+    assert_eq!(body.len(), 100);
+
+    server.await.unwrap();
+
+    let keepalive = client_metrics.protocol(8);
+    assert_eq!(keepalive.segments_out, 1);
+    assert_eq!(keepalive.messages_out.get(&0), Some(&1));
+    assert_eq!(keepalive.messages_in.get(&1), Some(&1));
+    assert_eq!(keepalive.round_trips.len(), 1);
+
+    // request range out, then start batch, block and batch done in
+    let blockfetch = client_metrics.protocol(3);
+    assert_eq!(blockfetch.messages_out.get(&0), Some(&1));
+    assert_eq!(blockfetch.messages_in.get(&2), Some(&1));
+    assert_eq!(blockfetch.messages_in.get(&4), Some(&1));
+    assert_eq!(blockfetch.messages_in.get(&5), Some(&1));
+    assert_eq!(blockfetch.round_trips.len(), 1);
+    assert!(blockfetch.agency_wait > Duration::ZERO);
+
+    // both ends see the same bytes, from opposite sides
+    for protocol in [3, 8] {
+        let client = client_metrics.protocol(protocol);
+        let server = server_metrics.protocol(protocol);
+
+        assert_eq!(client.bytes_out, server.bytes_in);
+        assert_eq!(client.bytes_in, server.bytes_out);
+        assert_eq!(client.segments_out, server.segments_in);
+    }
+
+    assert_eq!(client_metrics.protocols(), vec![3, 8]);
+    assert!(server_metrics.protocol(8).round_trips.is_empty());
+
+    client_plexer.abort().await;
+    server_plexer.abort().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn spec_timeouts_apply_by_default() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let mut client_plexer = Plexer::new(client_bearer);
+    let mut blockfetch_client = blockfetch::Client::new(client_plexer.subscribe_client(3));
+    let client_plexer = client_plexer.spawn();
+
+    let mut server_plexer = Plexer::new(server_bearer);
+    let mut keepalive_server = keepalive::Server::new(server_plexer.subscribe_server(8));
+    let _blockfetch_server = server_plexer.subscribe_server(3);
+    let server_plexer = server_plexer.spawn();
+
+    // the server never answers the request
+    let result = blockfetch_client
+        .fetch_single(Point::Specific(1, vec![2; 32]))
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(blockfetch::ClientError::Plexer(Error::Timeout(
+            3, LONG_WAIT
+        )))
+    ));
+
+    // and the client never sends a keepalive
+    let result = keepalive_server.recv_keepalive_request().await;
+
+    assert!(matches!(
+        result,
+        Err(keepalive::ServerError::Plexer(Error::Timeout(8, timeout)))
+            if timeout == Duration::from_secs(97)
+    ));
+
+    client_plexer.abort().await;
+    server_plexer.abort().await;
+}
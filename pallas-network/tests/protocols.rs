@@ -5,24 +5,43 @@ use std::time::Duration;
 
 use pallas_codec::utils::{AnyCbor, AnyUInt, Bytes, KeyValuePairs, TagWrap};
 use pallas_crypto::hash::Hash;
-use pallas_network::facades::{NodeClient, PeerClient, PeerServer};
-use pallas_network::miniprotocols::blockfetch::BlockRequest;
-use pallas_network::miniprotocols::chainsync::{ClientRequest, HeaderContent, Tip};
+use pallas_network::facades::follower::ChainEvent;
+use pallas_network::facades::manager::{FollowEvent, PeerManagerConfig, PeerStatus};
+use pallas_network::facades::{
+    ChainFollower, Error, NodeClient, PeerClient, PeerManager, PeerServer,
+};
+use pallas_network::miniprotocols::blockfetch::{
+    BlockRequest, FetchScheduler, SchedulerConfig, SchedulerError,
+};
+use pallas_network::miniprotocols::chainsync::{BlockContent, ClientRequest, HeaderContent, Tip};
 use pallas_network::miniprotocols::handshake::n2n::VersionData;
+use pallas_network::miniprotocols::handshake::{n2c, n2n};
 use pallas_network::miniprotocols::localstate::queries_v16::{
     Addr, Addrs, ChainBlockNumber, Fraction, Genesis, Snapshots, Stakes, SystemStart, UnitInterval,
     Value,
 };
 use pallas_network::miniprotocols::localstate::ClientQueryRequest;
-use pallas_network::miniprotocols::txsubmission::{EraTxBody, TxIdAndSize};
+use pallas_network::miniprotocols::localtxsubmission::{EraTx, RejectReason, Response};
+use pallas_network::miniprotocols::peersharing::{self, PeerAddress};
+use pallas_network::miniprotocols::txmonitor::{
+    self, Mempool, MempoolSizeAndCapacity, MempoolSnapshot,
+};
+use pallas_network::miniprotocols::txsubmission::{
+    EraTxBody, EraTxId, TxIdAndSize, TxSubmissionAgent,
+};
 use pallas_network::miniprotocols::{
     blockfetch,
     chainsync::{self, NextResponse},
     Point,
 };
-use pallas_network::miniprotocols::{handshake, localstate, txsubmission, MAINNET_MAGIC};
+use pallas_network::miniprotocols::{
+    handshake, keepalive, localstate, localtxsubmission, txsubmission, MAINNET_MAGIC,
+    PROTOCOL_N2N_BLOCK_FETCH, PROTOCOL_N2N_CHAIN_SYNC, PROTOCOL_N2N_HANDSHAKE,
+    PROTOCOL_N2N_KEEP_ALIVE, PROTOCOL_N2N_TX_SUBMISSION,
+};
+use pallas_network::multiplexer::recording::{Recorder, Replay};
 use pallas_network::multiplexer::{Bearer, Plexer};
-use std::path::Path;
+use pallas_traverse::MultiEraBlock;
 
 use tokio::net::TcpListener;
 
@@ -168,7 +187,6 @@ pub async fn blockfetch_happy_path() {
 }
 
 #[tokio::test]
-#[ignore]
 pub async fn blockfetch_server_and_client_happy_path() {
     let block_bodies = vec![
         hex::decode("deadbeefdeadbeef").unwrap(),
@@ -180,9 +198,7 @@ pub async fn blockfetch_server_and_client_happy_path() {
         hex::decode("deadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef").unwrap(),
     );
 
-    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 30003))
-        .await
-        .unwrap();
+    let (client_bearer, server_bearer) = Bearer::pair();
 
     let server = tokio::spawn({
         let bodies = block_bodies.clone();
@@ -190,7 +206,7 @@ pub async fn blockfetch_server_and_client_happy_path() {
         async move {
             // server setup
 
-            let mut peer_server = PeerServer::accept(&listener, 0).await.unwrap();
+            let mut peer_server = PeerServer::accept_bearer(server_bearer, 0).await.unwrap();
 
             let server_bf = peer_server.blockfetch();
 
@@ -220,9 +236,7 @@ pub async fn blockfetch_server_and_client_happy_path() {
     });
 
     let client = tokio::spawn(async move {
-        tokio::time::sleep(Duration::from_secs(1)).await;
-
-        let mut client_to_server_conn = PeerClient::connect("localhost:30003", 0).await.unwrap();
+        let mut client_to_server_conn = PeerClient::connect_bearer(client_bearer, 0).await.unwrap();
 
         let client_bf = client_to_server_conn.blockfetch();
 
@@ -264,23 +278,79 @@ pub async fn blockfetch_server_and_client_happy_path() {
 }
 
 #[tokio::test]
-#[ignore]
+pub async fn peersharing_server_and_client_happy_path() {
+    let peers = vec![
+        PeerAddress::V4(Ipv4Addr::new(10, 0, 0, 1), 3001),
+        PeerAddress::V6(std::net::Ipv6Addr::LOCALHOST, 3002),
+    ];
+
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn({
+        let peers = peers.clone();
+        async move {
+            // server setup
+
+            let mut peer_server = PeerServer::accept_bearer(server_bearer, 0).await.unwrap();
+
+            let server_ps = peer_server.peersharing();
+
+            // server receives share request from client, sends peers
+
+            let amount = server_ps.recv_while_idle().await.unwrap().unwrap();
+
+            assert_eq!(amount, 5);
+            assert_eq!(*server_ps.state(), peersharing::State::Busy(5));
+
+            server_ps.send_peer_addresses(peers).await.unwrap();
+
+            assert_eq!(*server_ps.state(), peersharing::State::Idle);
+
+            // server receives done from client
+
+            assert!(server_ps.recv_while_idle().await.unwrap().is_none());
+
+            assert_eq!(*server_ps.state(), peersharing::State::Done);
+        }
+    });
+
+    let client = tokio::spawn(async move {
+        let mut client_to_server_conn = PeerClient::connect_bearer(client_bearer, 0).await.unwrap();
+
+        let client_ps = client_to_server_conn.peersharing();
+
+        // client requests peers
+
+        let received = client_ps.request_peers(5).await.unwrap();
+
+        assert_eq!(received, peers);
+        assert_eq!(*client_ps.state(), peersharing::State::Idle);
+
+        // client sends done
+
+        client_ps.send_done().await.unwrap();
+
+        assert_eq!(*client_ps.state(), peersharing::State::Done);
+    });
+
+    let (client, server) = tokio::join!(client, server);
+    client.unwrap();
+    server.unwrap();
+}
+
+#[tokio::test]
 pub async fn chainsync_server_and_client_happy_path_n2n() {
     let point1 = Point::Specific(1, vec![0x01]);
     let point2 = Point::Specific(2, vec![0x02]);
 
+    let (client_bearer, bearer) = Bearer::pair();
+
     let server = tokio::spawn({
         let point1 = point1.clone();
         let point2 = point2.clone();
         async move {
             // server setup
 
-            let server_listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 30002))
-                .await
-                .unwrap();
-
-            let (bearer, _) = Bearer::accept_tcp(&server_listener).await.unwrap();
-
             let mut server_plexer = Plexer::new(bearer);
 
             let mut server_hs: handshake::Server<VersionData> =
@@ -389,7 +459,7 @@ pub async fn chainsync_server_and_client_happy_path_n2n() {
     });
 
     let client = tokio::spawn(async move {
-        let mut client_to_server_conn = PeerClient::connect("localhost:30002", 0).await.unwrap();
+        let mut client_to_server_conn = PeerClient::connect_bearer(client_bearer, 0).await.unwrap();
 
         let client_cs = client_to_server_conn.chainsync();
 
@@ -463,18 +533,12 @@ pub async fn chainsync_server_and_client_happy_path_n2n() {
 #[cfg(unix)]
 #[tokio::test]
 pub async fn local_state_query_server_and_client_happy_path() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
     let server = tokio::spawn({
         async move {
             // server setup
-            let socket_path = Path::new("node1.socket");
-
-            if socket_path.exists() {
-                fs::remove_file(socket_path).unwrap();
-            }
-
-            let listener = UnixListener::bind(socket_path).unwrap();
-
-            let mut server = pallas_network::facades::NodeServer::accept(&listener, 0)
+            let mut server = pallas_network::facades::NodeServer::accept_bearer(server_bearer, 0)
                 .await
                 .unwrap();
 
@@ -795,12 +859,8 @@ pub async fn local_state_query_server_and_client_happy_path() {
     });
 
     let client = tokio::spawn(async move {
-        tokio::time::sleep(Duration::from_secs(1)).await;
-
         // client setup
-        let socket_path = "node1.socket";
-
-        let mut client = NodeClient::connect(socket_path, 0).await.unwrap();
+        let mut client = NodeClient::connect_bearer(client_bearer, 0).await.unwrap();
 
         // client sends acquire
 
@@ -1095,19 +1155,318 @@ pub async fn local_state_query_server_and_client_happy_path() {
     _ = tokio::join!(client, server);
 }
 
+/// Runs a local tx-submission exchange between a server and a client that
+/// already went through the handshake
+#[cfg(unix)]
+async fn local_tx_submission_happy_path(
+    mut server: pallas_network::facades::NodeServer,
+    mut client: NodeClient,
+) {
+    let accepted_tx = EraTx(6, vec![0x81, 0x01]);
+    let rejected_tx = EraTx(6, vec![0x81, 0x02]);
+
+    let server = tokio::spawn({
+        let accepted_tx = accepted_tx.clone();
+        let rejected_tx = rejected_tx.clone();
+        async move {
+            // server receives a tx from client and accepts it
+
+            let tx = server.submission().recv_submit_tx().await.unwrap();
+
+            assert_eq!(tx, Some(accepted_tx));
+            assert_eq!(*server.submission().state(), localtxsubmission::State::Busy);
+
+            server.submission().accept_tx().await.unwrap();
+
+            assert_eq!(*server.submission().state(), localtxsubmission::State::Idle);
+
+            // server receives a tx from client and rejects it
+
+            let tx = server.submission().recv_submit_tx().await.unwrap();
+
+            assert_eq!(tx, Some(rejected_tx));
+
+            server
+                .submission()
+                .reject_tx(RejectReason(vec![0x82, 0x00, 0x01]))
+                .await
+                .unwrap();
+
+            assert_eq!(*server.submission().state(), localtxsubmission::State::Idle);
+
+            // server receives done from client
+
+            assert!(server
+                .submission()
+                .recv_submit_tx()
+                .await
+                .unwrap()
+                .is_none());
+
+            assert!(server.submission().is_done());
+        }
+    });
+
+    let client = tokio::spawn(async move {
+        // client submits a tx that gets accepted
+
+        let response = client.submission().submit_tx(accepted_tx).await.unwrap();
+
+        assert!(matches!(response, Response::Accepted));
+
+        // client submits a tx that gets rejected
+
+        let response = client.submission().submit_tx(rejected_tx).await.unwrap();
+
+        assert!(matches!(response, Response::Rejected(_)));
+
+        // client sends done
+
+        client.submission().terminate_gracefully().await.unwrap();
+    });
+
+    let (client, server) = tokio::join!(client, server);
+    client.unwrap();
+    server.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+pub async fn local_tx_submission_server_and_client_happy_path() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let (server, client) = tokio::join!(
+        pallas_network::facades::NodeServer::accept_bearer(server_bearer, 0),
+        NodeClient::connect_bearer(client_bearer, 0)
+    );
+
+    local_tx_submission_happy_path(server.unwrap(), client.unwrap()).await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+pub async fn local_tx_submission_over_unix_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("node.socket");
+
+    let listener = UnixListener::bind(&socket_path).unwrap();
+
+    let (server, client) = tokio::join!(
+        pallas_network::facades::NodeServer::accept(&listener, 0),
+        NodeClient::connect(&socket_path, 0)
+    );
+
+    local_tx_submission_happy_path(server.unwrap(), client.unwrap()).await;
+}
+
+struct TestMempool(tokio::sync::watch::Receiver<Vec<txmonitor::Tx>>);
+
+struct TestMempoolSnapshot(Vec<txmonitor::Tx>, usize);
+
+impl Mempool for TestMempool {
+    type Snapshot = TestMempoolSnapshot;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        TestMempoolSnapshot(self.0.borrow().clone(), 0)
+    }
+
+    async fn wait_for_change(&self, since: &Self::Snapshot) {
+        let mut txs = self.0.clone();
+        txs.wait_for(|txs| *txs != since.0).await.unwrap();
+    }
+}
+
+impl MempoolSnapshot for TestMempoolSnapshot {
+    fn slot(&self) -> txmonitor::Slot {
+        1337
+    }
+
+    fn has_tx(&self, id: &txmonitor::TxId) -> bool {
+        id == "c0ffee"
+    }
+
+    fn next_tx(&mut self) -> Option<txmonitor::Tx> {
+        let tx = self.0.get(self.1).cloned();
+        self.1 += 1;
+        tx
+    }
+
+    fn size_and_capacity(&self) -> MempoolSizeAndCapacity {
+        MempoolSizeAndCapacity {
+            capacity_in_bytes: 1000,
+            size_in_bytes: 6,
+            number_of_txs: self.0.len() as u32,
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn local_tx_monitor_server_and_client_happy_path() {
+    let txs: Vec<txmonitor::Tx> = vec![
+        (5, TagWrap(Bytes::from(vec![0x81, 0x01, 0x02]))),
+        (5, TagWrap(Bytes::from(vec![0x81, 0x03, 0x04]))),
+    ];
+
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn({
+        let txs = txs.clone();
+        async move {
+            // server setup
+            let mut server = pallas_network::facades::NodeServer::accept_bearer(server_bearer, 0)
+                .await
+                .unwrap();
+
+            let (_keep, txs) = tokio::sync::watch::channel(txs);
+            let mempool = TestMempool(txs);
+
+            // server waits for acquire, serves the snapshot until release
+
+            assert!(server.monitor().recv_while_idle().await.unwrap());
+            assert_eq!(*server.monitor().state(), txmonitor::State::Acquiring);
+
+            server.monitor().serve_snapshot(&mempool).await.unwrap();
+
+            assert_eq!(*server.monitor().state(), txmonitor::State::Idle);
+        }
+    });
+
+    let client = tokio::spawn(async move {
+        // client setup
+        let mut client = NodeClient::connect_bearer(client_bearer, 0).await.unwrap();
+
+        // client acquires a snapshot
+
+        let slot = client.monitor().acquire().await.unwrap();
+
+        assert_eq!(slot, 1337);
+        assert_eq!(*client.monitor().state(), txmonitor::State::Acquired);
+
+        // client queries the snapshot
+
+        let has_tx = client
+            .monitor()
+            .query_has_tx("c0ffee".to_string())
+            .await
+            .unwrap();
+
+        assert!(has_tx);
+
+        let has_tx = client
+            .monitor()
+            .query_has_tx("deadbeef".to_string())
+            .await
+            .unwrap();
+
+        assert!(!has_tx);
+
+        let sizes = client.monitor().query_size_and_capacity().await.unwrap();
+
+        assert_eq!(sizes.number_of_txs, 2);
+        assert_eq!(sizes.capacity_in_bytes, 1000);
+
+        // client drains all the txs in the snapshot
+
+        assert_eq!(
+            client.monitor().query_next_tx().await.unwrap(),
+            Some(txs[0].clone())
+        );
+        assert_eq!(
+            client.monitor().query_next_tx().await.unwrap(),
+            Some(txs[1].clone())
+        );
+        assert_eq!(client.monitor().query_next_tx().await.unwrap(), None);
+
+        // client re-acquires a fresh snapshot
+
+        let slot = client.monitor().acquire().await.unwrap();
+
+        assert_eq!(slot, 1337);
+        assert_eq!(
+            client.monitor().query_next_tx().await.unwrap(),
+            Some(txs[0].clone())
+        );
+
+        // client releases the snapshot
+
+        client.monitor().release().await.unwrap();
+
+        assert_eq!(*client.monitor().state(), txmonitor::State::Idle);
+    });
+
+    let (client, server) = tokio::join!(client, server);
+    client.unwrap();
+    server.unwrap();
+}
+
+#[tokio::test]
+pub async fn local_tx_monitor_await_acquire_waits_for_changes() {
+    let tx: txmonitor::Tx = (5, TagWrap(Bytes::from(vec![0x81, 0x01, 0x02])));
+
+    let (mempool_tx, mempool_rx) = tokio::sync::watch::channel(vec![]);
+
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn(async move {
+        let mut server = pallas_network::facades::NodeServer::accept_bearer(server_bearer, 0)
+            .await
+            .unwrap();
+
+        let mempool = TestMempool(mempool_rx);
+
+        server.monitor().serve(&mempool).await.unwrap();
+    });
+
+    let client = tokio::spawn({
+        let tx = tx.clone();
+        async move {
+            let mut client = NodeClient::connect_bearer(client_bearer, 0).await.unwrap();
+
+            client.monitor().acquire().await.unwrap();
+            assert_eq!(client.monitor().query_next_tx().await.unwrap(), None);
+
+            // the server holds the reply while the mempool stays the same
+
+            let slot = {
+                let acquire = client.monitor().await_acquire();
+                tokio::pin!(acquire);
+
+                let pending = tokio::time::timeout(Duration::from_millis(100), &mut acquire).await;
+                assert!(pending.is_err());
+
+                mempool_tx.send(vec![tx.clone()]).unwrap();
+
+                acquire.await.unwrap()
+            };
+
+            assert_eq!(slot, 1337);
+
+            assert_eq!(client.monitor().query_next_tx().await.unwrap(), Some(tx));
+
+            client.monitor().release().await.unwrap();
+            client
+                .monitor()
+                .send_message(&txmonitor::Message::Done)
+                .await
+                .unwrap();
+        }
+    });
+
+    let (client, server) = tokio::join!(client, server);
+    client.unwrap();
+    server.unwrap();
+}
+
 #[tokio::test]
-#[ignore]
 pub async fn txsubmission_server_and_client_happy_path_n2n() {
     let test_txs = vec![(vec![0], vec![0, 0, 0]), (vec![1], vec![1, 1, 1])];
 
-    let server_listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 30001))
-        .await
-        .unwrap();
+    let (client_bearer, server_bearer) = Bearer::pair();
 
     let server = tokio::spawn({
         let test_txs = test_txs.clone();
         async move {
-            let mut peer_server = PeerServer::accept(&server_listener, 0).await.unwrap();
+            let mut peer_server = PeerServer::accept_bearer(server_bearer, 0).await.unwrap();
 
             let server_txsub = peer_server.txsubmission();
 
@@ -1177,11 +1536,10 @@ pub async fn txsubmission_server_and_client_happy_path_n2n() {
     });
 
     let client = tokio::spawn(async move {
-        tokio::time::sleep(Duration::from_secs(3)).await;
         let mut mempool = test_txs.clone();
 
         // client setup
-        let mut client_to_server_conn = PeerClient::connect("localhost:30001", 0).await.unwrap();
+        let mut client_to_server_conn = PeerClient::connect_bearer(client_bearer, 0).await.unwrap();
 
         let client_txsub = client_to_server_conn.txsubmission();
 
@@ -1359,3 +1717,1149 @@ pub async fn txsubmission_submit_to_mainnet_peer_n2n() {
     // server should acknowledge the one transaction we sent now
     assert_eq!(ack, 1);
 }
+
+async fn sync_two_headers(
+    client: &mut chainsync::N2NClient,
+) -> (
+    chainsync::IntersectResponse,
+    Vec<NextResponse<HeaderContent>>,
+) {
+    let intersect = client
+        .find_intersect(vec![Point::Specific(1, vec![0x01])])
+        .await
+        .unwrap();
+
+    let mut responses = vec![];
+
+    for _ in 0..2 {
+        responses.push(client.request_next().await.unwrap());
+    }
+
+    (intersect, responses)
+}
+
+#[tokio::test]
+async fn chainsync_client_replays_recorded_session() {
+    let point1 = Point::Specific(1, vec![0x01]);
+    let dir = tempfile::tempdir().unwrap();
+    let recording = dir.path().join("chainsync.cbor");
+
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn({
+        let point1 = point1.clone();
+
+        async move {
+            let mut plexer = Plexer::new(server_bearer);
+            let mut server_cs = chainsync::N2NServer::new(plexer.subscribe_server(2));
+            let plexer = plexer.spawn();
+
+            server_cs.recv_while_idle().await.unwrap();
+            server_cs
+                .send_intersect_found(point1.clone(), Tip(point1.clone(), 1))
+                .await
+                .unwrap();
+
+            for slot in 2..4u64 {
+                server_cs.recv_while_idle().await.unwrap();
+
+                let header = HeaderContent {
+                    variant: 1,
+                    byron_prefix: None,
+                    cbor: vec![slot as u8; 8],
+                };
+
+                let tip = Tip(Point::Specific(slot, vec![slot as u8]), slot);
+                server_cs.send_roll_forward(header, tip).await.unwrap();
+            }
+
+            // wait for the client to hang up
+            let _ = server_cs.recv_while_idle().await;
+
+            plexer.abort().await;
+        }
+    });
+
+    // first, run against the live server while recording the traffic
+
+    let recorder = Recorder::create(&recording).await.unwrap();
+    let bearer = client_bearer.recorded(recorder.clone());
+
+    let mut plexer = Plexer::new(bearer);
+    let mut client = chainsync::N2NClient::new(plexer.subscribe_client(2));
+    let plexer = plexer.spawn();
+
+    let live = sync_two_headers(&mut client).await;
+
+    plexer.abort().await;
+    server.await.unwrap();
+    recorder.sync().await.unwrap();
+
+    // then, replay the recorded traffic with no network at all
+
+    let replay = Replay::open(&recording).unwrap();
+    assert_eq!(replay.remaining(), 6);
+
+    let mut plexer = Plexer::new(Bearer::replay(replay.clone()));
+    let mut client = chainsync::N2NClient::new(plexer.subscribe_client(2));
+    let plexer = plexer.spawn();
+
+    let replayed = sync_two_headers(&mut client).await;
+
+    assert_eq!(replayed.0 .0, live.0 .0);
+    assert_eq!((replayed.0 .1).1, (live.0 .1).1);
+    assert_eq!(replayed.1.len(), 2);
+
+    for (replayed, live) in replayed.1.iter().zip(live.1.iter()) {
+        match (replayed, live) {
+            (NextResponse::RollForward(a, a_tip), NextResponse::RollForward(b, b_tip)) => {
+                assert_eq!(a.cbor, b.cbor);
+                assert_eq!(a_tip.0, b_tip.0);
+                assert_eq!(a_tip.1, b_tip.1);
+            }
+            _ => panic!("unexpected response"),
+        }
+    }
+
+    assert_eq!(replay.remaining(), 0);
+
+    plexer.abort().await;
+    let _ = fs::remove_file(&recording);
+}
+
+/// Serves the connections of a fake N2N peer, replying to keepalive after the
+/// given delay, to every block request with the same body and to chain-sync
+/// with headers wrapping the given cbor.
+async fn serve_fake_peer(
+    listener: TcpListener,
+    keepalive_delay: Duration,
+    body: Vec<u8>,
+    headers: Vec<Vec<u8>>,
+) {
+    loop {
+        let PeerServer {
+            mut keepalive,
+            mut blockfetch,
+            mut chainsync,
+            ..
+        } = PeerServer::accept(&listener, 0).await.unwrap();
+
+        tokio::spawn(async move {
+            while keepalive.recv_keepalive_request().await.is_ok() {
+                tokio::time::sleep(keepalive_delay).await;
+
+                if keepalive.send_keepalive_response().await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let body = body.clone();
+
+        tokio::spawn(async move {
+            while let Ok(Some(_)) = blockfetch.recv_while_idle().await {
+                blockfetch
+                    .send_block_range(vec![body.clone()])
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let mut headers = headers.clone().into_iter();
+
+        tokio::spawn(async move {
+            let tip = Tip(Point::Origin, 0);
+
+            while let Ok(Some(request)) = chainsync.recv_while_idle().await {
+                match request {
+                    ClientRequest::Intersect(points) => chainsync
+                        .send_intersect_found(points[0].clone(), tip.clone())
+                        .await
+                        .unwrap(),
+                    ClientRequest::RequestNext => match headers.next() {
+                        Some(cbor) => {
+                            let header = HeaderContent {
+                                variant: 1,
+                                byron_prefix: None,
+                                cbor,
+                            };
+
+                            chainsync
+                                .send_roll_forward(header, tip.clone())
+                                .await
+                                .unwrap()
+                        }
+                        None => {
+                            chainsync.send_await_reply().await.unwrap();
+                            break;
+                        }
+                    },
+                }
+            }
+        });
+    }
+}
+
+/// Binds a listener on a free local port, returning it along with its address
+async fn bind_local() -> (TcpListener, String) {
+    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap();
+
+    let address = listener.local_addr().unwrap().to_string();
+
+    (listener, address)
+}
+
+#[tokio::test]
+pub async fn peer_manager_reconnects_with_backoff() {
+    let mut config = PeerManagerConfig::new(0);
+    config.min_backoff = Duration::from_millis(100);
+    config.max_backoff = Duration::from_millis(400);
+
+    // pick a free port and release it, so that the peer is unreachable at first
+    let address = bind_local().await.1;
+
+    let manager = PeerManager::with_peers(config, [address.clone()]);
+
+    // nothing is listening yet, so the first attempts must fail
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let peers = manager.peers();
+    assert_eq!(peers.len(), 1);
+    assert!(matches!(peers[0].status, PeerStatus::Backoff(_)));
+    assert!(manager.best_peer().is_none());
+
+    let listener = TcpListener::bind(&address).await.unwrap();
+    let server = tokio::spawn(serve_fake_peer(listener, Duration::ZERO, vec![], vec![]));
+
+    manager
+        .wait_connected(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    assert_eq!(manager.peers()[0].status, PeerStatus::Connected);
+    assert_eq!(manager.best_peer(), Some(address));
+
+    server.abort();
+}
+
+#[tokio::test]
+pub async fn peer_manager_fetches_from_best_peer() {
+    let (listener, fast_address) = bind_local().await;
+    let fast = tokio::spawn(serve_fake_peer(
+        listener,
+        Duration::ZERO,
+        vec![0xaa],
+        vec![],
+    ));
+
+    let (listener, slow_address) = bind_local().await;
+    let slow = tokio::spawn(serve_fake_peer(
+        listener,
+        Duration::from_millis(300),
+        vec![0xbb],
+        vec![],
+    ));
+
+    let manager = PeerManager::with_peers(
+        PeerManagerConfig::new(0),
+        [slow_address, fast_address.clone()],
+    );
+
+    // wait for the first keepalive round-trip of both peers
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while manager.peers().iter().any(|x| x.latency.is_none()) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(manager.best_peer(), Some(fast_address));
+
+    let body = manager.fetch_block(Point::Origin).await.unwrap();
+    assert_eq!(body, vec![0xaa]);
+
+    fast.abort();
+    slow.abort();
+}
+
+#[tokio::test]
+pub async fn peer_manager_follows_chain() {
+    let headers = vec![
+        hex::decode("deadbeef").unwrap(),
+        hex::decode("c0ffee").unwrap(),
+    ];
+
+    let (listener, address) = bind_local().await;
+    let server = tokio::spawn(serve_fake_peer(listener, Duration::ZERO, vec![], headers));
+
+    let manager = PeerManager::with_peers(PeerManagerConfig::new(0), [address.clone()]);
+
+    let mut follow = manager.follow_chain(vec![Point::Origin]);
+
+    let event = follow.next().await.unwrap();
+    assert!(matches!(event, FollowEvent::PeerChanged(x) if x == address));
+
+    for expected in ["deadbeef", "c0ffee"] {
+        match follow.next().await.unwrap() {
+            FollowEvent::RollForward(header, _) => {
+                assert_eq!(header.cbor, hex::decode(expected).unwrap())
+            }
+            x => panic!("unexpected event {x:?}"),
+        }
+    }
+
+    follow.abort();
+    server.abort();
+}
+
+#[tokio::test]
+pub async fn peer_manager_skips_broken_peers() {
+    let (listener, address) = bind_local().await;
+
+    // keeps the connection healthy, but answers chain-sync with bytes that
+    // aren't CBOR, which leaves the client stuck mid-exchange
+    let server = tokio::spawn(async move {
+        let (bearer, _) = Bearer::accept_tcp(&listener).await.unwrap();
+        let mut plexer = Plexer::new(bearer);
+
+        let mut handshake =
+            handshake::N2NServer::new(plexer.subscribe_server(PROTOCOL_N2N_HANDSHAKE));
+        let mut keepalive =
+            keepalive::Server::new(plexer.subscribe_server(PROTOCOL_N2N_KEEP_ALIVE));
+        let mut chainsync = plexer.subscribe_server(PROTOCOL_N2N_CHAIN_SYNC);
+
+        let _plexer = plexer.spawn();
+
+        let policy = handshake::N2NPolicy::new(n2n::VersionTable::v7_and_above(0));
+        handshake.handshake_with_policy(&policy).await.unwrap();
+
+        tokio::spawn(async move {
+            while keepalive.recv_keepalive_request().await.is_ok() {
+                if keepalive.send_keepalive_response().await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        chainsync.dequeue_chunk().await.unwrap();
+        chainsync.enqueue_chunk(vec![0xff]).await.unwrap();
+
+        std::future::pending::<()>().await;
+    });
+
+    let mut config = PeerManagerConfig::new(0);
+    config.min_backoff = Duration::from_secs(10);
+
+    let manager = PeerManager::with_peers(config, [address]);
+    let mut follow = manager.follow_chain(vec![Point::Origin]);
+
+    let mut switches = 0;
+
+    let _ = tokio::time::timeout(Duration::from_secs(1), async {
+        while let Some(event) = follow.next().await {
+            if let FollowEvent::PeerChanged(_) = event {
+                switches += 1;
+            }
+        }
+    })
+    .await;
+
+    // the broken connection isn't handed out again, the follower waits for
+    // the peer to reconnect instead
+    assert_eq!(switches, 1);
+    assert!(manager.best_peer().is_none());
+
+    follow.abort();
+    server.abort();
+}
+
+#[tokio::test]
+pub async fn peer_manager_discovers_peers_when_negotiated() {
+    let (listener, address) = bind_local().await;
+
+    let server = tokio::spawn(async move {
+        let versions = n2n::VersionTable {
+            values: n2n::VersionTable::v7_and_above(0)
+                .values
+                .into_iter()
+                .map(|(version, data)| {
+                    let sharing = data.peer_sharing().map(|_| n2n::PEER_SHARING_ENABLED);
+                    (version, VersionData::new(0, false, sharing, data.query()))
+                })
+                .collect(),
+        };
+
+        let policy = handshake::N2NPolicy::new(versions).with_acceptor(VersionData::negotiate);
+
+        let (bearer, _) = Bearer::accept_tcp(&listener).await.unwrap();
+        let mut server = PeerServer::accept_bearer_with_policy(bearer, &policy)
+            .await
+            .unwrap();
+
+        let amount = server.peersharing().recv_while_idle().await.unwrap();
+        assert_eq!(amount, Some(5));
+
+        server
+            .peersharing()
+            .send_peer_addresses(vec![PeerAddress::V4(Ipv4Addr::new(127, 0, 0, 2), 3001)])
+            .await
+            .unwrap();
+
+        // keep the connection open until the test is done
+        std::future::pending::<()>().await;
+    });
+
+    let mut config = PeerManagerConfig::new(0);
+    config.peer_sharing = Some(5);
+
+    let manager = PeerManager::with_peers(config, [address]);
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while manager.peers().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert!(manager
+        .peers()
+        .iter()
+        .any(|x| x.address == "127.0.0.2:3001"));
+
+    manager.shutdown();
+    server.abort();
+}
+
+/// Real blocks to fetch, along with the points that identify them
+fn scheduler_chain() -> (Vec<Point>, Vec<Vec<u8>>) {
+    let blocks: Vec<_> = [
+        include_str!("../../test_data/babbage1.block"),
+        include_str!("../../test_data/babbage2.block"),
+        include_str!("../../test_data/babbage3.block"),
+        include_str!("../../test_data/babbage4.block"),
+        include_str!("../../test_data/babbage5.block"),
+        include_str!("../../test_data/babbage6.block"),
+        include_str!("../../test_data/babbage7.block"),
+        include_str!("../../test_data/babbage8.block"),
+        include_str!("../../test_data/babbage9.block"),
+        include_str!("../../test_data/babbage10.block"),
+    ]
+    .iter()
+    .map(|x| hex::decode(x.trim()).unwrap())
+    .collect();
+
+    let points = blocks.iter().map(|x| follower_point(x)).collect();
+
+    (points, blocks)
+}
+
+/// How a scheduler peer answers the requested ranges
+#[derive(Clone, Copy)]
+enum PeerBehavior {
+    Honest,
+    NoBlocks,
+    /// Sends the blocks of the range in reverse order
+    WrongBlocks,
+    /// Leaves out the last block of the range
+    MissingBlocks,
+}
+
+/// Spawns an in-process blockfetch server over a duplex bearer that serves the
+/// given chain
+fn spawn_scheduler_peer(
+    chain: (Vec<Point>, Vec<Vec<u8>>),
+    behavior: PeerBehavior,
+) -> blockfetch::Client {
+    let (points, blocks) = chain;
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let mut server_plexer = Plexer::new(server_bearer);
+    let mut server =
+        blockfetch::Server::new(server_plexer.subscribe_server(PROTOCOL_N2N_BLOCK_FETCH));
+    server_plexer.spawn();
+
+    tokio::spawn(async move {
+        while let Ok(Some(BlockRequest((start, end)))) = server.recv_while_idle().await {
+            let start = points.iter().position(|x| *x == start);
+            let end = points.iter().position(|x| *x == end);
+
+            let (Some(start), Some(end)) = (start, end) else {
+                server.send_no_blocks().await.unwrap();
+                continue;
+            };
+
+            let mut bodies = blocks[start..=end].to_vec();
+
+            match behavior {
+                PeerBehavior::Honest => (),
+                PeerBehavior::NoBlocks => {
+                    server.send_no_blocks().await.unwrap();
+                    continue;
+                }
+                PeerBehavior::WrongBlocks => bodies.reverse(),
+                PeerBehavior::MissingBlocks => {
+                    bodies.pop();
+                }
+            }
+
+            server.send_block_range(bodies).await.unwrap();
+        }
+    });
+
+    let mut client_plexer = Plexer::new(client_bearer);
+    let client = blockfetch::Client::new(client_plexer.subscribe_client(PROTOCOL_N2N_BLOCK_FETCH));
+    client_plexer.spawn();
+
+    client
+}
+
+#[tokio::test]
+pub async fn blockfetch_scheduler_reassembles_ranges_in_order() {
+    let chain = scheduler_chain();
+
+    let clients = vec![
+        spawn_scheduler_peer(chain.clone(), PeerBehavior::NoBlocks),
+        spawn_scheduler_peer(chain.clone(), PeerBehavior::Honest),
+        spawn_scheduler_peer(chain.clone(), PeerBehavior::Honest),
+    ];
+
+    let config = SchedulerConfig {
+        range_size: 3,
+        max_in_flight: 3,
+        max_attempts: 3,
+    };
+
+    let (points, blocks) = chain;
+    let mut scheduler = FetchScheduler::start(clients, points.clone(), config).unwrap();
+
+    let mut fetched = vec![];
+
+    while let Some(block) = scheduler.next().await {
+        fetched.push(block.unwrap());
+    }
+
+    assert_eq!(fetched.len(), points.len());
+
+    for ((point, body), (expected_point, expected_body)) in
+        fetched.into_iter().zip(points.into_iter().zip(blocks))
+    {
+        assert_eq!(point, expected_point);
+        assert_eq!(body, expected_body);
+    }
+
+    // the peer without blocks is still usable
+    assert_eq!(scheduler.finish().await.len(), 3);
+}
+
+#[tokio::test]
+pub async fn blockfetch_scheduler_retries_ranges_that_dont_match() {
+    let chain = scheduler_chain();
+
+    let clients = vec![
+        spawn_scheduler_peer(chain.clone(), PeerBehavior::WrongBlocks),
+        spawn_scheduler_peer(chain.clone(), PeerBehavior::MissingBlocks),
+        spawn_scheduler_peer(chain.clone(), PeerBehavior::Honest),
+    ];
+
+    // nothing is fetched ahead, which still hands out one range at a time
+    let config = SchedulerConfig {
+        range_size: 4,
+        max_in_flight: 0,
+        max_attempts: 3,
+    };
+
+    let (points, blocks) = chain;
+    let mut scheduler = FetchScheduler::start(clients, points, config).unwrap();
+
+    let mut fetched = vec![];
+
+    while let Some(block) = scheduler.next().await {
+        fetched.push(block.unwrap().1);
+    }
+
+    assert_eq!(fetched, blocks);
+
+    // the peers that sent the wrong blocks are dropped
+    assert_eq!(scheduler.finish().await.len(), 1);
+}
+
+#[tokio::test]
+pub async fn blockfetch_scheduler_fails_when_no_peer_has_the_range() {
+    let chain = scheduler_chain();
+
+    let clients = vec![
+        spawn_scheduler_peer(chain.clone(), PeerBehavior::NoBlocks),
+        spawn_scheduler_peer(chain.clone(), PeerBehavior::NoBlocks),
+    ];
+
+    let config = SchedulerConfig {
+        range_size: 3,
+        ..Default::default()
+    };
+
+    let mut scheduler = FetchScheduler::start(clients, chain.0, config).unwrap();
+
+    let result = scheduler.next().await.unwrap();
+    assert!(matches!(result, Err(SchedulerError::RangeFailed(..))));
+    assert!(scheduler.next().await.is_none());
+}
+
+/// Babbage test blocks, already wrapped in the hard-fork combinator envelope
+fn follower_blocks() -> Vec<Vec<u8>> {
+    [
+        include_str!("../../test_data/babbage1.block"),
+        include_str!("../../test_data/babbage2.block"),
+        include_str!("../../test_data/babbage3.block"),
+    ]
+    .iter()
+    .map(|x| hex::decode(x.trim()).unwrap())
+    .collect()
+}
+
+fn follower_point(block: &[u8]) -> Point {
+    let block = MultiEraBlock::decode(block).unwrap();
+    Point::Specific(block.slot(), block.hash().to_vec())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+pub async fn chain_follower_node_absorbs_rollbacks_within_depth() {
+    let blocks = follower_blocks();
+    let points: Vec<_> = blocks.iter().map(|x| follower_point(x)).collect();
+
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn({
+        let blocks = blocks.clone();
+        let points = points.clone();
+
+        async move {
+            let mut server = pallas_network::facades::NodeServer::accept_bearer(server_bearer, 0)
+                .await
+                .unwrap();
+
+            let tip = Tip(Point::Origin, 0);
+            let cs = server.chainsync();
+
+            let forward = |i: usize| {
+                chainsync::Message::RollForward(BlockContent(blocks[i].clone()), tip.clone())
+            };
+
+            let backward =
+                |point: &Point| chainsync::Message::RollBackward(point.clone(), tip.clone());
+
+            let mut script = vec![
+                backward(&Point::Origin),
+                forward(0),
+                forward(1),
+                backward(&points[0]),
+                forward(2),
+                forward(1),
+                backward(&points[2]),
+                backward(&Point::Origin),
+            ]
+            .into_iter();
+
+            while let Some(request) = cs.recv_while_idle().await.unwrap() {
+                match request {
+                    ClientRequest::Intersect(_) => {
+                        cs.send_intersect_found(Point::Origin, tip.clone())
+                            .await
+                            .unwrap();
+                    }
+                    ClientRequest::RequestNext => match script.next() {
+                        Some(chainsync::Message::RollForward(block, tip)) => {
+                            cs.send_roll_forward(block, tip).await.unwrap()
+                        }
+                        Some(chainsync::Message::RollBackward(point, tip)) => {
+                            cs.send_roll_backward(point, tip).await.unwrap()
+                        }
+                        _ => break,
+                    },
+                }
+            }
+        }
+    });
+
+    let client = NodeClient::connect_bearer(client_bearer, 0).await.unwrap();
+    let mut follower = ChainFollower::node(client).with_confirmations(2);
+
+    let intersect = follower.find_intersect(vec![Point::Origin]).await.unwrap();
+    assert_eq!(intersect, Some(Point::Origin));
+
+    // the rollback to the intersection is out of the (empty) buffer
+    assert_eq!(
+        follower.next().await.unwrap(),
+        ChainEvent::RollBack(Point::Origin)
+    );
+
+    // block 1 is rolled back within the confirmation depth and never reaches
+    // us, block 0 is confirmed once two blocks are on top of it
+    assert_eq!(
+        follower.next().await.unwrap(),
+        ChainEvent::RollForward(blocks[0].clone(), points[0].clone())
+    );
+
+    assert_eq!(
+        follower.next().await.unwrap(),
+        ChainEvent::RollBack(Point::Origin)
+    );
+
+    server.abort();
+}
+
+/// Serves the headers of the blocks through chain-sync and their bodies
+/// through block-fetch, answering the first `failed_fetches` requests with no
+/// blocks
+fn spawn_follower_peer(
+    server_bearer: Bearer,
+    blocks: Vec<Vec<u8>>,
+    failed_fetches: usize,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        // keepalive needs to stay subscribed, even if we never reply
+        let PeerServer {
+            mut chainsync,
+            mut blockfetch,
+            keepalive: _keepalive,
+            ..
+        } = PeerServer::accept_bearer(server_bearer, 0).await.unwrap();
+
+        let bodies: Vec<_> = blocks
+            .iter()
+            .map(|x| (follower_point(x), x.clone()))
+            .collect();
+
+        tokio::spawn(async move {
+            let mut failed = 0;
+
+            while let Ok(Some(BlockRequest((point, _)))) = blockfetch.recv_while_idle().await {
+                if failed < failed_fetches {
+                    failed += 1;
+                    blockfetch.send_no_blocks().await.unwrap();
+                    continue;
+                }
+
+                let (_, body) = bodies.iter().find(|(x, _)| *x == point).unwrap();
+                blockfetch
+                    .send_block_range(vec![body.clone()])
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let tip = Tip(Point::Origin, 0);
+        let mut headers = blocks.iter().map(|x| {
+            let block = MultiEraBlock::decode(x).unwrap();
+
+            HeaderContent {
+                variant: 5,
+                byron_prefix: None,
+                cbor: block.header().cbor().to_vec(),
+            }
+        });
+
+        while let Some(request) = chainsync.recv_while_idle().await.unwrap() {
+            match request {
+                ClientRequest::Intersect(_) => chainsync
+                    .send_intersect_found(Point::Origin, tip.clone())
+                    .await
+                    .unwrap(),
+                ClientRequest::RequestNext => match headers.next() {
+                    Some(header) => chainsync
+                        .send_roll_forward(header, tip.clone())
+                        .await
+                        .unwrap(),
+                    None => break,
+                },
+            }
+        }
+    })
+}
+
+#[tokio::test]
+pub async fn chain_follower_peer_fetches_confirmed_blocks() {
+    let blocks = follower_blocks();
+    let points: Vec<_> = blocks.iter().map(|x| follower_point(x)).collect();
+
+    let (client_bearer, server_bearer) = Bearer::pair();
+    let server = spawn_follower_peer(server_bearer, blocks.clone(), 0);
+
+    let client = PeerClient::connect_bearer(client_bearer, 0).await.unwrap();
+    let mut follower = ChainFollower::peer(client);
+
+    follower.find_intersect(vec![Point::Origin]).await.unwrap();
+
+    for (block, point) in blocks.into_iter().zip(points) {
+        assert_eq!(
+            follower.next().await.unwrap(),
+            ChainEvent::RollForward(block, point)
+        );
+    }
+
+    server.abort();
+}
+
+#[tokio::test]
+pub async fn chain_follower_peer_retries_failed_fetches() {
+    let blocks = follower_blocks();
+    let points: Vec<_> = blocks.iter().map(|x| follower_point(x)).collect();
+
+    let (client_bearer, server_bearer) = Bearer::pair();
+    let server = spawn_follower_peer(server_bearer, blocks.clone(), 1);
+
+    let client = PeerClient::connect_bearer(client_bearer, 0).await.unwrap();
+    let mut follower = ChainFollower::peer(client);
+
+    follower.find_intersect(vec![Point::Origin]).await.unwrap();
+
+    assert!(matches!(
+        follower.next().await,
+        Err(pallas_network::facades::follower::Error::BlockFetch(_))
+    ));
+
+    // the block that failed to be fetched is still the next one
+    for (block, point) in blocks.into_iter().zip(points) {
+        assert_eq!(
+            follower.next().await.unwrap(),
+            ChainEvent::RollForward(block, point)
+        );
+    }
+
+    server.abort();
+}
+
+#[tokio::test]
+pub async fn handshake_policy_prefers_version_and_negotiates_params() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn(async move {
+        let policy = handshake::N2NPolicy::new(n2n::VersionTable::v13_and_above(0))
+            .with_preferred(13)
+            .with_acceptor(VersionData::negotiate);
+
+        let server = PeerServer::accept_bearer_with_policy(server_bearer, &policy)
+            .await
+            .unwrap();
+
+        server.accepted_version().cloned().unwrap()
+    });
+
+    let client = PeerClient::connect_bearer(client_bearer, 0).await.unwrap();
+
+    let (version, data) = client.negotiated_version();
+    assert_eq!(*version, 13);
+    assert_eq!(data.network_magic(), 0);
+    assert_eq!(data.peer_sharing(), Some(n2n::PEER_SHARING_DISABLED));
+    assert_eq!(data.query(), Some(false));
+
+    let (version, accepted) = server.await.unwrap();
+    assert_eq!(version, 13);
+    assert_eq!(&accepted, data);
+}
+
+#[tokio::test]
+pub async fn handshake_policy_refuses_proposed_params() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn(async move {
+        let policy = handshake::N2NPolicy::new(n2n::VersionTable::v13_and_above(0)).with_acceptor(
+            |_, ours, theirs| match theirs.peer_sharing() {
+                Some(n2n::PEER_SHARING_ENABLED) => Ok(ours.clone()),
+                _ => Err("peer sharing is required".into()),
+            },
+        );
+
+        PeerServer::accept_bearer_with_policy(server_bearer, &policy).await
+    });
+
+    // the server aborts its plexer right after refusing, so the client might
+    // see the connection drop instead of the refusal
+    let client = PeerClient::connect_bearer(client_bearer, 0).await;
+    assert!(client.is_err());
+
+    assert!(matches!(
+        server.await.unwrap(),
+        Err(Error::IncompatibleVersion)
+    ));
+}
+
+#[tokio::test]
+pub async fn peer_server_negotiates_different_version_data() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn(async move {
+        let server = PeerServer::accept_bearer(server_bearer, 0).await.unwrap();
+        server.accepted_version().cloned().unwrap()
+    });
+
+    // a duplex relay that has peer sharing enabled, unlike the server
+    let proposed = VersionData::new(0, false, Some(n2n::PEER_SHARING_ENABLED), Some(false));
+
+    let versions = n2n::VersionTable {
+        values: [(14, proposed)].into_iter().collect(),
+    };
+
+    let policy = handshake::N2NPolicy::new(versions).with_acceptor(VersionData::negotiate);
+
+    let client = PeerClient::connect_bearer_with_policy(client_bearer, &policy)
+        .await
+        .unwrap();
+
+    let (version, data) = client.negotiated_version();
+    assert_eq!(*version, 14);
+    assert!(data.initiator_only_diffusion_mode());
+    assert_eq!(data.peer_sharing(), Some(n2n::PEER_SHARING_DISABLED));
+
+    let (version, accepted) = server.await.unwrap();
+    assert_eq!(version, 14);
+    assert_eq!(&accepted, data);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+pub async fn node_server_negotiates_different_version_data() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn(async move {
+        pallas_network::facades::NodeServer::accept_bearer(server_bearer, 0)
+            .await
+            .unwrap()
+    });
+
+    // a client that doesn't send the query flag on a version that has it
+    let versions = n2c::VersionTable {
+        values: [(32784, n2c::VersionData::new(0, None))]
+            .into_iter()
+            .collect(),
+    };
+
+    let policy = handshake::N2CPolicy::new(versions).with_acceptor(n2c::VersionData::negotiate);
+
+    let client = NodeClient::connect_bearer_with_policy(client_bearer, &policy)
+        .await
+        .unwrap();
+
+    let (version, data) = client.negotiated_version().unwrap();
+    assert_eq!(*version, 32784);
+    assert_eq!(data.query(), None);
+
+    server.abort();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+pub async fn handshake_policy_negotiates_n2c_conway_versions() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let server = tokio::spawn(async move {
+        pallas_network::facades::NodeServer::accept_bearer(server_bearer, 0)
+            .await
+            .unwrap()
+    });
+
+    let policy = handshake::N2CPolicy::v10_and_above(0);
+    assert_eq!(policy.ranked_versions()[0], 32784);
+
+    let conway = handshake::N2CPolicy::new(n2c::VersionTable::v16_and_above(0))
+        .with_acceptor(n2c::VersionData::negotiate);
+
+    let client = NodeClient::connect_bearer_with_policy(client_bearer, &conway)
+        .await
+        .unwrap();
+
+    let (version, data) = client.negotiated_version().unwrap();
+    assert_eq!(*version, 32784);
+    assert_eq!(data.query(), Some(false));
+
+    server.abort();
+}
+
+#[tokio::test]
+pub async fn txsubmission_agent_drops_acknowledgements_nobody_reads() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let mut server_plexer = Plexer::new(server_bearer);
+    let mut server =
+        txsubmission::Server::new(server_plexer.subscribe_server(PROTOCOL_N2N_TX_SUBMISSION));
+    let server_plexer = server_plexer.spawn();
+
+    let mut client_plexer = Plexer::new(client_bearer);
+    let client =
+        txsubmission::Client::new(client_plexer.subscribe_client(PROTOCOL_N2N_TX_SUBMISSION));
+    let client_plexer = client_plexer.spawn();
+
+    let txs: Vec<_> = (0..2u8)
+        .map(|i| (EraTxId(6, vec![i; 32]), EraTxBody(6, vec![i; 10])))
+        .collect();
+
+    // room for a single acknowledged id
+    let mut agent = TxSubmissionAgent::spawn(client, 1);
+
+    let (id, body) = txs[0].clone();
+    agent.submit(id, body).await.unwrap();
+
+    server.wait_for_init().await.unwrap();
+
+    server
+        .acknowledge_and_request_tx_ids(true, 0, 5)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        server.receive_next_reply().await.unwrap(),
+        txsubmission::Reply::TxIds(ids) if ids.len() == 1
+    ));
+
+    let (id, body) = txs[1].clone();
+    agent.submit(id, body).await.unwrap();
+
+    server
+        .acknowledge_and_request_tx_ids(false, 1, 5)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        server.receive_next_reply().await.unwrap(),
+        txsubmission::Reply::TxIds(ids) if ids.len() == 1
+    ));
+
+    // the buffer is still full with the first id, the second one is dropped
+    // instead of piling up
+    server
+        .acknowledge_and_request_tx_ids(false, 1, 5)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        server.receive_next_reply().await.unwrap(),
+        txsubmission::Reply::TxIds(ids) if ids.is_empty()
+    ));
+
+    assert_eq!(agent.next_acknowledged().await, Some(txs[0].0.clone()));
+
+    let next = tokio::time::timeout(Duration::from_millis(100), agent.next_acknowledged()).await;
+    assert!(next.is_err());
+
+    server
+        .acknowledge_and_request_tx_ids(true, 0, 5)
+        .await
+        .unwrap();
+
+    agent.finish().await.unwrap();
+
+    assert!(matches!(
+        server.receive_next_reply().await.unwrap(),
+        txsubmission::Reply::Done
+    ));
+
+    server_plexer.abort().await;
+    client_plexer.abort().await;
+}
+
+#[tokio::test]
+pub async fn txsubmission_agent_serves_queued_txs() {
+    let (client_bearer, server_bearer) = Bearer::pair();
+
+    let mut server_plexer = Plexer::new(server_bearer);
+    let mut server =
+        txsubmission::Server::new(server_plexer.subscribe_server(PROTOCOL_N2N_TX_SUBMISSION));
+    let server_plexer = server_plexer.spawn();
+
+    let mut client_plexer = Plexer::new(client_bearer);
+    let client =
+        txsubmission::Client::new(client_plexer.subscribe_client(PROTOCOL_N2N_TX_SUBMISSION));
+    let client_plexer = client_plexer.spawn();
+
+    let txs: Vec<_> = (0..3u8)
+        .map(|i| {
+            (
+                EraTxId(6, vec![i; 32]),
+                EraTxBody(6, vec![i; 10 + i as usize]),
+            )
+        })
+        .collect();
+
+    let mut agent = TxSubmissionAgent::spawn(client, 10);
+
+    for (id, body) in txs.iter().take(2).cloned() {
+        agent.submit(id, body).await.unwrap();
+    }
+
+    server.wait_for_init().await.unwrap();
+
+    // blocking request, both queued txs are announced
+    server
+        .acknowledge_and_request_tx_ids(true, 0, 5)
+        .await
+        .unwrap();
+
+    let ids = match server.receive_next_reply().await.unwrap() {
+        txsubmission::Reply::TxIds(ids) => ids,
+        _ => panic!("expected tx ids"),
+    };
+
+    assert_eq!(ids.len(), 2);
+    assert_eq!(ids[0].0, txs[0].0);
+    assert_eq!(ids[1].1, 11);
+
+    // only known txs are served
+    server
+        .request_txs(vec![txs[1].0.clone(), txs[2].0.clone()])
+        .await
+        .unwrap();
+
+    match server.receive_next_reply().await.unwrap() {
+        txsubmission::Reply::Txs(bodies) => assert_eq!(bodies, vec![txs[1].1.clone()]),
+        _ => panic!("expected tx bodies"),
+    }
+
+    // a non-blocking request with nothing new is answered right away
+    server
+        .acknowledge_and_request_tx_ids(false, 1, 5)
+        .await
+        .unwrap();
+
+    match server.receive_next_reply().await.unwrap() {
+        txsubmission::Reply::TxIds(ids) => assert!(ids.is_empty()),
+        _ => panic!("expected tx ids"),
+    }
+
+    assert_eq!(agent.next_acknowledged().await, Some(txs[0].0.clone()));
+
+    // a blocking request waits until a new tx is submitted
+    server
+        .acknowledge_and_request_tx_ids(true, 1, 5)
+        .await
+        .unwrap();
+
+    let (id, body) = txs[2].clone();
+    agent.submit(id, body).await.unwrap();
+
+    match server.receive_next_reply().await.unwrap() {
+        txsubmission::Reply::TxIds(ids) => assert_eq!(ids[0].0, txs[2].0),
+        _ => panic!("expected tx ids"),
+    }
+
+    assert_eq!(agent.next_acknowledged().await, Some(txs[1].0.clone()));
+
+    // once the queue is closed, the next blocking request ends the protocol
+    server
+        .acknowledge_and_request_tx_ids(true, 1, 5)
+        .await
+        .unwrap();
+
+    agent.finish().await.unwrap();
+
+    assert!(matches!(
+        server.receive_next_reply().await.unwrap(),
+        txsubmission::Reply::Done
+    ));
+
+    server_plexer.abort().await;
+    client_plexer.abort().await;
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    path::Path,
+    sync::RwLock,
+};
+
+use super::{
+    Backend, BatchOp, Direction, IteratorMode, RawEntry, RawIterator, ReadView, WriteBatch,
+};
+use crate::kvtable::Error;
+
+type Table = BTreeMap<Box<[u8]>, Box<[u8]>>;
+
+/// Backend keeping all tables in memory, meant for tests
+///
+/// Nothing is persisted: opening a path always returns an empty db.
+#[derive(Default)]
+pub struct MemoryBackend(RwLock<HashMap<&'static str, Table>>);
+
+/// Copy of the tables of a [`MemoryBackend`] taken by [`Backend::view`]
+pub struct MemoryView(HashMap<&'static str, Table>);
+
+impl ReadView for MemoryView {
+    fn get(&self, table: &'static str, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        let table = self.0.get(table).ok_or(Error::IO)?;
+
+        Ok(table.get(key).cloned())
+    }
+
+    fn iter(&self, table: &'static str, mode: IteratorMode) -> RawIterator<'_> {
+        let Some(table) = self.0.get(table) else {
+            return Box::new(std::iter::once(Err(Error::IO)));
+        };
+
+        let entries: Box<dyn DoubleEndedIterator<Item = _>> = match mode {
+            IteratorMode::Start | IteratorMode::End => Box::new(table.iter()),
+            IteratorMode::From(key, Direction::Forward) => {
+                Box::new(table.range::<[u8], _>((Bound::Included(key), Bound::Unbounded)))
+            }
+            IteratorMode::From(key, Direction::Reverse) => {
+                Box::new(table.range::<[u8], _>((Bound::Unbounded, Bound::Included(key))))
+            }
+        };
+
+        let entries = entries.map(|(k, v)| Ok((k.clone(), v.clone())));
+
+        match mode {
+            IteratorMode::End | IteratorMode::From(_, Direction::Reverse) => {
+                Box::new(entries.rev())
+            }
+            _ => Box::new(entries),
+        }
+    }
+}
+
+/// Iterator that looks up the entry following the last one it returned on
+/// each step, so that no lock is held between steps
+struct Cursor<'a> {
+    db: &'a MemoryBackend,
+    table: &'static str,
+    direction: Direction,
+    next: Bound<Box<[u8]>>,
+    done: bool,
+}
+
+impl Cursor<'_> {
+    fn step(&mut self) -> Result<Option<RawEntry>, Error> {
+        let tables = self.db.0.read().map_err(|_| Error::IO)?;
+
+        let Some(table) = tables.get(self.table) else {
+            return Err(Error::IO);
+        };
+
+        let next = self.next.as_ref().map(|x| x.as_ref());
+
+        let entry = match self.direction {
+            Direction::Forward => table.range::<[u8], _>((next, Bound::Unbounded)).next(),
+            Direction::Reverse => table.range::<[u8], _>((Bound::Unbounded, next)).next_back(),
+        };
+
+        Ok(entry.map(|(k, v)| (k.clone(), v.clone())))
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = Result<RawEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.step() {
+            Ok(Some((key, value))) => {
+                self.next = Bound::Excluded(key.clone());
+                Some(Ok((key, value)))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl ReadView for MemoryBackend {
+    fn get(&self, table: &'static str, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        let tables = self.0.read().map_err(|_| Error::IO)?;
+        let table = tables.get(table).ok_or(Error::IO)?;
+
+        Ok(table.get(key).cloned())
+    }
+
+    fn iter(&self, table: &'static str, mode: IteratorMode) -> RawIterator<'_> {
+        let (direction, next) = match mode {
+            IteratorMode::Start => (Direction::Forward, Bound::Unbounded),
+            IteratorMode::End => (Direction::Reverse, Bound::Unbounded),
+            IteratorMode::From(key, direction) => (direction, Bound::Included(key.into())),
+        };
+
+        Box::new(Cursor {
+            db: self,
+            table,
+            direction,
+            next,
+            done: false,
+        })
+    }
+}
+
+impl Backend for MemoryBackend {
+    type View<'a> = MemoryView;
+
+    fn open(_path: &Path) -> Result<Self, Error> {
+        Ok(Self::default())
+    }
+
+    fn destroy(_path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn ensure_table(&self, table: &'static str) -> Result<(), Error> {
+        let mut tables = self.0.write().map_err(|_| Error::IO)?;
+        tables.entry(table).or_default();
+
+        Ok(())
+    }
+
+    fn view(&self) -> Result<Self::View<'_>, Error> {
+        let tables = self.0.read().map_err(|_| Error::IO)?;
+
+        Ok(MemoryView(tables.clone()))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<(), Error> {
+        let mut tables = self.0.write().map_err(|_| Error::IO)?;
+
+        // check every table first so that the batch is applied in full or not
+        // at all
+        for op in batch.0.iter() {
+            let (BatchOp::Put(table, ..) | BatchOp::Delete(table, _)) = op;
+
+            if !tables.contains_key(table) {
+                return Err(Error::IO);
+            }
+        }
+
+        for op in batch {
+            match op {
+                BatchOp::Put(table, key, value) => {
+                    tables.get_mut(table).unwrap().insert(key, value);
+                }
+                BatchOp::Delete(table, key) => {
+                    tables.get_mut(table).unwrap().remove(&key);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reset(&self, table: &'static str) -> Result<(), Error> {
+        let mut tables = self.0.write().map_err(|_| Error::IO)?;
+        tables.insert(table, Table::default());
+
+        Ok(())
+    }
+}
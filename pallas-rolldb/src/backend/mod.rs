@@ -0,0 +1,222 @@
+//! Key-value engines that the stores can be built upon
+//!
+//! Stores only need ordered tables of raw bytes, atomic batches of writes
+//! across tables, iteration in both directions and consistent views to read
+//! from while writes go on, which is what the [`Backend`] trait describes. RocksDB is the default engine, redb is a
+//! pure-Rust alternative for targets where building RocksDB is not an option,
+//! and the in-memory engine is meant for tests.
+
+use std::path::Path;
+
+use crate::kvtable::Error;
+
+pub mod memory;
+
+#[cfg(feature = "redb")]
+pub mod redb;
+
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb;
+
+/// Backend used by the stores when none is specified
+#[cfg(feature = "rocksdb")]
+pub type DefaultBackend = rocksdb::RocksDbBackend;
+
+/// Backend used by the stores when none is specified
+#[cfg(all(not(feature = "rocksdb"), feature = "redb"))]
+pub type DefaultBackend = redb::RedbBackend;
+
+// the memory backend doesn't persist anything, so it's never picked as the
+// default: it has to be passed explicitly to the stores
+#[cfg(not(any(feature = "rocksdb", feature = "redb")))]
+compile_error!("pallas-rolldb needs a persistent backend, enable the `rocksdb` or `redb` feature");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// Where an iteration over a table starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IteratorMode<'a> {
+    Start,
+    End,
+
+    /// Starts at the given key, or at the closest one in the direction of
+    /// the iteration if it doesn't exist
+    From(&'a [u8], Direction),
+}
+
+pub type RawEntry = (Box<[u8]>, Box<[u8]>);
+
+pub type RawIterator<'a> = Box<dyn Iterator<Item = Result<RawEntry, Error>> + 'a>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    Put(&'static str, Box<[u8]>, Box<[u8]>),
+    Delete(&'static str, Box<[u8]>),
+}
+
+/// Writes to apply atomically, in order, across tables
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch(Vec<BatchOp>);
+
+impl WriteBatch {
+    pub fn put(&mut self, table: &'static str, key: Box<[u8]>, value: Box<[u8]>) {
+        self.0.push(BatchOp::Put(table, key, value));
+    }
+
+    pub fn delete(&mut self, table: &'static str, key: Box<[u8]>) {
+        self.0.push(BatchOp::Delete(table, key));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = BatchOp;
+    type IntoIter = std::vec::IntoIter<BatchOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Read access to the tables of a backend
+pub trait ReadView {
+    fn get(&self, table: &'static str, key: &[u8]) -> Result<Option<Box<[u8]>>, Error>;
+
+    fn iter(&self, table: &'static str, mode: IteratorMode) -> RawIterator<'_>;
+}
+
+/// Storage engine holding a set of named tables of ordered byte keys
+///
+/// Reads through the backend itself see writes as soon as they're applied,
+/// reads through a [`Backend::View`] see the tables as they were when the
+/// view was taken.
+pub trait Backend: ReadView + Send + Sync + Sized + 'static {
+    /// Consistent read-only view of all the tables at a point in time
+    type View<'a>: ReadView
+    where
+        Self: 'a;
+    /// Opens the db at the given directory, creating it if needed
+    fn open(path: &Path) -> Result<Self, Error>;
+
+    /// Deletes the db at the given directory
+    fn destroy(path: &Path) -> Result<(), Error>;
+
+    /// Creates a table if it doesn't exist yet
+    fn ensure_table(&self, table: &'static str) -> Result<(), Error>;
+
+    /// Takes a view of the tables that later writes don't affect
+    fn view(&self) -> Result<Self::View<'_>, Error>;
+
+    fn write(&self, batch: WriteBatch) -> Result<(), Error>;
+
+    /// Removes all the entries of a table
+    fn reset(&self, table: &'static str) -> Result<(), Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, Direction, IteratorMode, ReadView, WriteBatch};
+
+    const TABLE: &str = "TestKV";
+    const OTHER: &str = "OtherKV";
+
+    fn key(x: u8) -> Box<[u8]> {
+        Box::new([x])
+    }
+
+    fn keys<B: ReadView>(db: &B, mode: IteratorMode) -> Vec<u8> {
+        db.iter(TABLE, mode).map(|x| x.unwrap().0[0]).collect()
+    }
+
+    /// Checks the behavior that the stores rely on
+    pub fn check_backend<B: Backend>(db: B) {
+        db.ensure_table(TABLE).unwrap();
+        db.ensure_table(OTHER).unwrap();
+
+        assert!(db.iter(TABLE, IteratorMode::Start).next().is_none());
+
+        let mut batch = WriteBatch::default();
+
+        for x in [5, 1, 3, 7] {
+            batch.put(TABLE, key(x), key(x * 10));
+        }
+
+        batch.put(OTHER, key(1), key(1));
+        batch.delete(TABLE, key(7));
+        db.write(batch).unwrap();
+
+        assert_eq!(db.get(TABLE, &[3]).unwrap(), Some(key(30)));
+        assert_eq!(db.get(TABLE, &[7]).unwrap(), None);
+        assert_eq!(db.get(OTHER, &[1]).unwrap(), Some(key(1)));
+
+        assert_eq!(keys(&db, IteratorMode::Start), vec![1, 3, 5]);
+        assert_eq!(keys(&db, IteratorMode::End), vec![5, 3, 1]);
+
+        let from = IteratorMode::From(&[2], Direction::Forward);
+        assert_eq!(keys(&db, from), vec![3, 5]);
+
+        let from = IteratorMode::From(&[3], Direction::Reverse);
+        assert_eq!(keys(&db, from), vec![3, 1]);
+
+        // writes that happen while iterating don't break the iteration
+        let mut iter = db.iter(TABLE, IteratorMode::Start);
+        assert_eq!(iter.next().unwrap().unwrap().0, key(1));
+
+        let mut batch = WriteBatch::default();
+        batch.put(TABLE, key(9), key(90));
+        db.write(batch).unwrap();
+
+        assert!(iter.all(|x| x.is_ok()));
+        drop(iter);
+
+        // views keep seeing the tables as they were when taken
+        let view = db.view().unwrap();
+
+        let mut batch = WriteBatch::default();
+        batch.put(TABLE, key(2), key(20));
+        batch.delete(TABLE, key(9));
+        db.write(batch).unwrap();
+
+        assert_eq!(keys(&view, IteratorMode::Start), vec![1, 3, 5, 9]);
+        assert_eq!(keys(&view, IteratorMode::End), vec![9, 5, 3, 1]);
+        assert_eq!(view.get(TABLE, &[9]).unwrap(), Some(key(90)));
+        assert_eq!(view.get(TABLE, &[2]).unwrap(), None);
+        drop(view);
+
+        assert_eq!(keys(&db, IteratorMode::Start), vec![1, 2, 3, 5]);
+
+        db.reset(TABLE).unwrap();
+        assert!(db.iter(TABLE, IteratorMode::Start).next().is_none());
+        assert_eq!(db.get(OTHER, &[1]).unwrap(), Some(key(1)));
+    }
+
+    #[test]
+    fn memory_backend() {
+        check_backend(super::memory::MemoryBackend::default());
+    }
+
+    #[cfg(feature = "redb")]
+    #[test]
+    fn redb_backend() {
+        let path = tempfile::tempdir().unwrap();
+        check_backend(super::redb::RedbBackend::open(path.path()).unwrap());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocksdb_backend() {
+        let path = tempfile::tempdir().unwrap();
+        check_backend(super::rocksdb::RocksDbBackend::open(path.path()).unwrap());
+    }
+}
@@ -0,0 +1,159 @@
+use std::{collections::HashMap, path::Path};
+
+use ::redb::{Database, ReadTransaction, TableDefinition};
+
+use super::{Backend, BatchOp, Direction, IteratorMode, RawIterator, ReadView, WriteBatch};
+use crate::kvtable::Error;
+
+const FILE_NAME: &str = "rolldb.redb";
+
+fn definition(table: &str) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(table)
+}
+
+fn io_error(err: impl Into<::redb::Error>) -> Error {
+    let err = err.into();
+    tracing::error!(?err);
+    Error::IO
+}
+
+fn get(txn: &ReadTransaction, table: &'static str, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+    let table = txn.open_table(definition(table)).map_err(io_error)?;
+
+    let value = table.get(key).map_err(io_error)?;
+
+    Ok(value.map(|x| x.value().into()))
+}
+
+fn iter<'a>(txn: &ReadTransaction, table: &'static str, mode: IteratorMode) -> RawIterator<'a> {
+    let range = txn
+        .open_table(definition(table))
+        .map_err(io_error)
+        .and_then(|table| {
+            // the range keeps its own reference to the read transaction, so
+            // it doesn't borrow the table
+            let range = match mode {
+                IteratorMode::Start | IteratorMode::End => table.range::<&[u8]>(..),
+                IteratorMode::From(key, Direction::Forward) => table.range(key..),
+                IteratorMode::From(key, Direction::Reverse) => table.range(..=key),
+            };
+
+            range.map_err(io_error)
+        });
+
+    let range = match range {
+        Ok(x) => x,
+        Err(err) => return Box::new(std::iter::once(Err(err))),
+    };
+
+    let entries = range.map(|x| {
+        x.map(|(k, v)| (k.value().into(), v.value().into()))
+            .map_err(io_error)
+    });
+
+    match mode {
+        IteratorMode::End | IteratorMode::From(_, Direction::Reverse) => Box::new(entries.rev()),
+        _ => Box::new(entries),
+    }
+}
+
+/// Read transaction over a [`RedbBackend`] taken by [`Backend::view`]
+pub struct RedbView(ReadTransaction);
+
+impl ReadView for RedbView {
+    fn get(&self, table: &'static str, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        get(&self.0, table, key)
+    }
+
+    fn iter(&self, table: &'static str, mode: IteratorMode) -> RawIterator<'_> {
+        iter(&self.0, table, mode)
+    }
+}
+
+/// Backend storing each table as a table of a redb database, in pure Rust
+///
+/// The path given to [`Backend::open`] is a directory holding the database
+/// file, as it is for RocksDB.
+pub struct RedbBackend(Database);
+
+impl ReadView for RedbBackend {
+    fn get(&self, table: &'static str, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        let txn = self.0.begin_read().map_err(io_error)?;
+        get(&txn, table, key)
+    }
+
+    fn iter(&self, table: &'static str, mode: IteratorMode) -> RawIterator<'_> {
+        match self.0.begin_read() {
+            Ok(txn) => iter(&txn, table, mode),
+            Err(err) => Box::new(std::iter::once(Err(io_error(err)))),
+        }
+    }
+}
+
+impl Backend for RedbBackend {
+    type View<'a> = RedbView;
+
+    fn open(path: &Path) -> Result<Self, Error> {
+        std::fs::create_dir_all(path).map_err(io_error)?;
+
+        let db = Database::create(path.join(FILE_NAME)).map_err(io_error)?;
+
+        Ok(Self(db))
+    }
+
+    fn destroy(path: &Path) -> Result<(), Error> {
+        match std::fs::remove_dir_all(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(io_error(err)),
+            _ => Ok(()),
+        }
+    }
+
+    fn ensure_table(&self, table: &'static str) -> Result<(), Error> {
+        let txn = self.0.begin_write().map_err(io_error)?;
+        txn.open_table(definition(table)).map_err(io_error)?;
+        txn.commit().map_err(io_error)
+    }
+
+    fn view(&self) -> Result<Self::View<'_>, Error> {
+        let txn = self.0.begin_read().map_err(io_error)?;
+
+        Ok(RedbView(txn))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<(), Error> {
+        let txn = self.0.begin_write().map_err(io_error)?;
+
+        {
+            let mut tables = HashMap::new();
+
+            for op in batch {
+                let (BatchOp::Put(name, ..) | BatchOp::Delete(name, _)) = &op;
+
+                if !tables.contains_key(name) {
+                    let table = txn.open_table(definition(name)).map_err(io_error)?;
+                    tables.insert(*name, table);
+                }
+
+                let table = tables.get_mut(name).unwrap();
+
+                match op {
+                    BatchOp::Put(_, key, value) => {
+                        table.insert(&*key, &*value).map_err(io_error)?;
+                    }
+                    BatchOp::Delete(_, key) => {
+                        table.remove(&*key).map_err(io_error)?;
+                    }
+                }
+            }
+        }
+
+        txn.commit().map_err(io_error)
+    }
+
+    fn reset(&self, table: &'static str) -> Result<(), Error> {
+        let txn = self.0.begin_write().map_err(io_error)?;
+        txn.delete_table(definition(table)).map_err(io_error)?;
+        txn.open_table(definition(table)).map_err(io_error)?;
+        txn.commit().map_err(io_error)
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::BTreeSet, path::Path};
+
+use ::rocksdb::{Options, Snapshot, DB};
+
+use super::{Backend, BatchOp, Direction, IteratorMode, RawIterator, ReadView, WriteBatch};
+use crate::kvtable::Error;
+
+/// Backend storing each table as a column family of a RocksDB database
+pub struct RocksDbBackend(DB);
+
+fn io_error(err: ::rocksdb::Error) -> Error {
+    tracing::error!(?err);
+    Error::IO
+}
+
+impl RocksDbBackend {
+    /// Underlying RocksDB database
+    pub fn db(&self) -> &DB {
+        &self.0
+    }
+
+    /// Creates a consistent copy of the database at a path that must not
+    /// exist yet, hard-linking its files when the path is on the same
+    /// filesystem
+    pub fn checkpoint(&self, path: &Path) -> Result<(), Error> {
+        ::rocksdb::checkpoint::Checkpoint::new(&self.0)
+            .and_then(|x| x.create_checkpoint(path))
+            .map_err(io_error)
+    }
+}
+
+fn iterator_mode(mode: IteratorMode) -> ::rocksdb::IteratorMode {
+    match mode {
+        IteratorMode::Start => ::rocksdb::IteratorMode::Start,
+        IteratorMode::End => ::rocksdb::IteratorMode::End,
+        IteratorMode::From(key, Direction::Forward) => {
+            ::rocksdb::IteratorMode::From(key, ::rocksdb::Direction::Forward)
+        }
+        IteratorMode::From(key, Direction::Reverse) => {
+            ::rocksdb::IteratorMode::From(key, ::rocksdb::Direction::Reverse)
+        }
+    }
+}
+
+impl ReadView for RocksDbBackend {
+    fn get(&self, table: &'static str, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        let cf = self.0.cf_handle(table).ok_or(Error::IO)?;
+
+        let value = self.0.get_cf(&cf, key).map_err(io_error)?;
+
+        Ok(value.map(Vec::into_boxed_slice))
+    }
+
+    fn iter(&self, table: &'static str, mode: IteratorMode) -> RawIterator<'_> {
+        let Some(cf) = self.0.cf_handle(table) else {
+            return Box::new(std::iter::once(Err(Error::IO)));
+        };
+
+        let inner = self.0.iterator_cf(&cf, iterator_mode(mode));
+
+        Box::new(inner.map(|x| x.map_err(io_error)))
+    }
+}
+
+/// RocksDB snapshot of a [`RocksDbBackend`] taken by [`Backend::view`]
+pub struct RocksDbView<'a>(&'a DB, Snapshot<'a>);
+
+impl ReadView for RocksDbView<'_> {
+    fn get(&self, table: &'static str, key: &[u8]) -> Result<Option<Box<[u8]>>, Error> {
+        let cf = self.0.cf_handle(table).ok_or(Error::IO)?;
+
+        let value = self.1.get_cf(&cf, key).map_err(io_error)?;
+
+        Ok(value.map(Vec::into_boxed_slice))
+    }
+
+    fn iter(&self, table: &'static str, mode: IteratorMode) -> RawIterator<'_> {
+        let Some(cf) = self.0.cf_handle(table) else {
+            return Box::new(std::iter::once(Err(Error::IO)));
+        };
+
+        let inner = self.1.iterator_cf(&cf, iterator_mode(mode));
+
+        Box::new(inner.map(|x| x.map_err(io_error)))
+    }
+}
+
+impl Backend for RocksDbBackend {
+    type View<'a> = RocksDbView<'a>;
+
+    /// Opens the database along with the column families it already has
+    fn open(path: &Path) -> Result<Self, Error> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+
+        let cfs: BTreeSet<String> = DB::list_cf(&opts, path)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+
+        let db = DB::open_cf(&opts, path, cfs).map_err(io_error)?;
+
+        Ok(Self(db))
+    }
+
+    fn destroy(path: &Path) -> Result<(), Error> {
+        DB::destroy(&Options::default(), path).map_err(io_error)
+    }
+
+    fn ensure_table(&self, table: &'static str) -> Result<(), Error> {
+        if self.0.cf_handle(table).is_some() {
+            return Ok(());
+        }
+
+        self.0
+            .create_cf(table, &Options::default())
+            .map_err(io_error)
+    }
+
+    fn view(&self) -> Result<Self::View<'_>, Error> {
+        Ok(RocksDbView(&self.0, self.0.snapshot()))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<(), Error> {
+        let mut inner = ::rocksdb::WriteBatch::default();
+
+        for op in batch {
+            match op {
+                BatchOp::Put(table, key, value) => {
+                    let cf = self.0.cf_handle(table).ok_or(Error::IO)?;
+                    inner.put_cf(&cf, key, value);
+                }
+                BatchOp::Delete(table, key) => {
+                    let cf = self.0.cf_handle(table).ok_or(Error::IO)?;
+                    inner.delete_cf(&cf, key);
+                }
+            }
+        }
+
+        self.0.write(inner).map_err(io_error)
+    }
+
+    fn reset(&self, table: &'static str) -> Result<(), Error> {
+        self.0.drop_cf(table).map_err(io_error)?;
+
+        self.0
+            .create_cf(table, &Options::default())
+            .map_err(io_error)
+    }
+}
@@ -0,0 +1,305 @@
+use pallas_addresses::{Address, ShelleyPaymentPart};
+use pallas_traverse::{MultiEraBlock, MultiEraInput, MultiEraTx};
+use serde::{Deserialize, Serialize};
+
+use super::{BlockHash, BlockSlot, TxHash};
+use crate::backend::{Backend, WriteBatch};
+use crate::chain::BlockByHashKV;
+use crate::kvtable::*;
+
+/// Secondary indexes maintained by the chain store
+///
+/// Indexes are updated in the same batch as the chain itself, so they are
+/// only complete when enabled since the store was created. Entries are derived
+/// from the block bodies, so when any index is enabled the store only accepts
+/// bodies that decode as blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Indexes {
+    /// Block hash by block number
+    pub block_number: bool,
+
+    /// Block hash and position of each tx by tx hash
+    pub tx_hash: bool,
+
+    /// Txs producing or spending outputs locked by each address
+    pub address: bool,
+
+    /// Same as `address`, by the payment credential of Shelley addresses
+    pub payment_credential: bool,
+}
+
+impl Indexes {
+    pub fn all() -> Self {
+        Self {
+            block_number: true,
+            tx_hash: true,
+            address: true,
+            payment_credential: true,
+        }
+    }
+
+    pub(crate) fn any(&self) -> bool {
+        self.block_number || self.tx_hash || self.address || self.payment_credential
+    }
+
+    pub(crate) fn cf_names(&self) -> Vec<&'static str> {
+        [
+            (self.block_number, HashByNumberKV::CF_NAME),
+            (self.tx_hash, TxRefByHashKV::CF_NAME),
+            (self.address, TxByAddressKV::CF_NAME),
+            (self.payment_credential, TxByPaymentKV::CF_NAME),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, name)| name)
+        .collect()
+    }
+}
+
+/// Location of a tx within the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxRef {
+    pub block: BlockHash,
+    pub index: u32,
+}
+
+/// Key of the indexes that hold many txs per item: the item, prefixed by its
+/// length so that the keys of an item are contiguous, followed by the slot
+/// and the position of the tx so that they are sorted in chain order
+pub struct DBIndexKey(pub Vec<u8>, pub BlockSlot, pub u32);
+
+impl From<DBIndexKey> for Box<[u8]> {
+    fn from(value: DBIndexKey) -> Self {
+        let len = value.0.len() as u16;
+
+        [
+            len.to_be_bytes().as_slice(),
+            value.0.as_slice(),
+            value.1.to_be_bytes().as_slice(),
+            value.2.to_be_bytes().as_slice(),
+        ]
+        .concat()
+        .into()
+    }
+}
+
+impl From<Box<[u8]>> for DBIndexKey {
+    fn from(value: Box<[u8]>) -> Self {
+        let len = u16::from_be_bytes(value[0..2].try_into().unwrap()) as usize;
+        let (item, rest) = value[2..].split_at(len);
+
+        let slot = u64::from_be_bytes(rest[0..8].try_into().unwrap());
+        let index = u32::from_be_bytes(rest[8..12].try_into().unwrap());
+
+        Self(item.to_vec(), slot, index)
+    }
+}
+
+// block number => block hash
+pub struct HashByNumberKV;
+
+impl KVTable<DBInt, DBHash> for HashByNumberKV {
+    const CF_NAME: &'static str = "HashByNumberKV";
+}
+
+// tx hash => tx ref
+pub struct TxRefByHashKV;
+
+impl KVTable<DBHash, DBSerde<TxRef>> for TxRefByHashKV {
+    const CF_NAME: &'static str = "TxRefByHashKV";
+}
+
+// address + slot + tx index => tx hash
+pub struct TxByAddressKV;
+
+impl KVTable<DBIndexKey, DBHash> for TxByAddressKV {
+    const CF_NAME: &'static str = "TxByAddressKV";
+}
+
+// payment credential + slot + tx index => tx hash
+pub struct TxByPaymentKV;
+
+impl KVTable<DBIndexKey, DBHash> for TxByPaymentKV {
+    const CF_NAME: &'static str = "TxByPaymentKV";
+}
+
+/// Index key of a payment credential, tagged as in the ledger so that key and
+/// script hashes don't collide
+pub(crate) fn payment_key(payment: &ShelleyPaymentPart) -> Vec<u8> {
+    let tag = match payment {
+        ShelleyPaymentPart::Key(_) => 0u8,
+        ShelleyPaymentPart::Script(_) => 1u8,
+    };
+
+    [[tag].as_slice(), payment.as_hash().as_slice()].concat()
+}
+
+/// Iterates the txs of an item of a multi-valued index, in chain order
+pub(crate) fn iter_txs<T, B: Backend>(
+    db: &B,
+    item: Vec<u8>,
+) -> impl Iterator<Item = Result<(BlockSlot, TxHash), Error>> + '_
+where
+    T: KVTable<DBIndexKey, DBHash>,
+{
+    let prefix = item.clone();
+
+    T::iter_entries_from(db, DBIndexKey(item, 0, 0))
+        .take_while(move |x| match x {
+            Ok((key, _)) => key.0 == prefix,
+            Err(_) => true,
+        })
+        .map(|x| x.map(|(key, tx)| (key.1, tx.0)))
+}
+
+/// Entries of the secondary indexes contributed by a block
+#[derive(Default)]
+pub(crate) struct IndexEntries {
+    number: Option<u64>,
+    txs: Vec<(TxHash, TxRef)>,
+    addresses: Vec<(Vec<u8>, u32, TxHash)>,
+    payments: Vec<(Vec<u8>, u32, TxHash)>,
+}
+
+/// Finds the address of the output spent by an input
+///
+/// The output is looked up in the txs of the same block first, then through
+/// the tx hash index, so spent outputs are only found when that index is
+/// enabled.
+fn resolve_address<B: Backend>(
+    db: &B,
+    indexes: &Indexes,
+    block_txs: &[MultiEraTx],
+    input: &MultiEraInput,
+) -> Result<Option<Address>, Error> {
+    let index = input.index() as usize;
+
+    let local = block_txs
+        .iter()
+        .find(|tx| tx.hash() == *input.hash())
+        .and_then(|tx| tx.produces_at(index));
+
+    if let Some(output) = local {
+        return Ok(output.address().ok());
+    }
+
+    if !indexes.tx_hash {
+        return Ok(None);
+    }
+
+    let Some(DBSerde(tx_ref)) = TxRefByHashKV::get_by_key(db, DBHash(*input.hash()))? else {
+        return Ok(None);
+    };
+
+    let Some(DBBytes(body)) = BlockByHashKV::get_by_key(db, DBHash(tx_ref.block))? else {
+        return Ok(None);
+    };
+
+    let block = MultiEraBlock::decode(&body).map_err(|_| Error::InvalidBlock)?;
+
+    let address = block
+        .txs()
+        .get(tx_ref.index as usize)
+        .and_then(|tx| tx.produces_at(index))
+        .and_then(|output| output.address().ok());
+
+    Ok(address)
+}
+
+impl IndexEntries {
+    pub(crate) fn for_block<B: Backend>(
+        db: &B,
+        indexes: &Indexes,
+        hash: BlockHash,
+        body: &[u8],
+    ) -> Result<Self, Error> {
+        let block = MultiEraBlock::decode(body).map_err(|_| Error::InvalidBlock)?;
+        let txs = block.txs();
+
+        let mut entries = IndexEntries::default();
+
+        if indexes.block_number {
+            entries.number = Some(block.number());
+        }
+
+        for (index, tx) in txs.iter().enumerate() {
+            let index = index as u32;
+            let tx_hash = tx.hash();
+
+            if indexes.tx_hash {
+                let tx_ref = TxRef { block: hash, index };
+                entries.txs.push((tx_hash, tx_ref));
+            }
+
+            if !indexes.address && !indexes.payment_credential {
+                continue;
+            }
+
+            let mut touched: Vec<_> = tx
+                .produces()
+                .iter()
+                .filter_map(|(_, output)| output.address().ok())
+                .collect();
+
+            for input in tx.consumes() {
+                if let Some(address) = resolve_address(db, indexes, &txs, &input)? {
+                    touched.push(address);
+                }
+            }
+
+            for address in touched {
+                if indexes.payment_credential {
+                    if let Address::Shelley(x) = &address {
+                        entries
+                            .payments
+                            .push((payment_key(x.payment()), index, tx_hash));
+                    }
+                }
+
+                if indexes.address {
+                    entries.addresses.push((address.to_vec(), index, tx_hash));
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub(crate) fn stage_upsert(self, slot: BlockSlot, hash: BlockHash, batch: &mut WriteBatch) {
+        if let Some(number) = self.number {
+            HashByNumberKV::stage_upsert(DBInt(number), DBHash(hash), batch);
+        }
+
+        for (tx_hash, tx_ref) in self.txs {
+            TxRefByHashKV::stage_upsert(DBHash(tx_hash), DBSerde(tx_ref), batch);
+        }
+
+        for (address, index, tx_hash) in self.addresses {
+            let key = DBIndexKey(address, slot, index);
+            TxByAddressKV::stage_upsert(key, DBHash(tx_hash), batch);
+        }
+
+        for (payment, index, tx_hash) in self.payments {
+            let key = DBIndexKey(payment, slot, index);
+            TxByPaymentKV::stage_upsert(key, DBHash(tx_hash), batch);
+        }
+    }
+
+    pub(crate) fn stage_delete(self, slot: BlockSlot, batch: &mut WriteBatch) {
+        if let Some(number) = self.number {
+            HashByNumberKV::stage_delete(DBInt(number), batch);
+        }
+
+        for (tx_hash, _) in self.txs {
+            TxRefByHashKV::stage_delete(DBHash(tx_hash), batch);
+        }
+
+        for (address, index, _) in self.addresses {
+            TxByAddressKV::stage_delete(DBIndexKey(address, slot, index), batch);
+        }
+
+        for (payment, index, _) in self.payments {
+            TxByPaymentKV::stage_delete(DBIndexKey(payment, slot, index), batch);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use pallas_crypto::hash::Hash;
 
+mod index;
 mod store;
 
 #[cfg(test)]
@@ -8,5 +9,7 @@ mod tests;
 pub type BlockSlot = u64;
 pub type BlockHash = Hash<32>;
 pub type BlockBody = Vec<u8>;
+pub type TxHash = Hash<32>;
 
+pub use index::{Indexes, TxRef};
 pub use store::*;
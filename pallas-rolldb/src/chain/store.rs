@@ -1,19 +1,35 @@
+use pallas_addresses::ShelleyPaymentPart;
 use pallas_crypto::hash::Hash;
-use std::{path::Path, sync::Arc};
+use std::{
+    io::{Read, Write},
+    path::Path,
+    sync::Arc,
+};
 use tracing::warn;
 
-use rocksdb::{Options, WriteBatch, DB};
-
-use super::{BlockBody, BlockHash, BlockSlot};
+use super::index::*;
+use super::{BlockBody, BlockHash, BlockSlot, TxHash};
 
+use crate::backend::{Backend, DefaultBackend, Direction, IteratorMode, WriteBatch};
 use crate::kvtable::*;
+use crate::snapshot::{self, Content, Record};
 
-#[derive(Clone)]
-pub struct Store {
-    db: Arc<DB>,
+pub struct Store<B = DefaultBackend> {
+    db: Arc<B>,
+    indexes: Indexes,
     pub tip_change: Arc<tokio::sync::Notify>,
 }
 
+impl<B> Clone for Store<B> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            indexes: self.indexes,
+            tip_change: self.tip_change.clone(),
+        }
+    }
+}
+
 pub struct BlockByHashKV;
 
 // hash -> block cbor
@@ -40,15 +56,38 @@ impl Iterator for ChainIterator<'_> {
 
 impl Store {
     pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        opts.create_missing_column_families(true);
+        Self::open_with_indexes(path, Indexes::default())
+    }
 
-        let db = DB::open_cf(&opts, path, [BlockByHashKV::CF_NAME, HashBySlotKV::CF_NAME])
-            .map_err(|_| Error::IO)?;
+    /// Opens the store, maintaining the given secondary indexes
+    ///
+    /// Tables of indexes that were enabled in previous runs are kept, but
+    /// they are no longer updated.
+    pub fn open_with_indexes(path: impl AsRef<Path>, indexes: Indexes) -> Result<Self, Error> {
+        let db = DefaultBackend::open(path.as_ref())?;
+        Self::from_backend(db, indexes)
+    }
+
+    pub fn destroy(path: impl AsRef<Path>) -> Result<(), Error> {
+        DefaultBackend::destroy(path.as_ref())
+    }
+}
+
+impl<B: Backend> Store<B> {
+    /// Builds the store on top of an already opened backend, creating the
+    /// tables it needs
+    pub fn from_backend(db: B, indexes: Indexes) -> Result<Self, Error> {
+        let tables = [BlockByHashKV::CF_NAME, HashBySlotKV::CF_NAME]
+            .into_iter()
+            .chain(indexes.cf_names());
+
+        for table in tables {
+            db.ensure_table(table)?;
+        }
 
         let out = Self {
             db: Arc::new(db),
+            indexes,
             tip_change: Arc::new(tokio::sync::Notify::new()),
         };
 
@@ -56,10 +95,19 @@ impl Store {
     }
 
     pub fn get_block(&self, hash: Hash<32>) -> Result<Option<BlockBody>, Error> {
-        let dbval = BlockByHashKV::get_by_key(&self.db, DBHash(hash))?;
+        let dbval = BlockByHashKV::get_by_key(self.db.as_ref(), DBHash(hash))?;
         Ok(dbval.map(|x| x.0))
     }
 
+    pub fn indexes(&self) -> &Indexes {
+        &self.indexes
+    }
+
+    /// Appends a block to the chain
+    ///
+    /// When any index is enabled, the body is decoded to derive the index
+    /// entries and the block is rejected with [`Error::InvalidBlock`] if it
+    /// can't be decoded.
     pub fn roll_forward(
         &mut self,
         slot: BlockSlot,
@@ -68,13 +116,18 @@ impl Store {
     ) -> Result<(), Error> {
         let mut batch = WriteBatch::default();
 
+        if self.indexes.any() {
+            IndexEntries::for_block(self.db.as_ref(), &self.indexes, hash, &body)?
+                .stage_upsert(slot, hash, &mut batch);
+        }
+
         // keep track of the new block body
-        BlockByHashKV::stage_upsert(&self.db, DBHash(hash), DBBytes(body), &mut batch);
+        BlockByHashKV::stage_upsert(DBHash(hash), DBBytes(body), &mut batch);
 
         // add new block to HashBySlotKV
-        HashBySlotKV::stage_upsert(&self.db, DBInt(slot), DBHash(hash), &mut batch);
+        HashBySlotKV::stage_upsert(DBInt(slot), DBHash(hash), &mut batch);
 
-        self.db.write(batch).map_err(|_| Error::IO)?;
+        self.db.write(batch)?;
         self.tip_change.notify_waiters();
 
         Ok(())
@@ -84,21 +137,50 @@ impl Store {
         let mut batch = WriteBatch::default();
 
         // remove rollback-ed blocks from HashBySlotKV
-        let to_remove = HashBySlotKV::iter_keys_from(&self.db, DBInt(until)).skip(1);
+        let to_remove = HashBySlotKV::iter_entries_from(self.db.as_ref(), DBInt(until)).skip(1);
+
+        for entry in to_remove {
+            let (slot, hash) = entry?;
+
+            // undo the index entries of the block, which are derived again
+            // from its body
+            if self.indexes.any() {
+                if let Some(DBBytes(body)) =
+                    BlockByHashKV::get_by_key(self.db.as_ref(), DBHash(hash.0))?
+                {
+                    IndexEntries::for_block(self.db.as_ref(), &self.indexes, hash.0, &body)?
+                        .stage_delete(slot.0, &mut batch);
+                }
+            }
 
-        for key in to_remove {
-            HashBySlotKV::stage_delete(&self.db, key?, &mut batch);
+            HashBySlotKV::stage_delete(slot, &mut batch);
         }
 
-        self.db.write(batch).map_err(|_| Error::IO)?;
+        self.db.write(batch)?;
         self.tip_change.notify_waiters();
 
         Ok(())
     }
 
     pub fn roll_back_origin(&mut self) -> Result<(), Error> {
-        HashBySlotKV::reset(&self.db)?;
-        BlockByHashKV::reset(&self.db)?;
+        HashBySlotKV::reset(self.db.as_ref())?;
+        BlockByHashKV::reset(self.db.as_ref())?;
+
+        if self.indexes.block_number {
+            HashByNumberKV::reset(self.db.as_ref())?;
+        }
+
+        if self.indexes.tx_hash {
+            TxRefByHashKV::reset(self.db.as_ref())?;
+        }
+
+        if self.indexes.address {
+            TxByAddressKV::reset(self.db.as_ref())?;
+        }
+
+        if self.indexes.payment_credential {
+            TxByPaymentKV::reset(self.db.as_ref())?;
+        }
 
         self.tip_change.notify_waiters();
 
@@ -106,7 +188,7 @@ impl Store {
     }
 
     pub fn find_tip(&self) -> Result<Option<(BlockSlot, BlockHash)>, Error> {
-        let mut iter = HashBySlotKV::iter_entries(&self.db, rocksdb::IteratorMode::End);
+        let mut iter = HashBySlotKV::iter_entries(self.db.as_ref(), IteratorMode::End);
 
         if let Some(last) = iter.next() {
             let (slot, hash) = last?;
@@ -120,7 +202,7 @@ impl Store {
         &self,
         max_items: usize,
     ) -> Result<Vec<(BlockSlot, BlockHash)>, Error> {
-        let mut iter = HashBySlotKV::iter_entries(&self.db, rocksdb::IteratorMode::End)
+        let mut iter = HashBySlotKV::iter_entries(self.db.as_ref(), IteratorMode::End)
             .filter_map(|res| res.ok())
             .map(|(k, v)| (k.0, v.0));
 
@@ -143,24 +225,24 @@ impl Store {
         Ok(out)
     }
 
-    pub fn crawl_after(&self, slot: Option<u64>) -> ChainIterator {
+    pub fn crawl_after(&self, slot: Option<u64>) -> ChainIterator<'_> {
         if let Some(slot) = slot {
             let slot = Box::<[u8]>::from(DBInt(slot));
-            let from = rocksdb::IteratorMode::From(&slot, rocksdb::Direction::Forward);
-            let mut iter = HashBySlotKV::iter_entries(&self.db, from);
+            let from = IteratorMode::From(&slot, Direction::Forward);
+            let mut iter = HashBySlotKV::iter_entries(self.db.as_ref(), from);
 
             // skip current
             iter.next();
 
             ChainIterator(iter)
         } else {
-            let from = rocksdb::IteratorMode::Start;
-            let iter = HashBySlotKV::iter_entries(&self.db, from);
+            let from = IteratorMode::Start;
+            let iter = HashBySlotKV::iter_entries(self.db.as_ref(), from);
             ChainIterator(iter)
         }
     }
 
-    pub fn crawl(&self) -> ChainIterator {
+    pub fn crawl(&self) -> ChainIterator<'_> {
         self.crawl_after(None)
     }
 
@@ -169,7 +251,7 @@ impl Store {
         from: BlockSlot,
         len: usize,
     ) -> impl Iterator<Item = Result<(BlockSlot, BlockHash), Error>> + '_ {
-        HashBySlotKV::iter_entries_from(&self.db, DBInt(from))
+        HashBySlotKV::iter_entries_from(self.db.as_ref(), DBInt(from))
             .map(|res| res.map(|(x, y)| (x.0, y.0)))
             .take(len)
     }
@@ -210,7 +292,7 @@ impl Store {
 
         // check p1 exists in HashBySlotKV if provided
         if let Some((slot, hash)) = from {
-            match HashBySlotKV::get_by_key(&self.db, DBInt(slot))? {
+            match HashBySlotKV::get_by_key(self.db.as_ref(), DBInt(slot))? {
                 Some(DBHash(found_hash)) => {
                     if hash != found_hash {
                         warn!("chain range start hash mismatch");
@@ -225,7 +307,7 @@ impl Store {
         }
 
         // check p2 exists in HashBySlotKV
-        match HashBySlotKV::get_by_key(&self.db, DBInt(to.0))? {
+        match HashBySlotKV::get_by_key(self.db.as_ref(), DBInt(to.0))? {
             Some(DBHash(found_hash)) => {
                 if to.1 != found_hash {
                     warn!("chain range end hash mismatch");
@@ -240,7 +322,7 @@ impl Store {
 
         // return iterator between p1 and p2 inclusive
         Ok(Some(
-            HashBySlotKV::iter_entries_from(&self.db, DBInt(p1_slot))
+            HashBySlotKV::iter_entries_from(self.db.as_ref(), DBInt(p1_slot))
                 .map(|res| res.map(|(x, y)| (x.0, y.0)))
                 .take_while(move |x| {
                     if let Ok((slot, _)) = x {
@@ -256,7 +338,7 @@ impl Store {
     /// Check if a point (pair of slot and block hash) exists in the
     /// HashBySlotKV
     pub fn chain_contains(&self, slot: BlockSlot, hash: &BlockHash) -> Result<bool, Error> {
-        if let Some(DBHash(found)) = HashBySlotKV::get_by_key(&self.db, DBInt(slot))? {
+        if let Some(DBHash(found)) = HashBySlotKV::get_by_key(self.db.as_ref(), DBInt(slot))? {
             if found == *hash {
                 return Ok(true);
             }
@@ -265,11 +347,125 @@ impl Store {
         Ok(false)
     }
 
+    /// Hash of the block with the given number, requires the block number
+    /// index
+    pub fn get_hash_by_number(&self, number: u64) -> Result<Option<BlockHash>, Error> {
+        if !self.indexes.block_number {
+            return Err(Error::IndexNotEnabled);
+        }
+
+        let dbval = HashByNumberKV::get_by_key(self.db.as_ref(), DBInt(number))?;
+        Ok(dbval.map(|x| x.0))
+    }
+
+    /// Location of a tx in the chain, requires the tx hash index
+    pub fn get_tx_ref(&self, tx: TxHash) -> Result<Option<TxRef>, Error> {
+        if !self.indexes.tx_hash {
+            return Err(Error::IndexNotEnabled);
+        }
+
+        let dbval = TxRefByHashKV::get_by_key(self.db.as_ref(), DBHash(tx))?;
+        Ok(dbval.map(|x| x.0))
+    }
+
+    /// Slot and hash of the txs touching an address, in chain order, requires
+    /// the address index
+    ///
+    /// The address is given in its raw binary form.
+    pub fn iter_txs_by_address(
+        &self,
+        address: &[u8],
+    ) -> Result<impl Iterator<Item = Result<(BlockSlot, TxHash), Error>> + '_, Error> {
+        if !self.indexes.address {
+            return Err(Error::IndexNotEnabled);
+        }
+
+        Ok(iter_txs::<TxByAddressKV, _>(
+            self.db.as_ref(),
+            address.to_vec(),
+        ))
+    }
+
+    /// Slot and hash of the txs touching addresses with a payment credential,
+    /// in chain order, requires the payment credential index
+    pub fn iter_txs_by_payment(
+        &self,
+        payment: &ShelleyPaymentPart,
+    ) -> Result<impl Iterator<Item = Result<(BlockSlot, TxHash), Error>> + '_, Error> {
+        if !self.indexes.payment_credential {
+            return Err(Error::IndexNotEnabled);
+        }
+
+        Ok(iter_txs::<TxByPaymentKV, _>(
+            self.db.as_ref(),
+            payment_key(payment),
+        ))
+    }
+
     pub fn is_empty(&self) -> bool {
-        HashBySlotKV::is_empty(&self.db) && BlockByHashKV::is_empty(&self.db)
+        HashBySlotKV::is_empty(self.db.as_ref()) && BlockByHashKV::is_empty(self.db.as_ref())
     }
 
-    pub fn destroy(path: impl AsRef<Path>) -> Result<(), Error> {
-        DB::destroy(&Options::default(), path).map_err(|_| Error::IO)
+    /// Writes the blocks of the chain to a portable snapshot, in chain order,
+    /// returning the tip of the exported chain
+    ///
+    /// The blocks are read from a view of the backend taken when the export
+    /// starts, so the store can keep being written to (by this or any clone of
+    /// it) while the export runs: the snapshot holds the chain as it was at
+    /// that point.
+    pub fn export(&self, writer: impl Write) -> Result<Option<(BlockSlot, BlockHash)>, Error> {
+        let view = self.db.view()?;
+
+        let mut out = snapshot::Writer::new(writer, Content::Chain)?;
+        let mut tip = None;
+
+        for entry in ChainIterator(HashBySlotKV::iter_entries(&view, IteratorMode::Start)) {
+            let (slot, hash) = entry?;
+
+            let body = BlockByHashKV::get_by_key(&view, DBHash(hash))?.ok_or(Error::NotFound)?;
+            out.write(&Record::Block(slot, hash, body.0))?;
+
+            tip = Some((slot, hash));
+        }
+
+        out.finish()?;
+
+        Ok(tip)
+    }
+
+    /// Builds the store from a snapshot written by [`Store::export`], on top
+    /// of an empty backend
+    ///
+    /// Blocks are rolled forward one by one, so the enabled indexes are
+    /// rebuilt as they would be when syncing. The backend is left with the
+    /// blocks imported so far when the snapshot is invalid, it's up to the
+    /// caller to discard it.
+    pub fn import(db: B, indexes: Indexes, reader: impl Read) -> Result<Self, Error> {
+        let mut store = Self::from_backend(db, indexes)?;
+
+        if !store.is_empty() {
+            return Err(Error::NotEmpty);
+        }
+
+        for record in snapshot::Reader::new(reader, Content::Chain)? {
+            let Record::Block(slot, hash, body) = record? else {
+                return Err(Error::InvalidSnapshot);
+            };
+
+            store.roll_forward(slot, hash, body)?;
+        }
+
+        Ok(store)
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+impl Store<crate::backend::rocksdb::RocksDbBackend> {
+    /// Creates a RocksDB checkpoint of the store at a path that must not exist
+    /// yet
+    ///
+    /// The checkpoint is a regular db that can be opened with [`Store::open`].
+    pub fn checkpoint(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.db.checkpoint(path.as_ref())
     }
 }
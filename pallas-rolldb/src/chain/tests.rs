@@ -1,4 +1,8 @@
-use super::{BlockBody, BlockHash, BlockSlot, Store};
+use pallas_addresses::Address;
+use pallas_traverse::MultiEraBlock;
+
+use super::{BlockBody, BlockHash, BlockSlot, Indexes, Store, TxRef};
+use crate::backend::memory::MemoryBackend;
 
 fn with_tmp_db<T>(op: fn(db: Store) -> T) {
     let path = tempfile::tempdir().unwrap().into_path();
@@ -14,6 +18,42 @@ fn dummy_block(slot: u64) -> (BlockSlot, BlockHash, BlockBody) {
     (slot, hash, slot.to_be_bytes().to_vec())
 }
 
+/// Sink for an export that runs `during` once a few writes went through, to
+/// change the store halfway through the export
+struct WriteDuring<F: FnMut()> {
+    out: Vec<u8>,
+    writes: usize,
+    during: Option<F>,
+}
+
+impl<F: FnMut()> WriteDuring<F> {
+    fn new(during: F) -> Self {
+        Self {
+            out: vec![],
+            writes: 0,
+            during: Some(during),
+        }
+    }
+}
+
+impl<F: FnMut()> std::io::Write for WriteDuring<F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writes += 1;
+
+        if self.writes == 3 {
+            if let Some(mut during) = self.during.take() {
+                during();
+            }
+        }
+
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 #[test]
 fn test_roll_forward_blackbox() {
     with_tmp_db(|mut db| {
@@ -102,3 +142,227 @@ fn test_intersect_options() {
         }
     });
 }
+
+fn with_tmp_indexed_db<T>(op: fn(db: Store) -> T) {
+    let path = tempfile::tempdir().unwrap().into_path();
+    let db = Store::open_with_indexes(path.clone(), Indexes::all()).unwrap();
+
+    op(db);
+
+    Store::destroy(path).unwrap();
+}
+
+fn real_block(cbor_hex: &str) -> (BlockSlot, BlockHash, BlockBody) {
+    let body = hex::decode(cbor_hex).unwrap();
+    let block = MultiEraBlock::decode(&body).unwrap();
+    (block.slot(), block.hash(), body)
+}
+
+#[test]
+fn test_secondary_indexes() {
+    with_tmp_indexed_db(|mut db| {
+        // blocks come from different networks, so they are sorted by slot
+        let mut blocks = [
+            real_block(include_str!("../../../test_data/alonzo1.block")),
+            real_block(include_str!("../../../test_data/babbage1.block")),
+        ];
+
+        blocks.sort_by_key(|(slot, ..)| *slot);
+        let [first, second] = blocks;
+
+        for (slot, hash, body) in [first.clone(), second.clone()] {
+            db.roll_forward(slot, hash, body).unwrap();
+        }
+
+        let block = MultiEraBlock::decode(&second.2).unwrap();
+        let tx = block.txs().pop().unwrap();
+        let index = block.txs().len() as u32 - 1;
+
+        // block and tx lookups
+        let found = db.get_hash_by_number(block.number()).unwrap();
+        assert_eq!(found, Some(second.1));
+
+        let tx_ref = db.get_tx_ref(tx.hash()).unwrap().unwrap();
+        assert_eq!(
+            tx_ref,
+            TxRef {
+                block: second.1,
+                index
+            }
+        );
+
+        // address lookups
+        let address = tx.outputs()[0].address().unwrap();
+
+        let txs: Vec<_> = db
+            .iter_txs_by_address(&address.to_vec())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert!(txs.contains(&(second.0, tx.hash())));
+
+        if let Address::Shelley(shelley) = &address {
+            let txs: Vec<_> = db
+                .iter_txs_by_payment(shelley.payment())
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+
+            assert!(txs.contains(&(second.0, tx.hash())));
+        }
+
+        // rolling back removes the entries of the undone block
+        db.roll_back(first.0).unwrap();
+
+        assert_eq!(db.get_hash_by_number(block.number()).unwrap(), None);
+        assert_eq!(db.get_tx_ref(tx.hash()).unwrap(), None);
+
+        let remaining = db
+            .iter_txs_by_address(&address.to_vec())
+            .unwrap()
+            .map(Result::unwrap)
+            .filter(|(slot, _)| *slot == second.0)
+            .count();
+
+        assert_eq!(remaining, 0);
+
+        let first_number = MultiEraBlock::decode(&first.2).unwrap().number();
+        assert_eq!(db.get_hash_by_number(first_number).unwrap(), Some(first.1));
+    });
+}
+
+#[test]
+fn test_disabled_indexes() {
+    with_tmp_db(|db| {
+        assert!(matches!(
+            db.get_hash_by_number(0),
+            Err(crate::Error::IndexNotEnabled)
+        ));
+
+        assert!(db.iter_txs_by_address(&[0u8; 29]).is_err());
+    });
+}
+
+#[test]
+fn test_export_import() {
+    with_tmp_indexed_db(|mut db| {
+        let mut blocks = [
+            real_block(include_str!("../../../test_data/alonzo1.block")),
+            real_block(include_str!("../../../test_data/babbage1.block")),
+        ];
+
+        blocks.sort_by_key(|(slot, ..)| *slot);
+
+        for (slot, hash, body) in blocks.clone() {
+            db.roll_forward(slot, hash, body).unwrap();
+        }
+
+        let (slot, hash, body) = blocks[1].clone();
+
+        let mut snapshot = vec![];
+        let tip = db.export(&mut snapshot).unwrap();
+        assert_eq!(tip, Some((slot, hash)));
+
+        let restored = Store::import(
+            MemoryBackend::default(),
+            Indexes::all(),
+            snapshot.as_slice(),
+        )
+        .unwrap();
+
+        let expected: Vec<_> = db.crawl().map(Result::unwrap).collect();
+        let found: Vec<_> = restored.crawl().map(Result::unwrap).collect();
+        assert_eq!(found, expected);
+
+        assert_eq!(restored.find_tip().unwrap(), tip);
+        assert_eq!(restored.get_block(hash).unwrap(), Some(body.clone()));
+
+        // indexes are rebuilt from the imported blocks
+        let block = MultiEraBlock::decode(&body).unwrap();
+        let tx = block.txs().pop().unwrap();
+
+        assert_eq!(
+            restored.get_tx_ref(tx.hash()).unwrap(),
+            db.get_tx_ref(tx.hash()).unwrap()
+        );
+
+        // snapshots of other stores are rejected
+        let mut wal = vec![];
+
+        crate::wal::Store::from_backend(MemoryBackend::default(), 30, None)
+            .unwrap()
+            .export(&mut wal)
+            .unwrap();
+
+        assert!(matches!(
+            Store::import(MemoryBackend::default(), Indexes::default(), wal.as_slice()),
+            Err(crate::Error::InvalidSnapshot)
+        ));
+    });
+}
+
+#[test]
+fn test_export_while_writing() {
+    with_tmp_db(|mut db| {
+        for i in 0..10 {
+            let (slot, hash, body) = dummy_block(i * 10);
+            db.roll_forward(slot, hash, body).unwrap();
+        }
+
+        let expected: Vec<_> = db.crawl().map(Result::unwrap).collect();
+        let tip = db.find_tip().unwrap();
+
+        let mut writer = db.clone();
+
+        let mut sink = WriteDuring::new(move || {
+            writer.roll_back(40).unwrap();
+
+            for slot in [45, 55] {
+                let (slot, hash, body) = dummy_block(slot);
+                writer.roll_forward(slot, hash, body).unwrap();
+            }
+        });
+
+        assert_eq!(db.export(&mut sink).unwrap(), tip);
+        assert!(sink.during.is_none());
+
+        // the snapshot holds the chain as it was when the export started
+        let restored = Store::import(
+            MemoryBackend::default(),
+            Indexes::default(),
+            sink.out.as_slice(),
+        )
+        .unwrap();
+
+        let found: Vec<_> = restored.crawl().map(Result::unwrap).collect();
+        assert_eq!(found, expected);
+
+        assert_eq!(restored.find_tip().unwrap(), tip);
+        assert_eq!(db.find_tip().unwrap().unwrap().0, 55);
+    });
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn test_checkpoint_restore() {
+    with_tmp_db(|mut db| {
+        for i in 0..10 {
+            let (slot, hash, body) = dummy_block(i * 10);
+            db.roll_forward(slot, hash, body).unwrap();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint");
+
+        db.checkpoint(&path).unwrap();
+
+        // blocks added after the checkpoint are not part of it
+        let (slot, hash, body) = dummy_block(100);
+        db.roll_forward(slot, hash, body).unwrap();
+
+        let restored = Store::open(&path).unwrap();
+        assert_eq!(restored.find_tip().unwrap().unwrap().0, 90);
+        assert_eq!(restored.crawl().count(), 10);
+    });
+}
@@ -3,6 +3,8 @@ use serde::{de::DeserializeOwned, Serialize};
 use std::marker::PhantomData;
 use thiserror::Error;
 
+use crate::backend::{Backend, Direction, IteratorMode, RawIterator, ReadView, WriteBatch};
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("IO error")]
@@ -13,6 +15,18 @@ pub enum Error {
 
     #[error("not found")]
     NotFound,
+
+    #[error("invalid block")]
+    InvalidBlock,
+
+    #[error("index not enabled")]
+    IndexNotEnabled,
+
+    #[error("invalid snapshot")]
+    InvalidSnapshot,
+
+    #[error("store is not empty")]
+    NotEmpty,
 }
 
 pub struct DBHash(pub Hash<32>);
@@ -168,69 +182,53 @@ impl<T> From<Box<[u8]>> for WithDBIntPrefix<T> {
     }
 }
 
-type RocksIterator<'a> = rocksdb::DBIteratorWithThreadMode<'a, rocksdb::DB>;
-
-pub struct ValueIterator<'a, V>(RocksIterator<'a>, PhantomData<V>);
+pub struct ValueIterator<'a, V>(RawIterator<'a>, PhantomData<V>);
 
 impl<'a, V> ValueIterator<'a, V> {
-    pub fn new(inner: RocksIterator<'a>) -> Self {
+    pub fn new(inner: RawIterator<'a>) -> Self {
         Self(inner, Default::default())
     }
 }
 
-impl<'a, V> Iterator for ValueIterator<'a, V>
+impl<V> Iterator for ValueIterator<'_, V>
 where
     V: From<Box<[u8]>>,
 {
     type Item = Result<V, Error>;
 
     fn next(&mut self) -> Option<Result<V, Error>> {
-        match self.0.next() {
-            Some(Ok((_, value))) => Some(Ok(V::from(value))),
-            Some(Err(err)) => {
-                tracing::error!(?err);
-                Some(Err(Error::IO))
-            }
-            None => None,
-        }
+        self.0.next().map(|x| x.map(|(_, value)| V::from(value)))
     }
 }
 
-pub struct KeyIterator<'a, K>(RocksIterator<'a>, PhantomData<K>);
+pub struct KeyIterator<'a, K>(RawIterator<'a>, PhantomData<K>);
 
 impl<'a, K> KeyIterator<'a, K> {
-    pub fn new(inner: RocksIterator<'a>) -> Self {
+    pub fn new(inner: RawIterator<'a>) -> Self {
         Self(inner, Default::default())
     }
 }
 
-impl<'a, K> Iterator for KeyIterator<'a, K>
+impl<K> Iterator for KeyIterator<'_, K>
 where
     K: From<Box<[u8]>>,
 {
     type Item = Result<K, Error>;
 
     fn next(&mut self) -> Option<Result<K, Error>> {
-        match self.0.next() {
-            Some(Ok((key, _))) => Some(Ok(K::from(key))),
-            Some(Err(err)) => {
-                tracing::error!(?err);
-                Some(Err(Error::IO))
-            }
-            None => None,
-        }
+        self.0.next().map(|x| x.map(|(key, _)| K::from(key)))
     }
 }
 
-pub struct EntryIterator<'a, K, V>(RocksIterator<'a>, PhantomData<(K, V)>);
+pub struct EntryIterator<'a, K, V>(RawIterator<'a>, PhantomData<(K, V)>);
 
 impl<'a, K, V> EntryIterator<'a, K, V> {
-    pub fn new(inner: RocksIterator<'a>) -> Self {
+    pub fn new(inner: RawIterator<'a>) -> Self {
         Self(inner, Default::default())
     }
 }
 
-impl<'a, K, V> Iterator for EntryIterator<'a, K, V>
+impl<K, V> Iterator for EntryIterator<'_, K, V>
 where
     K: From<Box<[u8]>>,
     V: From<Box<[u8]>>,
@@ -238,19 +236,9 @@ where
     type Item = Result<(K, V), Error>;
 
     fn next(&mut self) -> Option<Result<(K, V), Error>> {
-        match self.0.next() {
-            Some(Ok((key, value))) => {
-                let key_out = K::from(key);
-                let value_out = V::from(value);
-
-                Some(Ok((key_out, value_out)))
-            }
-            Some(Err(err)) => {
-                tracing::error!(?err);
-                Some(Err(Error::IO))
-            }
-            None => None,
-        }
+        self.0
+            .next()
+            .map(|x| x.map(|(key, value)| (K::from(key), V::from(value))))
     }
 }
 
@@ -263,113 +251,76 @@ where
 {
     const CF_NAME: &'static str;
 
-    fn cf(db: &rocksdb::DB) -> rocksdb::ColumnFamilyRef {
-        db.cf_handle(Self::CF_NAME).unwrap()
+    fn reset<B: Backend>(db: &B) -> Result<(), Error> {
+        db.reset(Self::CF_NAME)
     }
 
-    fn reset(db: &rocksdb::DB) -> Result<(), Error> {
-        db.drop_cf(Self::CF_NAME).map_err(|_| Error::IO)?;
-
-        db.create_cf(Self::CF_NAME, &rocksdb::Options::default())
-            .map_err(|_| Error::IO)?;
-
-        Ok(())
-    }
-
-    fn get_by_key(db: &rocksdb::DB, k: K) -> Result<Option<V>, Error> {
-        let cf = Self::cf(db);
+    fn get_by_key<B: ReadView>(db: &B, k: K) -> Result<Option<V>, Error> {
         let raw_key = Box::<[u8]>::from(k);
-        let raw_value = db
-            .get_cf(&cf, raw_key)
-            .map_err(|_| Error::IO)?
-            .map(|x| Box::from(x.as_slice()));
-
-        match raw_value {
-            Some(x) => {
-                let out = <V>::from(x);
-                Ok(Some(out))
-            }
-            None => Ok(None),
-        }
-    }
+        let raw_value = db.get(Self::CF_NAME, &raw_key)?;
 
-    fn stage_upsert(db: &rocksdb::DB, k: K, v: V, batch: &mut rocksdb::WriteBatch) {
-        let cf = Self::cf(db);
+        Ok(raw_value.map(V::from))
+    }
 
+    fn stage_upsert(k: K, v: V, batch: &mut WriteBatch) {
         let k_raw = Box::<[u8]>::from(k);
         let v_raw = Box::<[u8]>::from(v);
 
-        batch.put_cf(&cf, k_raw, v_raw);
+        batch.put(Self::CF_NAME, k_raw, v_raw);
     }
 
-    fn is_empty(db: &rocksdb::DB) -> bool {
+    fn is_empty<B: ReadView>(db: &B) -> bool {
         // HACK: can't find an easy way to size the num of keys, so we'll start an
         // iterator and see if we have at least one value. If someone know a better way
         // to accomplish this, please refactor.
-        let mut iter = Self::iter_keys(db, rocksdb::IteratorMode::Start);
+        let mut iter = Self::iter_keys(db, IteratorMode::Start);
         iter.next().is_none()
     }
 
-    fn iter_keys<'a>(db: &'a rocksdb::DB, mode: rocksdb::IteratorMode) -> KeyIterator<'a, K> {
-        let cf = Self::cf(db);
-        let inner = db.iterator_cf(&cf, mode);
-        KeyIterator::new(inner)
+    fn iter_keys<'a, B: ReadView>(db: &'a B, mode: IteratorMode) -> KeyIterator<'a, K> {
+        KeyIterator::new(db.iter(Self::CF_NAME, mode))
     }
 
     #[allow(dead_code)]
-    fn iter_keys_start(db: &rocksdb::DB) -> KeyIterator<'_, K> {
-        Self::iter_keys(db, rocksdb::IteratorMode::Start)
-    }
-
-    fn iter_keys_from(db: &rocksdb::DB, from: K) -> KeyIterator<'_, K> {
-        let from_raw = Box::<[u8]>::from(from);
-        let mode = rocksdb::IteratorMode::From(&from_raw, rocksdb::Direction::Forward);
-
-        Self::iter_keys(db, mode)
+    fn iter_keys_start<B: ReadView>(db: &B) -> KeyIterator<'_, K> {
+        Self::iter_keys(db, IteratorMode::Start)
     }
 
-    fn iter_values<'a>(db: &'a rocksdb::DB, mode: rocksdb::IteratorMode) -> ValueIterator<'a, V> {
-        let cf = Self::cf(db);
-        let inner = db.iterator_cf(&cf, mode);
-        ValueIterator::new(inner)
+    fn iter_values<'a, B: ReadView>(db: &'a B, mode: IteratorMode) -> ValueIterator<'a, V> {
+        ValueIterator::new(db.iter(Self::CF_NAME, mode))
     }
 
     #[allow(dead_code)]
-    fn iter_values_start(db: &rocksdb::DB) -> ValueIterator<'_, V> {
-        Self::iter_values(db, rocksdb::IteratorMode::Start)
+    fn iter_values_start<B: ReadView>(db: &B) -> ValueIterator<'_, V> {
+        Self::iter_values(db, IteratorMode::Start)
     }
 
     #[allow(dead_code)]
-    fn iter_values_from(db: &rocksdb::DB, from: K) -> ValueIterator<'_, V> {
+    fn iter_values_from<B: ReadView>(db: &B, from: K) -> ValueIterator<'_, V> {
         let from_raw = Box::<[u8]>::from(from);
-        let mode = rocksdb::IteratorMode::From(&from_raw, rocksdb::Direction::Forward);
+        let mode = IteratorMode::From(&from_raw, Direction::Forward);
 
         Self::iter_values(db, mode)
     }
 
-    fn iter_entries<'a>(
-        db: &'a rocksdb::DB,
-        mode: rocksdb::IteratorMode,
-    ) -> EntryIterator<'a, K, V> {
-        let cf = Self::cf(db);
-        let inner = db.iterator_cf(&cf, mode);
-        EntryIterator::new(inner)
+    fn iter_entries<'a, B: ReadView>(db: &'a B, mode: IteratorMode) -> EntryIterator<'a, K, V> {
+        EntryIterator::new(db.iter(Self::CF_NAME, mode))
     }
 
     #[allow(dead_code)]
-    fn iter_entries_start(db: &rocksdb::DB) -> EntryIterator<'_, K, V> {
-        Self::iter_entries(db, rocksdb::IteratorMode::Start)
+    fn iter_entries_start<B: ReadView>(db: &B) -> EntryIterator<'_, K, V> {
+        Self::iter_entries(db, IteratorMode::Start)
     }
 
-    fn iter_entries_from(db: &rocksdb::DB, from: K) -> EntryIterator<'_, K, V> {
+    fn iter_entries_from<B: ReadView>(db: &B, from: K) -> EntryIterator<'_, K, V> {
         let from_raw = Box::<[u8]>::from(from);
-        let mode = rocksdb::IteratorMode::From(&from_raw, rocksdb::Direction::Forward);
+        let mode = IteratorMode::From(&from_raw, Direction::Forward);
 
         Self::iter_entries(db, mode)
     }
 
-    fn last_key(db: &rocksdb::DB) -> Result<Option<K>, Error> {
-        let mut iter = Self::iter_keys(db, rocksdb::IteratorMode::End);
+    fn last_key<B: ReadView>(db: &B) -> Result<Option<K>, Error> {
+        let mut iter = Self::iter_keys(db, IteratorMode::End);
 
         match iter.next() {
             None => Ok(None),
@@ -378,8 +329,8 @@ where
     }
 
     #[allow(dead_code)]
-    fn last_value(db: &rocksdb::DB) -> Result<Option<V>, Error> {
-        let mut iter = Self::iter_values(db, rocksdb::IteratorMode::End);
+    fn last_value<B: ReadView>(db: &B) -> Result<Option<V>, Error> {
+        let mut iter = Self::iter_values(db, IteratorMode::End);
 
         match iter.next() {
             None => Ok(None),
@@ -388,8 +339,8 @@ where
     }
 
     #[allow(dead_code)]
-    fn last_entry(db: &rocksdb::DB) -> Result<Option<(K, V)>, Error> {
-        let mut iter = Self::iter_entries(db, rocksdb::IteratorMode::End);
+    fn last_entry<B: ReadView>(db: &B) -> Result<Option<(K, V)>, Error> {
+        let mut iter = Self::iter_entries(db, IteratorMode::End);
 
         match iter.next() {
             None => Ok(None),
@@ -397,9 +348,9 @@ where
         }
     }
 
-    fn scan_until<F>(
-        db: &rocksdb::DB,
-        mode: rocksdb::IteratorMode,
+    fn scan_until<B: Backend, F>(
+        db: &B,
+        mode: IteratorMode,
         predicate: F,
     ) -> Result<Option<K>, Error>
     where
@@ -414,9 +365,8 @@ where
         Ok(None)
     }
 
-    fn stage_delete(db: &rocksdb::DB, key: K, batch: &mut rocksdb::WriteBatch) {
-        let cf = Self::cf(db);
+    fn stage_delete(key: K, batch: &mut WriteBatch) {
         let k_raw = Box::<[u8]>::from(key);
-        batch.delete_cf(&cf, k_raw);
+        batch.delete(Self::CF_NAME, k_raw);
     }
 }
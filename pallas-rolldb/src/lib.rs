@@ -1,3 +1,7 @@
+pub mod backend;
 pub mod chain;
 mod kvtable;
+mod snapshot;
 pub mod wal;
+
+pub use kvtable::Error;
@@ -0,0 +1,142 @@
+//! Portable snapshot format shared by the stores
+//!
+//! A snapshot is a stream of bincode records: a header naming the store it
+//! was exported from, the entries of the store in order and a trailer with
+//! the amount of entries, so that truncated streams are detected. Unlike
+//! RocksDB checkpoints, snapshots don't depend on the backend, so they can be
+//! imported into any of them.
+
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chain::{BlockBody, BlockHash, BlockSlot};
+use crate::kvtable::Error;
+use crate::wal::{Log, Seq};
+
+const MAGIC: [u8; 6] = *b"rolldb";
+
+const FORMAT_VERSION: u32 = 1;
+
+/// Store whose entries a snapshot holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Content {
+    Wal,
+    Chain,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum Record {
+    Header {
+        magic: [u8; 6],
+        version: u32,
+        content: Content,
+    },
+    Wal(Seq, Log),
+    Block(BlockSlot, BlockHash, BlockBody),
+    End(u64),
+}
+
+fn write_record(writer: &mut impl Write, record: &Record) -> Result<(), Error> {
+    bincode::serialize_into(writer, record).map_err(|err| {
+        tracing::error!(?err);
+        Error::IO
+    })
+}
+
+fn read_record(reader: &mut impl Read) -> Result<Record, Error> {
+    bincode::deserialize_from(reader).map_err(|err| {
+        tracing::error!(?err);
+
+        match *err {
+            bincode::ErrorKind::Io(ref x) if x.kind() != std::io::ErrorKind::UnexpectedEof => {
+                Error::IO
+            }
+            _ => Error::InvalidSnapshot,
+        }
+    })
+}
+
+pub(crate) struct Writer<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut inner: W, content: Content) -> Result<Self, Error> {
+        let header = Record::Header {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            content,
+        };
+
+        write_record(&mut inner, &header)?;
+
+        Ok(Self { inner, count: 0 })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        write_record(&mut self.inner, record)?;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        write_record(&mut self.inner, &Record::End(self.count))?;
+        self.inner.flush().map_err(|_| Error::IO)
+    }
+}
+
+/// Iterates the entries of a snapshot, checking the trailer at the end
+pub(crate) struct Reader<R> {
+    inner: R,
+    count: u64,
+    done: bool,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(mut inner: R, content: Content) -> Result<Self, Error> {
+        match read_record(&mut inner)? {
+            Record::Header {
+                magic,
+                version,
+                content: found,
+            } if magic == MAGIC && version == FORMAT_VERSION && found == content => Ok(Self {
+                inner,
+                count: 0,
+                done: false,
+            }),
+            _ => Err(Error::InvalidSnapshot),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match read_record(&mut self.inner) {
+            Ok(Record::End(count)) if count == self.count => {
+                self.done = true;
+                None
+            }
+            Ok(Record::End(_)) | Ok(Record::Header { .. }) => {
+                self.done = true;
+                Some(Err(Error::InvalidSnapshot))
+            }
+            Ok(record) => {
+                self.count += 1;
+                Some(Ok(record))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
@@ -1,12 +1,19 @@
-use rocksdb::Options;
-use rocksdb::{IteratorMode, WriteBatch, DB};
 use serde::{Deserialize, Serialize};
-use std::{path::Path, sync::Arc};
+use std::{
+    io::{Read, Write},
+    path::Path,
+    sync::Arc,
+};
 
+use crate::backend::{Backend, DefaultBackend, Direction, IteratorMode, WriteBatch};
 use crate::kvtable::*;
+use crate::snapshot::{self, Content, Record};
 
 use super::{BlockBody, BlockHash, BlockSlot, Seq};
 
+/// Amount of entries written per batch when importing a snapshot
+const IMPORT_BATCH_SIZE: usize = 1000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Log {
     Apply(BlockSlot, BlockHash, BlockBody),
@@ -122,7 +129,7 @@ impl Iterator for WalIterator<'_> {
 }
 
 impl WalKV {
-    pub fn initialize(db: &DB) -> Result<Seq, Error> {
+    pub fn initialize<B: Backend>(db: &B) -> Result<Seq, Error> {
         if Self::is_empty(db) {
             Self::write_seed(db)?;
             Ok(0)
@@ -132,55 +139,79 @@ impl WalKV {
         }
     }
 
-    fn write_seed(db: &DB) -> Result<(), Error> {
+    fn write_seed<B: Backend>(db: &B) -> Result<(), Error> {
         let mut batch = WriteBatch::default();
         let k = DBInt(0);
         let v = DBSerde(Log::Origin);
-        Self::stage_upsert(db, k, v, &mut batch);
+        Self::stage_upsert(k, v, &mut batch);
 
-        db.write(batch).map_err(|_| Error::IO)
+        db.write(batch)
     }
 }
 
-pub struct RollBatch<'a>(&'a DB, WriteBatch, Seq);
+pub struct RollBatch<'a, B>(&'a B, WriteBatch, Seq);
 
-impl<'a> RollBatch<'a> {
-    fn new(db: &'a DB, last_seq: Seq) -> Self {
+impl<'a, B: Backend> RollBatch<'a, B> {
+    fn new(db: &'a B, last_seq: Seq) -> Self {
         Self(db, Default::default(), last_seq)
     }
 
     fn stage_append(&mut self, log: Log) {
         let new_seq = self.2 + 1;
-        WalKV::stage_upsert(self.0, DBInt(new_seq), DBSerde(log), &mut self.1);
+        WalKV::stage_upsert(DBInt(new_seq), DBSerde(log), &mut self.1);
         self.2 = new_seq;
     }
 
     fn apply(self) -> Result<Seq, Error> {
-        self.0.write(self.1).map_err(|_| Error::IO)?;
+        self.0.write(self.1)?;
         Ok(self.2)
     }
 }
 
-#[derive(Clone)]
-pub struct Store {
-    db: Arc<DB>,
+pub struct Store<B = DefaultBackend> {
+    db: Arc<B>,
     pub tip_change: Arc<tokio::sync::Notify>,
     wal_seq: u64,
     k_param: u64,
     immutable_overlap: u64,
 }
 
+impl<B> Clone for Store<B> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            tip_change: self.tip_change.clone(),
+            wal_seq: self.wal_seq,
+            k_param: self.k_param,
+            immutable_overlap: self.immutable_overlap,
+        }
+    }
+}
+
 impl Store {
     pub fn open(
         path: impl AsRef<Path>,
         k_param: u64,
         immutable_overlap: Option<u64>,
     ) -> Result<Self, Error> {
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        opts.create_missing_column_families(true);
+        let db = DefaultBackend::open(path.as_ref())?;
+        Self::from_backend(db, k_param, immutable_overlap)
+    }
 
-        let db = DB::open_cf(&opts, path, [WalKV::CF_NAME]).map_err(|_| Error::IO)?;
+    pub fn destroy(path: impl AsRef<Path>) -> Result<(), Error> {
+        DefaultBackend::destroy(path.as_ref())
+    }
+}
+
+impl<B: Backend> Store<B> {
+    /// Builds the store on top of an already opened backend, creating the
+    /// tables it needs
+    pub fn from_backend(
+        db: B,
+        k_param: u64,
+        immutable_overlap: Option<u64>,
+    ) -> Result<Self, Error> {
+        db.ensure_table(WalKV::CF_NAME)?;
 
         let wal_seq = WalKV::initialize(&db)?;
 
@@ -201,7 +232,7 @@ impl Store {
         hash: BlockHash,
         body: BlockBody,
     ) -> Result<(), Error> {
-        let mut batch = RollBatch::new(&self.db, self.wal_seq);
+        let mut batch = RollBatch::new(self.db.as_ref(), self.wal_seq);
 
         batch.stage_append(Log::Apply(slot, hash, body));
 
@@ -212,9 +243,9 @@ impl Store {
     }
 
     pub fn roll_back(&mut self, until: BlockSlot) -> Result<(), Error> {
-        let mut batch = RollBatch::new(&self.db, self.wal_seq);
+        let mut batch = RollBatch::new(self.db.as_ref(), self.wal_seq);
 
-        let iter = WalKV::iter_values(&self.db, IteratorMode::End);
+        let iter = WalKV::iter_values(self.db.as_ref(), IteratorMode::End);
 
         for step in iter {
             let value = step.map_err(|_| Error::IO)?.0;
@@ -239,9 +270,9 @@ impl Store {
     }
 
     pub fn roll_back_origin(&mut self) -> Result<(), Error> {
-        let mut batch = RollBatch::new(&self.db, self.wal_seq);
+        let mut batch = RollBatch::new(self.db.as_ref(), self.wal_seq);
 
-        let iter = WalKV::iter_values(&self.db, IteratorMode::End);
+        let iter = WalKV::iter_values(self.db.as_ref(), IteratorMode::End);
 
         for step in iter {
             let value = step.map_err(|_| Error::IO)?.0;
@@ -265,7 +296,7 @@ impl Store {
     }
 
     pub fn find_tip(&self) -> Result<Option<(BlockSlot, BlockHash)>, Error> {
-        let iter = WalKV::iter_values(&self.db, IteratorMode::End);
+        let iter = WalKV::iter_values(self.db.as_ref(), IteratorMode::End);
 
         for value in iter {
             let value = value?;
@@ -284,7 +315,7 @@ impl Store {
         &self,
         max_items: usize,
     ) -> Result<Vec<(BlockSlot, BlockHash)>, Error> {
-        let mut iter = WalKV::iter_values(&self.db, rocksdb::IteratorMode::End)
+        let mut iter = WalKV::iter_values(self.db.as_ref(), IteratorMode::End)
             .filter_map(|res| res.ok())
             .filter(|v| !v.is_undo());
 
@@ -312,19 +343,19 @@ impl Store {
         Ok(out)
     }
 
-    pub fn crawl_after(&self, seq: Option<u64>) -> WalIterator {
+    pub fn crawl_after(&self, seq: Option<u64>) -> WalIterator<'_> {
         if let Some(seq) = seq {
             let seq = Box::<[u8]>::from(DBInt(seq));
-            let from = rocksdb::IteratorMode::From(&seq, rocksdb::Direction::Forward);
-            let mut iter = WalKV::iter_entries(&self.db, from);
+            let from = IteratorMode::From(&seq, Direction::Forward);
+            let mut iter = WalKV::iter_entries(self.db.as_ref(), from);
 
             // skip current
             iter.next();
 
             WalIterator(iter)
         } else {
-            let from = rocksdb::IteratorMode::Start;
-            let iter = WalKV::iter_entries(&self.db, from);
+            let from = IteratorMode::Start;
+            let iter = WalKV::iter_entries(self.db.as_ref(), from);
             WalIterator(iter)
         }
     }
@@ -334,7 +365,7 @@ impl Store {
             return Ok(None);
         }
 
-        let found = WalKV::scan_until(&self.db, rocksdb::IteratorMode::End, |v| {
+        let found = WalKV::scan_until(self.db.as_ref(), IteratorMode::End, |v| {
             v.equals_any_point(intersect)
         })?;
 
@@ -347,7 +378,7 @@ impl Store {
     pub fn crawl_from_intersect(
         &self,
         options: &[(BlockSlot, BlockHash)],
-    ) -> Result<WalIterator, Error> {
+    ) -> Result<WalIterator<'_>, Error> {
         let seq = self.find_wal_seq(options)?;
 
         // TODO: we need to create a RocksDB snapshot (with `db.snapshot()`) to use as
@@ -357,16 +388,16 @@ impl Store {
 
         if let Some(seq) = seq {
             let seq = Box::<[u8]>::from(DBInt(seq));
-            let from = rocksdb::IteratorMode::From(&seq, rocksdb::Direction::Forward);
-            let mut iter = WalKV::iter_entries(&self.db, from);
+            let from = IteratorMode::From(&seq, Direction::Forward);
+            let mut iter = WalKV::iter_entries(self.db.as_ref(), from);
 
             // skip current
             iter.next();
 
             Ok(WalIterator(iter))
         } else {
-            let from = rocksdb::IteratorMode::Start;
-            let iter = WalKV::iter_entries(&self.db, from);
+            let from = IteratorMode::Start;
+            let iter = WalKV::iter_entries(self.db.as_ref(), from);
             Ok(WalIterator(iter))
         }
     }
@@ -376,7 +407,7 @@ impl Store {
         let tip = self.find_tip()?.map(|(slot, _)| slot).unwrap_or_default();
 
         // iterate through all values in Wal from start
-        let mut iter = WalKV::iter_entries(&self.db, rocksdb::IteratorMode::Start);
+        let mut iter = WalKV::iter_entries(self.db.as_ref(), IteratorMode::Start);
 
         let mut batch = WriteBatch::default();
 
@@ -387,20 +418,99 @@ impl Store {
             if slot_delta <= self.k_param + self.immutable_overlap {
                 break;
             } else {
-                WalKV::stage_delete(&self.db, wal_key, &mut batch);
+                WalKV::stage_delete(wal_key, &mut batch);
             }
         }
 
-        self.db.write(batch).map_err(|_| Error::IO)?;
+        self.db.write(batch)?;
 
         Ok(())
     }
 
     pub fn is_empty(&self) -> bool {
-        WalKV::is_empty(&self.db)
+        WalKV::is_empty(self.db.as_ref())
     }
 
-    pub fn destroy(path: impl AsRef<Path>) -> Result<(), Error> {
-        DB::destroy(&Options::default(), path).map_err(|_| Error::IO)
+    /// Writes the entries of the WAL to a portable snapshot, returning the
+    /// seq of the last entry exported
+    ///
+    /// The entries are read from a view of the backend taken when the export
+    /// starts, so the store can keep being written to (by this or any clone of
+    /// it) while the export runs: later entries, rollbacks or pruning don't
+    /// make it into the snapshot.
+    pub fn export(&self, writer: impl Write) -> Result<Seq, Error> {
+        let view = self.db.view()?;
+
+        let last = WalKV::last_key(&view)?.ok_or(Error::NotFound)?.0;
+
+        let mut out = snapshot::Writer::new(writer, Content::Wal)?;
+
+        for entry in WalIterator(WalKV::iter_entries(&view, IteratorMode::Start)) {
+            let (seq, log) = entry?;
+            out.write(&Record::Wal(seq, log))?;
+        }
+
+        out.finish()?;
+
+        Ok(last)
+    }
+
+    /// Builds the store from a snapshot written by [`Store::export`], on top
+    /// of an empty backend
+    ///
+    /// Entries keep their seq, so readers can resume with
+    /// [`Store::crawl_after`] from the last seq they processed. The backend is
+    /// left with the entries imported so far when the snapshot is invalid,
+    /// it's up to the caller to discard it.
+    pub fn import(
+        db: B,
+        reader: impl Read,
+        k_param: u64,
+        immutable_overlap: Option<u64>,
+    ) -> Result<Self, Error> {
+        db.ensure_table(WalKV::CF_NAME)?;
+
+        if !WalKV::is_empty(&db) {
+            return Err(Error::NotEmpty);
+        }
+
+        let mut batch = WriteBatch::default();
+
+        for record in snapshot::Reader::new(reader, Content::Wal)? {
+            let Record::Wal(seq, log) = record? else {
+                return Err(Error::InvalidSnapshot);
+            };
+
+            WalKV::stage_upsert(DBInt(seq), DBSerde(log), &mut batch);
+
+            if batch.len() >= IMPORT_BATCH_SIZE {
+                db.write(std::mem::take(&mut batch))?;
+            }
+        }
+
+        db.write(batch)?;
+
+        Self::from_backend(db, k_param, immutable_overlap)
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+impl Store<crate::backend::rocksdb::RocksDbBackend> {
+    /// Creates a RocksDB checkpoint of the store at a path that must not exist
+    /// yet, returning the seq of the last entry it holds
+    ///
+    /// The checkpoint is a regular db that can be opened with [`Store::open`],
+    /// after which the WAL continues from the returned seq.
+    pub fn checkpoint(&self, path: impl AsRef<Path>) -> Result<Seq, Error> {
+        use crate::backend::rocksdb::RocksDbBackend;
+
+        self.db.checkpoint(path.as_ref())?;
+
+        // the seq is read from the checkpoint itself, since other clones of
+        // the store might have appended entries in the meantime
+        let copy = RocksDbBackend::open(path.as_ref())?;
+        let last = WalKV::last_key(&copy)?.ok_or(Error::NotFound)?;
+
+        Ok(last.0)
     }
 }
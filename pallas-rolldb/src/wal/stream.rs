@@ -1,12 +1,13 @@
 use futures_core::Stream;
 
 use super::{BlockHash, BlockSlot, Log, Store};
+use crate::backend::Backend;
 
 pub struct RollStream;
 
 impl RollStream {
-    pub fn intersect(
-        store: Store,
+    pub fn intersect<B: Backend>(
+        store: Store<B>,
         intersect: Vec<(BlockSlot, BlockHash)>,
     ) -> impl Stream<Item = Log> {
         async_stream::stream! {
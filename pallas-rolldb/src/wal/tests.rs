@@ -1,4 +1,6 @@
 use super::{BlockBody, BlockHash, BlockSlot, Store};
+use crate::backend::memory::MemoryBackend;
+use crate::Error;
 
 fn with_tmp_db<T>(k_param: u64, op: fn(store: Store) -> T) {
     let path = tempfile::tempdir().unwrap().into_path();
@@ -23,6 +25,42 @@ fn dummy_block(slot: u64) -> (BlockSlot, BlockHash, BlockBody) {
     (slot, hash, slot.to_be_bytes().to_vec())
 }
 
+/// Sink for an export that runs `during` once a few writes went through, to
+/// change the store halfway through the export
+struct WriteDuring<F: FnMut()> {
+    out: Vec<u8>,
+    writes: usize,
+    during: Option<F>,
+}
+
+impl<F: FnMut()> WriteDuring<F> {
+    fn new(during: F) -> Self {
+        Self {
+            out: vec![],
+            writes: 0,
+            during: Some(during),
+        }
+    }
+}
+
+impl<F: FnMut()> std::io::Write for WriteDuring<F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writes += 1;
+
+        if self.writes == 3 {
+            if let Some(mut during) = self.during.take() {
+                during();
+            }
+        }
+
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 #[test]
 fn test_origin_event() {
     with_tmp_db(30, |db| {
@@ -211,3 +249,132 @@ fn test_intersect_options() {
         }
     });
 }
+
+#[test]
+fn test_export_import() {
+    with_tmp_db(30, |mut db| {
+        for i in 0..50 {
+            let (slot, hash, body) = dummy_block(i * 10);
+            db.roll_forward(slot, hash, body).unwrap();
+        }
+
+        db.roll_back(400).unwrap();
+        db.prune_wal().unwrap();
+
+        let mut snapshot = vec![];
+        let seq = db.export(&mut snapshot).unwrap();
+
+        let mut restored =
+            Store::import(MemoryBackend::default(), snapshot.as_slice(), 30, None).unwrap();
+
+        // the restored wal holds the same entries, with the same seq
+        let expected: Vec<_> = db.crawl_after(None).map(|x| x.unwrap()).collect();
+        let found: Vec<_> = restored.crawl_after(None).map(|x| x.unwrap()).collect();
+
+        assert_eq!(found.len(), expected.len());
+        assert_eq!(found.last().unwrap().0, seq);
+
+        for ((seq_a, log_a), (seq_b, log_b)) in found.iter().zip(expected.iter()) {
+            assert_eq!(seq_a, seq_b);
+            assert_eq!(log_a.slot(), log_b.slot());
+            assert_eq!(log_a.hash(), log_b.hash());
+            assert_eq!(log_a.is_mark(), log_b.is_mark());
+        }
+
+        assert_eq!(restored.find_tip().unwrap(), db.find_tip().unwrap());
+
+        // readers resume from the snapshot seq
+        assert!(restored.crawl_after(Some(seq)).next().is_none());
+
+        let (slot, hash, body) = dummy_block(500);
+        restored.roll_forward(slot, hash, body).unwrap();
+
+        let (next, log) = restored.crawl_after(Some(seq)).next().unwrap().unwrap();
+        assert_eq!(next, seq + 1);
+        assert_eq!(log.slot(), Some(500));
+
+        // truncated snapshots are rejected
+        let truncated = &snapshot[..snapshot.len() - 4];
+        assert!(matches!(
+            Store::import(MemoryBackend::default(), truncated, 30, None),
+            Err(Error::InvalidSnapshot)
+        ));
+    });
+}
+
+#[test]
+fn test_export_while_writing() {
+    with_tmp_db(30, |mut db| {
+        for i in 0..20 {
+            let (slot, hash, body) = dummy_block(i * 10);
+            db.roll_forward(slot, hash, body).unwrap();
+        }
+
+        let expected: Vec<_> = db.crawl_after(None).map(|x| x.unwrap()).collect();
+
+        let mut writer = db.clone();
+
+        let mut sink = WriteDuring::new(move || {
+            writer.roll_back(50).unwrap();
+
+            for slot in [55, 65, 75] {
+                let (slot, hash, body) = dummy_block(slot);
+                writer.roll_forward(slot, hash, body).unwrap();
+            }
+
+            writer.prune_wal().unwrap();
+        });
+
+        let seq = db.export(&mut sink).unwrap();
+        assert!(sink.during.is_none());
+        assert_eq!(seq, expected.last().unwrap().0);
+
+        // the snapshot holds the wal as it was when the export started
+        let restored =
+            Store::import(MemoryBackend::default(), sink.out.as_slice(), 30, None).unwrap();
+
+        let found: Vec<_> = restored.crawl_after(None).map(|x| x.unwrap()).collect();
+        assert_eq!(found.len(), expected.len());
+
+        for ((seq_a, log_a), (seq_b, log_b)) in found.iter().zip(expected.iter()) {
+            assert_eq!(seq_a, seq_b);
+            assert_eq!(log_a.slot(), log_b.slot());
+            assert_eq!(log_a.hash(), log_b.hash());
+        }
+
+        assert_eq!(restored.find_tip().unwrap().unwrap().0, 190);
+        assert_eq!(db.find_tip().unwrap().unwrap().0, 75);
+    });
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn test_checkpoint_restore() {
+    with_tmp_db(30, |mut db| {
+        for i in 0..10 {
+            let (slot, hash, body) = dummy_block(i * 10);
+            db.roll_forward(slot, hash, body).unwrap();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint");
+
+        let seq = db.checkpoint(&path).unwrap();
+        assert_eq!(seq, 10);
+
+        // entries appended after the checkpoint are not part of it
+        let (slot, hash, body) = dummy_block(100);
+        db.roll_forward(slot, hash, body).unwrap();
+
+        let mut restored = Store::open(&path, 30, None).unwrap();
+        assert_eq!(restored.find_tip().unwrap().unwrap().0, 90);
+        assert!(restored.crawl_after(Some(seq)).next().is_none());
+
+        let (slot, hash, body) = dummy_block(200);
+        restored.roll_forward(slot, hash, body).unwrap();
+
+        let (next, log) = restored.crawl_after(Some(seq)).next().unwrap().unwrap();
+        assert_eq!(next, seq + 1);
+        assert_eq!(log.slot(), Some(200));
+    });
+}